@@ -3,10 +3,27 @@ use std::collections::HashMap;
 use std::borrow::Cow;
 use std::rc::Rc;
 
-use compiler::PrimitiveSyntax;
+use compiler::{PrimitiveSyntax, Syntax};
+use bytevector::libbytevector;
+use condition::{libcondition, PRIM_ASSERTION_CONDITION, PRIM_ERROR_CONDITION, PRIM_SYNTAX_VIOLATION_CONDITION};
 use datum::Datum;
+use enums::{enum_set_constructor_code, enum_set_indexer_code, libenums};
+use error::RuntimeErrorKind;
+use hashtable::{libhashtable, PRIM_HASHTABLE_CANDIDATES, PRIM_HASHTABLE_ENTRIES,
+                PRIM_HASHTABLE_EQUIVALENCE_FUNCTION, PRIM_HASHTABLE_HASH_FUNCTION,
+                PRIM_HASHTABLE_HAS_KEY, PRIM_HASHTABLE_LOOKUP, PRIM_HASHTABLE_REMOVE,
+                PRIM_HASHTABLE_STORE};
+use library::{Export, Library};
+use parameter::make_parameter_code;
+use port::{libport, port_procedures, simple_procedures};
 use primitive::libprimitive;
-use runtime::{DatumType, Inst, PrimFuncPtr, RuntimeData, Closure, RDatum};
+use promise::{force_code, libpromise};
+use record::{librecord, record_accessor_code, record_constructor_code, record_mutator_code,
+             record_predicate_code};
+use runtime::{DatumType, Inst, MemRef, PrimFuncPtr, RuntimeData, Closure, RDatum};
+use string::{libstring, PRIM_STRING_COLUMNS};
+use syntax::libsyntax;
+use unicode::libunicode;
 
 /// Compiles the global env from `base`
 pub fn base_syntax() -> HashMap<Cow<'static, str>, PrimitiveSyntax> {
@@ -23,13 +40,54 @@ pub fn static_closure(bytecode: Vec<Inst>) -> Rc<RefCell<RDatum>> {
     Rc::new(RefCell::new(Datum::Ext(RuntimeData::Closure(Closure::new(Rc::new(bytecode), None, None)))))
 }
 
-pub fn libbase() -> HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>> {
-    let mut lib = HashMap::new();
-    for &(name, func) in libprimitive().iter() {
-        lib.insert(Cow::Borrowed(name), Rc::new(RefCell::new(Datum::Ext(RuntimeData::PrimFunc(PrimFuncPtr::new(name, func))))));
+/// Bytecode of `raise` or `raise-continuable`. The raised object is passed to the current
+/// exception handler, which runs with the outer handlers installed
+pub fn raise_code(continuable: bool) -> Vec<Inst> {
+    let mut code = vec![
+        Inst::TakeHandler,
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1)
+    ];
+
+    if continuable {
+        code.push(Inst::SwapArg);
+        code.push(Inst::InstallHandler);
+        code.push(Inst::Return);
+    } else {
+        code.push(Inst::Throw(RuntimeErrorKind::NonContinuable,
+                              "handler returned from non-continuable exception"));
     }
 
-    let apply: Vec<Inst> = vec![
+    code
+}
+
+/// Bytecode of the exception handler of `guard` without an `else` clause. The handler captures
+/// the continuation of the raise, and passes the condition and the continuation to the clauses
+/// through the escape at argument 1 of the enclosing frame. When no clause matches, the clauses
+/// resume the continuation, and the condition is raised again to the outer handler with
+/// `raise-continuable` in the dynamic environment of the original raise
+pub fn guard_handler_code() -> Vec<Inst> {
+    vec![
+        // the continuation resumes at 6
+        Inst::PushContinuation(6),
+        Inst::PushArg(MemRef::UpValue(0, 1)),
+        Inst::SwapArg,
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::SwapArg,
+        Inst::Call(2),
+        // 6
+        Inst::DropArg(1),
+        Inst::PushArg(MemRef::Closure(Rc::new(raise_code(true)), 0, None)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `apply`, which pushes the elements of the last argument and calls the procedure
+pub fn apply_code() -> Vec<Inst> {
+    vec![
         // 0
         Inst::Type(DatumType::Pair),
         Inst::JumpIfFalse(5),
@@ -43,7 +101,214 @@ pub fn libbase() -> HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>> {
         Inst::DropArg(2),
         Inst::CallSplicing,
         Inst::Return
+    ]
+}
+
+/// Bytecode of `string-for-each`. The strings are transposed into the lists of characters at
+/// each index, and the procedure is applied to each list
+fn string_for_each_code() -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(1),
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("string-for-each", &PRIM_STRING_COLUMNS))),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(1),
+        // 4
+        Inst::Type(DatumType::Null),
+        Inst::JumpIfFalse(8),
+        Inst::PushArg(MemRef::Undefined),
+        Inst::Return,
+        // 8
+        Inst::DropArg(1),
+        Inst::Uncons,
+        Inst::SwapArg,
+        Inst::PushArg(MemRef::Closure(Rc::new(apply_code()), 0, None)),
+        Inst::SwapArg,
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::SwapArg,
+        Inst::Call(2),
+        Inst::DropArg(1),
+        Inst::Jump(4)
+    ]
+}
+
+/// Bytecode of the hashtable procedure `name` taking `nargs` arguments, the hashtable and the key
+/// first. The hash value and the canonical key are pushed, then `op` runs with them as the
+/// arguments `nargs` and `nargs+1`.
+///
+/// The hash value is `#f` for eq and eqv hashtables, whose canonical key is the key itself. For
+/// custom hashtables, the canonical key is the first key stored under the hash value which is
+/// equivalent to the key, or the key itself if there is none
+fn hashtable_code(name: &'static str, nargs: usize, op: Vec<Inst>) -> Vec<Inst> {
+    let mut code = vec![
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new(name, &PRIM_HASHTABLE_HASH_FUNCTION))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::JumpIfFalse(31),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(1),
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new(name, &PRIM_HASHTABLE_CANDIDATES))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(nargs)),
+        Inst::Call(2),
+        // 10: loop over the candidates
+        Inst::Type(DatumType::Null),
+        Inst::JumpIfFalse(15),
+        Inst::DropArg(2),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Jump(32),
+        // 15
+        Inst::DropArg(1),
+        Inst::Uncons,
+        Inst::SwapArg,
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new(name, &PRIM_HASHTABLE_EQUIVALENCE_FUNCTION))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(nargs + 2)),
+        Inst::Call(2),
+        Inst::JumpIfFalse(29),
+        Inst::DropArg(1),
+        Inst::SwapArg,
+        Inst::DropArg(1),
+        Inst::Jump(32),
+        // 29
+        Inst::DropArg(2),
+        Inst::Jump(10),
+        // 31: eq or eqv hashtable
+        Inst::PushArg(MemRef::Arg(1)),
+        // 32
+        Inst::SetArgSize(nargs + 2)
     ];
+    code.extend(op);
+    code.push(Inst::Return);
+    code
+}
+
+/// Bytecode of `(hashtable-ref hashtable key default)`
+fn hashtable_ref_code() -> Vec<Inst> {
+    hashtable_code("hashtable-ref", 3, vec![
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-ref", &PRIM_HASHTABLE_LOOKUP))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(4)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::Call(3)
+    ])
+}
+
+/// Bytecode of `(hashtable-contains? hashtable key)`
+fn hashtable_contains_code() -> Vec<Inst> {
+    hashtable_code("hashtable-contains?", 2, vec![
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-contains?", &PRIM_HASHTABLE_HAS_KEY))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::Call(2)
+    ])
+}
+
+/// Bytecode of `(hashtable-set! hashtable key value)`
+fn hashtable_set_code() -> Vec<Inst> {
+    hashtable_code("hashtable-set!", 3, vec![
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-set!", &PRIM_HASHTABLE_STORE))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::PushArg(MemRef::Arg(4)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::Call(4),
+        Inst::TrackStore
+    ])
+}
+
+/// Bytecode of `(hashtable-delete! hashtable key)`
+fn hashtable_delete_code() -> Vec<Inst> {
+    hashtable_code("hashtable-delete!", 2, vec![
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-delete!", &PRIM_HASHTABLE_REMOVE))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::Call(3)
+    ])
+}
+
+/// Bytecode of `(hashtable-update! hashtable key proc default)`, which stores the value returned
+/// by `proc` applied to the current value
+fn hashtable_update_code() -> Vec<Inst> {
+    hashtable_code("hashtable-update!", 4, vec![
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-update!", &PRIM_HASHTABLE_STORE))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(4)),
+        Inst::PushArg(MemRef::Arg(5)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-update!", &PRIM_HASHTABLE_LOOKUP))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(5)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::Call(3),
+        Inst::Call(1),
+        Inst::Call(4),
+        Inst::TrackStore
+    ])
+}
+
+/// Bytecode of `hashtable-entries`, returning the keys and the values as two vectors
+fn hashtable_entries_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(MemRef::Closure(Rc::new(vec![Inst::ReturnValues]), 0, None)),
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("hashtable-entries", &PRIM_HASHTABLE_ENTRIES))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::Uncons,
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `error` or `assertion-violation`, which builds the condition with `make_condition`
+/// and raises it
+fn error_code(make_condition: PrimFuncPtr) -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(2),
+        Inst::PushArg(MemRef::Closure(Rc::new(raise_code(false)), 0, None)),
+        Inst::PushArg(MemRef::PrimFunc(make_condition)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::Call(3),
+        Inst::TailCall
+    ]
+}
+
+/// Bytecode of `(syntax-violation who message form [subform])`, which raises the `&syntax`
+/// condition
+fn syntax_violation_code() -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(3),
+        Inst::PushArg(MemRef::Closure(Rc::new(raise_code(false)), 0, None)),
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("syntax-violation", &PRIM_SYNTAX_VIOLATION_CONDITION))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::Call(4),
+        Inst::TailCall
+    ]
+}
+
+pub fn libbase() -> HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>> {
+    let mut lib = HashMap::new();
+    let prims = libprimitive().into_iter()
+        .chain(libcondition().into_iter())
+        .chain(libsyntax().into_iter())
+        .chain(libstring().into_iter())
+        .chain(libunicode().into_iter())
+        .chain(libhashtable().into_iter())
+        .chain(librecord().into_iter())
+        .chain(libenums().into_iter())
+        .chain(libbytevector().into_iter())
+        .chain(libport().into_iter())
+        .chain(libpromise().into_iter());
+    for (name, func) in prims {
+        lib.insert(Cow::Borrowed(name), Rc::new(RefCell::new(Datum::Ext(RuntimeData::PrimFunc(PrimFuncPtr::new(name, func))))));
+    }
 
     let eqv: Vec<Inst> = vec![
         Inst::Eqv,
@@ -60,10 +325,342 @@ pub fn libbase() -> HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>> {
         Inst::Return
     ];
 
-    lib.insert(Cow::Borrowed("apply"), static_closure(apply));
+    let set_car: Vec<Inst> = vec![
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::SetCar,
+        Inst::Return
+    ];
+
+    let set_cdr: Vec<Inst> = vec![
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::SetCdr,
+        Inst::Return
+    ];
+
+    let call_cc: Vec<Inst> = vec![
+        // Calling the continuation resumes at 4 with the value pushed on top of the args
+        Inst::PushContinuation(4),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::SwapArg,
+        Inst::TailCall,
+        Inst::Return
+    ];
+
+    let with_exception_handler: Vec<Inst> = vec![
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::InstallHandler,
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(0),
+        Inst::UninstallHandler,
+        Inst::Return
+    ];
+
+    let call_with_values: Vec<Inst> = vec![
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(0),
+        // the values returned by the producer become the arguments of the consumer
+        Inst::SpreadValues,
+        Inst::TailCall,
+        Inst::Return
+    ];
+
+    let dynamic_wind: Vec<Inst> = vec![
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(0),
+        Inst::DropArg(1),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::PushWinder,
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(0),
+        Inst::PopWinder,
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::Call(0),
+        Inst::DropArg(1),
+        Inst::Return
+    ];
+
+    let eval: Vec<Inst> = vec![
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Eval,
+        Inst::Return
+    ];
+
+    let environment: Vec<Inst> = vec![
+        Inst::RollArgs(0),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Environment,
+        Inst::Return
+    ];
+
+    lib.insert(Cow::Borrowed("apply"), static_closure(apply_code()));
+    lib.insert(Cow::Borrowed("error"),
+               static_closure(error_code(PrimFuncPtr::new("error", &PRIM_ERROR_CONDITION))));
+    lib.insert(Cow::Borrowed("assertion-violation"),
+               static_closure(error_code(PrimFuncPtr::new("assertion-violation", &PRIM_ASSERTION_CONDITION))));
+    lib.insert(Cow::Borrowed("syntax-violation"), static_closure(syntax_violation_code()));
     lib.insert(Cow::Borrowed("eqv?"), static_closure(eqv));
     lib.insert(Cow::Borrowed("eq?"), static_closure(eq));
     lib.insert(Cow::Borrowed("equal?"), static_closure(equal));
+    lib.insert(Cow::Borrowed("set-car!"), static_closure(set_car));
+    lib.insert(Cow::Borrowed("set-cdr!"), static_closure(set_cdr));
+    lib.insert(Cow::Borrowed("string-for-each"), static_closure(string_for_each_code()));
+    lib.insert(Cow::Borrowed("hashtable-ref"), static_closure(hashtable_ref_code()));
+    lib.insert(Cow::Borrowed("hashtable-contains?"), static_closure(hashtable_contains_code()));
+    lib.insert(Cow::Borrowed("hashtable-set!"), static_closure(hashtable_set_code()));
+    lib.insert(Cow::Borrowed("hashtable-delete!"), static_closure(hashtable_delete_code()));
+    lib.insert(Cow::Borrowed("hashtable-update!"), static_closure(hashtable_update_code()));
+    lib.insert(Cow::Borrowed("hashtable-entries"), static_closure(hashtable_entries_code()));
+    lib.insert(Cow::Borrowed("record-constructor"), static_closure(record_constructor_code()));
+    lib.insert(Cow::Borrowed("record-predicate"), static_closure(record_predicate_code()));
+    lib.insert(Cow::Borrowed("record-accessor"), static_closure(record_accessor_code()));
+    lib.insert(Cow::Borrowed("record-mutator"), static_closure(record_mutator_code()));
+    lib.insert(Cow::Borrowed("enum-set-indexer"), static_closure(enum_set_indexer_code()));
+    lib.insert(Cow::Borrowed("enum-set-constructor"), static_closure(enum_set_constructor_code()));
+    lib.insert(Cow::Borrowed("call-with-current-continuation"), static_closure(call_cc.clone()));
+    lib.insert(Cow::Borrowed("call/cc"), static_closure(call_cc));
+    lib.insert(Cow::Borrowed("values"), static_closure(vec![Inst::ReturnValues]));
+    lib.insert(Cow::Borrowed("call-with-values"), static_closure(call_with_values));
+    lib.insert(Cow::Borrowed("dynamic-wind"), static_closure(dynamic_wind));
+    lib.insert(Cow::Borrowed("with-exception-handler"), static_closure(with_exception_handler));
+    lib.insert(Cow::Borrowed("raise"), static_closure(raise_code(false)));
+    lib.insert(Cow::Borrowed("raise-continuable"), static_closure(raise_code(true)));
+    lib.insert(Cow::Borrowed("eval"), static_closure(eval));
+    lib.insert(Cow::Borrowed("environment"), static_closure(environment));
+    lib.insert(Cow::Borrowed("force"), static_closure(force_code()));
+    lib.insert(Cow::Borrowed("make-parameter"), static_closure(make_parameter_code()));
+    for (name, code) in port_procedures() {
+        lib.insert(Cow::Borrowed(name), static_closure(code));
+    }
+    for (name, datum) in simple_procedures() {
+        lib.insert(Cow::Borrowed(name), Rc::new(RefCell::new(datum)));
+    }
 
     return lib;
 }
+
+/// Identifiers exported by `(rnrs base)`
+const RNRS_BASE: &'static [&'static str] = &[
+    "lambda", "if", "let", "let*", "letrec", "letrec*", "define", "set!", "begin", "quote",
+    "quasiquote", "unquote", "unquote-splicing", "cond", "case", "and", "or", "syntax-rules",
+    "let-syntax", "letrec-syntax", "define-syntax", "let-values", "let*-values", "define-values",
+    "+", "-", "*", "/", "=", "<", ">", "<=", ">=", "zero?",
+    "number?", "complex?", "real?", "rational?", "integer?",
+    "boolean?", "pair?", "symbol?", "char?", "string?", "vector?", "procedure?", "null?", "not",
+    "char->integer", "integer->char", "char=?", "char<?", "char>?", "char<=?", "char>=?",
+    "cons", "car", "cdr", "list", "append", "symbol->string",
+    "make-string", "string", "string-length", "string-ref", "substring", "string-append",
+    "string->list", "list->string", "string-copy", "string-for-each",
+    "string=?", "string<?", "string>?", "string<=?", "string>=?",
+    "vector", "make-vector", "vector-ref", "vector->list", "list->vector",
+    "eqv?", "eq?", "equal?", "apply", "call-with-current-continuation", "call/cc",
+    "values", "call-with-values", "dynamic-wind", "error", "assertion-violation",
+    "make-parameter", "parameterize"
+];
+
+/// Identifiers exported by `(rnrs control)`
+const RNRS_CONTROL: &'static [&'static str] = &[
+    "when", "unless", "do", "case-lambda"
+];
+
+/// Identifiers exported by `(rnrs lists)`
+const RNRS_LISTS: &'static [&'static str] = &[
+    "memq", "memv", "member", "assq", "assv", "assoc", "cons*"
+];
+
+/// Identifiers exported by `(rnrs mutable-pairs)`
+const RNRS_MUTABLE_PAIRS: &'static [&'static str] = &[
+    "set-car!", "set-cdr!"
+];
+
+/// Identifiers exported by `(rnrs mutable-strings)`
+const RNRS_MUTABLE_STRINGS: &'static [&'static str] = &[
+    "string-set!", "string-fill!"
+];
+
+/// Identifiers exported by `(rnrs unicode)`
+const RNRS_UNICODE: &'static [&'static str] = &[
+    "char-upcase", "char-downcase", "char-titlecase", "char-foldcase",
+    "char-ci=?", "char-ci<?", "char-ci>?", "char-ci<=?", "char-ci>=?",
+    "char-alphabetic?", "char-numeric?", "char-whitespace?", "char-upper-case?",
+    "char-lower-case?", "char-title-case?", "char-general-category",
+    "string-upcase", "string-downcase", "string-titlecase", "string-foldcase",
+    "string-ci=?", "string-ci<?", "string-ci>?", "string-ci<=?", "string-ci>=?",
+    "string-normalize-nfd", "string-normalize-nfkd", "string-normalize-nfc", "string-normalize-nfkc"
+];
+
+/// Identifiers exported by `(rnrs hashtables)`
+const RNRS_HASHTABLES: &'static [&'static str] = &[
+    "make-eq-hashtable", "make-eqv-hashtable", "make-hashtable", "hashtable?", "hashtable-size",
+    "hashtable-ref", "hashtable-set!", "hashtable-delete!", "hashtable-contains?",
+    "hashtable-update!", "hashtable-copy", "hashtable-clear!", "hashtable-keys",
+    "hashtable-entries", "hashtable-equivalence-function", "hashtable-hash-function",
+    "hashtable-mutable?", "equal-hash", "string-hash", "string-ci-hash", "symbol-hash"
+];
+
+/// Identifiers exported by `(rnrs records syntactic)`
+const RNRS_RECORDS_SYNTACTIC: &'static [&'static str] = &[
+    "define-record-type", "fields", "mutable", "immutable", "parent", "protocol", "sealed",
+    "opaque", "nongenerative", "parent-rtd", "record-type-descriptor",
+    "record-constructor-descriptor"
+];
+
+/// Identifiers exported by `(rnrs records procedural)`
+const RNRS_RECORDS_PROCEDURAL: &'static [&'static str] = &[
+    "make-record-type-descriptor", "record-type-descriptor?", "make-record-constructor-descriptor",
+    "record-constructor", "record-predicate", "record-accessor", "record-mutator"
+];
+
+/// Identifiers exported by `(rnrs records inspection)`
+const RNRS_RECORDS_INSPECTION: &'static [&'static str] = &[
+    "record?", "record-rtd", "record-type-name", "record-type-parent", "record-type-uid",
+    "record-type-generative?", "record-type-sealed?", "record-type-opaque?",
+    "record-type-field-names", "record-field-mutable?"
+];
+
+/// Identifiers exported by `(rnrs enums)`
+const RNRS_ENUMS: &'static [&'static str] = &[
+    "make-enumeration", "enum-set-universe", "enum-set-indexer", "enum-set-constructor",
+    "enum-set->list", "enum-set-member?", "enum-set-subset?", "enum-set=?", "enum-set-union",
+    "enum-set-intersection", "enum-set-difference", "enum-set-complement",
+    "enum-set-projection", "define-enumeration"
+];
+
+/// Identifiers exported by `(rnrs bytevectors)`
+const RNRS_BYTEVECTORS: &'static [&'static str] = &[
+    "endianness", "native-endianness", "bytevector?", "make-bytevector", "bytevector-length",
+    "bytevector=?", "bytevector-fill!", "bytevector-copy!", "bytevector-copy",
+    "bytevector-u8-ref", "bytevector-u8-set!", "bytevector-s8-ref", "bytevector-s8-set!",
+    "bytevector->u8-list", "u8-list->bytevector",
+    "bytevector-uint-ref", "bytevector-sint-ref", "bytevector-uint-set!", "bytevector-sint-set!",
+    "bytevector->uint-list", "bytevector->sint-list", "uint-list->bytevector", "sint-list->bytevector",
+    "bytevector-u16-ref", "bytevector-u16-set!", "bytevector-u16-native-ref",
+    "bytevector-u16-native-set!", "bytevector-s16-ref", "bytevector-s16-set!",
+    "bytevector-s16-native-ref", "bytevector-s16-native-set!",
+    "bytevector-u32-ref", "bytevector-u32-set!", "bytevector-u32-native-ref",
+    "bytevector-u32-native-set!", "bytevector-s32-ref", "bytevector-s32-set!",
+    "bytevector-s32-native-ref", "bytevector-s32-native-set!",
+    "bytevector-u64-ref", "bytevector-u64-set!", "bytevector-u64-native-ref",
+    "bytevector-u64-native-set!", "bytevector-s64-ref", "bytevector-s64-set!",
+    "bytevector-s64-native-ref", "bytevector-s64-native-set!",
+    "bytevector-ieee-single-ref", "bytevector-ieee-single-set!",
+    "bytevector-ieee-single-native-ref", "bytevector-ieee-single-native-set!",
+    "bytevector-ieee-double-ref", "bytevector-ieee-double-set!",
+    "bytevector-ieee-double-native-ref", "bytevector-ieee-double-native-set!",
+    "string->utf8", "utf8->string", "string->utf16", "utf16->string", "string->utf32",
+    "utf32->string"
+];
+
+/// Identifiers exported by `(rnrs io ports)`
+const RNRS_IO_PORTS: &'static [&'static str] = &[
+    "file-options", "buffer-mode", "buffer-mode?", "latin-1-codec", "utf-8-codec", "eol-style",
+    "native-eol-style", "error-handling-mode", "make-transcoder", "native-transcoder",
+    "transcoder-codec", "transcoder-eol-style", "transcoder-error-handling-mode",
+    "bytevector->string", "string->bytevector", "eof-object", "eof-object?", "port?",
+    "port-transcoder", "textual-port?", "binary-port?", "transcoded-port", "close-port",
+    "call-with-port", "input-port?", "port-eof?", "open-file-input-port",
+    "open-bytevector-input-port", "open-string-input-port", "standard-input-port",
+    "make-custom-binary-input-port", "make-custom-textual-input-port", "get-u8", "lookahead-u8",
+    "get-bytevector-n", "get-bytevector-all", "get-char", "lookahead-char", "get-string-n",
+    "get-string-all", "get-line", "output-port?", "flush-output-port", "open-file-output-port",
+    "open-bytevector-output-port", "open-string-output-port", "standard-output-port",
+    "standard-error-port", "make-custom-binary-output-port", "make-custom-textual-output-port",
+    "put-u8", "put-bytevector", "put-char", "put-string", "make-i/o-error", "i/o-error?",
+    "make-i/o-read-error", "i/o-read-error?", "make-i/o-write-error", "i/o-write-error?",
+    "make-i/o-invalid-position-error", "i/o-invalid-position-error?", "i/o-error-position",
+    "make-i/o-filename-error", "i/o-filename-error?", "i/o-error-filename",
+    "make-i/o-file-protection-error", "i/o-file-protection-error?",
+    "make-i/o-file-is-read-only-error", "i/o-file-is-read-only-error?",
+    "make-i/o-file-already-exists-error", "i/o-file-already-exists-error?",
+    "make-i/o-file-does-not-exist-error", "i/o-file-does-not-exist-error?",
+    "make-i/o-port-error", "i/o-port-error?", "i/o-error-port", "make-i/o-decoding-error",
+    "i/o-decoding-error?", "make-i/o-encoding-error", "i/o-encoding-error?",
+    "i/o-encoding-error-char"
+];
+
+/// Identifiers exported by `(rnrs io simple)`
+const RNRS_IO_SIMPLE: &'static [&'static str] = &[
+    "eof-object", "eof-object?", "call-with-input-file", "call-with-output-file", "input-port?",
+    "output-port?", "current-input-port", "current-output-port", "current-error-port",
+    "with-input-from-file", "with-output-to-file", "open-input-file", "open-output-file",
+    "close-input-port", "close-output-port", "read-char", "peek-char", "read", "write-char",
+    "newline", "display", "write"
+];
+
+/// Identifiers exported by `(rnrs exceptions)`
+const RNRS_EXCEPTIONS: &'static [&'static str] = &[
+    "with-exception-handler", "guard", "raise", "raise-continuable"
+];
+
+/// Identifiers exported by `(rnrs eval)`
+const RNRS_EVAL: &'static [&'static str] = &[
+    "eval", "environment"
+];
+
+/// Identifiers exported by `(rnrs r5rs)`, with the promises of R7RS
+const RNRS_R5RS: &'static [&'static str] = &[
+    "delay", "force", "delay-force", "make-promise", "promise?"
+];
+
+/// Identifiers exported by `(rnrs syntax-case)`
+const RNRS_SYNTAX_CASE: &'static [&'static str] = &[
+    "syntax-case", "syntax", "quasisyntax", "unsyntax", "unsyntax-splicing", "with-syntax",
+    "identifier?", "bound-identifier=?", "free-identifier=?", "datum->syntax", "syntax->datum",
+    "syntax-violation"
+];
+
+/// The standard libraries exporting the bindings of the base environment, including the
+/// composite library `(rnrs)`. Identifiers not bound in the environment are not exported
+pub fn rnrs_libraries(base: &HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
+                      syntax: &HashMap<Cow<'static, str>, PrimitiveSyntax>)
+        -> Vec<Library>
+{
+    let conditions: Vec<&'static str> = libcondition().iter().map(|&(name, _)| name).collect();
+    let specs: Vec<(&'static str, &[&'static str])> = vec![
+        ("base", RNRS_BASE),
+        ("control", RNRS_CONTROL),
+        ("lists", RNRS_LISTS),
+        ("mutable-pairs", RNRS_MUTABLE_PAIRS),
+        ("mutable-strings", RNRS_MUTABLE_STRINGS),
+        ("unicode", RNRS_UNICODE),
+        ("hashtables", RNRS_HASHTABLES),
+        ("records syntactic", RNRS_RECORDS_SYNTACTIC),
+        ("records procedural", RNRS_RECORDS_PROCEDURAL),
+        ("records inspection", RNRS_RECORDS_INSPECTION),
+        ("enums", RNRS_ENUMS),
+        ("bytevectors", RNRS_BYTEVECTORS),
+        ("io ports", RNRS_IO_PORTS),
+        ("io simple", RNRS_IO_SIMPLE),
+        ("exceptions", RNRS_EXCEPTIONS),
+        ("eval", RNRS_EVAL),
+        ("r5rs", RNRS_R5RS),
+        ("syntax-case", RNRS_SYNTAX_CASE),
+        ("conditions", &conditions)
+    ];
+
+    let mut libraries = Vec::new();
+    let mut composite = HashMap::new();
+    for (name, ids) in specs.into_iter() {
+        let mut exports = HashMap::new();
+        for &id in ids.iter() {
+            let export = if let Some(cell) = base.get(id) {
+                Export::Var(cell.clone())
+            } else if let Some(&syn) = syntax.get(id) {
+                Export::Syntax(Syntax::Primitive(syn))
+            } else {
+                continue;
+            };
+            exports.insert(Cow::Borrowed(id), export);
+        }
+        composite.extend(exports.clone());
+        let lib_name = Some("rnrs").into_iter().chain(name.split(' ')).map(Cow::Borrowed).collect();
+        libraries.push(Library::new(lib_name, vec![6], exports));
+    }
+    libraries.push(Library::new(vec![Cow::Borrowed("rnrs")], vec![6], composite));
+
+    libraries
+}
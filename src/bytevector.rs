@@ -0,0 +1,841 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::char;
+use std::rc::Rc;
+
+use num::{BigInt, FromPrimitive, One, ToPrimitive, Zero};
+
+use cast::DatumCast;
+use datum::Datum;
+use error::{RuntimeError, RuntimeErrorKind};
+use number::Number;
+use primitive::{F1, F2, F3, FoldErr, PrimFunc, R1};
+use real::Real;
+use runtime::{DatumType, RDatum, RuntimeData};
+
+/// Bytes of the bytevector, shared by every reference to the bytevector
+type Bytes = Rc<RefCell<Vec<u8>>>;
+
+/// Characters of a string
+type Chars = Rc<RefCell<Vec<char>>>;
+
+fn new_bytes(v: Vec<u8>) -> Bytes {
+    Rc::new(RefCell::new(v))
+}
+
+fn invalid(desc: String) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::InvalidType,
+        desc: desc
+    }
+}
+
+fn index_error(len: usize, k: usize, size: usize) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::IndexOutOfRange,
+        desc: format!("bytevector length is {}, but {} bytes are accessed at {}", len, size, k)
+    }
+}
+
+/// Whether the endianness symbol is `big`
+fn is_big(endianness: &str) -> Result<bool, RuntimeError> {
+    match endianness {
+        "big" => Ok(true),
+        "little" => Ok(false),
+        _ => Err(invalid(format!("expected big or little, but received {}", endianness)))
+    }
+}
+
+/// Name of the native endianness, which the `native` accessors use
+pub fn native_endianness_name() -> &'static str {
+    if cfg!(target_endian = "big") { "big" } else { "little" }
+}
+
+fn exact_integer(datum: RDatum) -> Result<BigInt, RuntimeError> {
+    let datumtype = DatumType::get_type(&datum);
+    if let Datum::Num(Number::Real(r)) = datum {
+        match r.reduce() {
+            Real::Fixnum(n) => return Ok(FromPrimitive::from_isize(n).unwrap()),
+            Real::Integer(n) => return Ok(n),
+            _ => ()
+        }
+    }
+    Err(invalid(format!("expected exact integer, but received {:?}", datumtype)))
+}
+
+fn integer_datum(n: BigInt) -> RDatum {
+    Datum::Num(Number::Real(Real::Integer(n).reduce()))
+}
+
+/// Byte stored by `make-bytevector` or `bytevector-fill!`, which is an octet or a signed byte
+fn fill_byte(datum: RDatum) -> Result<u8, RuntimeError> {
+    let n = exact_integer(datum)?;
+    match n.to_isize() {
+        Some(b) if -128 <= b && b <= 255 => Ok(b as u8),
+        _ => Err(invalid(format!("{} is not a byte", n)))
+    }
+}
+
+/// Reads the unsigned integer of `size` bytes at `k`
+fn read_uint(bv: &Bytes, k: usize, size: usize, big: bool) -> Result<u64, RuntimeError> {
+    let bytes = bv.borrow();
+    if k + size > bytes.len() {
+        return Err(index_error(bytes.len(), k, size));
+    }
+    let slice = &bytes[k .. k+size];
+    let mut n = 0;
+    for i in 0 .. size {
+        let b = if big { slice[i] } else { slice[size-1-i] };
+        n = (n << 8) | b as u64;
+    }
+    Ok(n)
+}
+
+/// Writes the lowest `size` bytes of `n` at `k`
+fn write_uint(bv: &Bytes, k: usize, size: usize, big: bool, n: u64) -> Result<(), RuntimeError> {
+    let mut bytes = bv.borrow_mut();
+    let len = bytes.len();
+    if k + size > len {
+        return Err(index_error(len, k, size));
+    }
+    for i in 0 .. size {
+        let b = (n >> (8 * i)) as u8;
+        if big {
+            bytes[k+size-1-i] = b;
+        } else {
+            bytes[k+i] = b;
+        }
+    }
+    Ok(())
+}
+
+/// Native accessors only take the indices aligned to the size
+fn check_aligned(k: usize, size: usize) -> Result<(), RuntimeError> {
+    if k % size == 0 {
+        Ok(())
+    } else {
+        Err(invalid(format!("index {} is not aligned to {} bytes", k, size)))
+    }
+}
+
+fn int_ref(bv: &Bytes, k: usize, size: usize, signed: bool, big: bool) -> Result<RDatum, RuntimeError> {
+    let n = read_uint(bv, k, size, big)?;
+    let bits = 8 * size;
+    let value: BigInt = if signed && bits < 64 && n >> (bits - 1) == 1 {
+        FromPrimitive::from_i64(n as i64 - (1i64 << bits)).unwrap()
+    } else if signed {
+        FromPrimitive::from_i64(n as i64).unwrap()
+    } else {
+        FromPrimitive::from_u64(n).unwrap()
+    };
+    Ok(integer_datum(value))
+}
+
+/// Checks the integer is representable in `size` bytes
+fn check_fits(n: &BigInt, size: usize, signed: bool) -> Result<(), RuntimeError> {
+    let bits = 8 * size;
+    let one: BigInt = One::one();
+    let (min, max) = if signed {
+        (-(one.clone() << (bits - 1)), (one.clone() << (bits - 1)) - one)
+    } else {
+        (BigInt::from_u64(0).unwrap(), (one.clone() << bits) - one)
+    };
+    if *n < min || *n > max {
+        Err(invalid(format!("{} does not fit in {} bytes", n, size)))
+    } else {
+        Ok(())
+    }
+}
+
+fn int_set(bv: &Bytes, k: usize, size: usize, signed: bool, big: bool, value: RDatum) -> Result<RDatum, RuntimeError> {
+    let n = exact_integer(value)?;
+    check_fits(&n, size, signed)?;
+    let raw = if signed { n.to_i64().unwrap() as u64 } else { n.to_u64().unwrap() };
+    write_uint(bv, k, size, big, raw)?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+fn check_size(size: usize) -> Result<(), RuntimeError> {
+    if size == 0 {
+        Err(invalid("size of the integer must be positive".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads the integer of `size` bytes at `k`, where `size` is not limited to the sizes of the
+/// machine integers
+fn any_int_ref(bv: &Bytes, k: usize, size: usize, signed: bool, big: bool) -> Result<BigInt, RuntimeError> {
+    check_size(size)?;
+    let bytes = bv.borrow();
+    if k + size > bytes.len() {
+        return Err(index_error(bytes.len(), k, size));
+    }
+    let slice = &bytes[k .. k+size];
+    let mut n: BigInt = Zero::zero();
+    for i in 0 .. size {
+        let b = if big { slice[i] } else { slice[size-1-i] };
+        n = (n << 8) + BigInt::from_u8(b).unwrap();
+    }
+    let top = if big { slice[0] } else { slice[size-1] };
+    if signed && top >= 0x80 {
+        let one: BigInt = One::one();
+        n = n - (one << (8 * size));
+    }
+    Ok(n)
+}
+
+/// Writes the integer in `size` bytes at `k`, where `size` is not limited to the sizes of the
+/// machine integers
+fn any_int_set(bv: &Bytes, k: usize, size: usize, signed: bool, big: bool, n: BigInt) -> Result<(), RuntimeError> {
+    check_size(size)?;
+    check_fits(&n, size, signed)?;
+    let mut bytes = bv.borrow_mut();
+    let len = bytes.len();
+    if k + size > len {
+        return Err(index_error(len, k, size));
+    }
+    // the two's complement of a negative integer
+    let mut n = if n < Zero::zero() {
+        let one: BigInt = One::one();
+        n + (one << (8 * size))
+    } else {
+        n
+    };
+    let base = BigInt::from_u32(256).unwrap();
+    for i in 0 .. size {
+        let b = (n.clone() % base.clone()).to_u8().unwrap();
+        n = n / base.clone();
+        if big {
+            bytes[k+size-1-i] = b;
+        } else {
+            bytes[k+i] = b;
+        }
+    }
+    Ok(())
+}
+
+/// Arguments of the mutators taking the endianness: the bytevector, the index, the value and the
+/// endianness
+fn set_args(args: Vec<RDatum>) -> Result<(Bytes, usize, RDatum, bool), RuntimeError> {
+    let mut args = args.into_iter();
+    match (args.next(), args.next(), args.next(), args.next(), args.next()) {
+        (Some(bv), Some(k), Some(value), Some(endianness), None) => {
+            let endianness: Cow<'static, str> = DatumCast::unwrap(endianness)?;
+            Ok((DatumCast::unwrap(bv)?, DatumCast::unwrap(k)?, value, is_big(&endianness)?))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: "Expected 4 arguments".to_string()
+        })
+    }
+}
+
+macro_rules! impl_int_access {
+    ($size:expr, $signed:expr,
+     $ref_static:ident, $ref_fn:ident, $set_static:ident, $set_fn:ident,
+     $native_ref_static:ident, $native_ref_fn:ident, $native_set_static:ident, $native_set_fn:ident) => (
+        fn $ref_fn(bv: Bytes, k: usize, endianness: Cow<'static, str>) -> Result<RDatum, RuntimeError> {
+            int_ref(&bv, k, $size, $signed, is_big(&endianness)?)
+        }
+
+        pub static $ref_static: F3<Bytes, usize, Cow<'static, str>, Result<RDatum, RuntimeError>> = F3 { f3: $ref_fn };
+
+        fn $set_fn(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+            let (bv, k, value, big) = set_args(args)?;
+            int_set(&bv, k, $size, $signed, big, value)
+        }
+
+        pub static $set_static: FoldErr<RDatum> = FoldErr { fold: $set_fn };
+
+        fn $native_ref_fn(bv: Bytes, k: usize) -> Result<RDatum, RuntimeError> {
+            check_aligned(k, $size)?;
+            int_ref(&bv, k, $size, $signed, cfg!(target_endian = "big"))
+        }
+
+        pub static $native_ref_static: F2<Bytes, usize, Result<RDatum, RuntimeError>> = F2 { f2: $native_ref_fn };
+
+        fn $native_set_fn(bv: Bytes, k: usize, value: RDatum) -> Result<RDatum, RuntimeError> {
+            check_aligned(k, $size)?;
+            int_set(&bv, k, $size, $signed, cfg!(target_endian = "big"), value)
+        }
+
+        pub static $native_set_static: F3<Bytes, usize, RDatum, Result<RDatum, RuntimeError>> = F3 { f3: $native_set_fn };
+    )
+}
+
+impl_int_access!(2, false, PRIM_U16_REF, u16_ref, PRIM_U16_SET, u16_set,
+                 PRIM_U16_NATIVE_REF, u16_native_ref, PRIM_U16_NATIVE_SET, u16_native_set);
+impl_int_access!(2, true, PRIM_S16_REF, s16_ref, PRIM_S16_SET, s16_set,
+                 PRIM_S16_NATIVE_REF, s16_native_ref, PRIM_S16_NATIVE_SET, s16_native_set);
+impl_int_access!(4, false, PRIM_U32_REF, u32_ref, PRIM_U32_SET, u32_set,
+                 PRIM_U32_NATIVE_REF, u32_native_ref, PRIM_U32_NATIVE_SET, u32_native_set);
+impl_int_access!(4, true, PRIM_S32_REF, s32_ref, PRIM_S32_SET, s32_set,
+                 PRIM_S32_NATIVE_REF, s32_native_ref, PRIM_S32_NATIVE_SET, s32_native_set);
+impl_int_access!(8, false, PRIM_U64_REF, u64_ref, PRIM_U64_SET, u64_set,
+                 PRIM_U64_NATIVE_REF, u64_native_ref, PRIM_U64_NATIVE_SET, u64_native_set);
+impl_int_access!(8, true, PRIM_S64_REF, s64_ref, PRIM_S64_SET, s64_set,
+                 PRIM_S64_NATIVE_REF, s64_native_ref, PRIM_S64_NATIVE_SET, s64_native_set);
+
+fn float_ref(bv: &Bytes, k: usize, size: usize, big: bool) -> Result<RDatum, RuntimeError> {
+    let n = read_uint(bv, k, size, big)?;
+    let f = if size == 4 { f32::from_bits(n as u32) as f64 } else { f64::from_bits(n) };
+    Ok(Datum::Num(Number::new_flonum(f)))
+}
+
+fn float_set(bv: &Bytes, k: usize, size: usize, big: bool, value: RDatum) -> Result<RDatum, RuntimeError> {
+    let f = match value {
+        Datum::Num(Number::Real(ref r)) => r.to_f64(),
+        _ => return Err(invalid(format!("expected Real, but received {:?}", DatumType::get_type(&value))))
+    };
+    let raw = if size == 4 { (f as f32).to_bits() as u64 } else { f.to_bits() };
+    write_uint(bv, k, size, big, raw)?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+macro_rules! impl_float_access {
+    ($size:expr,
+     $ref_static:ident, $ref_fn:ident, $set_static:ident, $set_fn:ident,
+     $native_ref_static:ident, $native_ref_fn:ident, $native_set_static:ident, $native_set_fn:ident) => (
+        fn $ref_fn(bv: Bytes, k: usize, endianness: Cow<'static, str>) -> Result<RDatum, RuntimeError> {
+            float_ref(&bv, k, $size, is_big(&endianness)?)
+        }
+
+        pub static $ref_static: F3<Bytes, usize, Cow<'static, str>, Result<RDatum, RuntimeError>> = F3 { f3: $ref_fn };
+
+        fn $set_fn(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+            let (bv, k, value, big) = set_args(args)?;
+            float_set(&bv, k, $size, big, value)
+        }
+
+        pub static $set_static: FoldErr<RDatum> = FoldErr { fold: $set_fn };
+
+        fn $native_ref_fn(bv: Bytes, k: usize) -> Result<RDatum, RuntimeError> {
+            check_aligned(k, $size)?;
+            float_ref(&bv, k, $size, cfg!(target_endian = "big"))
+        }
+
+        pub static $native_ref_static: F2<Bytes, usize, Result<RDatum, RuntimeError>> = F2 { f2: $native_ref_fn };
+
+        fn $native_set_fn(bv: Bytes, k: usize, value: RDatum) -> Result<RDatum, RuntimeError> {
+            check_aligned(k, $size)?;
+            float_set(&bv, k, $size, cfg!(target_endian = "big"), value)
+        }
+
+        pub static $native_set_static: F3<Bytes, usize, RDatum, Result<RDatum, RuntimeError>> = F3 { f3: $native_set_fn };
+    )
+}
+
+impl_float_access!(4, PRIM_SINGLE_REF, single_ref, PRIM_SINGLE_SET, single_set,
+                   PRIM_SINGLE_NATIVE_REF, single_native_ref, PRIM_SINGLE_NATIVE_SET, single_native_set);
+impl_float_access!(8, PRIM_DOUBLE_REF, double_ref, PRIM_DOUBLE_SET, double_set,
+                   PRIM_DOUBLE_NATIVE_REF, double_native_ref, PRIM_DOUBLE_NATIVE_SET, double_native_set);
+
+fn native_endianness(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() {
+        Ok(Datum::Sym(Cow::Borrowed(native_endianness_name())))
+    } else {
+        Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 0 arguments, received {}", args.len())
+        })
+    }
+}
+
+/// `(native-endianness)`
+pub static PRIM_NATIVE_ENDIANNESS: FoldErr<RDatum> = FoldErr { fold: native_endianness };
+
+fn is_bytevector(datum: RDatum) -> bool {
+    DatumType::get_type(&datum) == DatumType::Bytes
+}
+
+/// `(bytevector? obj)`
+pub static PRIM_IS_BYTEVECTOR: F1<RDatum, bool> = F1 { f1: is_bytevector };
+
+fn make_bytevector(k: usize, fill: Option<RDatum>) -> Result<Bytes, RuntimeError> {
+    let b = match fill {
+        Some(fill) => fill_byte(fill)?,
+        None => 0
+    };
+    Ok(new_bytes(vec![b; k]))
+}
+
+/// `(make-bytevector k)` or `(make-bytevector k fill)`
+pub static PRIM_MAKE_BYTEVECTOR: F2<usize, Option<RDatum>, Result<Bytes, RuntimeError>> = F2 { f2: make_bytevector };
+
+fn bytevector_length(bv: &Bytes) -> usize {
+    bv.borrow().len()
+}
+
+/// `(bytevector-length bytevector)`
+pub static PRIM_BYTEVECTOR_LENGTH: R1<Bytes, usize> = R1 { r1: bytevector_length };
+
+fn bytevector_eq(bv1: Bytes, bv2: Bytes) -> bool {
+    bv1 == bv2
+}
+
+/// `(bytevector=? bytevector1 bytevector2)`
+pub static PRIM_BYTEVECTOR_EQ: F2<Bytes, Bytes, bool> = F2 { f2: bytevector_eq };
+
+fn bytevector_fill(bv: Bytes, fill: RDatum) -> Result<RDatum, RuntimeError> {
+    let b = fill_byte(fill)?;
+    for place in bv.borrow_mut().iter_mut() {
+        *place = b;
+    }
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// `(bytevector-fill! bytevector fill)`
+pub static PRIM_BYTEVECTOR_FILL: F2<Bytes, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: bytevector_fill };
+
+fn bytevector_copy_to(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.len() != 5 {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 5 arguments, received {}", args.len())
+        });
+    }
+    let mut args = args.into_iter();
+    let src: Bytes = DatumCast::unwrap(args.next().unwrap())?;
+    let src_start: usize = DatumCast::unwrap(args.next().unwrap())?;
+    let dst: Bytes = DatumCast::unwrap(args.next().unwrap())?;
+    let dst_start: usize = DatumCast::unwrap(args.next().unwrap())?;
+    let k: usize = DatumCast::unwrap(args.next().unwrap())?;
+
+    // the source and the target may be the same bytevector, so the bytes are copied out first
+    let copied = {
+        let src = src.borrow();
+        if src_start + k > src.len() {
+            return Err(index_error(src.len(), src_start, k));
+        }
+        src[src_start .. src_start+k].to_vec()
+    };
+    let mut dst = dst.borrow_mut();
+    if dst_start + k > dst.len() {
+        return Err(index_error(dst.len(), dst_start, k));
+    }
+    dst[dst_start .. dst_start+k].copy_from_slice(&copied);
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// `(bytevector-copy! source source-start target target-start k)`
+pub static PRIM_BYTEVECTOR_COPY_TO: FoldErr<RDatum> = FoldErr { fold: bytevector_copy_to };
+
+fn bytevector_copy(bv: Bytes) -> Bytes {
+    new_bytes(bv.borrow().clone())
+}
+
+/// `(bytevector-copy bytevector)`
+pub static PRIM_BYTEVECTOR_COPY: F1<Bytes, Bytes> = F1 { f1: bytevector_copy };
+
+fn u8_ref(bv: Bytes, k: usize) -> Result<RDatum, RuntimeError> {
+    int_ref(&bv, k, 1, false, true)
+}
+
+/// `(bytevector-u8-ref bytevector k)`
+pub static PRIM_U8_REF: F2<Bytes, usize, Result<RDatum, RuntimeError>> = F2 { f2: u8_ref };
+
+fn u8_set(bv: Bytes, k: usize, value: RDatum) -> Result<RDatum, RuntimeError> {
+    int_set(&bv, k, 1, false, true, value)
+}
+
+/// `(bytevector-u8-set! bytevector k octet)`
+pub static PRIM_U8_SET: F3<Bytes, usize, RDatum, Result<RDatum, RuntimeError>> = F3 { f3: u8_set };
+
+fn s8_ref(bv: Bytes, k: usize) -> Result<RDatum, RuntimeError> {
+    int_ref(&bv, k, 1, true, true)
+}
+
+/// `(bytevector-s8-ref bytevector k)`
+pub static PRIM_S8_REF: F2<Bytes, usize, Result<RDatum, RuntimeError>> = F2 { f2: s8_ref };
+
+fn s8_set(bv: Bytes, k: usize, value: RDatum) -> Result<RDatum, RuntimeError> {
+    int_set(&bv, k, 1, true, true, value)
+}
+
+/// `(bytevector-s8-set! bytevector k byte)`
+pub static PRIM_S8_SET: F3<Bytes, usize, RDatum, Result<RDatum, RuntimeError>> = F3 { f3: s8_set };
+
+fn bytevector_to_u8_list(bv: Bytes) -> RDatum {
+    let bytes = bv.borrow();
+    bytes.iter().map(|&b| Datum::Num(Number::new_int(b as isize, 0))).collect()
+}
+
+/// `(bytevector->u8-list bytevector)`
+pub static PRIM_BYTEVECTOR_TO_U8_LIST: F1<Bytes, RDatum> = F1 { f1: bytevector_to_u8_list };
+
+fn u8_list_to_bytevector(list: RDatum) -> Result<Bytes, RuntimeError> {
+    let mut v = Vec::new();
+    for item in list.iter() {
+        let item = item.map_err(|_| invalid(format!("Expected list, but received {:?}", DatumType::get_type(&list))))?;
+        let n = exact_integer(item)?;
+        match n.to_u8() {
+            Some(b) => v.push(b),
+            None => return Err(invalid(format!("{} is not an octet", n)))
+        }
+    }
+    Ok(new_bytes(v))
+}
+
+/// `(u8-list->bytevector list)`
+pub static PRIM_U8_LIST_TO_BYTEVECTOR: F1<RDatum, Result<Bytes, RuntimeError>> = F1 { f1: u8_list_to_bytevector };
+
+fn any_int_ref_args(args: Vec<RDatum>, signed: bool) -> Result<RDatum, RuntimeError> {
+    let mut args = args.into_iter();
+    match (args.next(), args.next(), args.next(), args.next(), args.next()) {
+        (Some(bv), Some(k), Some(endianness), Some(size), None) => {
+            let bv: Bytes = DatumCast::unwrap(bv)?;
+            let endianness: Cow<'static, str> = DatumCast::unwrap(endianness)?;
+            let n = any_int_ref(&bv, DatumCast::unwrap(k)?, DatumCast::unwrap(size)?, signed, is_big(&endianness)?)?;
+            Ok(integer_datum(n))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: "Expected 4 arguments".to_string()
+        })
+    }
+}
+
+fn uint_ref(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    any_int_ref_args(args, false)
+}
+
+/// `(bytevector-uint-ref bytevector k endianness size)`
+pub static PRIM_UINT_REF: FoldErr<RDatum> = FoldErr { fold: uint_ref };
+
+fn sint_ref(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    any_int_ref_args(args, true)
+}
+
+/// `(bytevector-sint-ref bytevector k endianness size)`
+pub static PRIM_SINT_REF: FoldErr<RDatum> = FoldErr { fold: sint_ref };
+
+fn any_int_set_args(args: Vec<RDatum>, signed: bool) -> Result<RDatum, RuntimeError> {
+    let mut args = args.into_iter();
+    match (args.next(), args.next(), args.next(), args.next(), args.next(), args.next()) {
+        (Some(bv), Some(k), Some(n), Some(endianness), Some(size), None) => {
+            let bv: Bytes = DatumCast::unwrap(bv)?;
+            let endianness: Cow<'static, str> = DatumCast::unwrap(endianness)?;
+            any_int_set(&bv, DatumCast::unwrap(k)?, DatumCast::unwrap(size)?, signed, is_big(&endianness)?,
+                        exact_integer(n)?)?;
+            Ok(Datum::Ext(RuntimeData::Undefined))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: "Expected 5 arguments".to_string()
+        })
+    }
+}
+
+fn uint_set(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    any_int_set_args(args, false)
+}
+
+/// `(bytevector-uint-set! bytevector k n endianness size)`
+pub static PRIM_UINT_SET: FoldErr<RDatum> = FoldErr { fold: uint_set };
+
+fn sint_set(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    any_int_set_args(args, true)
+}
+
+/// `(bytevector-sint-set! bytevector k n endianness size)`
+pub static PRIM_SINT_SET: FoldErr<RDatum> = FoldErr { fold: sint_set };
+
+fn bytevector_to_int_list(bv: Bytes, endianness: Cow<'static, str>, size: usize, signed: bool)
+        -> Result<RDatum, RuntimeError>
+{
+    check_size(size)?;
+    let big = is_big(&endianness)?;
+    let len = bv.borrow().len();
+    if len % size != 0 {
+        return Err(invalid(format!("bytevector length {} is not a multiple of {}", len, size)));
+    }
+    let ints: Vec<RDatum> = (0 .. len / size)
+        .map(|i| any_int_ref(&bv, i * size, size, signed, big).map(integer_datum))
+        .collect::<Result<_, _>>()?;
+    Ok(ints.into_iter().collect())
+}
+
+fn bytevector_to_uint_list(bv: Bytes, endianness: Cow<'static, str>, size: usize) -> Result<RDatum, RuntimeError> {
+    bytevector_to_int_list(bv, endianness, size, false)
+}
+
+/// `(bytevector->uint-list bytevector endianness size)`
+pub static PRIM_BYTEVECTOR_TO_UINT_LIST: F3<Bytes, Cow<'static, str>, usize, Result<RDatum, RuntimeError>> = F3 { f3: bytevector_to_uint_list };
+
+fn bytevector_to_sint_list(bv: Bytes, endianness: Cow<'static, str>, size: usize) -> Result<RDatum, RuntimeError> {
+    bytevector_to_int_list(bv, endianness, size, true)
+}
+
+/// `(bytevector->sint-list bytevector endianness size)`
+pub static PRIM_BYTEVECTOR_TO_SINT_LIST: F3<Bytes, Cow<'static, str>, usize, Result<RDatum, RuntimeError>> = F3 { f3: bytevector_to_sint_list };
+
+fn int_list_to_bytevector(list: RDatum, endianness: Cow<'static, str>, size: usize, signed: bool)
+        -> Result<Bytes, RuntimeError>
+{
+    check_size(size)?;
+    let big = is_big(&endianness)?;
+    let mut ints = Vec::new();
+    for item in list.iter() {
+        let item = item.map_err(|_| invalid(format!("Expected list, but received {:?}", DatumType::get_type(&list))))?;
+        ints.push(exact_integer(item)?);
+    }
+    let bv = new_bytes(vec![0; ints.len() * size]);
+    for (i, n) in ints.into_iter().enumerate() {
+        any_int_set(&bv, i * size, size, signed, big, n)?;
+    }
+    Ok(bv)
+}
+
+fn uint_list_to_bytevector(list: RDatum, endianness: Cow<'static, str>, size: usize) -> Result<Bytes, RuntimeError> {
+    int_list_to_bytevector(list, endianness, size, false)
+}
+
+/// `(uint-list->bytevector list endianness size)`
+pub static PRIM_UINT_LIST_TO_BYTEVECTOR: F3<RDatum, Cow<'static, str>, usize, Result<Bytes, RuntimeError>> = F3 { f3: uint_list_to_bytevector };
+
+fn sint_list_to_bytevector(list: RDatum, endianness: Cow<'static, str>, size: usize) -> Result<Bytes, RuntimeError> {
+    int_list_to_bytevector(list, endianness, size, true)
+}
+
+/// `(sint-list->bytevector list endianness size)`
+pub static PRIM_SINT_LIST_TO_BYTEVECTOR: F3<RDatum, Cow<'static, str>, usize, Result<Bytes, RuntimeError>> = F3 { f3: sint_list_to_bytevector };
+
+fn new_string(chars: Vec<char>) -> Chars {
+    Rc::new(RefCell::new(chars))
+}
+
+fn string_to_utf8(s: Chars) -> Bytes {
+    let s: String = s.borrow().iter().cloned().collect();
+    new_bytes(s.into_bytes())
+}
+
+/// `(string->utf8 string)`
+pub static PRIM_STRING_TO_UTF8: F1<Chars, Bytes> = F1 { f1: string_to_utf8 };
+
+fn utf8_to_string(bv: Bytes) -> Chars {
+    new_string(String::from_utf8_lossy(&bv.borrow()).chars().collect())
+}
+
+/// `(utf8->string bytevector)`, replacing the invalid sequences with U+FFFD
+pub static PRIM_UTF8_TO_STRING: F1<Bytes, Chars> = F1 { f1: utf8_to_string };
+
+/// Endianness given to the UTF-16 and UTF-32 encoders, which is big by default
+fn encoding_endianness(endianness: Option<Cow<'static, str>>) -> Result<bool, RuntimeError> {
+    match endianness {
+        Some(e) => is_big(&e),
+        None => Ok(true)
+    }
+}
+
+/// Decoding endianness and the length of the byte order mark at the start of the bytes. The mark
+/// overrides the given endianness unless the endianness is mandatory
+fn decoding_endianness(bytes: &[u8], endianness: Cow<'static, str>, mandatory: Option<bool>,
+                       big_bom: &[u8], little_bom: &[u8])
+        -> Result<(bool, usize), RuntimeError>
+{
+    let big = is_big(&endianness)?;
+    if mandatory == Some(true) {
+        Ok((big, 0))
+    } else if bytes.starts_with(big_bom) {
+        Ok((true, big_bom.len()))
+    } else if bytes.starts_with(little_bom) {
+        Ok((false, little_bom.len()))
+    } else {
+        Ok((big, 0))
+    }
+}
+
+fn string_to_utf16(s: Chars, endianness: Option<Cow<'static, str>>) -> Result<Bytes, RuntimeError> {
+    let big = encoding_endianness(endianness)?;
+    let s: String = s.borrow().iter().cloned().collect();
+    let mut v = Vec::new();
+    for unit in s.encode_utf16() {
+        if big {
+            v.push((unit >> 8) as u8);
+            v.push(unit as u8);
+        } else {
+            v.push(unit as u8);
+            v.push((unit >> 8) as u8);
+        }
+    }
+    Ok(new_bytes(v))
+}
+
+/// `(string->utf16 string)` or `(string->utf16 string endianness)`
+pub static PRIM_STRING_TO_UTF16: F2<Chars, Option<Cow<'static, str>>, Result<Bytes, RuntimeError>> = F2 { f2: string_to_utf16 };
+
+fn utf16_to_string(bv: Bytes, endianness: Cow<'static, str>, mandatory: Option<bool>) -> Result<Chars, RuntimeError> {
+    let bytes = bv.borrow();
+    let (big, start) = decoding_endianness(&bytes, endianness, mandatory, &[0xfe, 0xff], &[0xff, 0xfe])?;
+    let units: Vec<u16> = bytes[start..].chunks(2).map(|pair| match pair {
+        &[hi, lo] if big => (hi as u16) << 8 | lo as u16,
+        &[lo, hi] => (hi as u16) << 8 | lo as u16,
+        // an odd byte at the end
+        _ => 0xfffd
+    }).collect();
+    let chars = char::decode_utf16(units.into_iter()).map(|c| c.unwrap_or('\u{fffd}')).collect();
+    Ok(new_string(chars))
+}
+
+/// `(utf16->string bytevector endianness)` or
+/// `(utf16->string bytevector endianness endianness-mandatory?)`
+pub static PRIM_UTF16_TO_STRING: F3<Bytes, Cow<'static, str>, Option<bool>, Result<Chars, RuntimeError>> = F3 { f3: utf16_to_string };
+
+fn string_to_utf32(s: Chars, endianness: Option<Cow<'static, str>>) -> Result<Bytes, RuntimeError> {
+    let big = encoding_endianness(endianness)?;
+    let bv = new_bytes(vec![0; s.borrow().len() * 4]);
+    for (i, &c) in s.borrow().iter().enumerate() {
+        write_uint(&bv, i * 4, 4, big, c as u64)?;
+    }
+    Ok(bv)
+}
+
+/// `(string->utf32 string)` or `(string->utf32 string endianness)`
+pub static PRIM_STRING_TO_UTF32: F2<Chars, Option<Cow<'static, str>>, Result<Bytes, RuntimeError>> = F2 { f2: string_to_utf32 };
+
+fn utf32_to_string(bv: Bytes, endianness: Cow<'static, str>, mandatory: Option<bool>) -> Result<Chars, RuntimeError> {
+    let (big, start) = decoding_endianness(&bv.borrow(), endianness, mandatory,
+                                           &[0, 0, 0xfe, 0xff], &[0xff, 0xfe, 0, 0])?;
+    let len = bv.borrow().len();
+    let mut chars = Vec::new();
+    let mut k = start;
+    while k < len {
+        let c = if k + 4 <= len {
+            char::from_u32(read_uint(&bv, k, 4, big)? as u32).unwrap_or('\u{fffd}')
+        } else {
+            '\u{fffd}'
+        };
+        chars.push(c);
+        k += 4;
+    }
+    Ok(new_string(chars))
+}
+
+/// `(utf32->string bytevector endianness)` or
+/// `(utf32->string bytevector endianness endianness-mandatory?)`
+pub static PRIM_UTF32_TO_STRING: F3<Bytes, Cow<'static, str>, Option<bool>, Result<Chars, RuntimeError>> = F3 { f3: utf32_to_string };
+
+/// Lists all bytevector procedures with its name
+pub fn libbytevector() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("native-endianness", &PRIM_NATIVE_ENDIANNESS),
+        ("bytevector?", &PRIM_IS_BYTEVECTOR),
+        ("make-bytevector", &PRIM_MAKE_BYTEVECTOR),
+        ("bytevector-length", &PRIM_BYTEVECTOR_LENGTH),
+        ("bytevector=?", &PRIM_BYTEVECTOR_EQ),
+        ("bytevector-fill!", &PRIM_BYTEVECTOR_FILL),
+        ("bytevector-copy!", &PRIM_BYTEVECTOR_COPY_TO),
+        ("bytevector-copy", &PRIM_BYTEVECTOR_COPY),
+        ("bytevector-u8-ref", &PRIM_U8_REF),
+        ("bytevector-u8-set!", &PRIM_U8_SET),
+        ("bytevector-s8-ref", &PRIM_S8_REF),
+        ("bytevector-s8-set!", &PRIM_S8_SET),
+        ("bytevector->u8-list", &PRIM_BYTEVECTOR_TO_U8_LIST),
+        ("u8-list->bytevector", &PRIM_U8_LIST_TO_BYTEVECTOR),
+        ("bytevector-uint-ref", &PRIM_UINT_REF),
+        ("bytevector-sint-ref", &PRIM_SINT_REF),
+        ("bytevector-uint-set!", &PRIM_UINT_SET),
+        ("bytevector-sint-set!", &PRIM_SINT_SET),
+        ("bytevector->uint-list", &PRIM_BYTEVECTOR_TO_UINT_LIST),
+        ("bytevector->sint-list", &PRIM_BYTEVECTOR_TO_SINT_LIST),
+        ("uint-list->bytevector", &PRIM_UINT_LIST_TO_BYTEVECTOR),
+        ("sint-list->bytevector", &PRIM_SINT_LIST_TO_BYTEVECTOR),
+        ("bytevector-u16-ref", &PRIM_U16_REF),
+        ("bytevector-u16-set!", &PRIM_U16_SET),
+        ("bytevector-u16-native-ref", &PRIM_U16_NATIVE_REF),
+        ("bytevector-u16-native-set!", &PRIM_U16_NATIVE_SET),
+        ("bytevector-s16-ref", &PRIM_S16_REF),
+        ("bytevector-s16-set!", &PRIM_S16_SET),
+        ("bytevector-s16-native-ref", &PRIM_S16_NATIVE_REF),
+        ("bytevector-s16-native-set!", &PRIM_S16_NATIVE_SET),
+        ("bytevector-u32-ref", &PRIM_U32_REF),
+        ("bytevector-u32-set!", &PRIM_U32_SET),
+        ("bytevector-u32-native-ref", &PRIM_U32_NATIVE_REF),
+        ("bytevector-u32-native-set!", &PRIM_U32_NATIVE_SET),
+        ("bytevector-s32-ref", &PRIM_S32_REF),
+        ("bytevector-s32-set!", &PRIM_S32_SET),
+        ("bytevector-s32-native-ref", &PRIM_S32_NATIVE_REF),
+        ("bytevector-s32-native-set!", &PRIM_S32_NATIVE_SET),
+        ("bytevector-u64-ref", &PRIM_U64_REF),
+        ("bytevector-u64-set!", &PRIM_U64_SET),
+        ("bytevector-u64-native-ref", &PRIM_U64_NATIVE_REF),
+        ("bytevector-u64-native-set!", &PRIM_U64_NATIVE_SET),
+        ("bytevector-s64-ref", &PRIM_S64_REF),
+        ("bytevector-s64-set!", &PRIM_S64_SET),
+        ("bytevector-s64-native-ref", &PRIM_S64_NATIVE_REF),
+        ("bytevector-s64-native-set!", &PRIM_S64_NATIVE_SET),
+        ("bytevector-ieee-single-ref", &PRIM_SINGLE_REF),
+        ("bytevector-ieee-single-set!", &PRIM_SINGLE_SET),
+        ("bytevector-ieee-single-native-ref", &PRIM_SINGLE_NATIVE_REF),
+        ("bytevector-ieee-single-native-set!", &PRIM_SINGLE_NATIVE_SET),
+        ("bytevector-ieee-double-ref", &PRIM_DOUBLE_REF),
+        ("bytevector-ieee-double-set!", &PRIM_DOUBLE_SET),
+        ("bytevector-ieee-double-native-ref", &PRIM_DOUBLE_NATIVE_REF),
+        ("bytevector-ieee-double-native-set!", &PRIM_DOUBLE_NATIVE_SET),
+        ("string->utf8", &PRIM_STRING_TO_UTF8),
+        ("utf8->string", &PRIM_UTF8_TO_STRING),
+        ("string->utf16", &PRIM_STRING_TO_UTF16),
+        ("utf16->string", &PRIM_UTF16_TO_STRING),
+        ("string->utf32", &PRIM_STRING_TO_UTF32),
+        ("utf32->string", &PRIM_UTF32_TO_STRING)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::{any_int_ref, any_int_set, int_ref, int_set, read_uint, utf16_to_string, Bytes};
+    use std::borrow::Cow;
+    use datum::Datum;
+    use num::{BigInt, FromPrimitive};
+    use number::Number;
+
+    fn bv(v: Vec<u8>) -> Bytes {
+        Rc::new(RefCell::new(v))
+    }
+
+    #[test]
+    fn test_endianness() {
+        let b = bv(vec![1, 2, 3, 4]);
+        assert_eq!(read_uint(&b, 0, 4, true), Ok(0x01020304));
+        assert_eq!(read_uint(&b, 0, 4, false), Ok(0x04030201));
+        assert_eq!(read_uint(&b, 2, 2, true), Ok(0x0304));
+        assert!(read_uint(&b, 2, 4, true).is_err());
+    }
+
+    #[test]
+    fn test_signed() {
+        let b = bv(vec![0; 8]);
+        int_set(&b, 0, 2, true, false, Datum::Num(Number::new_int(-2, 0))).unwrap();
+        assert_eq!(*b.borrow(), vec![0xfe, 0xff, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(int_ref(&b, 0, 2, true, false), Ok(Datum::Num(Number::new_int(-2, 0))));
+        assert_eq!(int_ref(&b, 0, 2, false, false), Ok(Datum::Num(Number::new_int(0xfffe, 0))));
+        assert!(int_set(&b, 0, 1, false, false, Datum::Num(Number::new_int(256, 0))).is_err());
+        assert!(int_set(&b, 0, 1, true, false, Datum::Num(Number::new_int(128, 0))).is_err());
+    }
+
+    #[test]
+    fn test_any_size() {
+        let b = bv(vec![0; 9]);
+        let n: BigInt = FromPrimitive::from_i64(-0x123456).unwrap();
+        any_int_set(&b, 1, 5, true, true, n.clone()).unwrap();
+        assert_eq!(*b.borrow(), vec![0, 0xff, 0xff, 0xed, 0xcb, 0xaa, 0, 0, 0]);
+        assert_eq!(any_int_ref(&b, 1, 5, true, true), Ok(n));
+        assert_eq!(any_int_ref(&b, 4, 2, false, false), Ok(FromPrimitive::from_u64(0xaacb).unwrap()));
+        let big: BigInt = FromPrimitive::from_u64(1).unwrap();
+        assert!(any_int_set(&b, 0, 9, false, false, big << 72).is_err());
+        assert!(any_int_ref(&b, 5, 0, false, false).is_err());
+    }
+
+    #[test]
+    fn test_utf16_bom() {
+        let b = bv(vec![0xff, 0xfe, 0x61, 0]);
+        let s = utf16_to_string(b.clone(), Cow::Borrowed("big"), None).unwrap();
+        assert_eq!(*s.borrow(), vec!['a']);
+        let s = utf16_to_string(b, Cow::Borrowed("little"), Some(true)).unwrap();
+        assert_eq!(*s.borrow(), vec!['\u{feff}', 'a']);
+    }
+}
@@ -1,14 +1,20 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use num::{BigInt, FromPrimitive};
 use num::rational::Ratio;
 
-use datum::{Datum, SimpleDatum};
+use condition::Condition;
+use datum::{cons, string, Datum, SimpleDatum};
+use enums::EnumSet;
 use error::{RuntimeError, RuntimeErrorKind};
+use hashtable::Hashtable;
 use number::Number;
+use port::{Codec, Port, Transcoder};
 use real::Real;
-use runtime::{DatumType, RDatum};
+use record::{Record, RecordConstructor, RecordType};
+use runtime::{DatumType, RDatum, RuntimeData};
 
 /// Types with implementing DatumCast trait can cast from/to Datum
 pub trait DatumCast: Sized {
@@ -96,7 +102,7 @@ impl DatumCast for bool {
 impl DatumCast for (RDatum, RDatum) {
     fn unwrap(datum: RDatum) -> Result<(RDatum, RDatum), RuntimeError> {
         match datum {
-            Datum::Cons(c) => Ok(c.as_ref().clone()),
+            Datum::Cons(c) => Ok(c.get()),
             _ => Err(RuntimeError {
                 kind: RuntimeErrorKind::InvalidType,
                 desc: format!("expected Pair, but received {:?}", DatumType::get_type(&datum))
@@ -105,7 +111,7 @@ impl DatumCast for (RDatum, RDatum) {
     }
 
     fn wrap(self) -> RDatum {
-        Datum::Cons(Rc::new(self))
+        cons(self.0, self.1)
     }
 }
 
@@ -128,7 +134,7 @@ impl DatumCast for Cow<'static, str> {
 impl DatumCast for String {
     fn unwrap(datum: RDatum) -> Result<String, RuntimeError> {
         match datum {
-            Datum::String(s) => Ok(s.as_ref().clone()),
+            Datum::String(s) => Ok(s.borrow().iter().cloned().collect()),
             _ => Err(RuntimeError {
                 kind: RuntimeErrorKind::InvalidType,
                 desc: format!("expected String, but received {:?}", DatumType::get_type(&datum))
@@ -137,7 +143,57 @@ impl DatumCast for String {
     }
 
     fn wrap(self) -> RDatum {
-        Datum::String(Rc::new(self))
+        string(&self)
+    }
+}
+
+/// The characters of the string itself, through which the string is modified
+impl DatumCast for Rc<RefCell<Vec<char>>> {
+    fn unwrap(datum: RDatum) -> Result<Rc<RefCell<Vec<char>>>, RuntimeError> {
+        match datum {
+            Datum::String(s) => Ok(s),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected String, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::String(self)
+    }
+}
+
+/// The bytes of the bytevector itself, through which the bytevector is modified
+impl DatumCast for Rc<RefCell<Vec<u8>>> {
+    fn unwrap(datum: RDatum) -> Result<Rc<RefCell<Vec<u8>>>, RuntimeError> {
+        match datum {
+            Datum::Bytes(b) => Ok(b),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Bytes, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Bytes(self)
+    }
+}
+
+impl DatumCast for char {
+    fn unwrap(datum: RDatum) -> Result<char, RuntimeError> {
+        match datum {
+            Datum::Char(c) => Ok(c),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Char, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Char(self)
     }
 }
 
@@ -157,6 +213,150 @@ impl <T: DatumCast> DatumCast for Vec<T> {
     }
 }
 
+impl DatumCast for Rc<Condition> {
+    fn unwrap(datum: RDatum) -> Result<Rc<Condition>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::Condition(c)) => Ok(c),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Condition, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::Condition(self))
+    }
+}
+
+impl DatumCast for Rc<Hashtable> {
+    fn unwrap(datum: RDatum) -> Result<Rc<Hashtable>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::Hashtable(t)) => Ok(t),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Hashtable, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::Hashtable(self))
+    }
+}
+
+impl DatumCast for Rc<RecordType> {
+    fn unwrap(datum: RDatum) -> Result<Rc<RecordType>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::RecordType(r)) => Ok(r),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected RecordType, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::RecordType(self))
+    }
+}
+
+impl DatumCast for Rc<RecordConstructor> {
+    fn unwrap(datum: RDatum) -> Result<Rc<RecordConstructor>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::RecordConstructor(r)) => Ok(r),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected RecordConstructor, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::RecordConstructor(self))
+    }
+}
+
+impl DatumCast for Rc<Record> {
+    fn unwrap(datum: RDatum) -> Result<Rc<Record>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::Record(r)) => Ok(r),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Record, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::Record(self))
+    }
+}
+
+impl DatumCast for Rc<EnumSet> {
+    fn unwrap(datum: RDatum) -> Result<Rc<EnumSet>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::EnumSet(e)) => Ok(e),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected EnumSet, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::EnumSet(self))
+    }
+}
+
+impl DatumCast for Rc<Port> {
+    fn unwrap(datum: RDatum) -> Result<Rc<Port>, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::Port(p)) => Ok(p),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Port, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::Port(self))
+    }
+}
+
+impl DatumCast for Transcoder {
+    fn unwrap(datum: RDatum) -> Result<Transcoder, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::Transcoder(t)) => Ok(t),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Transcoder, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::Transcoder(self))
+    }
+}
+
+impl DatumCast for Codec {
+    fn unwrap(datum: RDatum) -> Result<Codec, RuntimeError> {
+        match datum {
+            Datum::Ext(RuntimeData::Codec(c)) => Ok(c),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Codec, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    fn wrap(self) -> RDatum {
+        Datum::Ext(RuntimeData::Codec(self))
+    }
+}
+
 impl DatumCast for RDatum {
     fn unwrap(datum: RDatum) -> Result<RDatum, RuntimeError> {
         Ok(datum)
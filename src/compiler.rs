@@ -1,5 +1,5 @@
 use std::borrow::Cow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::Debug;
@@ -9,11 +9,19 @@ use std::rc::Rc;
 use num::FromPrimitive;
 use immutable_map::TreeMap;
 
-use error::{CompileError, CompileErrorKind};
+use base::guard_handler_code;
+use error::{CompileError, CompileErrorKind, RuntimeErrorKind};
+use library::{import_set_library, parse_library_name, Environment, Export, Library, LibraryName,
+              LibraryRegistry};
 use datum::{cons, Datum, TryConv, SimpleDatum};
-use primitive::{PRIM_APPEND, PRIM_CONS, PRIM_LIST, PRIM_VECTOR};
-use runtime::{Inst, MemRef, PrimFuncPtr, RDatum, RuntimeData};
-use syntax::CompiledMacro;
+use enums::{EnumSet, EnumType, PRIM_ENUM_SET_FROM_LIST};
+use parameter::swap_parameters_code;
+use port::{file_options_universe, BUFFER_MODES, EOL_STYLES, ERROR_HANDLING_MODES};
+use primitive::{PRIM_APPEND, PRIM_CONS, PRIM_LIST, PRIM_LIST_TO_VECTOR, PRIM_VECTOR};
+use record::{record_definition_code, RecordSpec, PRIM_RECORD_CONSTRUCTOR_DESCRIPTOR};
+use runtime::{CaseClause, DatumType, Inst, MemRef, PrimFuncPtr, ProgramEnv, RDatum, Runtime, RuntimeData};
+use syntax::{alias, base_name, identifiers, strip_aliases, toggle_alias, toggle_mark, unalias, CompiledMacro,
+             SyntaxPattern, SyntaxTemplate, Vars};
 
 /// Syntax variables
 enum_from_primitive! {
@@ -37,6 +45,35 @@ enum_from_primitive! {
         Or = 15, // `or`
         SyntaxRules = 16, // `syntax-rules`
         LetSyntax = 17, // `let-syntax`
+        Guard = 18, // `guard`
+        LetValues = 19, // `let-values`
+        LetStarValues = 20, // `let*-values`
+        DefineValues = 21, // `define-values`
+        Library = 22, // `library`
+        Import = 23, // `import`
+        SyntaxCase = 24, // `syntax-case`
+        Syntax = 25, // `syntax`
+        Quasisyntax = 26, // `quasisyntax`
+        Unsyntax = 27, // `unsyntax`
+        UnsyntaxSplicing = 28, // `unsyntax-splicing`
+        WithSyntax = 29, // `with-syntax`
+        DefineSyntax = 30, // `define-syntax`
+        LetRecSyntax = 31, // `letrec-syntax`
+        DefineRecordType = 32, // `define-record-type`
+        DefineEnumeration = 33, // `define-enumeration`
+        Endianness = 34, // `endianness`
+        EolStyle = 35, // `eol-style`
+        ErrorHandlingMode = 36, // `error-handling-mode`
+        BufferMode = 37, // `buffer-mode`
+        FileOptions = 38, // `file-options`
+        Begin = 39, // `begin`
+        When = 40, // `when`
+        Unless = 41, // `unless`
+        Do = 42, // `do`
+        CaseLambda = 43, // `case-lambda`
+        Delay = 44, // `delay`
+        DelayForce = 45, // `delay-force`
+        Parameterize = 46, // `parameterize`
     }
 }
 
@@ -48,7 +85,17 @@ pub struct PrimitiveSyntaxIter {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Syntax {
     Primitive(PrimitiveSyntax),
-    Macro(Rc<CompiledMacro>)
+    Macro(Rc<CompiledMacro>),
+    /// Procedure transforming the syntax of the macro use into its expansion
+    Transformer(RDatum),
+    /// Type name of `define-enumeration`. `(<type name> <symbol>)` is the symbol, which must be
+    /// in the universe
+    EnumTypeName(Rc<EnumSet>),
+    /// Constructor syntax of `define-enumeration`, making the set of the symbols in the universe
+    EnumConstructor(Rc<EnumSet>),
+    /// Macro exported by a library. The identifiers introduced by its expansions refer to the
+    /// top-level bindings of the library body
+    Library(Box<Syntax>, Rc<Environment>)
 }
 
 struct Binding<T> {
@@ -62,6 +109,14 @@ impl<T> Binding<T> {
     }
 }
 
+/// Keyword bound by a syntax definition
+enum Keyword<T> {
+    /// Keyword bound to the transformer of the expression
+    Transformer(Binding<T>),
+    /// Keyword bound to the syntax made by the definition itself, as `define-enumeration` does
+    Syntax(Cow<'static, str>, Syntax)
+}
+
 impl Iterator for PrimitiveSyntaxIter {
     type Item = PrimitiveSyntax;
 
@@ -96,7 +151,36 @@ impl PrimitiveSyntax {
             &PrimitiveSyntax::And => "and",
             &PrimitiveSyntax::Or => "or",
             &PrimitiveSyntax::SyntaxRules => "syntax-rules",
-            &PrimitiveSyntax::LetSyntax => "let-syntax"
+            &PrimitiveSyntax::LetSyntax => "let-syntax",
+            &PrimitiveSyntax::Guard => "guard",
+            &PrimitiveSyntax::LetValues => "let-values",
+            &PrimitiveSyntax::LetStarValues => "let*-values",
+            &PrimitiveSyntax::DefineValues => "define-values",
+            &PrimitiveSyntax::Library => "library",
+            &PrimitiveSyntax::Import => "import",
+            &PrimitiveSyntax::SyntaxCase => "syntax-case",
+            &PrimitiveSyntax::Syntax => "syntax",
+            &PrimitiveSyntax::Quasisyntax => "quasisyntax",
+            &PrimitiveSyntax::Unsyntax => "unsyntax",
+            &PrimitiveSyntax::UnsyntaxSplicing => "unsyntax-splicing",
+            &PrimitiveSyntax::WithSyntax => "with-syntax",
+            &PrimitiveSyntax::DefineSyntax => "define-syntax",
+            &PrimitiveSyntax::LetRecSyntax => "letrec-syntax",
+            &PrimitiveSyntax::DefineRecordType => "define-record-type",
+            &PrimitiveSyntax::DefineEnumeration => "define-enumeration",
+            &PrimitiveSyntax::Endianness => "endianness",
+            &PrimitiveSyntax::EolStyle => "eol-style",
+            &PrimitiveSyntax::ErrorHandlingMode => "error-handling-mode",
+            &PrimitiveSyntax::BufferMode => "buffer-mode",
+            &PrimitiveSyntax::FileOptions => "file-options",
+            &PrimitiveSyntax::Begin => "begin",
+            &PrimitiveSyntax::When => "when",
+            &PrimitiveSyntax::Unless => "unless",
+            &PrimitiveSyntax::Do => "do",
+            &PrimitiveSyntax::CaseLambda => "case-lambda",
+            &PrimitiveSyntax::Delay => "delay",
+            &PrimitiveSyntax::DelayForce => "delay-force",
+            &PrimitiveSyntax::Parameterize => "parameterize"
         }
     }
 }
@@ -104,7 +188,15 @@ impl PrimitiveSyntax {
 /// Compiler compiles Datum into a bytecode evaluates it
 pub struct Compiler {
     /// Syntax environment
-    syntax_env: HashMap<Cow<'static, str>, PrimitiveSyntax>,
+    syntax_env: HashMap<Cow<'static, str>, Syntax>,
+    /// Variables of the global environment imported from libraries
+    imported: HashSet<Cow<'static, str>>,
+    /// Mark of the next macro expansion
+    next_mark: Cell<usize>,
+    /// Global environment and libraries of the program, which the transformers share
+    program_env: Option<ProgramEnv>,
+    /// Runtime calling the transformer procedures, created on the first use
+    transformer_vm: RefCell<Option<Box<Runtime>>>
 }
 
 struct CodeGenContext {
@@ -112,43 +204,339 @@ struct CodeGenContext {
     link_size: usize
 }
 
+/// Part of the lexical context visible from a macro definition. The identifiers introduced by
+/// the expansions of the macro are resolved in it, rather than in the context of the macro use
+struct MacroScope {
+    /// Number of the outermost frames
+    frames: usize,
+    /// Number of the innermost keywords of `syntax_env`
+    keywords: usize,
+    /// Scopes of the expansions which the macro definition is part of
+    marks: TreeMap<usize, Rc<MacroScope>>,
+    /// Top-level bindings of the library defining the macro, or `None` for the global environment
+    /// of the context
+    library: Option<Rc<Environment>>
+}
+
+impl MacroScope {
+    /// Scope of the macros bound in the global environment or the library
+    fn global(library: Option<Rc<Environment>>) -> MacroScope {
+        MacroScope {
+            frames: 0,
+            keywords: 0,
+            marks: TreeMap::new(),
+            library: library
+        }
+    }
+}
+
+/// Binding an identifier refers to
+enum Resolved {
+    Var(MemRef),
+    /// Variable imported from a library, which cannot be assigned
+    Imported(MemRef),
+    Syntax(Syntax, Rc<MacroScope>)
+}
+
+impl Resolved {
+    /// Whether the identifiers resolved to the bindings refer to the same binding
+    fn same_binding(&self, other: &Resolved) -> bool {
+        match (self, other) {
+            (&Resolved::Var(ref p1), &Resolved::Var(ref p2)) |
+            (&Resolved::Var(ref p1), &Resolved::Imported(ref p2)) |
+            (&Resolved::Imported(ref p1), &Resolved::Var(ref p2)) |
+            (&Resolved::Imported(ref p1), &Resolved::Imported(ref p2)) => match (p1, p2) {
+                (&MemRef::Global(ref c1), &MemRef::Global(ref c2)) =>
+                    (&**c1 as *const RefCell<RDatum>) == (&**c2 as *const RefCell<RDatum>),
+                _ => p1 == p2
+            },
+            (&Resolved::Syntax(ref s1, _), &Resolved::Syntax(ref s2, _)) => s1 == s2,
+            _ => false
+        }
+    }
+}
+
 #[derive(Clone)]
 struct LexicalContext<'g> {
     /// Current global environment
     global_env: &'g HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
-    syntax_env: TreeMap<Cow<'static, str>, Rc<CompiledMacro>>,
+    /// Keywords bound in the global environment
+    global_syntax: &'g HashMap<Cow<'static, str>, Syntax>,
+    /// Variables of the global environment imported from libraries
+    imported: &'g HashSet<Cow<'static, str>>,
+    /// Local keywords and the scopes of their definitions, the innermost last
+    syntax_env: Vec<(Cow<'static, str>, Syntax, Rc<MacroScope>)>,
+    /// Scopes of the macro expansions, by the marks of the identifiers they introduce
+    marks: TreeMap<usize, Rc<MacroScope>>,
+    /// Depths of the pattern variables of `syntax-case`, by their frames and slots
+    pattern_vars: TreeMap<(usize, usize), usize>,
     static_scope: Vec<Vec<Cow<'static, str>>>,
-    args: Vec<Cow<'static, str>>
+    args: Vec<Cow<'static, str>>,
+    /// Library whose top-level bindings are the global environment, when the identifiers
+    /// introduced by its macros are resolved
+    library: Option<Rc<Environment>>
 }
 
 impl<'g> LexicalContext<'g> {
+    fn new(global_env: &'g HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
+           global_syntax: &'g HashMap<Cow<'static, str>, Syntax>,
+           imported: &'g HashSet<Cow<'static, str>>)
+            -> LexicalContext<'g>
+    {
+        LexicalContext {
+            global_env,
+            global_syntax,
+            imported,
+            syntax_env: Vec::new(),
+            marks: TreeMap::new(),
+            pattern_vars: TreeMap::new(),
+            static_scope: Vec::new(),
+            args: Vec::new(),
+            library: None
+        }
+    }
+
+    /// Context of the top level of the library body
+    fn library(library: &'g Rc<Environment>) -> LexicalContext<'g> {
+        let mut env = LexicalContext::new(&library.vars, &library.syntax, &library.imported);
+        env.library = Some(library.clone());
+        env
+    }
+
     fn update_arg(&self, args: Vec<Cow<'static, str>>) -> LexicalContext<'g> {
         let mut scope = self.static_scope.clone();
         scope.push(self.args.clone());
 
         LexicalContext {
             global_env: self.global_env,
+            global_syntax: self.global_syntax,
+            imported: self.imported,
             syntax_env: self.syntax_env.clone(),
+            marks: self.marks.clone(),
+            pattern_vars: self.pattern_vars.clone(),
             static_scope: scope,
-            args
+            args,
+            library: self.library.clone()
         }
     }
 
     fn push_arg(&mut self, arg: Cow<'static, str>) {
         self.args.push(arg);
     }
+
+    /// Binds the pattern variables in a new frame
+    fn update_pattern_vars(&self, vars: &[(Cow<'static, str>, usize)]) -> LexicalContext<'g> {
+        let mut env = self.update_arg(vars.iter().map(|&(ref var, _)| var.clone()).collect());
+        let frame = env.static_scope.len();
+        for (slot, &(_, depth)) in vars.iter().enumerate() {
+            env.pattern_vars = env.pattern_vars.insert((frame, slot), depth);
+        }
+        env
+    }
+
+    /// Depth of the pattern variable the reference points to, if it is one
+    fn pattern_var_depth(&self, ptr: &MemRef) -> Option<usize> {
+        let frame = self.static_scope.len();
+        let key = match ptr {
+            &MemRef::Arg(slot) => (frame, slot),
+            &MemRef::UpValue(i, slot) => (frame - 1 - i, slot),
+            _ => return None
+        };
+        self.pattern_vars.get(&key).cloned()
+    }
+
+    fn lookup(&self, sym: &Cow<'static, str>) -> Result<Resolved, CompileError> {
+        self.resolve(sym, self.static_scope.len() + 1, self.syntax_env.len(), &self.marks)
+    }
+
+    /// `free-identifier=?`: whether the identifiers refer to the same binding, or are both unbound
+    /// and have the same name
+    fn free_identifier_eq(&self, id1: &Cow<'static, str>, id2: &Cow<'static, str>) -> bool {
+        match (self.lookup(id1), self.lookup(id2)) {
+            (Ok(b1), Ok(b2)) => b1.same_binding(&b2),
+            (Err(_), Err(_)) => base_name(id1) == base_name(id2),
+            _ => false
+        }
+    }
+
+    /// Resolves the identifier with the outermost `frames` frames and `keywords` keywords of the
+    /// context. An alias not bound by its own expansion is resolved as the identifier it renames
+    /// in the scope of the macro definition, so that the bindings around the macro use do not
+    /// capture it. The scope of a macro exported by a library is the top level of the library body
+    fn resolve(&self,
+               sym: &Cow<'static, str>,
+               frames: usize,
+               keywords: usize,
+               marks: &TreeMap<usize, Rc<MacroScope>>)
+            -> Result<Resolved, CompileError>
+    {
+        let depth = self.static_scope.len() + 1;
+
+        if frames >= depth {
+            if let Some(i) = (0..self.args.len()).find(|&i| self.args[i] == *sym) {
+                return Ok(Resolved::Var(MemRef::Arg(i)));
+            }
+        }
+
+        // (0, static_scope[-1]), (1, static_scope[-2]), (2, static_scope[-3]), ...
+        for (i, up_args) in self.static_scope.iter().rev().enumerate() {
+            if depth - 2 - i >= frames {
+                continue;
+            }
+            for (j, arg) in up_args.iter().enumerate() {
+                if *arg == *sym {
+                    return Ok(Resolved::Var(MemRef::UpValue(i, j)));
+                }
+            }
+        }
+
+        if let Some(&(_, ref syntax, ref scope)) =
+                self.syntax_env[..keywords].iter().rev().find(|&&(ref name, _, _)| name == sym) {
+            return Ok(Resolved::Syntax(syntax.clone(), scope.clone()));
+        }
+
+        if let Some(data) = self.global_env.get(sym) {
+            let ptr = match data.borrow().deref() {
+                &Datum::Ext(RuntimeData::PrimFunc(ref fptr)) =>
+                    MemRef::PrimFunc(fptr.clone()),
+                _ =>
+                    MemRef::Global(data.clone())
+            };
+            return Ok(if self.imported.contains(sym) { Resolved::Imported(ptr) } else { Resolved::Var(ptr) });
+        }
+
+        if let Some(syntax) = self.global_syntax.get(sym) {
+            return Ok(match syntax {
+                &Syntax::Library(ref syntax, ref library) =>
+                    Resolved::Syntax((**syntax).clone(), Rc::new(MacroScope::global(Some(library.clone())))),
+                _ => Resolved::Syntax(syntax.clone(), Rc::new(MacroScope::global(self.library.clone())))
+            });
+        }
+
+        match unalias(sym) {
+            Some((renamed, mark)) => match marks.get(&mark) {
+                Some(scope) => match scope.library {
+                    Some(ref library) =>
+                        LexicalContext::library(library).resolve(&renamed, 0, 0, &scope.marks),
+                    None => self.resolve(&renamed, scope.frames, scope.keywords, &scope.marks)
+                },
+                None => self.resolve(&renamed, 0, 0, &TreeMap::new())
+            },
+            None => Err(CompileError { kind: CompileErrorKind::UnboundVariable(sym.clone()) })
+        }
+    }
+
+    /// Scope of the macros defined in this context
+    fn macro_scope(&self) -> MacroScope {
+        MacroScope {
+            frames: self.static_scope.len() + 1,
+            keywords: self.syntax_env.len(),
+            marks: self.marks.clone(),
+            library: self.library.clone()
+        }
+    }
+}
+
+/// Lexical context of a macro use whose transformer is running, owned so that `free-identifier=?`
+/// can resolve the identifiers of the transformer in it
+struct TransformerCall {
+    global_env: HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
+    global_syntax: HashMap<Cow<'static, str>, Syntax>,
+    imported: HashSet<Cow<'static, str>>,
+    syntax_env: Vec<(Cow<'static, str>, Syntax, Rc<MacroScope>)>,
+    marks: TreeMap<usize, Rc<MacroScope>>,
+    static_scope: Vec<Vec<Cow<'static, str>>>,
+    args: Vec<Cow<'static, str>>,
+    library: Option<Rc<Environment>>,
+    /// Mark of the call, which the identifiers of the macro use have while the transformer runs
+    mark: usize
+}
+
+impl TransformerCall {
+    /// `env` is the context of the expansion, where `mark` refers to the scope of the macro
+    fn new(env: &LexicalContext, mark: usize) -> TransformerCall {
+        TransformerCall {
+            global_env: env.global_env.clone(),
+            global_syntax: env.global_syntax.clone(),
+            imported: env.imported.clone(),
+            syntax_env: env.syntax_env.clone(),
+            marks: env.marks.clone(),
+            static_scope: env.static_scope.clone(),
+            args: env.args.clone(),
+            library: env.library.clone(),
+            mark: mark
+        }
+    }
+
+    /// Toggling the mark gives the identifiers as the expansion has them: the identifiers of
+    /// the macro use lose the mark, and the identifiers introduced by the transformer get it
+    fn free_identifier_eq(&self, id1: &str, id2: &str) -> bool {
+        let env = LexicalContext {
+            global_env: &self.global_env,
+            global_syntax: &self.global_syntax,
+            imported: &self.imported,
+            syntax_env: self.syntax_env.clone(),
+            marks: self.marks.clone(),
+            pattern_vars: TreeMap::new(),
+            static_scope: self.static_scope.clone(),
+            args: self.args.clone(),
+            library: self.library.clone()
+        };
+        env.free_identifier_eq(&toggle_alias(id1, self.mark), &toggle_alias(id2, self.mark))
+    }
+}
+
+thread_local!(static TRANSFORMER_CALLS: RefCell<Vec<TransformerCall>> = RefCell::new(Vec::new()));
+
+/// `free-identifier=?`, resolving the identifiers in the context of the macro use whose
+/// transformer is running. Outside of the transformers, only the names of the identifiers are
+/// compared, as the global bindings of the same name are the same
+pub fn free_identifier_eq(id1: &str, id2: &str) -> bool {
+    TRANSFORMER_CALLS.with(|calls| match calls.borrow().last() {
+        Some(call) => call.free_identifier_eq(id1, id2),
+        None => base_name(id1) == base_name(id2)
+    })
 }
 
 enum Def<T> {
     Proc(Datum<T>, Vec<Datum<T>>),
     Expr(Datum<T>),
-    Void
+    Void,
+    /// `define-values`, where the flag tells the last variable takes the rest of the values
+    Values(Datum<T>, bool),
+    /// `define-record-type`, defining the type, the constructor, the predicate, the accessors and
+    /// the mutators
+    Record(RecordDef<T>)
+}
+
+/// Parsed `define-record-type`
+struct RecordDef<T> {
+    spec: RecordSpec,
+    parent: RecordParent<T>,
+    protocol: Option<Datum<T>>
+}
+
+enum RecordParent<T> {
+    None,
+    /// `(parent <record name>)`
+    Name(Datum<T>),
+    /// `(parent-rtd <rtd> <rcd>)`
+    Descriptors(Datum<T>, Datum<T>)
 }
 
 fn to_list<T: Clone>(datum: &Datum<T>) -> Result<Vec<Datum<T>>, CompileError> {
     datum.iter().collect::<Result<Vec<Datum<T>>, ()>>().map_err(|_| CompileError { kind: CompileErrorKind::BadSyntax })
 }
 
+fn list_of<T>(items: Vec<Datum<T>>) -> Datum<T> {
+    items.into_iter().collect()
+}
+
+/// Hidden pattern variable bound to the value of the `i`th `unsyntax` of a `quasisyntax`
+fn unsyntax_hole(i: usize) -> Cow<'static, str> {
+    Cow::Owned(format!("#unsyntax{}", i))
+}
+
 fn to_exprs<T: Clone>(datum: &Datum<T>) -> Result<Vec<Datum<T>>, CompileError> {
     datum.iter().collect::<Result<Vec<Datum<T>>, ()>>().map_err(|_| CompileError { kind: CompileErrorKind::DottedBody })
 }
@@ -156,7 +544,50 @@ fn to_exprs<T: Clone>(datum: &Datum<T>) -> Result<Vec<Datum<T>>, CompileError> {
 impl Compiler {
     /// Creates a new compiler with given environment
     pub fn new(syntax_env: HashMap<Cow<'static, str>, PrimitiveSyntax>) -> Compiler {
-        Compiler { syntax_env }
+        Compiler {
+            syntax_env: syntax_env.into_iter().map(|(k, v)| (k, Syntax::Primitive(v))).collect(),
+            imported: HashSet::new(),
+            next_mark: Cell::new(0),
+            program_env: None,
+            transformer_vm: RefCell::new(None)
+        }
+    }
+
+    /// Lets the transformers of the macros run in a runtime sharing the global environment and
+    /// the libraries of the program
+    pub fn share_env(&mut self, env: ProgramEnv) {
+        self.program_env = Some(env);
+        *self.transformer_vm.borrow_mut() = None;
+    }
+
+    /// Binds the keyword in the global environment
+    pub fn define_syntax(&mut self, sym: Cow<'static, str>, syntax: Syntax) {
+        self.imported.remove(&sym);
+        self.syntax_env.insert(sym, syntax);
+    }
+
+    /// Records whether the global variable is imported from a library, which makes it immutable,
+    /// or defined at the top level
+    pub fn define_var(&mut self, sym: Cow<'static, str>, imported: bool) {
+        if imported {
+            self.imported.insert(sym);
+        } else {
+            self.imported.remove(&sym);
+        }
+    }
+
+    /// Finds the primitive syntax keyword at the head of the top-level form
+    pub fn toplevel_syntax<T: Clone>(&self,
+                                     global_env: &HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
+                                     datum: &Datum<T>)
+            -> Option<PrimitiveSyntax>
+    {
+        if let &Datum::Cons(ref pair) = datum {
+            let env = LexicalContext::new(global_env, &self.syntax_env, &self.imported);
+            self.get_syntax_name(&env, &pair.car())
+        } else {
+            None
+        }
     }
 
     /// Compiles the datum into a bytecode evaluates it
@@ -170,17 +601,212 @@ impl Compiler {
             code: Vec::new(),
             link_size: 0
         };
-        let env = LexicalContext {
-            global_env,
-            syntax_env: TreeMap::new(),
-            static_scope: Vec::new(),
-            args: Vec::new()
-        };
+        let env = LexicalContext::new(global_env, &self.syntax_env, &self.imported);
         self.compile_expr(&env, &mut ctx, true, datum)?;
         ctx.code.push(Inst::Return);
         return Ok(ctx.code);
     }
 
+    /// Compiles the expression of `eval` in the environment made by `environment`. The bindings of
+    /// the environment are immutable, so the expression may not be a definition
+    pub fn compile_eval<T>(&self, env: &Environment, datum: &Datum<T>) -> Result<Vec<Inst>, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let lex_env = LexicalContext::new(&env.vars, &env.syntax, &env.imported);
+        if let &Datum::Cons(ref pair) = datum {
+            match self.get_syntax_name(&lex_env, &pair.car()) {
+                Some(PrimitiveSyntax::Define) | Some(PrimitiveSyntax::DefineValues) |
+                Some(PrimitiveSyntax::DefineRecordType) =>
+                    return Err(CompileError { kind: CompileErrorKind::DefineContext }),
+                _ => ()
+            }
+        }
+
+        let mut ctx = CodeGenContext {
+            code: Vec::new(),
+            link_size: 0
+        };
+        self.compile_expr(&lex_env, &mut ctx, true, datum)?;
+        ctx.code.push(Inst::Return);
+        Ok(ctx.code)
+    }
+
+    /// Compiles the transformers of the top-level `(define-syntax <keyword> <expr>)`, or the
+    /// keywords of the top-level `define-enumeration`
+    pub fn compile_define_syntax<T>(&self,
+                                    global_env: &HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
+                                    datum: &Datum<T>)
+            -> Result<Vec<(Cow<'static, str>, Syntax)>, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let env = LexicalContext::new(global_env, &self.syntax_env, &self.imported);
+        match self.parse_define_syntax(&env, datum)? {
+            Some(keywords) => keywords.into_iter().map(|keyword| self.compile_keyword(&env, keyword)).collect(),
+            None => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    /// Compiles `(library <name> (export <export spec> ...) (import <import set> ...) <body>)`.
+    /// Running the code defines the variables of the library, which the returned library exports
+    pub fn compile_library<T>(&self, registry: &LibraryRegistry, datum: &Datum<T>)
+            -> Result<(Library, Vec<Inst>), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let form = to_list(datum)?;
+        if form.len() < 4 {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+        let (name, version) = parse_library_name(&form[1])?;
+        let exports = self.parse_exports(&form[2])?;
+        let mut lib_env = self.parse_imports(registry, &form[3])?;
+
+        // The keywords are bound before compiling the body, so that any form of the body can use
+        // them
+        let mut keywords = Vec::new();
+        let mut body = Vec::new();
+        {
+            let env = LexicalContext::new(&lib_env.vars, &lib_env.syntax, &lib_env.imported);
+            for expr in self.splice_begin(&env, &form[4..])?.iter() {
+                match self.parse_define_syntax(&env, expr)? {
+                    Some(defined) => keywords.extend(defined),
+                    None => body.push(expr.clone())
+                }
+            }
+        }
+        for keyword in keywords.into_iter() {
+            let (sym, syntax) = {
+                let env = LexicalContext::new(&lib_env.vars, &lib_env.syntax, &lib_env.imported);
+                self.compile_keyword(&env, keyword)?
+            };
+            lib_env.syntax.insert(sym, syntax);
+        }
+
+        let mut defs = Vec::new();
+        {
+            let env = LexicalContext::new(&lib_env.vars, &lib_env.syntax, &lib_env.imported);
+            for expr in body.iter() {
+                defs.push(self.parse_define(&env, expr)?);
+            }
+        }
+
+        // Each variable defined in the body gets a cell before compiling the body, so that
+        // definitions can refer to the variables defined later
+        for def in defs.iter() {
+            if let &Some((ref vars, _)) = def {
+                for var in vars.iter() {
+                    lib_env.vars.insert(var.clone(), Rc::new(RefCell::new(Datum::Ext(RuntimeData::Undefined))));
+                    lib_env.imported.remove(var);
+                }
+            }
+        }
+
+        let mut ctx = CodeGenContext {
+            code: Vec::new(),
+            link_size: 0
+        };
+        {
+            let env = LexicalContext::new(&lib_env.vars, &lib_env.syntax, &lib_env.imported);
+            for (expr, def) in body.iter().zip(defs.into_iter()) {
+                match def {
+                    Some((vars, def)) => {
+                        let cells = vars.iter().map(|var| lib_env.vars[var].clone()).collect();
+                        self.compile_define_global(&env, &mut ctx, expr, def, cells)?;
+                    },
+                    None => {
+                        self.compile_expr(&env, &mut ctx, false, expr)?;
+                        ctx.code.push(Inst::DropArg(1));
+                    }
+                }
+            }
+        }
+        ctx.code.push(Inst::PushArg(MemRef::Undefined));
+        ctx.code.push(Inst::Return);
+
+        // The macros defined by the library keep its bindings, which their expansions refer to
+        let lib_env = Rc::new(lib_env);
+        let mut export_map = HashMap::new();
+        for (internal, external) in exports.into_iter() {
+            let binding = match lib_env.get(&internal) {
+                Some(Export::Syntax(syntax @ Syntax::Macro(_))) |
+                Some(Export::Syntax(syntax @ Syntax::Transformer(_))) =>
+                    Export::Syntax(Syntax::Library(Box::new(syntax), lib_env.clone())),
+                Some(binding) => binding,
+                None => return Err(CompileError { kind: CompileErrorKind::UnboundVariable(internal) })
+            };
+            export_map.insert(external, binding);
+        }
+
+        Ok((Library::new(name, version, export_map), ctx.code))
+    }
+
+    /// Resolves `(import <import set> ...)` into the imported bindings
+    pub fn compile_import<T: Clone>(&self, registry: &LibraryRegistry, datum: &Datum<T>)
+            -> Result<Environment, CompileError>
+    {
+        self.parse_imports(registry, datum)
+    }
+
+    /// Names of the libraries imported by the `library` or `import` form
+    pub fn imported_libraries<T: Clone>(&self, datum: &Datum<T>) -> Result<Vec<LibraryName>, CompileError> {
+        let form = to_list(datum)?;
+        let imports = match form.first() {
+            Some(head) if self.is_sym(head, "library") => match form.get(3) {
+                Some(imports) => to_list(imports)?,
+                None => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            },
+            _ => form
+        };
+
+        imports.iter().skip(1).map(import_set_library).collect()
+    }
+
+    /// Parses `(export <export spec> ...)` into the pairs of the internal and the external names
+    fn parse_exports<T: Clone>(&self, form: &Datum<T>)
+            -> Result<Vec<(Cow<'static, str>, Cow<'static, str>)>, CompileError>
+    {
+        let list = to_list(form)?;
+        if !list.first().map_or(false, |head| self.is_sym(head, "export")) {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+
+        let mut exports = Vec::new();
+        for spec in list[1..].iter() {
+            if let &Datum::Sym(ref sym) = spec {
+                exports.push((sym.clone(), sym.clone()));
+                continue;
+            }
+
+            let renames = to_list(spec)?;
+            if !renames.first().map_or(false, |head| self.is_sym(head, "rename")) {
+                return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+            }
+            for rename in renames[1..].iter() {
+                match to_list(rename)?.as_slice() {
+                    &[Datum::Sym(ref internal), Datum::Sym(ref external)] =>
+                        exports.push((internal.clone(), external.clone())),
+                    _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+                }
+            }
+        }
+        Ok(exports)
+    }
+
+    /// Parses `(import <import set> ...)` into the environment of the imported bindings
+    fn parse_imports<T: Clone>(&self, registry: &LibraryRegistry, form: &Datum<T>)
+            -> Result<Environment, CompileError>
+    {
+        let list = to_list(form)?;
+        if !list.first().map_or(false, |head| self.is_sym(head, "import")) {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+
+        let mut env = Environment::new();
+        for set in list[1..].iter() {
+            env.import(registry.import(set)?)?;
+        }
+        Ok(env)
+    }
+
     fn compile_app<T>(&self,
                       env: &LexicalContext,
                       ctx: &mut CodeGenContext,
@@ -190,60 +816,121 @@ impl Compiler {
         where T: Clone + Debug + TryConv<(), CompileError>
     {
         let (callee, c_args) = match datum {
-            &Datum::Cons(ref ptr) => ptr.as_ref().clone(),
+            &Datum::Cons(ref ptr) => ptr.get(),
             _ => return Err(CompileError { kind: CompileErrorKind::NullEval })
         };
 
         if let Datum::Sym(ref s) = callee {
-            match self.compile_ref(env, ctx, s) {
-                Ok(ptr) => ctx.code.push(Inst::PushArg(ptr)),
-                Err(e) => match e.kind {
-                    CompileErrorKind::SyntaxReference(Syntax::Primitive(syn)) => {
-                        return match syn {
-                            PrimitiveSyntax::Lambda =>
-                                self.compile_lambda(env, ctx, &c_args),
-                            PrimitiveSyntax::If =>
-                                self.compile_if(env, ctx, tail_ctx, &c_args),
-                            PrimitiveSyntax::Let =>
-                                self.compile_let(env, ctx, &c_args),
-                            PrimitiveSyntax::LetStar =>
-                                self.compile_let_star(env, ctx, &c_args),
-                            PrimitiveSyntax::LetRec | PrimitiveSyntax::LetRecStar =>
-                                self.compile_letrec(env, ctx, &c_args),
-                            PrimitiveSyntax::Define =>
-                                self.compile_define_toplevel(env, ctx, &datum),
-                            PrimitiveSyntax::Set =>
-                                self.compile_set(env, ctx, &c_args),
-                            PrimitiveSyntax::Quote =>
-                                self.compile_quote(ctx, &c_args),
-                            PrimitiveSyntax::Quasiquote =>
-                                self.compile_quasiquote(env, ctx, &c_args),
-                            PrimitiveSyntax::Unquote | PrimitiveSyntax::UnquoteSplicing =>
-                                return Err(CompileError {
-                                    kind: CompileErrorKind::UnquoteContext
-                                }),
-                            PrimitiveSyntax::Cond =>
-                                self.compile_cond(env, ctx, tail_ctx, &c_args),
-                            PrimitiveSyntax::Case =>
-                                self.compile_case(env, ctx, tail_ctx, &c_args),
-                            PrimitiveSyntax::And =>
-                                self.compile_and(env, ctx, tail_ctx, &c_args),
-                            PrimitiveSyntax::Or =>
-                                self.compile_or(env, ctx, tail_ctx, &c_args),
-                            PrimitiveSyntax::SyntaxRules =>
-                                return Err(CompileError {
-                                    kind: CompileErrorKind::SyntaxRulesContext
-                                }),
-                            PrimitiveSyntax::LetSyntax =>
-                                self.compile_let_syntax(env, ctx, tail_ctx, &c_args),
-                        };
-                    },
-                    CompileErrorKind::SyntaxReference(Syntax::Macro(syn)) => {
-                        let expanded = syn.transform(&datum)?;
-                        return self.compile_expr(env, ctx, tail_ctx, &expanded);
-                    },
-                    _ => return Err(e)
-                }
+            match env.lookup(s)? {
+                Resolved::Var(ptr) | Resolved::Imported(ptr) => {
+                    self.link_upvalue(ctx, &ptr);
+                    ctx.code.push(Inst::PushArg(ptr));
+                },
+                Resolved::Syntax(Syntax::Primitive(syn), _) => {
+                    return match syn {
+                        PrimitiveSyntax::Lambda =>
+                            self.compile_lambda(env, ctx, &c_args),
+                        PrimitiveSyntax::If =>
+                            self.compile_if(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::Let =>
+                            self.compile_let(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::LetStar =>
+                            self.compile_let_star(env, ctx, &c_args),
+                        PrimitiveSyntax::LetRec | PrimitiveSyntax::LetRecStar =>
+                            self.compile_letrec(env, ctx, &c_args),
+                        PrimitiveSyntax::Define | PrimitiveSyntax::DefineValues |
+                        PrimitiveSyntax::DefineRecordType =>
+                            self.compile_define_toplevel(env, ctx, &datum),
+                        PrimitiveSyntax::Set =>
+                            self.compile_set(env, ctx, &c_args),
+                        PrimitiveSyntax::Quote =>
+                            self.compile_quote(ctx, &c_args),
+                        PrimitiveSyntax::Quasiquote =>
+                            self.compile_quasiquote(env, ctx, &c_args),
+                        PrimitiveSyntax::Unquote | PrimitiveSyntax::UnquoteSplicing =>
+                            return Err(CompileError {
+                                kind: CompileErrorKind::UnquoteContext
+                            }),
+                        PrimitiveSyntax::Cond =>
+                            self.compile_cond(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::Case =>
+                            self.compile_case(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::And =>
+                            self.compile_and(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::Or =>
+                            self.compile_or(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::SyntaxRules =>
+                            return Err(CompileError {
+                                kind: CompileErrorKind::SyntaxRulesContext
+                            }),
+                        PrimitiveSyntax::LetSyntax =>
+                            self.compile_let_syntax(env, ctx, tail_ctx, &c_args, false),
+                        PrimitiveSyntax::LetRecSyntax =>
+                            self.compile_let_syntax(env, ctx, tail_ctx, &c_args, true),
+                        PrimitiveSyntax::DefineSyntax | PrimitiveSyntax::DefineEnumeration =>
+                            return Err(CompileError {
+                                kind: CompileErrorKind::DefineContext
+                            }),
+                        PrimitiveSyntax::Guard =>
+                            self.compile_guard(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::LetValues =>
+                            self.compile_let_values(env, ctx, &c_args),
+                        PrimitiveSyntax::LetStarValues =>
+                            self.compile_let_star_values(env, ctx, &c_args),
+                        PrimitiveSyntax::Library | PrimitiveSyntax::Import =>
+                            return Err(CompileError {
+                                kind: CompileErrorKind::LibraryContext
+                            }),
+                        PrimitiveSyntax::SyntaxCase =>
+                            self.compile_syntax_case(env, ctx, &c_args),
+                        PrimitiveSyntax::Syntax =>
+                            self.compile_syntax(env, ctx, &c_args),
+                        PrimitiveSyntax::Quasisyntax =>
+                            self.compile_quasisyntax(env, ctx, &c_args),
+                        PrimitiveSyntax::Unsyntax | PrimitiveSyntax::UnsyntaxSplicing =>
+                            return Err(CompileError {
+                                kind: CompileErrorKind::UnquoteContext
+                            }),
+                        PrimitiveSyntax::WithSyntax =>
+                            self.compile_with_syntax(env, ctx, &c_args),
+                        PrimitiveSyntax::Endianness =>
+                            self.compile_symbol_syntax(ctx, &c_args, &["big", "little"]),
+                        PrimitiveSyntax::EolStyle =>
+                            self.compile_symbol_syntax(ctx, &c_args, EOL_STYLES),
+                        PrimitiveSyntax::ErrorHandlingMode =>
+                            self.compile_symbol_syntax(ctx, &c_args, ERROR_HANDLING_MODES),
+                        PrimitiveSyntax::BufferMode =>
+                            self.compile_symbol_syntax(ctx, &c_args, BUFFER_MODES),
+                        PrimitiveSyntax::FileOptions =>
+                            self.compile_enum_constructor(ctx, &file_options_universe(), &c_args),
+                        PrimitiveSyntax::Begin =>
+                            self.compile_sequence(env, ctx, tail_ctx, &to_exprs(&c_args)?),
+                        PrimitiveSyntax::When =>
+                            self.compile_when(env, ctx, tail_ctx, &c_args, true),
+                        PrimitiveSyntax::Unless =>
+                            self.compile_when(env, ctx, tail_ctx, &c_args, false),
+                        PrimitiveSyntax::Do =>
+                            self.compile_do(env, ctx, tail_ctx, &c_args),
+                        PrimitiveSyntax::CaseLambda =>
+                            self.compile_case_lambda(env, ctx, &c_args),
+                        PrimitiveSyntax::Delay =>
+                            self.compile_delay(env, ctx, &c_args, false),
+                        PrimitiveSyntax::DelayForce =>
+                            self.compile_delay(env, ctx, &c_args, true),
+                        PrimitiveSyntax::Parameterize =>
+                            self.compile_parameterize(env, ctx, &c_args),
+                    };
+                },
+                Resolved::Syntax(Syntax::Macro(syn), scope) =>
+                    return self.compile_macro(env, ctx, tail_ctx, &syn, scope, datum),
+                Resolved::Syntax(Syntax::Transformer(f), scope) =>
+                    return self.compile_transformer_call(env, ctx, tail_ctx, &f, scope, datum),
+                Resolved::Syntax(Syntax::EnumTypeName(set), _) =>
+                    return self.compile_enum_type_name(ctx, &set, &c_args),
+                Resolved::Syntax(Syntax::EnumConstructor(set), _) =>
+                    return self.compile_enum_constructor(ctx, &set, &c_args),
+                // `resolve` unwraps the macros of the libraries
+                Resolved::Syntax(Syntax::Library(_, _), _) => unreachable!()
             }
         } else {
             self.compile_expr(env, ctx, false, &callee)?;
@@ -287,7 +974,17 @@ impl Compiler {
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
-        if let Some((var, def)) = self.parse_define(env, &expr)? {
+        if let Some((mut vars, def)) = self.parse_define(env, &expr)? {
+            if let Some(rest) = self.compile_values_def(env, ctx, &def)? {
+                ctx.code.push(Inst::ReceiveValues(vars.len() - rest as usize, rest));
+                while let Some(var) = vars.pop() {
+                    ctx.code.push(Inst::PopGlobal(var));
+                }
+                ctx.code.push(Inst::PushArg(MemRef::Undefined));
+                return Ok(());
+            }
+
+            let var = vars.remove(0);
             ctx.code.push(Inst::PushFrame(1));
             ctx.code.push(Inst::PushArg(MemRef::Undefined));
             let new_env = env.update_arg(vec![var.clone()]);
@@ -304,7 +1001,7 @@ impl Compiler {
                 Def::Expr(expr) => {
                     self.compile_expr(&new_env, ctx, false, &expr)?;
                 },
-                Def::Void => {
+                Def::Void | Def::Values(_, _) | Def::Record(_) => {
                     ctx.code.push(Inst::PushArg(MemRef::Undefined));
                 }
             }
@@ -320,8 +1017,42 @@ impl Compiler {
         }
     }
 
+    /// Compiles the definition in a library body, which stores the values into the cells of the
+    /// library
+    fn compile_define_global<T>(&self,
+                                env: &LexicalContext,
+                                ctx: &mut CodeGenContext,
+                                src: &Datum<T>,
+                                def: Def<T>,
+                                cells: Vec<Rc<RefCell<RDatum>>>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        match def {
+            Def::Proc(formals, body) => {
+                let proc_ctx = self.compile_proc(env, &formals, &body)?;
+                ctx.code.push(Inst::PushArg(MemRef::Closure(
+                    Rc::new(proc_ctx.code),
+                    proc_ctx.link_size,
+                    Some(src.try_conv()?)
+                )));
+            },
+            Def::Expr(expr) => self.compile_expr(env, ctx, false, &expr)?,
+            Def::Void => ctx.code.push(Inst::PushArg(MemRef::Undefined)),
+            Def::Values(_, _) | Def::Record(_) => {
+                let rest = self.compile_values_def(env, ctx, &def)?.unwrap_or(false);
+                ctx.code.push(Inst::ReceiveValues(cells.len() - rest as usize, rest));
+            }
+        }
+
+        for cell in cells.into_iter().rev() {
+            ctx.code.push(Inst::PopArg(MemRef::Global(cell)));
+        }
+        Ok(())
+    }
+
     fn parse_define<T: Clone+Debug>(&self, env: &LexicalContext, def: &Datum<T>)
-            -> Result<Option<(Cow<'static, str>, Def<T>)>, CompileError>
+            -> Result<Option<(Vec<Cow<'static, str>>, Def<T>)>, CompileError>
     {
         let list: Vec<Datum<T>> = match def.iter().collect() {
             Ok(l) => l,
@@ -332,29 +1063,294 @@ impl Compiler {
             return Ok(None);
         }
 
-        if let Some(PrimitiveSyntax::Define) = self.get_syntax_name(env, &list[0]) {
-            match &list[1..] {
-                &[Datum::Sym(ref v)] =>
-                    Ok(Some((v.clone(), Def::Void))),
-                &[Datum::Sym(ref v), ref e] =>
-                    Ok(Some((v.clone(), Def::Expr(e.clone())))),
-                &[Datum::Cons(ref form), ..] => {
-                    if let Datum::Sym(ref v) = form.0 {
-                        Ok(Some((
-                            v.clone(),
-                            Def::Proc(form.1.clone(), list[2..].to_vec())
-                        )))
+        match self.get_syntax_name(env, &list[0]) {
+            Some(PrimitiveSyntax::Define) => (),
+            Some(PrimitiveSyntax::DefineValues) => {
+                return match &list[1..] {
+                    &[ref formals, ref e] => {
+                        let (vars, rest) = self.parse_formals(formals)?;
+                        Ok(Some((vars, Def::Values(e.clone(), rest))))
+                    },
+                    _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+                };
+            },
+            Some(PrimitiveSyntax::DefineRecordType) =>
+                return self.parse_record_definition(&list[1..]).map(Some),
+            _ => return Ok(None)
+        }
+
+        match &list[1..] {
+            &[Datum::Sym(ref v)] =>
+                Ok(Some((vec![v.clone()], Def::Void))),
+            &[Datum::Sym(ref v), ref e] =>
+                Ok(Some((vec![v.clone()], Def::Expr(e.clone())))),
+            &[Datum::Cons(ref form), ..] => {
+                if let Datum::Sym(ref v) = form.car() {
+                    Ok(Some((
+                        vec![v.clone()],
+                        Def::Proc(form.cdr(), list[2..].to_vec())
+                    )))
+                } else {
+                    Err(CompileError {
+                        kind: CompileErrorKind::BadSyntax
+                    })
+                }
+            },
+            _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    /// Parses the name spec and the clauses of `define-record-type` into the defined variables
+    /// and the definition
+    fn parse_record_definition<T: Clone+Debug>(&self, args: &[Datum<T>])
+            -> Result<(Vec<Cow<'static, str>>, Def<T>), CompileError>
+    {
+        let bad_syntax = || CompileError { kind: CompileErrorKind::BadSyntax };
+        let sym = |datum: &Datum<T>| match datum {
+            &Datum::Sym(ref s) => Ok(s.clone()),
+            _ => Err(bad_syntax())
+        };
+
+        let (name_spec, clauses) = args.split_first().ok_or_else(bad_syntax)?;
+        let (name, ctor, pred) = match name_spec {
+            &Datum::Sym(ref name) => {
+                let base = base_name(name);
+                (name.clone(), Cow::Owned(format!("make-{}", base)), Cow::Owned(format!("{}?", base)))
+            },
+            spec => match to_list(spec)?.as_slice() {
+                &[ref name, ref ctor, ref pred] => (sym(name)?, sym(ctor)?, sym(pred)?),
+                _ => return Err(bad_syntax())
+            }
+        };
+        let type_name: Cow<'static, str> = Cow::Owned(base_name(&name).to_string());
+
+        let mut spec = RecordSpec {
+            name: type_name.clone(),
+            uid: None,
+            sealed: false,
+            opaque: false,
+            fields: Vec::new()
+        };
+        let mut accessors = Vec::new();
+        let mut mutators = Vec::new();
+        let mut parent = RecordParent::None;
+        let mut protocol = None;
+
+        for clause in clauses.iter() {
+            let clause = to_list(clause)?;
+            let (head, rest) = clause.split_first().ok_or_else(bad_syntax)?;
+            if self.is_sym(head, "fields") {
+                for field in rest.iter() {
+                    let field_spec = match field {
+                        &Datum::Sym(_) => vec![Datum::Sym(Cow::Borrowed("immutable")), field.clone()],
+                        _ => to_list(field)?
+                    };
+                    let (kind, names) = field_spec.split_first().ok_or_else(bad_syntax)?;
+                    let mutable = if self.is_sym(kind, "mutable") {
+                        true
+                    } else if self.is_sym(kind, "immutable") {
+                        false
                     } else {
-                        Err(CompileError {
-                            kind: CompileErrorKind::BadSyntax
-                        })
+                        return Err(bad_syntax());
+                    };
+                    let field_name = sym(names.first().ok_or_else(bad_syntax)?)?;
+                    let base = base_name(&field_name).to_string();
+                    match (names.len(), mutable) {
+                        (1, _) => {
+                            accessors.push(Cow::Owned(format!("{}-{}", type_name, base)));
+                            if mutable {
+                                mutators.push(Cow::Owned(format!("{}-{}-set!", type_name, base)));
+                            }
+                        },
+                        (2, false) => accessors.push(sym(&names[1])?),
+                        (3, true) => {
+                            accessors.push(sym(&names[1])?);
+                            mutators.push(sym(&names[2])?);
+                        },
+                        _ => return Err(bad_syntax())
                     }
+                    spec.fields.push((Cow::Owned(base), mutable));
+                }
+            } else if self.is_sym(head, "parent") {
+                match rest {
+                    &[ref name] => parent = RecordParent::Name(name.clone()),
+                    _ => return Err(bad_syntax())
+                }
+            } else if self.is_sym(head, "parent-rtd") {
+                match rest {
+                    &[ref rtd, ref rcd] => parent = RecordParent::Descriptors(rtd.clone(), rcd.clone()),
+                    _ => return Err(bad_syntax())
+                }
+            } else if self.is_sym(head, "protocol") {
+                match rest {
+                    &[ref expr] => protocol = Some(expr.clone()),
+                    _ => return Err(bad_syntax())
+                }
+            } else if self.is_sym(head, "sealed") {
+                match rest {
+                    &[Datum::Bool(b)] => spec.sealed = b,
+                    _ => return Err(bad_syntax())
+                }
+            } else if self.is_sym(head, "opaque") {
+                match rest {
+                    &[Datum::Bool(b)] => spec.opaque = b,
+                    _ => return Err(bad_syntax())
+                }
+            } else if self.is_sym(head, "nongenerative") {
+                spec.uid = match rest {
+                    &[] => Some(type_name.clone()),
+                    &[Datum::Sym(ref uid)] => Some(Cow::Owned(base_name(uid).to_string())),
+                    _ => return Err(bad_syntax())
+                };
+            } else {
+                return Err(bad_syntax());
+            }
+        }
+
+        let mut vars = vec![name, ctor, pred];
+        vars.extend(accessors);
+        vars.extend(mutators);
+        Ok((vars, Def::Record(RecordDef {
+            spec: spec,
+            parent: parent,
+            protocol: protocol
+        })))
+    }
+
+    /// Compiles the expression of the definition returning multiple values, then returns whether
+    /// the last variable takes the rest of the values. Returns `None` for the other definitions
+    fn compile_values_def<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, def: &Def<T>)
+            -> Result<Option<bool>, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        match def {
+            &Def::Values(ref expr, rest) => {
+                self.compile_expr(env, ctx, false, expr)?;
+                Ok(Some(rest))
+            },
+            &Def::Record(ref record) => {
+                let code = record_definition_code(&record.spec);
+                ctx.code.push(Inst::PushArg(MemRef::Closure(Rc::new(code), 0, None)));
+                match record.parent {
+                    RecordParent::None => {
+                        ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Bool(false))));
+                        ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Bool(false))));
+                    },
+                    RecordParent::Name(ref name) => {
+                        self.compile_expr(env, ctx, false, name)?;
+                        ctx.code.push(Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new(
+                            "define-record-type", &PRIM_RECORD_CONSTRUCTOR_DESCRIPTOR))));
+                        self.compile_expr(env, ctx, false, name)?;
+                        ctx.code.push(Inst::Call(1));
+                    },
+                    RecordParent::Descriptors(ref rtd, ref rcd) => {
+                        self.compile_expr(env, ctx, false, rtd)?;
+                        self.compile_expr(env, ctx, false, rcd)?;
+                    }
+                }
+                match record.protocol {
+                    Some(ref expr) => self.compile_expr(env, ctx, false, expr)?,
+                    None => ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Bool(false))))
+                }
+                ctx.code.push(Inst::Call(3));
+                Ok(Some(false))
+            },
+            _ => Ok(None)
+        }
+    }
+
+    /// Parses `(define-syntax <keyword> <expr>)` or
+    /// `(define-enumeration <type name> (<symbol> ...) <constructor syntax>)` into the keywords
+    /// it defines
+    fn parse_define_syntax<T: Clone+Debug>(&self, env: &LexicalContext, def: &Datum<T>)
+            -> Result<Option<Vec<Keyword<T>>>, CompileError>
+    {
+        let head = match def {
+            &Datum::Cons(ref pair) => pair.car(),
+            _ => return Ok(None)
+        };
+        match self.get_syntax_name(env, &head) {
+            Some(PrimitiveSyntax::DefineSyntax) => match to_list(def)?.as_slice() {
+                &[_, Datum::Sym(ref keyword), ref expr] =>
+                    Ok(Some(vec![Keyword::Transformer(Binding::new(keyword.clone(), expr.clone()))])),
+                _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            },
+            Some(PrimitiveSyntax::DefineEnumeration) => match to_list(def)?.as_slice() {
+                &[_, Datum::Sym(ref type_name), ref symbols, Datum::Sym(ref constructor)] => {
+                    let mut universe: Vec<Cow<'static, str>> = Vec::new();
+                    for sym in to_list(symbols)? {
+                        match sym {
+                            Datum::Sym(ref s) if !universe.iter().any(|u| u == base_name(s)) =>
+                                universe.push(Cow::Owned(base_name(s).to_string())),
+                            Datum::Sym(_) => (),
+                            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+                        }
+                    }
+                    let set = Rc::new(EnumSet::full(Rc::new(EnumType { symbols: universe })));
+                    Ok(Some(vec![
+                        Keyword::Syntax(type_name.clone(), Syntax::EnumTypeName(set.clone())),
+                        Keyword::Syntax(constructor.clone(), Syntax::EnumConstructor(set))
+                    ]))
                 },
                 _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            },
+            _ => Ok(None)
+        }
+    }
+
+    /// Compiles `(<type name> <symbol>)` of `define-enumeration` into the symbol
+    fn compile_enum_type_name<T>(&self, ctx: &mut CodeGenContext, set: &EnumSet, args: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone
+    {
+        match to_list(args)?.as_slice() {
+            &[Datum::Sym(ref sym)] if set.is_member(base_name(sym)) => {
+                let sym = Cow::Owned(base_name(sym).to_string());
+                ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Sym(sym))));
+                Ok(())
+            },
+            _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    /// Compiles `(endianness <symbol>)` and the other syntax naming one of the `symbols` into
+    /// the symbol
+    fn compile_symbol_syntax<T>(&self, ctx: &mut CodeGenContext, args: &Datum<T>, symbols: &[&str])
+            -> Result<(), CompileError>
+        where T: Clone
+    {
+        match to_list(args)?.as_slice() {
+            &[Datum::Sym(ref sym)] if symbols.contains(&base_name(sym)) => {
+                let sym = Cow::Owned(base_name(sym).to_string());
+                ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Sym(sym))));
+                Ok(())
+            },
+            _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    /// Compiles `(<constructor syntax> <symbol> ...)` of `define-enumeration` into the call
+    /// making the enum set of the symbols
+    fn compile_enum_constructor<T>(&self, ctx: &mut CodeGenContext, set: &Rc<EnumSet>, args: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone
+    {
+        let syms = to_list(args)?;
+        ctx.code.push(Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("define-enumeration", &PRIM_ENUM_SET_FROM_LIST))));
+        ctx.code.push(Inst::PushArg(MemRef::Global(Rc::new(RefCell::new(Datum::Ext(RuntimeData::EnumSet(set.clone())))))));
+        ctx.code.push(Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("define-enumeration", &PRIM_LIST))));
+        for sym in syms.iter() {
+            match sym {
+                &Datum::Sym(ref s) if set.is_member(base_name(s)) => {
+                    let s = Cow::Owned(base_name(s).to_string());
+                    ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Sym(s))));
+                },
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
             }
-        } else {
-            Ok(None)
         }
+        ctx.code.push(Inst::Call(syms.len()));
+        ctx.code.push(Inst::Call(2));
+        Ok(())
     }
 
     fn compile_body<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, body: &Datum<T>)
@@ -378,6 +1374,7 @@ impl Compiler {
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
+        let body = &self.splice_begin(env, body)?[..];
         if body.is_empty() {
             return Err(CompileError { kind: CompileErrorKind::EmptyBody });
         }
@@ -385,23 +1382,41 @@ impl Compiler {
         let mut def_vars = Vec::new();
         let mut defs = Vec::new();
         let mut srcs = Vec::new();
+        let mut keywords = Vec::new();
+        // number of the definitions, including the keyword definitions
+        let mut ndefs = 0;
+
+        // the first frame slot of each definition
+        let mut slots = Vec::new();
 
         for expr in body.iter() {
+            if let Some(defined) = self.parse_define_syntax(env, &expr)? {
+                keywords.extend(defined);
+                ndefs += 1;
+                continue;
+            }
+
             match self.parse_define(env, &expr)? {
-                Some((var, def)) => {
-                    def_vars.push(var);
+                Some((vars, def)) => {
+                    slots.push(env.args.len() + def_vars.len());
+                    def_vars.extend(vars);
                     defs.push(def);
                     srcs.push(expr);
+                    ndefs += 1;
                 },
                 None => break
             }
         }
 
-        let mut mod_env = env.clone();
+        let mut mod_env = self.bind_keywords(env, keywords, true)?;
         for var in def_vars.iter() {
             mod_env.push_arg(var.clone());
             ctx.code.push(Inst::PushArg(MemRef::Undefined));
         }
+        if !def_vars.is_empty() {
+            // internal definitions are part of the frame
+            ctx.code.push(Inst::SetArgSize(mod_env.args.len()));
+        }
 
         for (i, def) in defs.iter().enumerate() {
             match def {
@@ -412,31 +1427,119 @@ impl Compiler {
                             proc_ctx.link_size,
                             Some(srcs[i].try_conv()?)
                     )));
-                    ctx.code.push(Inst::PopArg(MemRef::Arg(env.args.len() + i)));
+                    ctx.code.push(Inst::PopArg(MemRef::Arg(slots[i])));
                 },
                 &Def::Expr(ref expr) => {
                     self.compile_expr(&mod_env, ctx, false, expr)?;
-                    ctx.code.push(Inst::PopArg(MemRef::Arg(env.args.len() + i)));
+                    ctx.code.push(Inst::PopArg(MemRef::Arg(slots[i])));
                 },
-                &Def::Void => ()
+                &Def::Void => (),
+                &Def::Values(_, _) | &Def::Record(_) => {
+                    let end = slots.get(i+1).cloned().unwrap_or(mod_env.args.len());
+                    let rest = self.compile_values_def(&mod_env, ctx, def)?.unwrap_or(false);
+                    ctx.code.push(Inst::ReceiveValues(end - slots[i] - rest as usize, rest));
+                    for slot in (slots[i] .. end).rev() {
+                        ctx.code.push(Inst::PopArg(MemRef::Arg(slot)));
+                    }
+                }
             }
         }
 
-        if def_vars.len() == body.len() {
+        if ndefs == body.len() {
             return Err(CompileError {
                 kind: CompileErrorKind::EmptyBody
             });
         }
 
-        for idx in def_vars.len() .. body.len() {
-            if idx != def_vars.len() {
+        for idx in ndefs .. body.len() {
+            if idx != ndefs {
+                ctx.code.push(Inst::DropArg(1));
+            }
+
+            let tail_expr = tail_ctx && idx == body.len() - 1;
+            self.compile_expr(&mod_env, ctx, tail_expr, &body[idx])?;
+        }
+
+        Ok(())
+    }
+
+    /// Splices the forms of `begin` into the body, as the definitions in them are part of the body
+    fn splice_begin<T: Clone>(&self, env: &LexicalContext, body: &[Datum<T>])
+            -> Result<Vec<Datum<T>>, CompileError>
+    {
+        let mut forms = Vec::new();
+        for form in body.iter() {
+            if let &Datum::Cons(ref pair) = form {
+                if let Some(PrimitiveSyntax::Begin) = self.get_syntax_name(env, &pair.car()) {
+                    forms.extend(self.splice_begin(env, &to_exprs(&pair.cdr())?)?);
+                    continue;
+                }
+            }
+            forms.push(form.clone());
+        }
+        Ok(forms)
+    }
+
+    /// Compiles the expressions of `begin` in order, leaving the value of the last one
+    fn compile_sequence<T>(&self,
+                           env: &LexicalContext,
+                           ctx: &mut CodeGenContext,
+                           tail_ctx: bool,
+                           exprs: &[Datum<T>])
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        if exprs.is_empty() {
+            return Err(CompileError { kind: CompileErrorKind::EmptyBody });
+        }
+
+        for (i, expr) in exprs.iter().enumerate() {
+            if i != 0 {
                 ctx.code.push(Inst::DropArg(1));
             }
+            self.compile_expr(env, ctx, tail_ctx && i == exprs.len() - 1, expr)?;
+        }
+        Ok(())
+    }
 
-            let tail_expr = tail_ctx && idx == body.len() - 1;
-            self.compile_expr(&mod_env, ctx, tail_expr, &body[idx])?;
+    /// Compiles `when`, or `unless` if `expected` is false
+    fn compile_when<T>(&self,
+                       env: &LexicalContext,
+                       ctx: &mut CodeGenContext,
+                       tail_ctx: bool,
+                       tail: &Datum<T>,
+                       expected: bool)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let exprs = to_exprs(tail)?;
+        if exprs.len() < 2 {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
         }
 
+        self.compile_expr(env, ctx, false, &exprs[0])?;
+
+        let cond_jump_pc = ctx.code.len();
+        // placeholder to replace with JumpIfFalse or JumpIfNotFalse
+        ctx.code.push(Inst::Nop);
+        ctx.code.push(Inst::DropArg(1));
+
+        self.compile_sequence(env, ctx, tail_ctx, &exprs[1..])?;
+
+        let jump_pc = ctx.code.len();
+        // placeholder to replace with Jump
+        ctx.code.push(Inst::Nop);
+
+        let skip_pc = ctx.code.len();
+        ctx.code[cond_jump_pc] = if expected {
+            Inst::JumpIfFalse(skip_pc)
+        } else {
+            Inst::JumpIfNotFalse(skip_pc)
+        };
+        ctx.code.push(Inst::DropArg(1));
+        ctx.code.push(Inst::PushArg(MemRef::Undefined));
+
+        ctx.code[jump_pc] = Inst::Jump(ctx.code.len());
         Ok(())
     }
 
@@ -492,7 +1595,7 @@ impl Compiler {
             -> Result<(Vec<Binding<T>>, Datum<T>), CompileError>
     {
         if let &Datum::Cons(ref ptr) = form {
-            let (ref binding_form, ref body) = *ptr.as_ref();
+            let (ref binding_form, ref body) = ptr.get();
             let mut bindings = Vec::new();
             for b in binding_form.iter() {
                 match b {
@@ -518,10 +1621,20 @@ impl Compiler {
         }
     }
 
-    fn compile_let<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+    fn compile_let<T>(&self,
+                      env: &LexicalContext,
+                      ctx: &mut CodeGenContext,
+                      tail_ctx: bool,
+                      tail: &Datum<T>)
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
+        if let &Datum::Cons(ref pair) = tail {
+            if let (Datum::Sym(name), form) = pair.get() {
+                return self.compile_named_let(env, ctx, tail_ctx, name, &form, tail);
+            }
+        }
+
         let (bindings, body) = self.get_form(tail)?;
         for binding in &bindings {
             self.compile_expr(env, ctx, false, &binding.expr)?;
@@ -538,6 +1651,75 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compiles `(let <name> <bindings> <body>)`, where `form` is the part after the name. The body
+    /// can call the procedure bound to the name
+    fn compile_named_let<T>(&self,
+                            env: &LexicalContext,
+                            ctx: &mut CodeGenContext,
+                            tail_ctx: bool,
+                            name: Cow<'static, str>,
+                            form: &Datum<T>,
+                            tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (bindings, body) = self.get_form(form)?;
+        let (vars, inits): (Vec<_>, Vec<_>) = bindings.into_iter().map(|b| (b.sym, b.expr)).unzip();
+        let body = to_exprs(&body)?;
+        let src = cons(Datum::Sym(Cow::Borrowed("let")), tail.try_conv()?);
+
+        self.compile_loop(env, ctx, tail_ctx, name, vars, &inits, src,
+                          |proc_env, proc_ctx| self.compile_exprs(proc_env, proc_ctx, true, &body))
+    }
+
+    /// Compiles the loop of named `let` or `do`. The procedure compiled by `compile_body` is bound
+    /// to `name` in its own scope, then called with the values of `inits` as `vars`. The loop
+    /// calls the procedure again in tail position, so it runs in constant space
+    fn compile_loop<T, F>(&self,
+                          env: &LexicalContext,
+                          ctx: &mut CodeGenContext,
+                          tail_ctx: bool,
+                          name: Cow<'static, str>,
+                          vars: Vec<Cow<'static, str>>,
+                          inits: &[Datum<T>],
+                          src: Datum<()>,
+                          compile_body: F)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>,
+              F: FnOnce(&LexicalContext, &mut CodeGenContext) -> Result<(), CompileError>
+    {
+        ctx.code.push(Inst::PushFrame(0));
+        ctx.code.push(Inst::PushArg(MemRef::Undefined));
+
+        let mut proc_ctx = CodeGenContext {
+            code: Vec::new(),
+            link_size: 0
+        };
+        compile_body(&env.update_arg(vec![name]).update_arg(vars), &mut proc_ctx)?;
+        proc_ctx.code.push(Inst::Return);
+
+        ctx.code.push(Inst::PushArg(MemRef::Closure(
+                Rc::new(proc_ctx.code),
+                proc_ctx.link_size,
+                Some(src)
+        )));
+        ctx.code.push(Inst::PopArg(MemRef::Arg(0)));
+        ctx.code.push(Inst::SetArgSize(1));
+        ctx.code.push(Inst::PushArg(MemRef::Arg(0)));
+        ctx.code.push(Inst::PopFrame);
+
+        for init in inits.iter() {
+            self.compile_expr(env, ctx, false, init)?;
+        }
+
+        if tail_ctx {
+            ctx.code.push(Inst::TailCall);
+        } else {
+            ctx.code.push(Inst::Call(inits.len()));
+        }
+        Ok(())
+    }
+
     fn compile_let_star<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
@@ -552,6 +1734,7 @@ impl Compiler {
         for (i, binding) in bindings.iter().enumerate() {
             new_env.args = syms[0..i].to_vec();
             self.compile_expr(&new_env, ctx, false, &binding.expr)?;
+            ctx.code.push(Inst::CheckSingleValue);
         }
 
         ctx.code.push(Inst::SetArgSize(syms.len()));
@@ -564,6 +1747,78 @@ impl Compiler {
         Ok(())
     }
 
+    /// Parses the bindings of `let-values` or `let*-values` into formals and init expressions
+    fn get_values_form<T: Clone+Debug>(&self, form: &Datum<T>)
+            -> Result<(Vec<(Vec<Cow<'static, str>>, bool, Datum<T>)>, Datum<T>), CompileError>
+    {
+        if let &Datum::Cons(ref ptr) = form {
+            let (ref binding_form, ref body) = ptr.get();
+            let mut bindings = Vec::new();
+            for binding in to_list(binding_form)? {
+                if let &[ref formals, ref expr] = to_list(&binding)?.as_slice() {
+                    let (vars, rest) = self.parse_formals(formals)?;
+                    bindings.push((vars, rest, expr.clone()));
+                } else {
+                    return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+                }
+            }
+            Ok((bindings, body.clone()))
+        } else {
+            Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    fn compile_let_values<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (bindings, body) = self.get_values_form(tail)?;
+        let mut syms = Vec::new();
+
+        for (vars, rest, expr) in bindings.into_iter() {
+            self.compile_expr(env, ctx, false, &expr)?;
+            ctx.code.push(Inst::ReceiveValues(vars.len() - rest as usize, rest));
+            syms.extend(vars);
+        }
+        ctx.code.push(Inst::PushFrame(syms.len()));
+
+        let new_env = env.update_arg(syms);
+
+        self.compile_body(&new_env, ctx, &body)?;
+
+        ctx.code.push(Inst::PopFrame);
+
+        Ok(())
+    }
+
+    fn compile_let_star_values<T>(&self,
+                                  env: &LexicalContext,
+                                  ctx: &mut CodeGenContext,
+                                  tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (bindings, body) = self.get_values_form(tail)?;
+
+        ctx.code.push(Inst::PushFrame(0));
+
+        let mut new_env = env.update_arg(Vec::new());
+
+        for (vars, rest, expr) in bindings.into_iter() {
+            self.compile_expr(&new_env, ctx, false, &expr)?;
+            ctx.code.push(Inst::ReceiveValues(vars.len() - rest as usize, rest));
+            new_env.args.extend(vars);
+        }
+
+        ctx.code.push(Inst::SetArgSize(new_env.args.len()));
+
+        self.compile_body(&new_env, ctx, &body)?;
+
+        ctx.code.push(Inst::PopFrame);
+
+        Ok(())
+    }
+
     fn compile_letrec<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
@@ -593,12 +1848,86 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compiles `(do ((<variable> <init> <step>) ...) (<test> <expression> ...) <command> ...)`
+    /// into a loop, which binds the variables afresh in each iteration
+    fn compile_do<T>(&self,
+                     env: &LexicalContext,
+                     ctx: &mut CodeGenContext,
+                     tail_ctx: bool,
+                     tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let form = to_list(tail)?;
+        if form.len() < 2 {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+
+        let mut vars = Vec::new();
+        let mut inits = Vec::new();
+        let mut steps = Vec::new();
+        for spec in to_list(&form[0])? {
+            match to_list(&spec)?.as_slice() {
+                &[Datum::Sym(ref var), ref init] => {
+                    steps.push(Datum::Sym(var.clone()));
+                    vars.push(var.clone());
+                    inits.push(init.clone());
+                },
+                &[Datum::Sym(ref var), ref init, ref step] => {
+                    steps.push(step.clone());
+                    vars.push(var.clone());
+                    inits.push(init.clone());
+                },
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            }
+        }
+
+        let exit = to_list(&form[1])?;
+        if exit.is_empty() {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+        let commands = &form[2..];
+        let src = cons(Datum::Sym(Cow::Borrowed("do")), tail.try_conv()?);
+
+        // the procedure of the loop is bound to a name no identifier can refer to
+        let name = Cow::Borrowed("#do");
+        self.compile_loop(env, ctx, tail_ctx, name.clone(), vars, &inits, src, |proc_env, proc_ctx| {
+            self.compile_expr(proc_env, proc_ctx, false, &exit[0])?;
+
+            let cond_jump_pc = proc_ctx.code.len();
+            // placeholder to replace with JumpIfFalse
+            proc_ctx.code.push(Inst::Nop);
+            proc_ctx.code.push(Inst::DropArg(1));
+            if exit.len() == 1 {
+                proc_ctx.code.push(Inst::PushArg(MemRef::Undefined));
+            } else {
+                self.compile_sequence(proc_env, proc_ctx, true, &exit[1..])?;
+            }
+            proc_ctx.code.push(Inst::Return);
+
+            proc_ctx.code[cond_jump_pc] = Inst::JumpIfFalse(proc_ctx.code.len());
+            proc_ctx.code.push(Inst::DropArg(1));
+            for command in commands.iter() {
+                self.compile_expr(proc_env, proc_ctx, false, command)?;
+                proc_ctx.code.push(Inst::DropArg(1));
+            }
+
+            let ptr = self.compile_ref(proc_env, proc_ctx, &name)?;
+            proc_ctx.code.push(Inst::PushArg(ptr));
+            for step in steps.iter() {
+                self.compile_expr(proc_env, proc_ctx, false, step)?;
+            }
+            proc_ctx.code.push(Inst::TailCall);
+            Ok(())
+        })
+    }
+
     fn compile_lambda<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
         if let &Datum::Cons(ref ptr) = tail {
-            let (ref cur_args, ref body) = *ptr.as_ref();
+            let (ref cur_args, ref body) = ptr.get();
             let res: Result<Vec<Datum<T>>, ()> = body.iter().collect();
             let expr = cons(Datum::Sym(Cow::Borrowed("lambda")), tail.try_conv()?);
             match res {
@@ -621,39 +1950,155 @@ impl Compiler {
         }
     }
 
-    fn compile_proc<T>(&self, env: &LexicalContext, formals: &Datum<T>, body: &[Datum<T>])
-            -> Result<CodeGenContext, CompileError>
+    /// Compiles `(case-lambda (<formals> <body>) ...)` into the closure dispatching on the number
+    /// of the arguments
+    fn compile_case_lambda<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
-        let (new_args, var_arg) = {
-            let mut nargs = Vec::new();
-            let mut var_arg = false;
-            let mut iter = formals.clone();
-
-            loop {
-                let (val, next) = match iter {
-                    Datum::Cons(ref ptr) => ptr.as_ref().clone(),
-                    Datum::Sym(ref s) => {
-                        nargs.push(s.clone());
-                        var_arg = true;
-                        break;
-                    },
-                    Datum::Nil => {
-                        break;
-                    }
-                    _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
-                };
-                match val {
-                    Datum::Sym(ref s) => {
-                        nargs.push(s.clone())
-                    },
-                    _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        let mut clauses = Vec::new();
+        for clause in to_list(tail)? {
+            let (formals, body) = match clause {
+                Datum::Cons(ref pair) => pair.get(),
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            };
+            let (vars, rest) = self.parse_formals(&formals)?;
+            let proc_ctx = self.compile_proc(env, &formals, &to_exprs(&body)?)?;
+            clauses.push(CaseClause {
+                nargs: vars.len() - rest as usize,
+                rest: rest,
+                code: Rc::new(proc_ctx.code)
+            });
+        }
+
+        let src = cons(Datum::Sym(Cow::Borrowed("case-lambda")), tail.try_conv()?);
+        ctx.code.push(Inst::PushArg(MemRef::CaseLambda(Rc::new(clauses), Some(src))));
+        Ok(())
+    }
+
+    /// Compiles `(delay <expr>)` or `(delay-force <expr>)` into the promise computed by the thunk
+    /// of the expression. The expression of `delay-force` returns a promise, and is evaluated in
+    /// tail position, while the thunk of `delay` makes the forced promise of the value
+    fn compile_delay<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>,
+                        force: bool)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let expr = match to_list(tail)?.as_slice() {
+            &[ref expr] => expr.clone(),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        let mut thunk_ctx = CodeGenContext {
+            code: Vec::new(),
+            link_size: 0
+        };
+        self.compile_expr(&env.update_arg(Vec::new()), &mut thunk_ctx, force, &expr)?;
+        if !force {
+            thunk_ctx.code.push(Inst::MakePromise(true));
+        }
+        thunk_ctx.code.push(Inst::Return);
+
+        let keyword = if force { "delay-force" } else { "delay" };
+        let src = cons(Datum::Sym(Cow::Borrowed(keyword)), tail.try_conv()?);
+        ctx.code.push(Inst::PushArg(MemRef::Closure(Rc::new(thunk_ctx.code), thunk_ctx.link_size, Some(src))));
+        ctx.code.push(Inst::MakePromise(false));
+        Ok(())
+    }
+
+    /// Compiles `(parameterize ((<parameter> <value>) ...) <body>)`. Each parameter and its
+    /// converted value are kept in the frame of the body, and the thunk swapping them with the
+    /// values of the parameters is the before and after thunk of the dynamic extent of the body.
+    /// The values are restored however the body is left, as when an error escapes the runtime
+    fn compile_parameterize<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (bindings, body) = match tail {
+            &Datum::Cons(ref pair) => pair.get(),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        let mut nparams = 0;
+        for binding in to_list(&bindings)? {
+            match to_list(&binding)?.as_slice() {
+                &[ref param, ref value] => {
+                    self.compile_expr(env, ctx, false, param)?;
+                    self.compile_expr(env, ctx, false, value)?;
+                },
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            }
+            nparams += 1;
+        }
+        ctx.code.push(Inst::PushFrame(2 * nparams));
+
+        for i in 0 .. nparams {
+            ctx.code.push(Inst::PushArg(MemRef::Arg(2*i)));
+            let converted_pc = ctx.code.len() + 4;
+            ctx.code.push(Inst::ParameterConverter(converted_pc));
+            ctx.code.push(Inst::PushArg(MemRef::Arg(2*i + 1)));
+            ctx.code.push(Inst::Call(1));
+            ctx.code.push(Inst::PopArg(MemRef::Arg(2*i + 1)));
+        }
+
+        let swap = MemRef::Closure(Rc::new(swap_parameters_code(nparams)), 1, None);
+        ctx.code.push(Inst::PushArg(swap.clone()));
+        ctx.code.push(Inst::Call(0));
+        ctx.code.push(Inst::DropArg(1));
+        ctx.code.push(Inst::PushArg(swap.clone()));
+        ctx.code.push(Inst::PushArg(swap.clone()));
+        ctx.code.push(Inst::PushWinder);
+
+        let vars = vec![Cow::Borrowed("#parameterize"); 2 * nparams];
+        self.compile_body(&env.update_arg(vars), ctx, &body)?;
+
+        ctx.code.push(Inst::PopWinder);
+        ctx.code.push(Inst::PushArg(swap));
+        ctx.code.push(Inst::Call(0));
+        ctx.code.push(Inst::DropArg(1));
+        ctx.code.push(Inst::PopFrame);
+        Ok(())
+    }
+
+    /// Parses the formals of `lambda` or `let-values`. The flag tells the last variable takes the
+    /// rest of the arguments
+    fn parse_formals<T: Clone>(&self, formals: &Datum<T>)
+            -> Result<(Vec<Cow<'static, str>>, bool), CompileError>
+    {
+        let mut nargs = Vec::new();
+        let mut var_arg = false;
+        let mut iter = formals.clone();
+
+        loop {
+            let (val, next) = match iter {
+                Datum::Cons(ref ptr) => ptr.get(),
+                Datum::Sym(ref s) => {
+                    nargs.push(s.clone());
+                    var_arg = true;
+                    break;
+                },
+                Datum::Nil => {
+                    break;
                 }
-                iter = next;
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            };
+            match val {
+                Datum::Sym(ref s) => {
+                    nargs.push(s.clone())
+                },
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
             }
+            iter = next;
+        }
 
-            (nargs, var_arg)
-        };
+        Ok((nargs, var_arg))
+    }
+
+    fn compile_proc<T>(&self, env: &LexicalContext, formals: &Datum<T>, body: &[Datum<T>])
+            -> Result<CodeGenContext, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (new_args, var_arg) = self.parse_formals(formals)?;
 
         let mut ctx = CodeGenContext {
             code: Vec::new(),
@@ -678,49 +2123,27 @@ impl Compiler {
             -> Result<MemRef, CompileError>
     {
         let ptr = self.find_var(env, sym);
-        match ptr {
-            Ok(MemRef::UpValue(i, _)) => {
-                if ctx.link_size < i+1 {
-                    ctx.link_size = i+1;
-                }
-            },
-            _ => ()
-        };
+        if let Ok(ref ptr) = ptr {
+            self.link_upvalue(ctx, ptr);
+        }
         return ptr;
     }
 
-    fn find_var(&self, env: &LexicalContext, sym: &Cow<'static, str>)
-            -> Result<MemRef, CompileError>
-    {
-        if let Some(i) = (0..env.args.len()).find(|&i| env.args[i] == *sym) {
-            return Ok(MemRef::Arg(i));
-        }
-
-        // (0, static_scope[-1]), (1, static_scope[-2]), (2, static_scope[-3]), ...
-        for (i, up_args) in env.static_scope.iter().rev().enumerate() {
-            for (j, arg) in up_args.iter().enumerate() {
-                if *arg == *sym {
-                    return Ok(MemRef::UpValue(i, j));
-                }
+    fn link_upvalue(&self, ctx: &mut CodeGenContext, ptr: &MemRef) {
+        if let &MemRef::UpValue(i, _) = ptr {
+            if ctx.link_size < i+1 {
+                ctx.link_size = i+1;
             }
         }
+    }
 
-        match env.global_env.get(sym) {
-            Some(data) => match data.borrow().deref() {
-                &Datum::Ext(RuntimeData::PrimFunc(ref fptr)) =>
-                    Ok(MemRef::PrimFunc(fptr.clone())),
-                _ =>
-                    Ok(MemRef::Global(data.clone()))
-            },
-            None => {
-                if let Some(syntax) = env.syntax_env.get(sym) {
-                    Err(CompileError { kind: CompileErrorKind::SyntaxReference(Syntax::Macro(syntax.clone())) })
-                } else if let Some(syntax) = self.syntax_env.get(sym) {
-                    Err(CompileError { kind: CompileErrorKind::SyntaxReference(Syntax::Primitive(syntax.clone())) })
-                } else {
-                    Err(CompileError { kind: CompileErrorKind::UnboundVariable(sym.clone()) })
-                }
-            }
+    fn find_var(&self, env: &LexicalContext, sym: &Cow<'static, str>)
+            -> Result<MemRef, CompileError>
+    {
+        match env.lookup(sym)? {
+            Resolved::Var(ptr) | Resolved::Imported(ptr) => Ok(ptr),
+            Resolved::Syntax(syntax, _) =>
+                Err(CompileError { kind: CompileErrorKind::SyntaxReference(syntax) })
         }
     }
 
@@ -730,6 +2153,9 @@ impl Compiler {
     {
         let assignment = to_list(formal)?;
         if let &[Datum::Sym(ref sym), ref expr] = assignment.as_slice() {
+            if let Resolved::Imported(_) = env.lookup(sym)? {
+                return Err(CompileError { kind: CompileErrorKind::ImmutableVariable(sym.clone()) });
+            }
             self.compile_expr(env, ctx, false, expr)?;
             let ptr = self.compile_ref(env, ctx, sym)?;
             ctx.code.push(Inst::PopArg(ptr));
@@ -763,8 +2189,8 @@ impl Compiler {
                 ctx.code.push(
                     Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("cons", &PRIM_CONS)))
                 );
-                self.rec_quote(ctx, &pair.0)?;
-                self.rec_quote(ctx, &pair.1)?;
+                self.rec_quote(ctx, &pair.car())?;
+                self.rec_quote(ctx, &pair.cdr())?;
                 ctx.code.push(Inst::Call(2));
             },
             &Datum::Vector(ref v) => {
@@ -776,7 +2202,7 @@ impl Compiler {
                 }
                 ctx.code.push(Inst::Call(v.len()));
             },
-            _ => match SimpleDatum::from_datum(v.clone()) {
+            _ => match SimpleDatum::from_datum(strip_aliases(v)) {
                 Some(c) => {
                     ctx.code.push(Inst::PushArg(MemRef::Const(c)));
                 },
@@ -814,7 +2240,7 @@ impl Compiler {
         };
 
         if let &[Datum::Sym(ref ptr), ref arg] = list.as_slice() {
-            match ptr.as_ref() {
+            match base_name(ptr) {
                 "quasiquote" => Some((PrimitiveSyntax::Quasiquote, arg.clone())),
                 "unquote" => Some((PrimitiveSyntax::Unquote, arg.clone())),
                 "unquote-splicing" => Some((PrimitiveSyntax::UnquoteSplicing, arg.clone())),
@@ -834,34 +2260,19 @@ impl Compiler {
         where T: Clone + Debug + TryConv<(), CompileError>
     {
         match self.get_syntax1(v) {
-            Some((PrimitiveSyntax::Quasiquote, arg)) => {
-                ctx.code.push(
-                    Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("list", &PRIM_LIST)))
-                );
-                ctx.code.push(
-                    Inst::PushArg(MemRef::Const(SimpleDatum::Sym(Cow::Borrowed("quasiquote"))))
-                );
-                self.rec_quasiquote(qq_level+1, env, ctx, &arg)?;
-                ctx.code.push(Inst::Call(2));
-            },
+            Some((PrimitiveSyntax::Quasiquote, _)) =>
+                self.rec_quasiquote_nested("quasiquote", qq_level+1, env, ctx, v)?,
             Some((PrimitiveSyntax::Unquote, arg)) => {
                 if qq_level == 0 {
                     self.compile_expr(env, ctx, false, &arg)?;
                 } else {
-                    ctx.code.push(
-                        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("list", &PRIM_LIST)))
-                    );
-                    ctx.code.push(
-                        Inst::PushArg(MemRef::Const(SimpleDatum::Sym(Cow::Borrowed("unquote"))))
-                    );
-                    self.rec_quasiquote(qq_level-1, env, ctx, &arg)?;
-                    ctx.code.push(Inst::Call(2));
+                    self.rec_quasiquote_nested("unquote", qq_level-1, env, ctx, v)?;
                 }
             },
             _ => {
                 match v {
                     &Datum::Cons(ref pair) => {
-                        if let Some((PrimitiveSyntax::UnquoteSplicing, arg)) = self.get_syntax1(&pair.0) {
+                        if let Some((PrimitiveSyntax::UnquoteSplicing, arg)) = self.get_syntax1(&pair.car()) {
                             if qq_level == 0 {
                                 ctx.code.push(Inst::PushArg(
                                     MemRef::PrimFunc(PrimFuncPtr::new("append", &PRIM_APPEND))
@@ -871,28 +2282,28 @@ impl Compiler {
                                 ctx.code.push(Inst::PushArg(
                                     MemRef::PrimFunc(PrimFuncPtr::new("cons", &PRIM_CONS))
                                 ));
-                                self.rec_quasiquote(qq_level-1, env, ctx, &pair.0)?;
+                                self.rec_quasiquote(qq_level-1, env, ctx, &pair.car())?;
                             }
                         } else {
                             ctx.code.push(Inst::PushArg(
                                 MemRef::PrimFunc(PrimFuncPtr::new("cons", &PRIM_CONS))
                             ));
-                            self.rec_quasiquote(qq_level, env, ctx, &pair.0)?;
+                            self.rec_quasiquote(qq_level, env, ctx, &pair.car())?;
                         }
 
-                        self.rec_quasiquote(qq_level, env, ctx, &pair.1)?;
+                        self.rec_quasiquote(qq_level, env, ctx, &pair.cdr())?;
                         ctx.code.push(Inst::Call(2));
                     },
                     &Datum::Vector(ref v) => {
+                        // the elements are built as a list, where `unquote-splicing` can splice
                         ctx.code.push(Inst::PushArg(
-                            MemRef::PrimFunc(PrimFuncPtr::new("vector", &PRIM_VECTOR))
+                            MemRef::PrimFunc(PrimFuncPtr::new("list->vector", &PRIM_LIST_TO_VECTOR))
                         ));
-                        for e in v.iter() {
-                            self.rec_quasiquote(qq_level, env, ctx, e)?;
-                        }
-                        ctx.code.push(Inst::Call(v.len()));
+                        let elems: Datum<T> = v.iter().cloned().collect();
+                        self.rec_quasiquote(qq_level, env, ctx, &elems)?;
+                        ctx.code.push(Inst::Call(1));
                     },
-                    _ => match SimpleDatum::from_datum(v.clone()) {
+                    _ => match SimpleDatum::from_datum(strip_aliases(v)) {
                         Some(c) => {
                             ctx.code.push(Inst::PushArg(MemRef::Const(c)));
                         },
@@ -905,23 +2316,55 @@ impl Compiler {
         Ok(())
     }
 
+    /// Quasiquotes the nested `quasiquote` or `unquote` form at the level of its operands. The
+    /// operands are built as a list, so that `unquote-splicing` can splice into them
+    fn rec_quasiquote_nested<T>(&self,
+                                keyword: &'static str,
+                                qq_level: usize,
+                                env: &LexicalContext,
+                                ctx: &mut CodeGenContext,
+                                v: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let operands = match v {
+            &Datum::Cons(ref pair) => pair.cdr(),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        ctx.code.push(Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("cons", &PRIM_CONS))));
+        ctx.code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Sym(Cow::Borrowed(keyword)))));
+        self.rec_quasiquote(qq_level, env, ctx, &operands)?;
+        ctx.code.push(Inst::Call(2));
+        Ok(())
+    }
+
     fn is_sym<T>(&self, datum: &Datum<T>, sym: &str) -> bool {
         if let &Datum::Sym(ref s) = datum {
-            s.as_ref() == sym
+            base_name(s) == sym
         } else {
             false
         }
     }
 
-    fn get_else_clause<T: Clone+Debug>(&self, clauses: &mut Vec<Datum<T>>)
+    /// Returns true if the datum is the auxiliary keyword of the name, such as `=>` in `cond`
+    /// clauses. As with `free-identifier=?`, a binding of the identifier shadows the keyword
+    fn is_aux_keyword<T>(&self, env: &LexicalContext, datum: &Datum<T>, name: &str) -> bool {
+        match datum {
+            &Datum::Sym(ref s) => base_name(s) == name && env.lookup(s).is_err(),
+            _ => false
+        }
+    }
+
+    fn get_else_clause<T: Clone+Debug>(&self, env: &LexicalContext, clauses: &mut Vec<Datum<T>>)
             -> Result<Option<Vec<Datum<T>>>, CompileError>
     {
         let else_exprs = match clauses.last() {
             None => return Err(CompileError { kind: CompileErrorKind::BadSyntax }),
             Some(last_clause) => match last_clause {
                 &Datum::Cons(ref pair) =>  {
-                    if self.is_sym(&pair.0, "else") {
-                        let exprs = to_list(&pair.1)?;
+                    if self.is_aux_keyword(env, &pair.car(), "else") {
+                        let exprs = to_list(&pair.cdr())?;
                         Some(exprs)
                     } else {
                         None
@@ -945,16 +2388,43 @@ impl Compiler {
                        preds: &Datum<T>)
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let mut clauses = to_list(preds)?;
+
+        let else_exprs = self.get_else_clause(env, &mut clauses)?;
+
+        let placeholders = self.compile_cond_clauses(env, ctx, tail_ctx, &clauses)?;
+
+        if let Some(exprs) = else_exprs {
+            self.compile_exprs(env, ctx, tail_ctx, &exprs)?;
+        } else {
+            ctx.code.push(Inst::PushArg(MemRef::Undefined));
+        }
+
+        let pos = ctx.code.len();
+        for inst in placeholders.into_iter() {
+            ctx.code[inst] = Inst::Jump(pos);
+        }
+
+        Ok(())
+    }
+
+    /// Compiles `cond` clauses, except for the `else` clause. The code falls through when no
+    /// clause matches. Returns the placeholders of jumps to the end of the whole expression.
+    fn compile_cond_clauses<T>(&self,
+                               env: &LexicalContext,
+                               ctx: &mut CodeGenContext,
+                               tail_ctx: bool,
+                               clauses: &[Datum<T>])
+            -> Result<Vec<usize>, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
     {
         let mut placeholders = Vec::new();
-        let mut clauses = to_list(preds)?;
-
-        let else_exprs = self.get_else_clause(&mut clauses)?;
 
-        for clause in &clauses {
+        for clause in clauses {
             let terms = to_list(&clause)?;
 
-            if terms.len() < 2 {
+            if terms.is_empty() {
                 return Err(CompileError { kind: CompileErrorKind::BadSyntax });
             }
 
@@ -964,18 +2434,10 @@ impl Compiler {
             let jump_inst = ctx.code.len();
             ctx.code.push(Inst::Nop);
 
-            if self.is_sym(&terms[1], "=>") {
-                if terms.len() != 3 {
-                    return Err(CompileError { kind: CompileErrorKind::BadSyntax });
-                }
-
-                self.compile_expr(env, ctx, false, &terms[2])?;
-                ctx.code.push(Inst::SwapArg);
-                if tail_ctx {
-                    ctx.code.push(Inst::TailCall);
-                } else {
-                    ctx.code.push(Inst::Call(1));
-                }
+            if terms.len() == 1 {
+                // the value of the test is the value of the clause
+            } else if self.is_aux_keyword(env, &terms[1], "=>") {
+                self.compile_receiver(env, ctx, tail_ctx, &terms[1..])?;
             } else {
                 ctx.code.push(Inst::DropArg(1));
                 self.compile_exprs(env, ctx, tail_ctx, &terms[1..])?;
@@ -992,14 +2454,132 @@ impl Compiler {
             ctx.code.push(Inst::DropArg(1));
         }
 
+        Ok(placeholders)
+    }
+
+    /// Compiles `=> <receiver>` of `cond` or `case`, calling the receiver with the value on top of
+    /// the stack
+    fn compile_receiver<T>(&self,
+                           env: &LexicalContext,
+                           ctx: &mut CodeGenContext,
+                           tail_ctx: bool,
+                           terms: &[Datum<T>])
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        if terms.len() != 2 {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+
+        self.compile_expr(env, ctx, false, &terms[1])?;
+        ctx.code.push(Inst::SwapArg);
+        if tail_ctx {
+            ctx.code.push(Inst::TailCall);
+        } else {
+            ctx.code.push(Inst::Call(1));
+        }
+        Ok(())
+    }
+
+    /// Compiles `(guard (var clause ...) body ...)`. The body runs with an exception handler which
+    /// escapes to the continuation of the guard expression with the raised object, then the
+    /// clauses are called with it as a procedure taking `var`, in tail position if the guard is.
+    /// When no clause matches, the object is re-raised by `raise-continuable`.
+    fn compile_guard<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail_ctx: bool,
+                        tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (spec, body) = match tail {
+            &Datum::Cons(ref ptr) => ptr.get(),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        let (var, clauses) = match spec {
+            Datum::Cons(ref ptr) => match ptr.car() {
+                Datum::Sym(ref s) => (s.clone(), to_list(&ptr.cdr())?),
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            },
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        let mut clauses = clauses;
+        let else_exprs = if clauses.is_empty() {
+            None
+        } else {
+            self.get_else_clause(&env.update_arg(vec![var.clone()]), &mut clauses)?
+        };
+        // without an else clause, the condition may be raised again in the continuation of the
+        // raise, which is passed to the clauses after the condition
+        let reraise = else_exprs.is_none();
+
+        let mut clause_ctx = CodeGenContext {
+            code: Vec::new(),
+            link_size: 0
+        };
+        let clause_env = if reraise {
+            env.update_arg(vec![var, Cow::Borrowed("#raise-continuation")])
+        } else {
+            env.update_arg(vec![var])
+        };
+
+        let placeholders = self.compile_cond_clauses(&clause_env, &mut clause_ctx, true, &clauses)?;
+
         if let Some(exprs) = else_exprs {
-            self.compile_exprs(env, ctx, tail_ctx, &exprs)?;
+            self.compile_exprs(&clause_env, &mut clause_ctx, true, &exprs)?;
+        } else {
+            clause_ctx.code.push(Inst::PushArg(MemRef::Arg(1)));
+            clause_ctx.code.push(Inst::PushArg(MemRef::Arg(0)));
+            clause_ctx.code.push(Inst::TailCall);
         }
 
-        let pos = ctx.code.len();
+        let pos = clause_ctx.code.len();
         for inst in placeholders.into_iter() {
-            ctx.code[inst] = Inst::Jump(pos);
+            clause_ctx.code[inst] = Inst::Jump(pos);
         }
+        clause_ctx.code.push(Inst::Return);
+
+        // the clauses are kept under the frame of the body, and called by the handler through the
+        // escape resuming at handler_pos
+        ctx.code.push(Inst::PushArg(MemRef::Closure(Rc::new(clause_ctx.code), clause_ctx.link_size, None)));
+        let handler_inst = ctx.code.len();
+        ctx.code.push(Inst::Nop);
+
+        let body_env = if reraise {
+            ctx.code.push(Inst::PushFrame(2));
+            ctx.code.push(Inst::PushArg(MemRef::Closure(Rc::new(guard_handler_code()), 1, None)));
+            ctx.code.push(Inst::InstallHandler);
+            env.update_arg(vec![Cow::Borrowed("#guard-clauses"), Cow::Borrowed("#guard-escape")])
+        } else {
+            ctx.code.push(Inst::InstallHandler);
+            ctx.code.push(Inst::PushFrame(1));
+            env.update_arg(vec![Cow::Borrowed("#guard-clauses")])
+        };
+        self.compile_body(&body_env, ctx, &body)?;
+        ctx.code.push(Inst::PopFrame);
+        ctx.code.push(Inst::UninstallHandler);
+
+        // placeholder for Jump: this jumps to the end of guard expr
+        let end_inst = ctx.code.len();
+        ctx.code.push(Inst::Nop);
+
+        let handler_pos = ctx.code.len();
+        ctx.code[handler_inst] = Inst::PushEscape(handler_pos);
+
+        let nargs = if reraise {
+            ctx.code.push(Inst::SpreadValues);
+            2
+        } else {
+            1
+        };
+        if tail_ctx {
+            ctx.code.push(Inst::TailCall);
+        } else {
+            ctx.code.push(Inst::Call(nargs));
+        }
+
+        let end_pos = ctx.code.len();
+        ctx.code[end_inst] = Inst::Jump(end_pos);
 
         Ok(())
     }
@@ -1022,8 +2602,9 @@ impl Compiler {
         let expr = clauses.remove(0);
 
         self.compile_expr(env, ctx, false, &expr)?;
+        ctx.code.push(Inst::CheckSingleValue);
 
-        let else_exprs = self.get_else_clause(&mut clauses)?;
+        let else_exprs = self.get_else_clause(env, &mut clauses)?;
 
         for clause in &clauses {
             let mut case_placeholders = Vec::new();
@@ -1058,9 +2639,14 @@ impl Compiler {
                 ctx.code[pos] = Inst::JumpIfNotFalse(match_case);
             }
 
-            ctx.code.push(Inst::DropArg(3));
-
-            self.compile_exprs(env, ctx, tail_ctx, &terms[1..])?;
+            if self.is_aux_keyword(env, &terms[1], "=>") {
+                // the key is passed to the receiver
+                ctx.code.push(Inst::DropArg(2));
+                self.compile_receiver(env, ctx, tail_ctx, &terms[1..])?;
+            } else {
+                ctx.code.push(Inst::DropArg(3));
+                self.compile_exprs(env, ctx, tail_ctx, &terms[1..])?;
+            }
 
             // placeholder for Jump: to the end of case expression
             placeholders.push(ctx.code.len());
@@ -1071,8 +2657,17 @@ impl Compiler {
             ctx.code[no_match_jump_pos] = Inst::Jump(next_case);
         }
 
-        if let Some(exprs) = else_exprs {
-            self.compile_exprs(env, ctx, tail_ctx, &exprs)?;
+        match else_exprs {
+            Some(ref exprs) if exprs.first().map_or(false, |e| self.is_aux_keyword(env, e, "=>")) =>
+                self.compile_receiver(env, ctx, tail_ctx, exprs)?,
+            Some(exprs) => {
+                ctx.code.push(Inst::DropArg(1));
+                self.compile_exprs(env, ctx, tail_ctx, &exprs)?;
+            },
+            None => {
+                ctx.code.push(Inst::DropArg(1));
+                ctx.code.push(Inst::PushArg(MemRef::Undefined));
+            }
         }
 
         let pos = ctx.code.len();
@@ -1151,24 +2746,86 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compiles `let-syntax`, or `letrec-syntax` if `recursive` is set
     fn compile_let_syntax<T>(&self,
                              env: &LexicalContext,
                              ctx: &mut CodeGenContext,
                              tail_ctx: bool,
-                             datum: &Datum<T>)
+                             datum: &Datum<T>,
+                             recursive: bool)
             -> Result<(), CompileError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
         let (bindings, body) = self.get_form(datum)?;
+        let keywords = bindings.into_iter().map(Keyword::Transformer).collect();
+        let new_env = self.bind_keywords(env, keywords, recursive)?;
+
+        let exprs = to_exprs(&body)?;
+        self.compile_exprs(&new_env, ctx, tail_ctx, &exprs)
+    }
+
+    /// Binds the keywords to their transformers. The transformers of the recursive bindings are
+    /// in the scope of all the keywords, so that they can refer to each other
+    fn bind_keywords<'g, T>(&self, env: &LexicalContext<'g>, keywords: Vec<Keyword<T>>, recursive: bool)
+            -> Result<LexicalContext<'g>, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
         let mut new_env = env.clone();
+        let mut scope = env.macro_scope();
+        if recursive {
+            scope.keywords += keywords.len();
+        }
+        let scope = Rc::new(scope);
 
-        for binding in bindings {
-            let syntax_rules = self.compile_syntax_rules(env, &binding.expr)?;
-            new_env.syntax_env = new_env.syntax_env.insert(binding.sym, Rc::new(syntax_rules));
+        for keyword in keywords {
+            let (sym, syntax) = self.compile_keyword(env, keyword)?;
+            new_env.syntax_env.push((sym, syntax, scope.clone()));
         }
 
-        let exprs = to_exprs(&body)?;
-        self.compile_exprs(&new_env, ctx, tail_ctx, &exprs)
+        Ok(new_env)
+    }
+
+    /// Compiles the transformer the keyword is bound to
+    fn compile_keyword<T>(&self, env: &LexicalContext, keyword: Keyword<T>)
+            -> Result<(Cow<'static, str>, Syntax), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        match keyword {
+            Keyword::Transformer(binding) => {
+                let syntax = self.compile_transformer(env, &binding.expr)?;
+                Ok((binding.sym, syntax))
+            },
+            Keyword::Syntax(sym, syntax) => Ok((sym, syntax))
+        }
+    }
+
+    fn fresh_mark(&self) -> usize {
+        let mark = self.next_mark.get();
+        self.next_mark.set(mark + 1);
+        mark
+    }
+
+    /// Expands the macro use with a fresh mark, and compiles the expansion in the context where
+    /// the mark refers to the scope of the macro definition
+    fn compile_macro<T>(&self,
+                        env: &LexicalContext,
+                        ctx: &mut CodeGenContext,
+                        tail_ctx: bool,
+                        syntax: &CompiledMacro,
+                        scope: Rc<MacroScope>,
+                        datum: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let mark = self.fresh_mark();
+        let mut new_env = env.clone();
+        new_env.marks = new_env.marks.insert(mark, scope);
+        // the literals are the identifiers of the macro definition, as if the expansion introduced
+        // them
+        let expanded = syntax.expand(datum, mark, &|sym: &str, literal: &str|
+            new_env.free_identifier_eq(&Cow::Owned(sym.to_string()), &alias(literal, mark))
+        )?;
+        self.compile_expr(&new_env, ctx, tail_ctx, &expanded)
     }
 
     fn compile_syntax_rules<T>(&self, env: &LexicalContext, datum: &Datum<T>)
@@ -1185,9 +2842,23 @@ impl Compiler {
             return Err(CompileError { kind: CompileErrorKind::BadSyntax });
         }
 
-        let literals = to_list(&rules[1])?;
+        let vars = self.parse_literals(&rules[1])?;
+
+        let mut syntax_rules = Vec::new();
+        for rule in &rules[2..] {
+            let form = to_list(rule)?;
+            if form.len() != 2 {
+                return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+            }
+            syntax_rules.push((form[0].clone(), form[1].clone()));
+        }
+
+        CompiledMacro::compile(&vars, &syntax_rules).map_err(|e| e.into())
+    }
+
+    fn parse_literals<T: Clone>(&self, datum: &Datum<T>) -> Result<Vars, CompileError> {
         let mut vars = HashSet::new();
-        for literal in literals {
+        for literal in to_list(datum)? {
             if let Datum::Sym(sym) = literal {
                 if vars.contains(&sym) {
                     return Err(CompileError { kind: CompileErrorKind::DuplicateVars })
@@ -1198,17 +2869,337 @@ impl Compiler {
                 return Err(CompileError { kind: CompileErrorKind::BadSyntax });
             }
         }
+        Ok(vars)
+    }
 
-        let mut syntax_rules = Vec::new();
-        for rule in &rules[2..] {
-            let form = to_list(rule)?;
-            if form.len() != 2 {
-                return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+    /// Compiles the transformer of a keyword binding. Other than `syntax-rules`, the expression
+    /// evaluates to a procedure, which is called with the macro use to produce the expansion
+    fn compile_transformer<T>(&self, env: &LexicalContext, datum: &Datum<T>)
+            -> Result<Syntax, CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        if let &Datum::Cons(ref pair) = datum {
+            if let Some(PrimitiveSyntax::SyntaxRules) = self.get_syntax_name(env, &pair.car()) {
+                return self.compile_syntax_rules(env, datum).map(|m| Syntax::Macro(Rc::new(m)));
             }
-            syntax_rules.push((form[0].clone(), form[1].clone()));
         }
 
-        CompiledMacro::compile(&vars, &syntax_rules).map_err(|e| e.into())
+        // The transformer runs while the code around it is compiled, so only the global
+        // bindings are visible from it
+        let mut ctx = CodeGenContext {
+            code: Vec::new(),
+            link_size: 0
+        };
+        let transformer_env = LexicalContext::new(env.global_env, env.global_syntax, env.imported);
+        self.compile_expr(&transformer_env, &mut ctx, true, datum)?;
+        ctx.code.push(Inst::Return);
+
+        let transformer = self.with_transformer_vm(|vm| vm.run_code(ctx.code))
+            .map_err(|e| CompileError { kind: CompileErrorKind::TransformerError(e) })?;
+        if DatumType::get_type(&transformer) != DatumType::Callable {
+            return Err(CompileError { kind: CompileErrorKind::NotCallable });
+        }
+        Ok(Syntax::Transformer(transformer))
+    }
+
+    fn with_transformer_vm<F, R>(&self, f: F) -> R
+        where F: FnOnce(&mut Runtime) -> R
+    {
+        let mut vm = self.transformer_vm.borrow_mut();
+        if vm.is_none() {
+            let runtime = match self.program_env {
+                Some(ref env) => Runtime::with_env(env.clone(), HashMap::new()),
+                None => Runtime::new(HashMap::new(), HashMap::new())
+            };
+            *vm = Some(Box::new(runtime));
+        }
+        f(vm.as_mut().unwrap())
+    }
+
+    /// Expands the macro use by calling the transformer procedure. The input is marked with a
+    /// fresh mark, which is toggled again on the output, so that only the identifiers introduced
+    /// by the transformer keep the mark
+    fn compile_transformer_call<T>(&self,
+                                   env: &LexicalContext,
+                                   ctx: &mut CodeGenContext,
+                                   tail_ctx: bool,
+                                   transformer: &RDatum,
+                                   scope: Rc<MacroScope>,
+                                   datum: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let mark = self.fresh_mark();
+        let form: Datum<()> = datum.try_conv()?;
+        let input: RDatum = form.try_conv()?;
+
+        let mut new_env = env.clone();
+        new_env.marks = new_env.marks.insert(mark, scope);
+
+        TRANSFORMER_CALLS.with(|calls| calls.borrow_mut().push(TransformerCall::new(&new_env, mark)));
+        let output = self.with_transformer_vm(|vm| vm.apply(transformer.clone(), vec![toggle_mark(&input, mark)]));
+        TRANSFORMER_CALLS.with(|calls| calls.borrow_mut().pop());
+        let output = output.map_err(|e| CompileError { kind: CompileErrorKind::TransformerError(e) })?;
+        let expanded = toggle_mark(&output, mark);
+
+        self.compile_expr(&new_env, ctx, tail_ctx, &expanded)
+    }
+
+    /// Compiles `(syntax-case <expr> (<literal> ...) <clause> ...)`, where the clause is
+    /// `(<pattern> <output expr>)` or `(<pattern> <fender> <output expr>)`
+    fn compile_syntax_case<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let form = to_list(tail)?;
+        if form.len() < 2 {
+            return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+        }
+
+        let literals = self.parse_literals(&form[1])?;
+        let mut clauses = Vec::new();
+        for clause in form[2..].iter() {
+            let terms = to_list(clause)?;
+            let (fender, expr) = match terms.len() {
+                2 => (None, terms[1].clone()),
+                3 => (Some(terms[1].clone()), terms[2].clone()),
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            };
+            clauses.push((SyntaxPattern::compile(&literals, &terms[0])?, fender, vec![expr]));
+        }
+
+        self.compile_expr(env, ctx, false, &form[0])?;
+        self.compile_syntax_clauses(env, ctx, clauses)
+    }
+
+    /// Matches the syntax on the top of the stack against the patterns of the clauses. The fender
+    /// and the body of each clause run in the frame of its pattern variables
+    fn compile_syntax_clauses<T>(&self,
+                                 env: &LexicalContext,
+                                 ctx: &mut CodeGenContext,
+                                 clauses: Vec<(SyntaxPattern, Option<Datum<T>>, Vec<Datum<T>>)>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let mut placeholders = Vec::new();
+
+        // the frame of the input syntax
+        ctx.code.push(Inst::PushFrame(1));
+        let input_env = env.update_arg(Vec::new());
+
+        for (pattern, fender, body) in clauses.into_iter() {
+            let clause_env = input_env.update_pattern_vars(pattern.vars());
+            let nvars = pattern.vars().len();
+
+            ctx.code.push(Inst::PushArg(MemRef::Arg(0)));
+            ctx.code.push(Inst::MatchSyntax(Rc::new(pattern)));
+
+            // placeholder for JumpIfFalse
+            let no_match_inst = ctx.code.len();
+            ctx.code.push(Inst::Nop);
+            ctx.code.push(Inst::DropArg(1));
+            ctx.code.push(Inst::PushFrame(nvars));
+
+            let fender_inst = match fender {
+                Some(fender) => {
+                    self.compile_expr(&clause_env, ctx, false, &fender)?;
+                    // placeholder for JumpIfFalse
+                    let pc = ctx.code.len();
+                    ctx.code.push(Inst::Nop);
+                    ctx.code.push(Inst::DropArg(1));
+                    Some(pc)
+                },
+                None => None
+            };
+
+            self.compile_exprs(&clause_env, ctx, false, &body)?;
+            ctx.code.push(Inst::PopFrame);
+
+            // placeholder for Jump: this jumps to the end of the syntax-case expr
+            placeholders.push(ctx.code.len());
+            ctx.code.push(Inst::Nop);
+
+            if let Some(pc) = fender_inst {
+                ctx.code[pc] = Inst::JumpIfFalse(ctx.code.len());
+                // leaves the `#f` of the fender like the failed match
+                ctx.code.push(Inst::PopFrame);
+            }
+
+            ctx.code[no_match_inst] = Inst::JumpIfFalse(ctx.code.len());
+            ctx.code.push(Inst::DropArg(1));
+        }
+
+        ctx.code.push(Inst::Throw(RuntimeErrorKind::CompileError, "no pattern matches the syntax"));
+
+        let pos = ctx.code.len();
+        for inst in placeholders.into_iter() {
+            ctx.code[inst] = Inst::Jump(pos);
+        }
+        ctx.code.push(Inst::PopFrame);
+
+        Ok(())
+    }
+
+    /// Compiles `(syntax <template>)`
+    fn compile_syntax<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug
+    {
+        match to_list(tail)?.as_slice() {
+            &[ref template] => self.compile_syntax_template(env, ctx, template),
+            _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    /// Pushes the values of the pattern variables in the template, then builds the syntax from
+    /// them. The identifiers of the template keep their marks
+    fn compile_syntax_template<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, template: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug
+    {
+        let mut ids: Vec<Cow<'static, str>> = identifiers(template).into_iter().collect();
+        ids.sort();
+
+        let mut vars = Vec::new();
+        let mut ptrs = Vec::new();
+        for id in ids.into_iter() {
+            if let Ok(ptr) = self.find_var(env, &id) {
+                if let Some(depth) = env.pattern_var_depth(&ptr) {
+                    vars.push((id, depth));
+                    ptrs.push(ptr);
+                }
+            }
+        }
+
+        let template = SyntaxTemplate::compile(template, &vars)?;
+        for ptr in ptrs.into_iter() {
+            self.link_upvalue(ctx, &ptr);
+            ctx.code.push(Inst::PushArg(ptr));
+        }
+        ctx.code.push(Inst::BuildSyntax(Rc::new(template)));
+
+        Ok(())
+    }
+
+    /// Compiles `(quasisyntax <template>)`. The expressions of `unsyntax` and `unsyntax-splicing`
+    /// are bound to hidden pattern variables, which replace them in the template
+    fn compile_quasisyntax<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let template = match to_list(tail)?.as_slice() {
+            &[ref template] => template.clone(),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        let mut holes = Vec::new();
+        let template = self.replace_unsyntax(0, &template, &mut holes)?;
+        if holes.is_empty() {
+            return self.compile_syntax_template(env, ctx, &template);
+        }
+
+        let mut vars = Vec::new();
+        for (i, (expr, depth)) in holes.into_iter().enumerate() {
+            self.compile_expr(env, ctx, false, &expr)?;
+            vars.push((unsyntax_hole(i), depth));
+        }
+        ctx.code.push(Inst::PushFrame(vars.len()));
+        self.compile_syntax_template(&env.update_pattern_vars(&vars), ctx, &template)?;
+        ctx.code.push(Inst::PopFrame);
+
+        Ok(())
+    }
+
+    /// Replaces `(unsyntax <expr>)` of the level 0 with a hole, and `(unsyntax-splicing <expr>)`
+    /// with a hole followed by an ellipsis. The expressions are collected with the depths of
+    /// their holes
+    fn replace_unsyntax<T>(&self, qs_level: usize, v: &Datum<T>, holes: &mut Vec<(Datum<T>, usize)>)
+            -> Result<Datum<T>, CompileError>
+        where T: Clone + Debug
+    {
+        if let Some((head, arg)) = self.get_unsyntax(v) {
+            let level = match base_name(&head) {
+                "quasisyntax" => qs_level + 1,
+                _ if qs_level == 0 => {
+                    holes.push((arg, 0));
+                    return Ok(Datum::Sym(unsyntax_hole(holes.len() - 1)));
+                },
+                _ => qs_level - 1
+            };
+            let arg = self.replace_unsyntax(level, &arg, holes)?;
+            return Ok(list_of(vec![Datum::Sym(head), arg]));
+        }
+
+        match v {
+            &Datum::Cons(ref pair) => match self.get_unsyntax(&pair.car()) {
+                Some((ref head, ref arg)) if base_name(head) == "unsyntax-splicing" => {
+                    if qs_level == 0 {
+                        holes.push((arg.clone(), 1));
+                        let hole = Datum::Sym(unsyntax_hole(holes.len() - 1));
+                        let rest = self.replace_unsyntax(qs_level, &pair.cdr(), holes)?;
+                        Ok(cons(hole, cons(Datum::Sym(Cow::Borrowed("...")), rest)))
+                    } else {
+                        let arg = self.replace_unsyntax(qs_level - 1, arg, holes)?;
+                        let rest = self.replace_unsyntax(qs_level, &pair.cdr(), holes)?;
+                        Ok(cons(list_of(vec![Datum::Sym(head.clone()), arg]), rest))
+                    }
+                },
+                _ => {
+                    let first = self.replace_unsyntax(qs_level, &pair.car(), holes)?;
+                    let rest = self.replace_unsyntax(qs_level, &pair.cdr(), holes)?;
+                    Ok(cons(first, rest))
+                }
+            },
+            &Datum::Vector(ref vec) => {
+                let list = self.replace_unsyntax(qs_level, &list_of(vec.to_vec()), holes)?;
+                Ok(Datum::Vector(Rc::new(to_list(&list)?)))
+            },
+            _ => Ok(v.clone())
+        }
+    }
+
+    /// Splits `(unsyntax <arg>)`, `(unsyntax-splicing <arg>)` or `(quasisyntax <arg>)` into the
+    /// keyword and the argument
+    fn get_unsyntax<T: Clone>(&self, v: &Datum<T>) -> Option<(Cow<'static, str>, Datum<T>)> {
+        match v.iter().collect::<Result<Vec<Datum<T>>, ()>>() {
+            Ok(list) => match list.as_slice() {
+                &[Datum::Sym(ref head), ref arg] => match base_name(head) {
+                    "unsyntax" | "unsyntax-splicing" | "quasisyntax" => Some((head.clone(), arg.clone())),
+                    _ => None
+                },
+                _ => None
+            },
+            Err(_) => None
+        }
+    }
+
+    /// Compiles `(with-syntax ((<pattern> <expr>) ...) <body>)`, which matches the list of the
+    /// values of the expressions against the list of the patterns
+    fn compile_with_syntax<T>(&self, env: &LexicalContext, ctx: &mut CodeGenContext, tail: &Datum<T>)
+            -> Result<(), CompileError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let (bindings, body) = match tail {
+            &Datum::Cons(ref ptr) => ptr.get(),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        };
+
+        let bindings = to_list(&bindings)?;
+        let mut patterns = Vec::new();
+        ctx.code.push(Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new("list", &PRIM_LIST))));
+        for binding in bindings.iter() {
+            match to_list(binding)?.as_slice() {
+                &[ref pattern, ref expr] => {
+                    patterns.push(pattern.clone());
+                    self.compile_expr(env, ctx, false, expr)?;
+                },
+                _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            }
+        }
+        ctx.code.push(Inst::Call(bindings.len()));
+
+        let pattern = SyntaxPattern::compile(&HashSet::new(), &list_of(patterns))?;
+        self.compile_syntax_clauses(env, ctx, vec![(pattern, None, to_exprs(&body)?)])
     }
 
     fn compile_expr<T>(&self,
@@ -0,0 +1,588 @@
+use std::fmt;
+use std::rc::Rc;
+
+use cast::DatumCast;
+use datum::{string, Datum};
+use error::{RuntimeError, RuntimeErrorKind};
+use primitive::{F1, FoldErr, PrimFunc};
+use runtime::{RDatum, RuntimeData};
+
+/// Standard condition types of R6RS
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ConditionType {
+    /// `&condition`, the root of the hierarchy
+    Condition,
+    /// `&warning`
+    Warning,
+    /// `&serious`
+    Serious,
+    /// `&error`
+    Error,
+    /// `&violation`
+    Violation,
+    /// `&assertion`
+    Assertion,
+    /// `&arity`, a procedure called with a wrong number of arguments
+    Arity,
+    /// `&wrong-type`, an argument of a wrong type
+    WrongType,
+    /// `&out-of-range`, an index or a value out of the valid range
+    OutOfRange,
+    /// `&divide-by-zero`, an exact division by zero
+    DivideByZero,
+    /// `&non-continuable`
+    NonContinuable,
+    /// `&lexical`
+    Lexical,
+    /// `&syntax`, with fields `form` and `subform`
+    Syntax,
+    /// `&undefined`
+    Undefined,
+    /// `&message`, with field `message`
+    Message,
+    /// `&irritants`, with field `irritants`
+    Irritants,
+    /// `&who`, with field `who`
+    Who,
+    /// `&i/o`
+    Io,
+    /// `&i/o-read`
+    IoRead,
+    /// `&i/o-write`
+    IoWrite,
+    /// `&i/o-invalid-position`, with field `position`
+    IoInvalidPosition,
+    /// `&i/o-filename`, with field `filename`
+    IoFilename,
+    /// `&i/o-file-protection`
+    IoFileProtection,
+    /// `&i/o-file-is-read-only`
+    IoFileIsReadOnly,
+    /// `&i/o-file-already-exists`
+    IoFileAlreadyExists,
+    /// `&i/o-file-does-not-exist`
+    IoFileDoesNotExist,
+    /// `&i/o-port`, with field `port`
+    IoPort,
+    /// `&i/o-decoding`
+    IoDecoding,
+    /// `&i/o-encoding`, with field `char` after `port`
+    IoEncoding
+}
+
+impl ConditionType {
+    /// Supertype of this condition type
+    pub fn parent(&self) -> Option<ConditionType> {
+        match *self {
+            ConditionType::Condition => None,
+            ConditionType::Warning | ConditionType::Serious | ConditionType::Message |
+                ConditionType::Irritants | ConditionType::Who => Some(ConditionType::Condition),
+            ConditionType::Error | ConditionType::Violation => Some(ConditionType::Serious),
+            ConditionType::Io => Some(ConditionType::Error),
+            ConditionType::IoRead | ConditionType::IoWrite | ConditionType::IoInvalidPosition |
+                ConditionType::IoFilename | ConditionType::IoPort => Some(ConditionType::Io),
+            ConditionType::IoFileProtection | ConditionType::IoFileAlreadyExists |
+                ConditionType::IoFileDoesNotExist => Some(ConditionType::IoFilename),
+            ConditionType::IoFileIsReadOnly => Some(ConditionType::IoFileProtection),
+            ConditionType::IoDecoding | ConditionType::IoEncoding => Some(ConditionType::IoPort),
+            ConditionType::Assertion | ConditionType::NonContinuable | ConditionType::Lexical |
+                ConditionType::Syntax | ConditionType::Undefined => Some(ConditionType::Violation),
+            ConditionType::Arity | ConditionType::WrongType | ConditionType::OutOfRange |
+                ConditionType::DivideByZero => Some(ConditionType::Assertion)
+        }
+    }
+
+    /// Returns true if `self` is `other` or one of its subtypes
+    pub fn is_subtype_of(&self, other: ConditionType) -> bool {
+        let mut ctype = Some(*self);
+        while let Some(t) = ctype {
+            if t == other {
+                return true;
+            }
+            ctype = t.parent();
+        }
+        false
+    }
+
+    /// Number of fields of the simple condition. A subtype has the fields of its parent first
+    pub fn field_count(&self) -> usize {
+        match *self {
+            ConditionType::Syntax | ConditionType::IoEncoding => 2,
+            ConditionType::Message | ConditionType::Irritants | ConditionType::Who |
+                ConditionType::IoInvalidPosition | ConditionType::IoPort | ConditionType::IoDecoding => 1,
+            ctype if ctype.is_subtype_of(ConditionType::IoFilename) => 1,
+            _ => 0
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ConditionType::Condition => "&condition",
+            ConditionType::Warning => "&warning",
+            ConditionType::Serious => "&serious",
+            ConditionType::Error => "&error",
+            ConditionType::Violation => "&violation",
+            ConditionType::Assertion => "&assertion",
+            ConditionType::Arity => "&arity",
+            ConditionType::WrongType => "&wrong-type",
+            ConditionType::OutOfRange => "&out-of-range",
+            ConditionType::DivideByZero => "&divide-by-zero",
+            ConditionType::NonContinuable => "&non-continuable",
+            ConditionType::Lexical => "&lexical",
+            ConditionType::Syntax => "&syntax",
+            ConditionType::Undefined => "&undefined",
+            ConditionType::Message => "&message",
+            ConditionType::Irritants => "&irritants",
+            ConditionType::Who => "&who",
+            ConditionType::Io => "&i/o",
+            ConditionType::IoRead => "&i/o-read",
+            ConditionType::IoWrite => "&i/o-write",
+            ConditionType::IoInvalidPosition => "&i/o-invalid-position",
+            ConditionType::IoFilename => "&i/o-filename",
+            ConditionType::IoFileProtection => "&i/o-file-protection",
+            ConditionType::IoFileIsReadOnly => "&i/o-file-is-read-only",
+            ConditionType::IoFileAlreadyExists => "&i/o-file-already-exists",
+            ConditionType::IoFileDoesNotExist => "&i/o-file-does-not-exist",
+            ConditionType::IoPort => "&i/o-port",
+            ConditionType::IoDecoding => "&i/o-decoding",
+            ConditionType::IoEncoding => "&i/o-encoding"
+        }
+    }
+}
+
+/// Condition of a single type, with the values of its fields
+#[derive(Debug, PartialEq, Clone)]
+pub struct SimpleCondition {
+    pub ctype: ConditionType,
+    pub fields: Vec<RDatum>
+}
+
+impl SimpleCondition {
+    pub fn new(ctype: ConditionType, fields: Vec<RDatum>) -> SimpleCondition {
+        SimpleCondition { ctype: ctype, fields: fields }
+    }
+}
+
+/// Condition object. A simple condition has exactly one component, and a compound condition has
+/// any number of them
+#[derive(Debug, PartialEq, Clone)]
+pub struct Condition {
+    pub components: Vec<SimpleCondition>,
+    // The error this condition was made from, reported as is when the condition is not handled
+    origin: Option<RuntimeError>
+}
+
+impl Condition {
+    pub fn new(components: Vec<SimpleCondition>) -> Condition {
+        Condition {
+            components: components,
+            origin: None
+        }
+    }
+
+    /// Returns true if any component is of the given type or its subtype
+    pub fn is_a(&self, ctype: ConditionType) -> bool {
+        self.components.iter().any(|c| c.ctype.is_subtype_of(ctype))
+    }
+
+    /// Returns the `idx`th field of the first component of the given type
+    pub fn field(&self, ctype: ConditionType, idx: usize) -> Option<&RDatum> {
+        self.components.iter()
+            .find(|c| c.ctype.is_subtype_of(ctype))
+            .and_then(|c| c.fields.get(idx))
+    }
+
+    /// Adds the procedure and the arguments of the failed call as `&who` and `&irritants`
+    /// components
+    pub fn with_call(mut self, who: Option<RDatum>, irritants: RDatum) -> Condition {
+        if let Some(who) = who {
+            self.components.insert(1, SimpleCondition::new(ConditionType::Who, vec![who]));
+        }
+        self.components.push(SimpleCondition::new(ConditionType::Irritants, vec![irritants]));
+        self
+    }
+
+    /// Converts the condition back into an error, when it was raised but not handled
+    pub fn to_runtime_error(&self) -> RuntimeError {
+        if let Some(ref e) = self.origin {
+            return e.clone();
+        }
+
+        let mut desc = String::new();
+        if let Some(who) = self.field(ConditionType::Who, 0) {
+            desc.push_str(&format!("{}: ", who));
+        }
+        match self.field(ConditionType::Message, 0) {
+            Some(&Datum::String(ref s)) => desc.extend(s.borrow().iter()),
+            Some(msg) => desc.push_str(&format!("{}", msg)),
+            None => desc.push_str(&format!("{}", self))
+        }
+        if let Some(irritants) = self.field(ConditionType::Irritants, 0) {
+            desc.push_str(&format!(" {}", irritants));
+        }
+
+        let kind = if self.is_a(ConditionType::Syntax) {
+            RuntimeErrorKind::SyntaxViolation(self.components.clone())
+        } else {
+            RuntimeErrorKind::Uncaught
+        };
+        RuntimeError {
+            kind: kind,
+            desc: desc
+        }
+    }
+}
+
+impl From<RuntimeError> for Condition {
+    fn from(err: RuntimeError) -> Condition {
+        if let RuntimeErrorKind::SyntaxViolation(ref components) = err.kind {
+            return Condition {
+                components: components.clone(),
+                origin: Some(err.clone())
+            };
+        }
+        let condition = match err.kind {
+            RuntimeErrorKind::IoCondition(ref c) => c.clone(),
+            ref kind => {
+                let ctype = kind.condition_type();
+                SimpleCondition::new(ctype, vec![Datum::Bool(false); ctype.field_count()])
+            }
+        };
+        let message = string(&err.desc);
+
+        Condition {
+            components: vec![
+                condition,
+                SimpleCondition::new(ConditionType::Message, vec![message])
+            ],
+            origin: Some(err)
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<condition")?;
+        for c in self.components.iter() {
+            write!(f, " {}", c.ctype.name())?;
+        }
+        write!(f, ">")
+    }
+}
+
+fn wrap(condition: Condition) -> RDatum {
+    Datum::Ext(RuntimeData::Condition(Rc::new(condition)))
+}
+
+/// `(make-error)`, `(make-message-condition message)` and other simple condition constructors
+pub struct MakeCondition {
+    ctype: ConditionType
+}
+
+impl PrimFunc for MakeCondition {
+    fn call(&self, args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        if args.len() != self.ctype.field_count() {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("Expected {} arguments, received {}", self.ctype.field_count(), args.len())
+            });
+        }
+        Ok(wrap(Condition::new(vec![SimpleCondition::new(self.ctype, args)])))
+    }
+}
+
+/// `(error? obj)` and other condition predicates
+pub struct ConditionPredicate {
+    ctype: ConditionType
+}
+
+impl PrimFunc for ConditionPredicate {
+    fn call(&self, args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        if args.len() != 1 {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("Expected 1 argument, received {:?}", args.len())
+            });
+        }
+        let res = match args[0] {
+            Datum::Ext(RuntimeData::Condition(ref c)) => c.is_a(self.ctype),
+            _ => false
+        };
+        Ok(Datum::Bool(res))
+    }
+}
+
+/// `(condition-message condition)` and other field accessors
+pub struct ConditionAccessor {
+    ctype: ConditionType,
+    idx: usize
+}
+
+impl PrimFunc for ConditionAccessor {
+    fn call(&self, mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        if args.len() != 1 {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("Expected 1 argument, received {:?}", args.len())
+            });
+        }
+        let c: Rc<Condition> = DatumCast::unwrap(args.remove(0))?;
+        match c.field(self.ctype, self.idx) {
+            Some(v) => Ok(v.clone()),
+            None => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected {} condition, but received {}", self.ctype.name(), c)
+            })
+        }
+    }
+}
+
+/// Builds the condition raised by `(error who message irritant ...)` and
+/// `(assertion-violation who message irritant ...)`. The irritants are passed as a list.
+pub struct ErrorCondition {
+    ctype: ConditionType
+}
+
+impl PrimFunc for ErrorCondition {
+    fn call(&self, mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        if args.len() != 3 {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("Expected 3 arguments, received {:?}", args.len())
+            });
+        }
+        let irritants = args.pop().unwrap();
+        let message = args.pop().unwrap();
+        let who = args.pop().unwrap();
+
+        match message {
+            Datum::String(_) => (),
+            _ => return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected String as a message, but received {}", message)
+            })
+        }
+
+        let mut components = vec![SimpleCondition::new(self.ctype, Vec::new())];
+        if who != Datum::Bool(false) {
+            components.push(SimpleCondition::new(ConditionType::Who, vec![who]));
+        }
+        components.push(SimpleCondition::new(ConditionType::Message, vec![message]));
+        components.push(SimpleCondition::new(ConditionType::Irritants, vec![irritants]));
+
+        Ok(wrap(Condition::new(components)))
+    }
+}
+
+fn syntax_violation_condition(mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.len() != 4 {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 4 arguments, received {:?}", args.len())
+        });
+    }
+    let subforms = args.pop().unwrap();
+    let subform = match subforms.iter().collect::<Result<Vec<RDatum>, ()>>() {
+        Ok(ref list) if list.len() <= 1 => list.get(0).cloned().unwrap_or(Datum::Bool(false)),
+        _ => return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected at most 1 subform, received {}", subforms)
+        })
+    };
+    let form = args.pop().unwrap();
+    let message = args.pop().unwrap();
+    let who = args.pop().unwrap();
+
+    match message {
+        Datum::String(_) => (),
+        _ => return Err(RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: format!("expected String as a message, but received {}", message)
+        })
+    }
+
+    let mut components = vec![SimpleCondition::new(ConditionType::Syntax, vec![form, subform])];
+    if who != Datum::Bool(false) {
+        components.push(SimpleCondition::new(ConditionType::Who, vec![who]));
+    }
+    components.push(SimpleCondition::new(ConditionType::Message, vec![message]));
+
+    Ok(wrap(Condition::new(components)))
+}
+
+/// Builds the condition raised by `(syntax-violation who message form [subform])`. The optional
+/// subform is passed as a list
+pub static PRIM_SYNTAX_VIOLATION_CONDITION: FoldErr<RDatum> = FoldErr { fold: syntax_violation_condition };
+
+fn condition(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    let mut components = Vec::new();
+    for arg in args.into_iter() {
+        let c: Rc<Condition> = DatumCast::unwrap(arg)?;
+        components.extend(c.components.iter().cloned());
+    }
+    Ok(wrap(Condition::new(components)))
+}
+
+/// `(condition condition0 ...)`
+pub static PRIM_CONDITION: FoldErr<RDatum> = FoldErr { fold: condition };
+
+fn simple_conditions(c: Rc<Condition>) -> RDatum {
+    c.components.iter().map(|s| wrap(Condition::new(vec![s.clone()]))).collect()
+}
+
+/// `(simple-conditions condition)`
+pub static PRIM_SIMPLE_CONDITIONS: F1<Rc<Condition>, RDatum> = F1 { f1: simple_conditions };
+
+/// `(condition? obj)`
+pub static PRIM_IS_CONDITION: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Condition };
+
+pub static PRIM_MAKE_WARNING: MakeCondition = MakeCondition { ctype: ConditionType::Warning };
+pub static PRIM_IS_WARNING: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Warning };
+pub static PRIM_MAKE_SERIOUS: MakeCondition = MakeCondition { ctype: ConditionType::Serious };
+pub static PRIM_IS_SERIOUS: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Serious };
+pub static PRIM_MAKE_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::Error };
+pub static PRIM_IS_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Error };
+pub static PRIM_MAKE_VIOLATION: MakeCondition = MakeCondition { ctype: ConditionType::Violation };
+pub static PRIM_IS_VIOLATION: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Violation };
+pub static PRIM_MAKE_ASSERTION: MakeCondition = MakeCondition { ctype: ConditionType::Assertion };
+pub static PRIM_IS_ASSERTION: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Assertion };
+pub static PRIM_MAKE_ARITY: MakeCondition = MakeCondition { ctype: ConditionType::Arity };
+pub static PRIM_IS_ARITY: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Arity };
+pub static PRIM_MAKE_WRONG_TYPE: MakeCondition = MakeCondition { ctype: ConditionType::WrongType };
+pub static PRIM_IS_WRONG_TYPE: ConditionPredicate = ConditionPredicate { ctype: ConditionType::WrongType };
+pub static PRIM_MAKE_OUT_OF_RANGE: MakeCondition = MakeCondition { ctype: ConditionType::OutOfRange };
+pub static PRIM_IS_OUT_OF_RANGE: ConditionPredicate = ConditionPredicate { ctype: ConditionType::OutOfRange };
+pub static PRIM_MAKE_DIVIDE_BY_ZERO: MakeCondition = MakeCondition { ctype: ConditionType::DivideByZero };
+pub static PRIM_IS_DIVIDE_BY_ZERO: ConditionPredicate = ConditionPredicate { ctype: ConditionType::DivideByZero };
+pub static PRIM_MAKE_NON_CONTINUABLE: MakeCondition = MakeCondition { ctype: ConditionType::NonContinuable };
+pub static PRIM_IS_NON_CONTINUABLE: ConditionPredicate = ConditionPredicate { ctype: ConditionType::NonContinuable };
+pub static PRIM_MAKE_LEXICAL: MakeCondition = MakeCondition { ctype: ConditionType::Lexical };
+pub static PRIM_IS_LEXICAL: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Lexical };
+pub static PRIM_MAKE_SYNTAX: MakeCondition = MakeCondition { ctype: ConditionType::Syntax };
+pub static PRIM_IS_SYNTAX: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Syntax };
+pub static PRIM_SYNTAX_FORM: ConditionAccessor = ConditionAccessor { ctype: ConditionType::Syntax, idx: 0 };
+pub static PRIM_SYNTAX_SUBFORM: ConditionAccessor = ConditionAccessor { ctype: ConditionType::Syntax, idx: 1 };
+pub static PRIM_MAKE_UNDEFINED: MakeCondition = MakeCondition { ctype: ConditionType::Undefined };
+pub static PRIM_IS_UNDEFINED: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Undefined };
+pub static PRIM_MAKE_MESSAGE: MakeCondition = MakeCondition { ctype: ConditionType::Message };
+pub static PRIM_IS_MESSAGE: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Message };
+pub static PRIM_MESSAGE: ConditionAccessor = ConditionAccessor { ctype: ConditionType::Message, idx: 0 };
+pub static PRIM_MAKE_IRRITANTS: MakeCondition = MakeCondition { ctype: ConditionType::Irritants };
+pub static PRIM_IS_IRRITANTS: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Irritants };
+pub static PRIM_IRRITANTS: ConditionAccessor = ConditionAccessor { ctype: ConditionType::Irritants, idx: 0 };
+pub static PRIM_MAKE_WHO: MakeCondition = MakeCondition { ctype: ConditionType::Who };
+pub static PRIM_IS_WHO: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Who };
+pub static PRIM_WHO: ConditionAccessor = ConditionAccessor { ctype: ConditionType::Who, idx: 0 };
+pub static PRIM_MAKE_IO_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::Io };
+pub static PRIM_IS_IO_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::Io };
+pub static PRIM_MAKE_IO_READ_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoRead };
+pub static PRIM_IS_IO_READ_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoRead };
+pub static PRIM_MAKE_IO_WRITE_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoWrite };
+pub static PRIM_IS_IO_WRITE_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoWrite };
+pub static PRIM_MAKE_IO_INVALID_POSITION_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoInvalidPosition };
+pub static PRIM_IS_IO_INVALID_POSITION_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoInvalidPosition };
+pub static PRIM_IO_ERROR_POSITION: ConditionAccessor = ConditionAccessor { ctype: ConditionType::IoInvalidPosition, idx: 0 };
+pub static PRIM_MAKE_IO_FILENAME_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoFilename };
+pub static PRIM_IS_IO_FILENAME_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoFilename };
+pub static PRIM_IO_ERROR_FILENAME: ConditionAccessor = ConditionAccessor { ctype: ConditionType::IoFilename, idx: 0 };
+pub static PRIM_MAKE_IO_FILE_PROTECTION_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoFileProtection };
+pub static PRIM_IS_IO_FILE_PROTECTION_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoFileProtection };
+pub static PRIM_MAKE_IO_FILE_IS_READ_ONLY_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoFileIsReadOnly };
+pub static PRIM_IS_IO_FILE_IS_READ_ONLY_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoFileIsReadOnly };
+pub static PRIM_MAKE_IO_FILE_ALREADY_EXISTS_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoFileAlreadyExists };
+pub static PRIM_IS_IO_FILE_ALREADY_EXISTS_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoFileAlreadyExists };
+pub static PRIM_MAKE_IO_FILE_DOES_NOT_EXIST_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoFileDoesNotExist };
+pub static PRIM_IS_IO_FILE_DOES_NOT_EXIST_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoFileDoesNotExist };
+pub static PRIM_MAKE_IO_PORT_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoPort };
+pub static PRIM_IS_IO_PORT_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoPort };
+pub static PRIM_IO_ERROR_PORT: ConditionAccessor = ConditionAccessor { ctype: ConditionType::IoPort, idx: 0 };
+pub static PRIM_MAKE_IO_DECODING_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoDecoding };
+pub static PRIM_IS_IO_DECODING_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoDecoding };
+pub static PRIM_MAKE_IO_ENCODING_ERROR: MakeCondition = MakeCondition { ctype: ConditionType::IoEncoding };
+pub static PRIM_IS_IO_ENCODING_ERROR: ConditionPredicate = ConditionPredicate { ctype: ConditionType::IoEncoding };
+pub static PRIM_IO_ENCODING_ERROR_CHAR: ConditionAccessor = ConditionAccessor { ctype: ConditionType::IoEncoding, idx: 1 };
+
+/// Condition of `(error who message irritant ...)`
+pub static PRIM_ERROR_CONDITION: ErrorCondition = ErrorCondition { ctype: ConditionType::Error };
+/// Condition of `(assertion-violation who message irritant ...)`
+pub static PRIM_ASSERTION_CONDITION: ErrorCondition = ErrorCondition { ctype: ConditionType::Assertion };
+
+/// Lists all condition procedures with its name
+pub fn libcondition() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("condition", &PRIM_CONDITION),
+        ("simple-conditions", &PRIM_SIMPLE_CONDITIONS),
+        ("condition?", &PRIM_IS_CONDITION),
+        ("make-warning", &PRIM_MAKE_WARNING),
+        ("warning?", &PRIM_IS_WARNING),
+        ("make-serious-condition", &PRIM_MAKE_SERIOUS),
+        ("serious-condition?", &PRIM_IS_SERIOUS),
+        ("make-error", &PRIM_MAKE_ERROR),
+        ("error?", &PRIM_IS_ERROR),
+        ("make-violation", &PRIM_MAKE_VIOLATION),
+        ("violation?", &PRIM_IS_VIOLATION),
+        ("make-assertion-violation", &PRIM_MAKE_ASSERTION),
+        ("assertion-violation?", &PRIM_IS_ASSERTION),
+        ("make-arity-violation", &PRIM_MAKE_ARITY),
+        ("arity-violation?", &PRIM_IS_ARITY),
+        ("make-wrong-type-violation", &PRIM_MAKE_WRONG_TYPE),
+        ("wrong-type-violation?", &PRIM_IS_WRONG_TYPE),
+        ("make-out-of-range-violation", &PRIM_MAKE_OUT_OF_RANGE),
+        ("out-of-range-violation?", &PRIM_IS_OUT_OF_RANGE),
+        ("make-divide-by-zero-violation", &PRIM_MAKE_DIVIDE_BY_ZERO),
+        ("divide-by-zero-violation?", &PRIM_IS_DIVIDE_BY_ZERO),
+        ("make-non-continuable-violation", &PRIM_MAKE_NON_CONTINUABLE),
+        ("non-continuable-violation?", &PRIM_IS_NON_CONTINUABLE),
+        ("make-lexical-violation", &PRIM_MAKE_LEXICAL),
+        ("lexical-violation?", &PRIM_IS_LEXICAL),
+        ("make-syntax-violation", &PRIM_MAKE_SYNTAX),
+        ("syntax-violation?", &PRIM_IS_SYNTAX),
+        ("syntax-violation-form", &PRIM_SYNTAX_FORM),
+        ("syntax-violation-subform", &PRIM_SYNTAX_SUBFORM),
+        ("make-undefined-violation", &PRIM_MAKE_UNDEFINED),
+        ("undefined-violation?", &PRIM_IS_UNDEFINED),
+        ("make-message-condition", &PRIM_MAKE_MESSAGE),
+        ("message-condition?", &PRIM_IS_MESSAGE),
+        ("condition-message", &PRIM_MESSAGE),
+        ("make-irritants-condition", &PRIM_MAKE_IRRITANTS),
+        ("irritants-condition?", &PRIM_IS_IRRITANTS),
+        ("condition-irritants", &PRIM_IRRITANTS),
+        ("make-who-condition", &PRIM_MAKE_WHO),
+        ("who-condition?", &PRIM_IS_WHO),
+        ("condition-who", &PRIM_WHO)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Condition, ConditionType};
+    use error::{RuntimeError, RuntimeErrorKind};
+
+    #[test]
+    fn test_hierarchy() {
+        assert!(ConditionType::Assertion.is_subtype_of(ConditionType::Violation));
+        assert!(ConditionType::Assertion.is_subtype_of(ConditionType::Serious));
+        assert!(ConditionType::Error.is_subtype_of(ConditionType::Condition));
+        assert!(!ConditionType::Error.is_subtype_of(ConditionType::Violation));
+        assert!(!ConditionType::Message.is_subtype_of(ConditionType::Serious));
+        assert!(ConditionType::IoFileIsReadOnly.is_subtype_of(ConditionType::IoFilename));
+        assert!(ConditionType::IoDecoding.is_subtype_of(ConditionType::Error));
+        assert_eq!(ConditionType::IoEncoding.field_count(), 2);
+        assert_eq!(ConditionType::IoFileDoesNotExist.field_count(), 1);
+    }
+
+    #[test]
+    fn test_from_runtime_error() {
+        let err = RuntimeError {
+            kind: RuntimeErrorKind::IndexOutOfRange,
+            desc: "bad index".to_string()
+        };
+        let c = Condition::from(err.clone());
+        assert!(c.is_a(ConditionType::Violation));
+        assert!(c.is_a(ConditionType::Assertion));
+        assert!(c.is_a(ConditionType::OutOfRange));
+        assert!(!c.is_a(ConditionType::WrongType));
+        assert!(c.is_a(ConditionType::Message));
+        assert_eq!(c.to_runtime_error(), err);
+    }
+}
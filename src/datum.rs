@@ -1,6 +1,9 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::iter::{FromIterator, IntoIterator};
+use std::mem;
 use std::rc::Rc;
 
 use number::Number;
@@ -20,22 +23,166 @@ pub enum Datum<T> {
     Bool(bool),
     /// Character
     Char(char),
-    /// String
-    String(Rc<String>),
+    /// String, holding the characters so that they are indexed in constant time. The characters
+    /// are replaced in place by `string-set!`
+    String(Rc<RefCell<Vec<char>>>),
     /// Vector
     Vector(Rc<Vec<Datum<T>>>),
-    /// Byte vector
-    Bytes(Rc<Vec<u8>>),
+    /// Byte vector, whose bytes are replaced in place by `bytevector-u8-set!` and the other
+    /// mutators
+    Bytes(Rc<RefCell<Vec<u8>>>),
     /// Numeric value
     Num(Number),
     /// `()`
     Nil,
     /// Pair
-    Cons(Rc<(Datum<T>, Datum<T>)>),
+    Cons(Rc<Pair<T>>),
     /// Extra values
     Ext(T)
 }
 
+/// Mutable pair cell. `set-car!` and `set-cdr!` replace the values in place, so the pairs sharing
+/// the cell see the change and cycles can be made
+pub struct Pair<T> {
+    car: RefCell<Datum<T>>,
+    cdr: RefCell<Datum<T>>
+}
+
+impl<T> Pair<T> {
+    pub fn new(car: Datum<T>, cdr: Datum<T>) -> Pair<T> {
+        Pair {
+            car: RefCell::new(car),
+            cdr: RefCell::new(cdr)
+        }
+    }
+
+    pub fn set_car(&self, car: Datum<T>) {
+        *self.car.borrow_mut() = car;
+    }
+
+    pub fn set_cdr(&self, cdr: Datum<T>) {
+        *self.cdr.borrow_mut() = cdr;
+    }
+}
+
+/// Drops the pairs and the vectors owned only by this pair with an explicit stack, so that long or
+/// deeply nested lists don't exhaust the stack
+impl<T> Drop for Pair<T> {
+    fn drop(&mut self) {
+        if !has_elements(self.car.get_mut()) && !has_elements(self.cdr.get_mut()) {
+            return;
+        }
+        let mut pending = vec![mem::replace(self.car.get_mut(), Datum::Nil),
+                               mem::replace(self.cdr.get_mut(), Datum::Nil)];
+        while let Some(datum) = pending.pop() {
+            match datum {
+                Datum::Cons(pair) => if let Ok(mut pair) = Rc::try_unwrap(pair) {
+                    pending.push(mem::replace(pair.car.get_mut(), Datum::Nil));
+                    pending.push(mem::replace(pair.cdr.get_mut(), Datum::Nil));
+                },
+                Datum::Vector(vec) => if let Ok(vec) = Rc::try_unwrap(vec) {
+                    pending.extend(vec);
+                },
+                _ => ()
+            }
+        }
+    }
+}
+
+fn has_elements<T>(datum: &Datum<T>) -> bool {
+    match *datum {
+        Datum::Cons(_) | Datum::Vector(_) => true,
+        _ => false
+    }
+}
+
+impl<T: Clone> Pair<T> {
+    pub fn car(&self) -> Datum<T> {
+        self.car.borrow().clone()
+    }
+
+    pub fn cdr(&self) -> Datum<T> {
+        self.cdr.borrow().clone()
+    }
+
+    /// Copies out the car and the cdr
+    pub fn get(&self) -> (Datum<T>, Datum<T>) {
+        (self.car(), self.cdr())
+    }
+}
+
+/// Compares the pairs structurally, as `equal?` does. The pairs and vectors are compared with an
+/// explicit stack, and a pair of nodes met again is assumed equal, so that long lists don't
+/// exhaust the stack and the comparison of cyclic data terminates
+impl<T: PartialEq> PartialEq for Pair<T> {
+    fn eq(&self, other: &Pair<T>) -> bool {
+        let self_id = self as *const Pair<T> as usize;
+        let other_id = other as *const Pair<T> as usize;
+        if self_id == other_id {
+            return true;
+        }
+
+        let mut visited = HashSet::new();
+        visited.insert((self_id, other_id));
+        let mut pending = Vec::new();
+        if !shallow_eq(&self.car.borrow(), &other.car.borrow(), &mut pending) ||
+                !shallow_eq(&self.cdr.borrow(), &other.cdr.borrow(), &mut pending) {
+            return false;
+        }
+
+        while let Some(node) = pending.pop() {
+            let ids = match node {
+                Node::Pair(ref x, ref y) => (pair_id(x), pair_id(y)),
+                Node::Vector(ref x, ref y) => (&**x as *const Vec<Datum<T>> as usize,
+                                               &**y as *const Vec<Datum<T>> as usize)
+            };
+            if ids.0 == ids.1 || !visited.insert(ids) {
+                continue;
+            }
+            let equal = match node {
+                Node::Pair(x, y) =>
+                    shallow_eq(&x.car.borrow(), &y.car.borrow(), &mut pending) &&
+                        shallow_eq(&x.cdr.borrow(), &y.cdr.borrow(), &mut pending),
+                Node::Vector(x, y) =>
+                    x.iter().zip(y.iter()).all(|(a, b)| shallow_eq(a, b, &mut pending))
+            };
+            if !equal {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Pair of the pairs or the vectors left to compare by `PartialEq for Pair`
+enum Node<T> {
+    Pair(Rc<Pair<T>>, Rc<Pair<T>>),
+    Vector(Rc<Vec<Datum<T>>>, Rc<Vec<Datum<T>>>)
+}
+
+/// Compares the data except the elements of the pairs and vectors, which are pushed to `pending`
+fn shallow_eq<T: PartialEq>(x: &Datum<T>, y: &Datum<T>, pending: &mut Vec<Node<T>>) -> bool {
+    match (x, y) {
+        (&Datum::Cons(ref x), &Datum::Cons(ref y)) => {
+            pending.push(Node::Pair(x.clone(), y.clone()));
+            true
+        },
+        (&Datum::Vector(ref x), &Datum::Vector(ref y)) => {
+            if x.len() != y.len() {
+                return false;
+            }
+            pending.push(Node::Vector(x.clone(), y.clone()));
+            true
+        },
+        _ => x == y
+    }
+}
+
+/// Address of the pair, which identifies the pair while it is alive
+fn pair_id<T>(pair: &Rc<Pair<T>>) -> usize {
+    &**pair as *const Pair<T> as usize
+}
+
 fn format_char(c: char, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "#\\")?;
     match c {
@@ -50,30 +197,198 @@ fn format_char(c: char, f: &mut fmt::Formatter) -> fmt::Result {
     }
 }
 
+/// Labels of the pairs reachable from themselves. Such a pair is written as `#n=` followed by the
+/// pair at the first occurrence, and as `#n#` afterwards, so that circular lists are written in
+/// finite length
+struct Labels {
+    cyclic: HashMap<usize, Option<usize>>,
+    next: usize
+}
+
+impl Labels {
+    fn new<T>(datum: &Datum<T>) -> Labels {
+        Labels {
+            cyclic: find_cycles(datum),
+            next: 0
+        }
+    }
+
+    fn contains<T>(&self, pair: &Rc<Pair<T>>) -> bool {
+        self.cyclic.contains_key(&pair_id(pair))
+    }
+
+    /// Writes the label of the pair if it's in a cycle. Returns true if the pair is already
+    /// written, so the reference to the label stands for the pair
+    fn write_label<T>(&mut self, pair: &Rc<Pair<T>>, f: &mut fmt::Formatter) -> Result<bool, fmt::Error> {
+        match self.cyclic.get_mut(&pair_id(pair)) {
+            Some(&mut Some(n)) => {
+                write!(f, "#{}#", n)?;
+                Ok(true)
+            },
+            Some(label) => {
+                *label = Some(self.next);
+                write!(f, "#{}=", self.next)?;
+                self.next += 1;
+                Ok(false)
+            },
+            None => Ok(false)
+        }
+    }
+}
+
+/// Collects the pairs in cycles, searching the pairs depth first with an explicit stack so that
+/// long or deeply nested lists don't exhaust the stack. `visiting` holds the pairs whose elements
+/// are being searched, and `done` holds the pairs searched already
+fn find_cycles<T>(datum: &Datum<T>) -> HashMap<usize, Option<usize>> {
+    let mut cyclic = HashMap::new();
+    let mut visiting = HashSet::new();
+    let mut done = HashSet::new();
+    let mut pending = Vec::new();
+    push_search(datum, &mut pending);
+
+    while let Some(node) = pending.pop() {
+        match node {
+            Search::Pair(pair) => {
+                let id = pair_id(&pair);
+                if visiting.contains(&id) {
+                    cyclic.insert(id, None);
+                    continue;
+                }
+                if done.contains(&id) {
+                    continue;
+                }
+                visiting.insert(id);
+                pending.push(Search::Leave(id));
+                push_search(&pair.cdr.borrow(), &mut pending);
+                push_search(&pair.car.borrow(), &mut pending);
+            },
+            Search::Vector(vec) => for x in vec.iter().rev() {
+                push_search(x, &mut pending);
+            },
+            Search::Leave(id) => {
+                visiting.remove(&id);
+                done.insert(id);
+            }
+        }
+    }
+    cyclic
+}
+
+/// Node left to search by `find_cycles`
+enum Search<T> {
+    Pair(Rc<Pair<T>>),
+    Vector(Rc<Vec<Datum<T>>>),
+    /// All the elements of the pair are searched
+    Leave(usize)
+}
+
+fn push_search<T>(datum: &Datum<T>, pending: &mut Vec<Search<T>>) {
+    match *datum {
+        Datum::Cons(ref pair) => pending.push(Search::Pair(pair.clone())),
+        Datum::Vector(ref vec) => pending.push(Search::Vector(vec.clone())),
+        _ => ()
+    }
+}
+
+/// Part of the datum left to write by `DatumFormatter::datum_fmt`
+enum Pending<T> {
+    /// Pair to write with its label or as an abbreviation
+    Pair(Rc<Pair<T>>),
+    /// Rest of the list after the car of the pair
+    Cdr(Rc<Pair<T>>),
+    /// Elements of the vector from the index
+    Vector(Rc<Vec<Datum<T>>>, usize),
+    /// Closing parenthesis of a dotted list
+    Close
+}
+
 trait DatumFormatter<T> {
     fn ext_fmt(&self, &T, &mut fmt::Formatter) -> fmt::Result;
 
-    fn datum_fmt(&self, datum: &Datum<T>, f: &mut fmt::Formatter) -> fmt::Result {
+    fn char_fmt(&self, c: char, f: &mut fmt::Formatter) -> fmt::Result {
+        format_char(c, f)
+    }
+
+    fn string_fmt(&self, s: &[char], f: &mut fmt::Formatter) -> fmt::Result {
+        let s: String = s.iter().cloned().collect();
+        write!(f, "{:?}", s)
+    }
+
+    fn write_datum(&self, datum: &Datum<T>, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut labels = Labels::new(datum);
+        self.datum_fmt(datum, &mut labels, f)
+    }
+
+    /// Writes the datum, keeping the pairs and the vectors left to write in an explicit stack so
+    /// that long or deeply nested lists don't exhaust the stack
+    fn datum_fmt(&self, datum: &Datum<T>, labels: &mut Labels, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut pending = Vec::new();
+        self.shallow_fmt(datum, &mut pending, f)?;
+
+        while let Some(part) = pending.pop() {
+            match part {
+                Pending::Pair(pair) => {
+                    if labels.write_label(&pair, f)? {
+                        continue;
+                    }
+                    if let Some((token, tail)) = abbreviated(&pair, labels) {
+                        write!(f, "{}", token)?;
+                        self.shallow_fmt(&tail.car.borrow(), &mut pending, f)?;
+                        continue;
+                    }
+                    write!(f, "(")?;
+                    pending.push(Pending::Cdr(pair.clone()));
+                    self.shallow_fmt(&pair.car.borrow(), &mut pending, f)?;
+                },
+                Pending::Cdr(pair) => match *pair.cdr.borrow() {
+                    Datum::Nil => write!(f, ")")?,
+                    Datum::Cons(ref next) if !labels.contains(next) => {
+                        write!(f, " ")?;
+                        pending.push(Pending::Cdr(next.clone()));
+                        self.shallow_fmt(&next.car.borrow(), &mut pending, f)?;
+                    },
+                    ref tail => {
+                        write!(f, " . ")?;
+                        pending.push(Pending::Close);
+                        self.shallow_fmt(tail, &mut pending, f)?;
+                    }
+                },
+                Pending::Vector(vec, i) => if i < vec.len() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    pending.push(Pending::Vector(vec.clone(), i + 1));
+                    self.shallow_fmt(&vec[i], &mut pending, f)?;
+                } else {
+                    write!(f, ")")?;
+                },
+                Pending::Close => write!(f, ")")?
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the datum except the pairs and the elements of the vectors, which are pushed to
+    /// `pending`
+    fn shallow_fmt(&self, datum: &Datum<T>, pending: &mut Vec<Pending<T>>, f: &mut fmt::Formatter)
+            -> fmt::Result
+    {
         match *datum {
             Datum::Sym(ref s) => write!(f, "{}", s),
             Datum::Bool(true) => write!(f, "#t"),
             Datum::Bool(false) => write!(f, "#f"),
-            Datum::Char(c) => format_char(c, f),
-            Datum::String(ref s) => write!(f, "{:?}", s),
+            Datum::Char(c) => self.char_fmt(c, f),
+            Datum::String(ref s) => self.string_fmt(&s.borrow(), f),
             Datum::Vector(ref vec) => {
                 if vec.is_empty() {
                     write!(f, "#()")
                 } else {
-                    write!(f, "#(")?;
-                    self.datum_fmt(&vec[0], f)?;
-                    for x in vec[1..].iter() {
-                        f.write_str(" ")?;
-                        self.datum_fmt(x, f)?;
-                    }
-                    write!(f, ")")
+                    pending.push(Pending::Vector(vec.clone(), 0));
+                    write!(f, "#(")
                 }
             },
             Datum::Bytes(ref vec) => {
+                let vec = vec.borrow();
                 if vec.is_empty() {
                     write!(f, "#vu8()")
                 } else {
@@ -88,41 +403,27 @@ trait DatumFormatter<T> {
             Datum::Ext(ref x) => self.ext_fmt(x, f),
             Datum::Nil => write!(f, "()"),
             Datum::Cons(ref pair) => {
-                if let Datum::Sym(ref s) = pair.0 {
-                    match SPECIAL_TOKEN_MAP.get(s.as_ref()) {
-                        Some(ch) => if let Datum::Cons(ref tail) = pair.1 {
-                            if let Datum::Nil = tail.1 {
-                                write!(f, "{}", ch)?;
-                                return self.datum_fmt(&tail.0, f);
-                            }
-                        },
-                        _ => ()
-                    }
-                }
-                write!(f, "(")?;
-                self.datum_fmt(&pair.0, f)?;
-                self.write_cons(&pair.1, f)
+                pending.push(Pending::Pair(pair.clone()));
+                Ok(())
             }
         }
     }
+}
 
-    fn write_cons(&self, tail: &Datum<T>, f: &mut fmt::Formatter) -> fmt::Result {
-        match tail {
-            &Datum::Nil => {
-                write!(f, ")")
-            },
-            &Datum::Cons(ref pair) => {
-                write!(f, " ")?;
-                self.datum_fmt(&pair.0, f)?;
-                self.write_cons(&pair.1, f)
-            },
-            _ => {
-                write!(f, " . ")?;
-                self.datum_fmt(&tail, f)?;
-                write!(f, ")")
+/// The abbreviation and the tail of `(quote x)` and the like, if the pair is written as `'x`
+fn abbreviated<T>(pair: &Rc<Pair<T>>, labels: &Labels) -> Option<(&'static str, Rc<Pair<T>>)> {
+    if let Datum::Sym(ref s) = *pair.car.borrow() {
+        if let Some(&token) = SPECIAL_TOKEN_MAP.get(s.as_ref()) {
+            if let Datum::Cons(ref tail) = *pair.cdr.borrow() {
+                if let Datum::Nil = *tail.cdr.borrow() {
+                    if !labels.contains(tail) {
+                        return Some((token, tail.clone()));
+                    }
+                }
             }
         }
     }
+    None
 }
 
 struct DebugFormatter;
@@ -137,7 +438,7 @@ impl<T: fmt::Debug> fmt::Debug for Datum<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let debug_fmt = DebugFormatter;
 
-        debug_fmt.datum_fmt(self, f)
+        debug_fmt.write_datum(self, f)
     }
 }
 
@@ -153,7 +454,43 @@ impl<T: fmt::Display> fmt::Display for Datum<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let debug_fmt = DisplayFormatter;
 
-        debug_fmt.datum_fmt(self, f)
+        debug_fmt.write_datum(self, f)
+    }
+}
+
+/// Writes the characters and the strings as is, as `display` does
+struct PlainFormatter;
+
+impl<T: fmt::Display> DatumFormatter<T> for PlainFormatter {
+    fn ext_fmt(&self, ext: &T, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ext)
+    }
+
+    fn char_fmt(&self, c: char, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", c)
+    }
+
+    fn string_fmt(&self, s: &[char], f: &mut fmt::Formatter) -> fmt::Result {
+        for &c in s.iter() {
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Helper struct for formatting the datum as `display` writes it, returned by `Datum::display`
+pub struct Displayed<'a, T: 'a>(&'a Datum<T>);
+
+impl<'a, T: fmt::Display> fmt::Display for Displayed<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        PlainFormatter.write_datum(self.0, f)
+    }
+}
+
+impl<T> Datum<T> {
+    /// Formats the datum without the quotes of the strings and the `#\` of the characters
+    pub fn display(&self) -> Displayed<T> {
+        Displayed(self)
     }
 }
 
@@ -170,8 +507,8 @@ impl<T: Clone> Datum<T> {
         loop {
             let next = match iter {
                 Datum::Cons(ref pair) => {
-                    list.push(pair.0.clone());
-                    pair.1.clone()
+                    list.push(pair.car());
+                    pair.cdr()
                 },
                 Datum::Nil => return (list, None),
                 _ => return (list, Some(iter))
@@ -206,9 +543,9 @@ impl<S, T, E> TryConv<Datum<T>, E> for Datum<S> where S: TryConv<T, E> {
             &Datum::Num(ref v) => Ok(Datum::Num(v.clone())),
             &Datum::Nil => Ok(Datum::Nil),
             &Datum::Cons(ref v) => {
-                let h = v.0.try_conv()?;
-                let t = v.1.try_conv()?;
-                Ok(Datum::Cons(Rc::new((h, t))))
+                let h = v.car.borrow().try_conv()?;
+                let t = v.cdr.borrow().try_conv()?;
+                Ok(cons(h, t))
             },
             &Datum::Ext(ref v) => v.try_conv().map(Datum::Ext)
         }
@@ -227,9 +564,7 @@ impl<T: Clone> Iterator for DatumIter<T> {
     fn next(&mut self) -> Option<Result<Datum<T>, ()>> {
         let (val, next) = match self.ptr {
             Datum::Nil => return None,
-            Datum::Cons(ref pair) => {
-                (pair.0.clone(), pair.1.clone())
-            }
+            Datum::Cons(ref pair) => pair.get(),
             _ => return Some(Err(()))
         };
 
@@ -252,14 +587,24 @@ impl<T> FromIterator<Datum<T>> for Datum<T> {
 
 /// `cons` the values into a pair
 pub fn cons<T>(head: Datum<T>, tail: Datum<T>) -> Datum<T> {
-    Datum::Cons(Rc::new((head, tail)))
+    Datum::Cons(Rc::new(Pair::new(head, tail)))
+}
+
+/// Makes a new string holding the characters of `s`
+pub fn string<T>(s: &str) -> Datum<T> {
+    Datum::String(Rc::new(RefCell::new(s.chars().collect())))
+}
+
+/// Makes a new bytevector holding the bytes
+pub fn bytes<T>(v: Vec<u8>) -> Datum<T> {
+    Datum::Bytes(Rc::new(RefCell::new(v)))
 }
 
 pub fn concat<T: Clone>(x: Datum<T>, y: Datum<T>) -> Result<Datum<T>, ()> {
     match x {
         Datum::Nil => Ok(y),
         Datum::Cons(pair) => {
-            concat(pair.1.clone(), y).map(|new_y| cons(pair.0.clone(), new_y))
+            concat(pair.cdr(), y).map(|new_y| cons(pair.car(), new_y))
         },
         _ => Err(())
     }
@@ -270,8 +615,8 @@ pub enum SimpleDatum {
     Sym(Cow<'static, str>),
     Bool(bool),
     Char(char),
-    String(Rc<String>),
-    Bytes(Rc<Vec<u8>>),
+    String(Rc<RefCell<Vec<char>>>),
+    Bytes(Rc<RefCell<Vec<u8>>>),
     Num(Number),
     Nil
 }
@@ -352,7 +697,7 @@ impl SimpleDatum {
 
 #[cfg(test)]
 mod test {
-    use super::{Datum, cons};
+    use super::{bytes, Datum, Pair, cons};
     use number::Number;
     use std::borrow::Cow;
     use std::rc::Rc;
@@ -380,8 +725,8 @@ mod test {
 
     #[test]
     fn test_bytes_fmt() {
-        compare_fmt("#vu8(1 2 3)", Datum::Bytes(Rc::new(vec![1, 2, 3])));
-        compare_fmt("#vu8()", Datum::Bytes(Rc::new(Vec::new())));
+        compare_fmt("#vu8(1 2 3)", bytes(vec![1, 2, 3]));
+        compare_fmt("#vu8()", bytes(Vec::new()));
     }
 
     #[test]
@@ -399,6 +744,41 @@ mod test {
         compare_fmt("#,@a", list!(sym!("unsyntax-splicing"), sym!("a")));
     }
 
+    #[test]
+    fn test_cycle_fmt() {
+        let list: Datum<()> = list!(sym!("a"), sym!("b"));
+        if let Datum::Cons(ref pair) = list {
+            if let Datum::Cons(ref tail) = pair.cdr() {
+                tail.set_cdr(list.clone());
+            }
+        }
+        compare_fmt("#0=(a b . #0#)", list.clone());
+
+        let nested: Datum<()> = list!(sym!("x"), list.clone());
+        if let Datum::Cons(ref pair) = nested {
+            pair.set_car(nested.clone());
+        }
+        compare_fmt("#0=(#0# #1=(a b . #1#))", nested);
+    }
+
+    #[test]
+    fn test_deep_fmt() {
+        let depth = 100000;
+        let mut nested: Datum<()> = list!();
+        for _ in 0 .. depth {
+            nested = list!(nested);
+        }
+        let s = format!("{:?}", nested);
+        assert_eq!(s.len(), 2 * depth + 2);
+        assert!(s.starts_with("(((") && s.ends_with(")))"));
+
+        let mut vectors: Datum<()> = list!();
+        for _ in 0 .. depth {
+            vectors = list!(Datum::Vector(Rc::new(vec![vectors])));
+        }
+        assert_eq!(format!("{:?}", vectors).len(), 5 * depth + 2);
+    }
+
     #[test]
     fn test_iter() {
         let n1 = FromPrimitive::from_isize(1).unwrap();
@@ -410,9 +790,9 @@ mod test {
 
     #[test]
     fn test_improper_list() {
-        let data: Datum<()> = Datum::Cons(Rc::new((
+        let data: Datum<()> = Datum::Cons(Rc::new(Pair::new(
             sym!("a"),
-            Datum::Cons(Rc::new((sym!("b"), sym!("c"))))
+            Datum::Cons(Rc::new(Pair::new(sym!("b"), sym!("c"))))
         )));
 
         assert_eq!((vec![sym!("a"), sym!("b")], Some(sym!("c"))), data.improper_list());
@@ -0,0 +1,345 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::rc::Rc;
+
+use cast::DatumCast;
+use datum::Datum;
+use error::{RuntimeError, RuntimeErrorKind};
+use primitive::{F1, F2, PrimFunc};
+use runtime::{DatumType, Inst, MemRef, PrimFuncPtr, RDatum};
+
+/// Number of the bits in a word of the bitset
+const WORD_BITS: usize = 64;
+
+/// Enumeration type, the ordered universe of the symbols
+#[derive(Debug)]
+pub struct EnumType {
+    pub symbols: Vec<Cow<'static, str>>
+}
+
+impl EnumType {
+    /// Index of the symbol in the universe
+    pub fn index(&self, sym: &str) -> Option<usize> {
+        self.symbols.iter().position(|s| s == sym)
+    }
+}
+
+/// Enumeration set, a subset of the universe of its enumeration type
+#[derive(Debug, Clone)]
+pub struct EnumSet {
+    pub universe: Rc<EnumType>,
+    /// The bit `i` is set if the `i`th symbol of the universe is a member
+    bits: Vec<u64>
+}
+
+impl EnumSet {
+    fn empty(universe: Rc<EnumType>) -> EnumSet {
+        let words = (universe.symbols.len() + WORD_BITS - 1) / WORD_BITS;
+        EnumSet {
+            universe: universe,
+            bits: vec![0; words]
+        }
+    }
+
+    /// The set of every symbol of the universe
+    pub fn full(universe: Rc<EnumType>) -> EnumSet {
+        let mut set = EnumSet::empty(universe);
+        for i in 0 .. set.universe.symbols.len() {
+            set.insert(i);
+        }
+        set
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.bits[i / WORD_BITS] |= 1 << (i % WORD_BITS);
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.bits[i / WORD_BITS] & (1 << (i % WORD_BITS)) != 0
+    }
+
+    /// Whether the symbol is a member of the set
+    pub fn is_member(&self, sym: &str) -> bool {
+        self.universe.index(sym).map_or(false, |i| self.contains(i))
+    }
+
+    /// Members of the set in the order of the universe
+    pub fn members(&self) -> Vec<Cow<'static, str>> {
+        self.universe.symbols.iter().enumerate()
+            .filter(|&(i, _)| self.contains(i))
+            .map(|(_, sym)| sym.clone())
+            .collect()
+    }
+
+    /// The set of the symbols in the universe, failing on the other symbols
+    pub fn from_symbols<I>(universe: Rc<EnumType>, symbols: I) -> Result<EnumSet, RuntimeError>
+        where I: IntoIterator<Item=Cow<'static, str>>
+    {
+        let mut set = EnumSet::empty(universe);
+        for sym in symbols {
+            match set.universe.index(&sym) {
+                Some(i) => set.insert(i),
+                None => return Err(RuntimeError {
+                    kind: RuntimeErrorKind::InvalidType,
+                    desc: format!("{} is not in the universe of {}", sym, EnumSet::full(set.universe.clone()))
+                })
+            }
+        }
+        Ok(set)
+    }
+
+    pub fn same_type(&self, other: &EnumSet) -> Result<(), RuntimeError> {
+        if Rc::ptr_eq(&self.universe, &other.universe) {
+            Ok(())
+        } else {
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("{} and {} have different enumeration types", self, other)
+            })
+        }
+    }
+
+    /// Combines the bits of the sets of the same enumeration type
+    fn combine<F>(&self, other: &EnumSet, op: F) -> Result<EnumSet, RuntimeError>
+        where F: Fn(u64, u64) -> u64
+    {
+        self.same_type(other)?;
+        Ok(EnumSet {
+            universe: self.universe.clone(),
+            bits: self.bits.iter().zip(other.bits.iter()).map(|(&x, &y)| op(x, y)).collect()
+        })
+    }
+}
+
+impl PartialEq for EnumSet {
+    fn eq(&self, other: &EnumSet) -> bool {
+        Rc::ptr_eq(&self.universe, &other.universe) && self.bits == other.bits
+    }
+}
+
+impl fmt::Display for EnumSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<enum-set (")?;
+        for (i, sym) in self.members().iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", sym)?;
+        }
+        write!(f, ")>")
+    }
+}
+
+fn symbols(list: &RDatum) -> Result<Vec<Cow<'static, str>>, RuntimeError> {
+    let mut syms = Vec::new();
+    for item in list.iter() {
+        let item = item.map_err(|_| RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: format!("Expected list, but received {:?}", DatumType::get_type(list))
+        })?;
+        syms.push(DatumCast::unwrap(item)?);
+    }
+    Ok(syms)
+}
+
+fn make_enumeration(list: RDatum) -> Result<Rc<EnumSet>, RuntimeError> {
+    let mut universe: Vec<Cow<'static, str>> = Vec::new();
+    for sym in symbols(&list)? {
+        if !universe.contains(&sym) {
+            universe.push(sym);
+        }
+    }
+    Ok(Rc::new(EnumSet::full(Rc::new(EnumType { symbols: universe }))))
+}
+
+/// `(make-enumeration symbol-list)`
+pub static PRIM_MAKE_ENUMERATION: F1<RDatum, Result<Rc<EnumSet>, RuntimeError>> = F1 { f1: make_enumeration };
+
+fn enum_set_universe(set: Rc<EnumSet>) -> Rc<EnumSet> {
+    Rc::new(EnumSet::full(set.universe.clone()))
+}
+
+/// `(enum-set-universe enum-set)`
+pub static PRIM_ENUM_SET_UNIVERSE: F1<Rc<EnumSet>, Rc<EnumSet>> = F1 { f1: enum_set_universe };
+
+fn enum_set_index(set: Rc<EnumSet>, sym: Cow<'static, str>) -> RDatum {
+    match set.universe.index(&sym) {
+        Some(i) => DatumCast::wrap(i),
+        None => Datum::Bool(false)
+    }
+}
+
+/// Index of the symbol in the universe of the set, or `#f`
+pub static PRIM_ENUM_SET_INDEX: F2<Rc<EnumSet>, Cow<'static, str>, RDatum> = F2 { f2: enum_set_index };
+
+fn enum_set_from_list(set: Rc<EnumSet>, list: RDatum) -> Result<Rc<EnumSet>, RuntimeError> {
+    EnumSet::from_symbols(set.universe.clone(), symbols(&list)?).map(Rc::new)
+}
+
+/// The set of the symbols in the list, of the same enumeration type as the set
+pub static PRIM_ENUM_SET_FROM_LIST: F2<Rc<EnumSet>, RDatum, Result<Rc<EnumSet>, RuntimeError>> = F2 { f2: enum_set_from_list };
+
+fn enum_set_to_list(set: Rc<EnumSet>) -> RDatum {
+    set.members().into_iter().map(Datum::Sym).collect()
+}
+
+/// `(enum-set->list enum-set)`
+pub static PRIM_ENUM_SET_TO_LIST: F1<Rc<EnumSet>, RDatum> = F1 { f1: enum_set_to_list };
+
+fn enum_set_member(sym: Cow<'static, str>, set: Rc<EnumSet>) -> bool {
+    set.is_member(&sym)
+}
+
+/// `(enum-set-member? symbol enum-set)`
+pub static PRIM_ENUM_SET_MEMBER: F2<Cow<'static, str>, Rc<EnumSet>, bool> = F2 { f2: enum_set_member };
+
+fn enum_set_subset(set1: Rc<EnumSet>, set2: Rc<EnumSet>) -> bool {
+    set1.universe.symbols.iter().all(|sym| set2.universe.index(sym).is_some()) &&
+        set1.members().iter().all(|sym| set2.is_member(sym))
+}
+
+/// `(enum-set-subset? enum-set1 enum-set2)`, where the universe of the first set must also be a
+/// subset of the universe of the second
+pub static PRIM_ENUM_SET_SUBSET: F2<Rc<EnumSet>, Rc<EnumSet>, bool> = F2 { f2: enum_set_subset };
+
+fn enum_set_eq(set1: Rc<EnumSet>, set2: Rc<EnumSet>) -> bool {
+    enum_set_subset(set1.clone(), set2.clone()) && enum_set_subset(set2, set1)
+}
+
+/// `(enum-set=? enum-set1 enum-set2)`
+pub static PRIM_ENUM_SET_EQ: F2<Rc<EnumSet>, Rc<EnumSet>, bool> = F2 { f2: enum_set_eq };
+
+fn enum_set_union(set1: Rc<EnumSet>, set2: Rc<EnumSet>) -> Result<Rc<EnumSet>, RuntimeError> {
+    set1.combine(&set2, |x, y| x | y).map(Rc::new)
+}
+
+/// `(enum-set-union enum-set1 enum-set2)`
+pub static PRIM_ENUM_SET_UNION: F2<Rc<EnumSet>, Rc<EnumSet>, Result<Rc<EnumSet>, RuntimeError>> = F2 { f2: enum_set_union };
+
+fn enum_set_intersection(set1: Rc<EnumSet>, set2: Rc<EnumSet>) -> Result<Rc<EnumSet>, RuntimeError> {
+    set1.combine(&set2, |x, y| x & y).map(Rc::new)
+}
+
+/// `(enum-set-intersection enum-set1 enum-set2)`
+pub static PRIM_ENUM_SET_INTERSECTION: F2<Rc<EnumSet>, Rc<EnumSet>, Result<Rc<EnumSet>, RuntimeError>> = F2 { f2: enum_set_intersection };
+
+fn enum_set_difference(set1: Rc<EnumSet>, set2: Rc<EnumSet>) -> Result<Rc<EnumSet>, RuntimeError> {
+    set1.combine(&set2, |x, y| x & !y).map(Rc::new)
+}
+
+/// `(enum-set-difference enum-set1 enum-set2)`
+pub static PRIM_ENUM_SET_DIFFERENCE: F2<Rc<EnumSet>, Rc<EnumSet>, Result<Rc<EnumSet>, RuntimeError>> = F2 { f2: enum_set_difference };
+
+fn enum_set_complement(set: Rc<EnumSet>) -> Rc<EnumSet> {
+    let full = EnumSet::full(set.universe.clone());
+    Rc::new(EnumSet {
+        universe: full.universe,
+        bits: full.bits.iter().zip(set.bits.iter()).map(|(&x, &y)| x & !y).collect()
+    })
+}
+
+/// `(enum-set-complement enum-set)`
+pub static PRIM_ENUM_SET_COMPLEMENT: F1<Rc<EnumSet>, Rc<EnumSet>> = F1 { f1: enum_set_complement };
+
+fn enum_set_projection(set1: Rc<EnumSet>, set2: Rc<EnumSet>) -> Rc<EnumSet> {
+    let members = set1.members().into_iter().filter(|sym| set2.universe.index(sym).is_some());
+    // every member is in the universe by the filter
+    Rc::new(EnumSet::from_symbols(set2.universe.clone(), members).unwrap())
+}
+
+/// `(enum-set-projection enum-set1 enum-set2)`, the members of the first set in the universe of
+/// the second
+pub static PRIM_ENUM_SET_PROJECTION: F2<Rc<EnumSet>, Rc<EnumSet>, Rc<EnumSet>> = F2 { f2: enum_set_projection };
+
+/// Bytecode of `(enum-set-indexer enum-set)`, returning the procedure which takes a symbol and
+/// returns its index in the universe, or `#f`
+pub fn enum_set_indexer_code() -> Vec<Inst> {
+    enum_set_procedure_code("enum-set-indexer", &PRIM_ENUM_SET_INDEX)
+}
+
+/// Bytecode of `(enum-set-constructor enum-set)`, returning the procedure which takes a list of
+/// symbols and returns the set of them of the same enumeration type
+pub fn enum_set_constructor_code() -> Vec<Inst> {
+    enum_set_procedure_code("enum-set-constructor", &PRIM_ENUM_SET_FROM_LIST)
+}
+
+/// Bytecode returning the closure which calls `func` with the enum set and its argument
+fn enum_set_procedure_code(name: &'static str, func: &'static (PrimFunc + 'static)) -> Vec<Inst> {
+    vec![
+        // fails early if the argument is not an enum set
+        Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new(name, &PRIM_ENUM_SET_UNIVERSE))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::DropArg(1),
+        Inst::PushArg(MemRef::Closure(Rc::new(vec![
+            Inst::PushArg(MemRef::PrimFunc(PrimFuncPtr::new(name, func))),
+            Inst::PushArg(MemRef::UpValue(0, 0)),
+            Inst::PushArg(MemRef::Arg(0)),
+            Inst::Call(2),
+            Inst::Return
+        ]), 0, None)),
+        Inst::Return
+    ]
+}
+
+/// Lists all enumeration procedures implemented natively with its name
+pub fn libenums() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("make-enumeration", &PRIM_MAKE_ENUMERATION),
+        ("enum-set-universe", &PRIM_ENUM_SET_UNIVERSE),
+        ("enum-set->list", &PRIM_ENUM_SET_TO_LIST),
+        ("enum-set-member?", &PRIM_ENUM_SET_MEMBER),
+        ("enum-set-subset?", &PRIM_ENUM_SET_SUBSET),
+        ("enum-set=?", &PRIM_ENUM_SET_EQ),
+        ("enum-set-union", &PRIM_ENUM_SET_UNION),
+        ("enum-set-intersection", &PRIM_ENUM_SET_INTERSECTION),
+        ("enum-set-difference", &PRIM_ENUM_SET_DIFFERENCE),
+        ("enum-set-complement", &PRIM_ENUM_SET_COMPLEMENT),
+        ("enum-set-projection", &PRIM_ENUM_SET_PROJECTION)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::rc::Rc;
+
+    use super::{enum_set_complement, enum_set_subset, enum_set_union, EnumSet, EnumType};
+
+    fn universe(syms: &[&'static str]) -> Rc<EnumType> {
+        Rc::new(EnumType { symbols: syms.iter().map(|&s| Cow::Borrowed(s)).collect() })
+    }
+
+    fn set(universe: &Rc<EnumType>, syms: &[&'static str]) -> Rc<EnumSet> {
+        Rc::new(EnumSet::from_symbols(universe.clone(), syms.iter().map(|&s| Cow::Borrowed(s))).unwrap())
+    }
+
+    #[test]
+    fn test_bitset() {
+        // spans more than one word
+        let syms: Vec<String> = (0..100).map(|i| format!("s{}", i)).collect();
+        let u = Rc::new(EnumType { symbols: syms.into_iter().map(Cow::Owned).collect() });
+        let full = EnumSet::full(u.clone());
+        assert_eq!(full.members().len(), 100);
+        assert!(full.is_member("s99"));
+        assert!(!full.is_member("s100"));
+        let none = enum_set_complement(Rc::new(full));
+        assert_eq!(none.members().len(), 0);
+    }
+
+    #[test]
+    fn test_set_ops() {
+        let u = universe(&["a", "b", "c"]);
+        let ab = set(&u, &["b", "a"]);
+        let c = set(&u, &["c"]);
+        assert_eq!(ab.members(), vec![Cow::Borrowed("a"), Cow::Borrowed("b")]);
+        assert_eq!(*enum_set_union(ab.clone(), c.clone()).unwrap(), EnumSet::full(u.clone()));
+        assert!(enum_set_subset(c.clone(), enum_set_complement(ab.clone())));
+
+        // different enumeration types with the same universe
+        let other = set(&universe(&["a", "b", "c"]), &["c"]);
+        assert!(enum_set_union(ab, other.clone()).is_err());
+        assert!(enum_set_subset(c, other));
+        assert!(EnumSet::from_symbols(u, vec![Cow::Borrowed("d")]).is_err());
+    }
+}
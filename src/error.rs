@@ -4,6 +4,7 @@ use std::fmt;
 use std::io::CharsError;
 
 use compiler::Syntax;
+use condition::{ConditionType, SimpleCondition};
 
 /// Possible parser errors
 #[derive(Debug, PartialEq)]
@@ -106,12 +107,26 @@ pub enum CompileErrorKind {
     SyntaxRulesContext,
     /// Trying to refer an unbound variable
     UnboundVariable(Cow<'static, str>),
+    /// Trying to assign a variable imported from a library
+    ImmutableVariable(Cow<'static, str>),
     /// Duplicate variables in binding form
     DuplicateVars,
     /// Trying to compile invalid datum
     InvalidDatum(String),
     /// Macro compilation error
     MacroError(MacroError),
+    /// `library` or `import` is not allowed in this context
+    LibraryContext,
+    /// No library matches the library reference
+    UnknownLibrary(String),
+    /// The identifier is imported with different bindings
+    ImportConflict(Cow<'static, str>),
+    /// Libraries import each other, such as `(a) -> (b) -> (a)`
+    CircularImport(String),
+    /// Failed to read or parse the library file
+    LoadLibrary(String),
+    /// Error raised while running a macro transformer
+    TransformerError(RuntimeError),
 }
 
 /// Compiler error
@@ -151,7 +166,7 @@ pub struct MacroError {
 }
 
 /// Errors raised in runtime
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum RuntimeErrorKind {
     /// Fatal non-recoverable error
     Panic,
@@ -163,10 +178,26 @@ pub enum RuntimeErrorKind {
     DivideByZero,
     /// Index out of range
     IndexOutOfRange,
+    /// Raised object was not handled by any exception handler
+    Uncaught,
+    /// Exception handler returned from non-continuable exception
+    NonContinuable,
+    /// Reference to an unbound variable
+    UnboundVariable,
     /// Invalid datum in source code
     CompileInvalidDatum,
+    /// Malformed datum read from a port
+    Lexical,
     /// Compile error
-    CompileError
+    CompileError,
+    /// Failure of the input or output of a port
+    Io,
+    /// Failure of a port described by a subtype of `&i/o` with its fields, such as
+    /// `&i/o-file-does-not-exist` with the file name
+    IoCondition(SimpleCondition),
+    /// Syntax violation raised and not handled, with the components of the condition, which is
+    /// raised again when the error reaches the program expanding the macro
+    SyntaxViolation(Vec<SimpleCondition>)
 }
 
 /// Errors raised in runtime
@@ -176,11 +207,39 @@ pub struct RuntimeError {
     pub desc: String
 }
 
+impl RuntimeErrorKind {
+    /// Condition type of the condition object raised for this error
+    pub fn condition_type(&self) -> ConditionType {
+        match *self {
+            RuntimeErrorKind::Panic => ConditionType::Error,
+            RuntimeErrorKind::NumArgs => ConditionType::Arity,
+            RuntimeErrorKind::InvalidType => ConditionType::WrongType,
+            RuntimeErrorKind::DivideByZero => ConditionType::DivideByZero,
+            RuntimeErrorKind::IndexOutOfRange => ConditionType::OutOfRange,
+            RuntimeErrorKind::Uncaught => ConditionType::Error,
+            RuntimeErrorKind::NonContinuable => ConditionType::NonContinuable,
+            RuntimeErrorKind::UnboundVariable => ConditionType::Undefined,
+            RuntimeErrorKind::CompileInvalidDatum => ConditionType::Lexical,
+            RuntimeErrorKind::Lexical => ConditionType::Lexical,
+            RuntimeErrorKind::CompileError => ConditionType::Syntax,
+            RuntimeErrorKind::Io => ConditionType::Io,
+            RuntimeErrorKind::IoCondition(ref c) => c.ctype,
+            RuntimeErrorKind::SyntaxViolation(_) => ConditionType::Syntax
+        }
+    }
+}
+
 impl From<CompileError> for RuntimeError {
     fn from(err: CompileError) -> RuntimeError {
+        let kind = match err.kind {
+            CompileErrorKind::TransformerError(e) => return e,
+            CompileErrorKind::UnboundVariable(_) => RuntimeErrorKind::UnboundVariable,
+            CompileErrorKind::InvalidDatum(_) => RuntimeErrorKind::CompileInvalidDatum,
+            _ => RuntimeErrorKind::CompileError
+        };
         RuntimeError {
-            kind: RuntimeErrorKind::CompileError,
-            desc: format!("{:?}", err)
+            kind: kind,
+            desc: format!("{:?}", err.kind)
         }
     }
 }
@@ -0,0 +1,622 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+use cast::DatumCast;
+use datum::Datum;
+use eqv::DatumEqv;
+use error::{RuntimeError, RuntimeErrorKind};
+use number::Number;
+use primitive::{F1, F2, FoldErr, PrimFunc, R1};
+use real::Real;
+use runtime::{Closure, DatumType, Inst, RDatum, RuntimeData};
+use unicode::fold_case;
+
+/// Number of the objects visited by `equal-hash`, which bounds the traversal of cyclic data
+const EQUAL_HASH_BUDGET: usize = 64;
+
+/// How the keys of a hashtable are compared
+#[derive(Debug)]
+pub enum HashtableKind {
+    /// `make-eq-hashtable`
+    Eq,
+    /// `make-eqv-hashtable`
+    Eqv,
+    /// `make-hashtable` with the hash and equivalence procedures
+    Custom { hash: RDatum, equiv: RDatum }
+}
+
+/// Key of the native maps, hashed and compared consistently with `eqv?`
+#[derive(Debug, Clone)]
+struct EqvKey(RDatum);
+
+impl Hash for EqvKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_eqv(&self.0, state);
+    }
+}
+
+impl PartialEq for EqvKey {
+    fn eq(&self, other: &EqvKey) -> bool {
+        self.0.eqv(&other.0)
+    }
+}
+
+impl Eq for EqvKey {}
+
+/// Hashtable object.
+///
+/// The entries are stored in a native map keyed with `eqv?`. Custom hashtables call the hash and
+/// equivalence procedures in the bytecode of the hashtable procedures, which looks up the
+/// *canonical key*: the key already stored in the table which is equivalent to the given key, or
+/// the given key itself if there is none. The native map is then accessed with the canonical key
+pub struct Hashtable {
+    pub kind: HashtableKind,
+    mutable: bool,
+    entries: RefCell<HashMap<EqvKey, RDatum>>,
+    /// Keys of a custom hashtable grouped by the value of the hash procedure
+    buckets: RefCell<HashMap<EqvKey, Vec<RDatum>>>
+}
+
+impl Hashtable {
+    pub fn new(kind: HashtableKind) -> Hashtable {
+        Hashtable {
+            kind: kind,
+            mutable: true,
+            entries: RefCell::new(HashMap::new()),
+            buckets: RefCell::new(HashMap::new())
+        }
+    }
+
+    pub fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// Keys and values of the entries, in the same order
+    pub fn entries(&self) -> (Vec<RDatum>, Vec<RDatum>) {
+        self.entries.borrow().iter().map(|(k, v)| (k.0.clone(), v.clone())).unzip()
+    }
+
+    /// Keys stored in the bucket of the hash value
+    pub fn candidates(&self, hash: &RDatum) -> Vec<RDatum> {
+        match self.buckets.borrow().get(&EqvKey(hash.clone())) {
+            Some(keys) => keys.clone(),
+            None => Vec::new()
+        }
+    }
+
+    pub fn get(&self, key: &RDatum) -> Option<RDatum> {
+        self.entries.borrow().get(&EqvKey(key.clone())).cloned()
+    }
+
+    /// Sets the value of the canonical key. `hash` is the value of the hash procedure for the key
+    /// of a custom hashtable
+    pub fn set(&self, hash: &RDatum, key: RDatum, value: RDatum) -> Result<(), RuntimeError> {
+        self.check_mutable()?;
+        let old = self.entries.borrow_mut().insert(EqvKey(key.clone()), value);
+        if old.is_none() && self.is_custom() {
+            self.buckets.borrow_mut().entry(EqvKey(hash.clone())).or_insert_with(Vec::new).push(key);
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, hash: &RDatum, key: &RDatum) -> Result<(), RuntimeError> {
+        self.check_mutable()?;
+        let old = self.entries.borrow_mut().remove(&EqvKey(key.clone()));
+        if old.is_some() && self.is_custom() {
+            let mut buckets = self.buckets.borrow_mut();
+            let empty = match buckets.get_mut(&EqvKey(hash.clone())) {
+                Some(keys) => {
+                    keys.retain(|k| !k.eqv(key));
+                    keys.is_empty()
+                },
+                None => false
+            };
+            if empty {
+                buckets.remove(&EqvKey(hash.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), RuntimeError> {
+        self.check_mutable()?;
+        self.entries.borrow_mut().clear();
+        self.buckets.borrow_mut().clear();
+        Ok(())
+    }
+
+    /// Copies the hashtable, sharing the keys and the values
+    pub fn copy(&self, mutable: bool) -> Hashtable {
+        let kind = match self.kind {
+            HashtableKind::Eq => HashtableKind::Eq,
+            HashtableKind::Eqv => HashtableKind::Eqv,
+            HashtableKind::Custom { ref hash, ref equiv } =>
+                HashtableKind::Custom { hash: hash.clone(), equiv: equiv.clone() }
+        };
+        Hashtable {
+            kind: kind,
+            mutable: mutable,
+            entries: RefCell::new(self.entries.borrow().clone()),
+            buckets: RefCell::new(self.buckets.borrow().clone())
+        }
+    }
+
+    /// Drops every reference held by the hashtable, used by the collector to break cycles
+    pub fn release(&self) {
+        let _entries = mem::replace(&mut *self.entries.borrow_mut(), HashMap::new());
+        let _buckets = mem::replace(&mut *self.buckets.borrow_mut(), HashMap::new());
+    }
+
+    /// Visits the keys and the values
+    pub fn for_each<F: FnMut(&RDatum)>(&self, mut f: F) {
+        if let HashtableKind::Custom { ref hash, ref equiv } = self.kind {
+            f(hash);
+            f(equiv);
+        }
+        for (k, v) in self.entries.borrow().iter() {
+            f(&k.0);
+            f(v);
+        }
+    }
+
+    fn is_custom(&self) -> bool {
+        match self.kind {
+            HashtableKind::Custom { .. } => true,
+            _ => false
+        }
+    }
+
+    fn check_mutable(&self) -> Result<(), RuntimeError> {
+        if self.mutable {
+            Ok(())
+        } else {
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: "attempt to modify an immutable hashtable".to_string()
+            })
+        }
+    }
+}
+
+impl PartialEq for Hashtable {
+    fn eq(&self, other: &Hashtable) -> bool {
+        (self as *const Hashtable) == (other as *const Hashtable)
+    }
+}
+
+/// Hashes the number consistently with `eqv?` and `equal?`. Equal numbers have the same
+/// inexact value, and the zeros of both signs are hashed alike
+fn hash_number<H: Hasher>(n: &Number, state: &mut H) {
+    let (re, im) = match n {
+        &Number::Real(ref r) => (r.to_f64(), 0.0),
+        &Number::ECmplx(ref c) =>
+            (Real::Rational(c.re.clone()).to_f64(), Real::Rational(c.im.clone()).to_f64()),
+        &Number::ICmplx(ref c) => (c.re, c.im)
+    };
+    (re + 0.0).to_bits().hash(state);
+    (im + 0.0).to_bits().hash(state);
+}
+
+fn hash_rc<T, H: Hasher>(ptr: &Rc<T>, state: &mut H) {
+    (&**ptr as *const T as usize).hash(state);
+}
+
+/// Hashes the datum consistently with `eqv?`
+fn hash_eqv<H: Hasher>(datum: &RDatum, state: &mut H) {
+    mem::discriminant(datum).hash(state);
+    match datum {
+        &Datum::Sym(ref s) => s.hash(state),
+        &Datum::Bool(b) => b.hash(state),
+        &Datum::Char(c) => c.hash(state),
+        &Datum::String(ref s) => hash_rc(s, state),
+        &Datum::Vector(ref v) => hash_rc(v, state),
+        &Datum::Bytes(ref b) => hash_rc(b, state),
+        &Datum::Num(ref n) => hash_number(n, state),
+        &Datum::Nil => (),
+        &Datum::Cons(ref pair) => hash_rc(pair, state),
+        &Datum::Ext(ref data) => {
+            mem::discriminant(data).hash(state);
+            match data {
+                &RuntimeData::Closure(ref closure) => hash_rc(&closure.code, state),
+                &RuntimeData::CaseLambda(ref case_lambda) => hash_rc(&case_lambda.clauses, state),
+                &RuntimeData::Continuation(ref k) => hash_rc(k, state),
+                &RuntimeData::Escape(ref k) => hash_rc(k, state),
+                &RuntimeData::Condition(ref c) => hash_rc(c, state),
+                &RuntimeData::Hashtable(ref h) => hash_rc(h, state),
+                &RuntimeData::RecordType(ref t) => hash_rc(t, state),
+                &RuntimeData::RecordConstructor(ref c) => hash_rc(c, state),
+                &RuntimeData::Record(ref r) => hash_rc(r, state),
+                &RuntimeData::EnumSet(ref e) => hash_rc(e, state),
+                &RuntimeData::Port(ref p) => hash_rc(p, state),
+                &RuntimeData::Environment(ref e) => hash_rc(e, state),
+                &RuntimeData::Promise(ref p) => hash_rc(p, state),
+                &RuntimeData::Parameter(ref p) => hash_rc(p, state),
+                &RuntimeData::Codec(c) => (c as usize).hash(state),
+                &RuntimeData::Values(n) => n.hash(state),
+                &RuntimeData::MultipleValues(ref vs) => for v in vs.iter() {
+                    hash_eqv(v, state)
+                },
+                &RuntimeData::Transcoder(_) | &RuntimeData::Eof |
+                &RuntimeData::PrimFunc(_) | &RuntimeData::Undefined => ()
+            }
+        }
+    }
+}
+
+/// Hashes the datum consistently with `equal?`, visiting at most `budget` objects
+fn hash_equal<H: Hasher>(datum: &RDatum, budget: &mut usize, state: &mut H) {
+    if *budget == 0 {
+        return;
+    }
+    *budget -= 1;
+    match datum {
+        &Datum::String(ref s) => {
+            mem::discriminant(datum).hash(state);
+            s.borrow().hash(state);
+        },
+        &Datum::Vector(ref v) => {
+            mem::discriminant(datum).hash(state);
+            v.len().hash(state);
+            for x in v.iter() {
+                hash_equal(x, budget, state);
+            }
+        },
+        &Datum::Bytes(ref b) => {
+            mem::discriminant(datum).hash(state);
+            b.borrow().hash(state);
+        },
+        &Datum::Cons(ref pair) => {
+            mem::discriminant(datum).hash(state);
+            hash_equal(&pair.car(), budget, state);
+            hash_equal(&pair.cdr(), budget, state);
+        },
+        &Datum::Ext(ref data) => mem::discriminant(data).hash(state),
+        _ => hash_eqv(datum, state)
+    }
+}
+
+/// Exact non-negative integer made from the hasher
+fn finish(state: DefaultHasher) -> usize {
+    (state.finish() >> 1) as usize & (isize::max_value() as usize)
+}
+
+fn wrap(table: Hashtable) -> RDatum {
+    Datum::Ext(RuntimeData::Hashtable(Rc::new(table)))
+}
+
+/// Checks the optional initial capacity given to the hashtable constructors
+fn check_capacity(args: Vec<RDatum>, max_args: usize) -> Result<Vec<RDatum>, RuntimeError> {
+    if args.len() + 1 < max_args || args.len() > max_args {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected {} or {} arguments, received {}", max_args - 1, max_args, args.len())
+        });
+    }
+    if args.len() == max_args {
+        let _k: usize = DatumCast::unwrap(args[max_args - 1].clone())?;
+    }
+    Ok(args)
+}
+
+fn make_eq_hashtable(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    check_capacity(args, 1)?;
+    Ok(wrap(Hashtable::new(HashtableKind::Eq)))
+}
+
+/// `(make-eq-hashtable)` or `(make-eq-hashtable k)`
+pub static PRIM_MAKE_EQ_HASHTABLE: FoldErr<RDatum> = FoldErr { fold: make_eq_hashtable };
+
+fn make_eqv_hashtable(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    check_capacity(args, 1)?;
+    Ok(wrap(Hashtable::new(HashtableKind::Eqv)))
+}
+
+/// `(make-eqv-hashtable)` or `(make-eqv-hashtable k)`
+pub static PRIM_MAKE_EQV_HASHTABLE: FoldErr<RDatum> = FoldErr { fold: make_eqv_hashtable };
+
+fn make_hashtable(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    let mut args = check_capacity(args, 3)?.into_iter();
+    let hash = args.next().unwrap();
+    let equiv = args.next().unwrap();
+    for procedure in [&hash, &equiv].iter() {
+        if DatumType::get_type(procedure) != DatumType::Callable {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Callable, but received {:?}", DatumType::get_type(procedure))
+            });
+        }
+    }
+    Ok(wrap(Hashtable::new(HashtableKind::Custom { hash: hash, equiv: equiv })))
+}
+
+/// `(make-hashtable hash-function equiv)` or `(make-hashtable hash-function equiv k)`
+pub static PRIM_MAKE_HASHTABLE: FoldErr<RDatum> = FoldErr { fold: make_hashtable };
+
+fn is_hashtable(datum: RDatum) -> bool {
+    DatumType::get_type(&datum) == DatumType::Hashtable
+}
+
+/// `(hashtable? obj)`
+pub static PRIM_IS_HASHTABLE: F1<RDatum, bool> = F1 { f1: is_hashtable };
+
+fn hashtable_size(table: &Rc<Hashtable>) -> usize {
+    table.len()
+}
+
+/// `(hashtable-size hashtable)`
+pub static PRIM_HASHTABLE_SIZE: R1<Rc<Hashtable>, usize> = R1 { r1: hashtable_size };
+
+fn hashtable_keys(table: &Rc<Hashtable>) -> RDatum {
+    Datum::Vector(Rc::new(table.entries().0))
+}
+
+/// `(hashtable-keys hashtable)`
+pub static PRIM_HASHTABLE_KEYS: R1<Rc<Hashtable>, RDatum> = R1 { r1: hashtable_keys };
+
+fn hashtable_entries(table: &Rc<Hashtable>) -> (RDatum, RDatum) {
+    let (keys, values) = table.entries();
+    (Datum::Vector(Rc::new(keys)), Datum::Vector(Rc::new(values)))
+}
+
+/// Pair of the key vector and the value vector, which `hashtable-entries` returns as two values
+pub static PRIM_HASHTABLE_ENTRIES: R1<Rc<Hashtable>, (RDatum, RDatum)> = R1 { r1: hashtable_entries };
+
+fn hashtable_copy(table: Rc<Hashtable>, mutable: Option<bool>) -> Rc<Hashtable> {
+    Rc::new(table.copy(mutable.unwrap_or(false)))
+}
+
+/// `(hashtable-copy hashtable)` or `(hashtable-copy hashtable mutable)`
+pub static PRIM_HASHTABLE_COPY: F2<Rc<Hashtable>, Option<bool>, Rc<Hashtable>> = F2 { f2: hashtable_copy };
+
+fn hashtable_clear(table: Rc<Hashtable>, k: Option<usize>) -> Result<RDatum, RuntimeError> {
+    let _ = k;
+    table.clear()?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// `(hashtable-clear! hashtable)` or `(hashtable-clear! hashtable k)`
+pub static PRIM_HASHTABLE_CLEAR: F2<Rc<Hashtable>, Option<usize>, Result<RDatum, RuntimeError>> = F2 { f2: hashtable_clear };
+
+fn hashtable_is_mutable(table: &Rc<Hashtable>) -> bool {
+    table.is_mutable()
+}
+
+/// `(hashtable-mutable? hashtable)`
+pub static PRIM_HASHTABLE_IS_MUTABLE: R1<Rc<Hashtable>, bool> = R1 { r1: hashtable_is_mutable };
+
+/// Closure of `eqv?`, which is also the equivalence of eq hashtables
+fn eqv_closure() -> RDatum {
+    Datum::Ext(RuntimeData::Closure(Closure::new(Rc::new(vec![Inst::Eqv, Inst::Return]), None, None)))
+}
+
+fn hashtable_equivalence_function(table: &Rc<Hashtable>) -> RDatum {
+    match table.kind {
+        HashtableKind::Eq | HashtableKind::Eqv => eqv_closure(),
+        HashtableKind::Custom { ref equiv, .. } => equiv.clone()
+    }
+}
+
+/// `(hashtable-equivalence-function hashtable)`
+pub static PRIM_HASHTABLE_EQUIVALENCE_FUNCTION: R1<Rc<Hashtable>, RDatum> = R1 { r1: hashtable_equivalence_function };
+
+fn hashtable_hash_function(table: &Rc<Hashtable>) -> RDatum {
+    match table.kind {
+        HashtableKind::Eq | HashtableKind::Eqv => Datum::Bool(false),
+        HashtableKind::Custom { ref hash, .. } => hash.clone()
+    }
+}
+
+/// `(hashtable-hash-function hashtable)`, which is `#f` for eq and eqv hashtables
+pub static PRIM_HASHTABLE_HASH_FUNCTION: R1<Rc<Hashtable>, RDatum> = R1 { r1: hashtable_hash_function };
+
+/// Checks the value returned by the hash procedure of a custom hashtable
+fn check_hash(hash: &RDatum) -> Result<(), RuntimeError> {
+    let valid = match hash {
+        &Datum::Num(Number::Real(ref r)) => r.is_exact() && r.is_integer(),
+        _ => false
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: format!("hash function returned {}, which is not an exact integer", hash)
+        })
+    }
+}
+
+fn hashtable_candidates(table: Rc<Hashtable>, hash: RDatum) -> Result<RDatum, RuntimeError> {
+    check_hash(&hash)?;
+    Ok(table.candidates(&hash).into_iter().collect())
+}
+
+/// List of the keys of the custom hashtable stored under the hash value
+pub static PRIM_HASHTABLE_CANDIDATES: F2<Rc<Hashtable>, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: hashtable_candidates };
+
+fn hashtable_lookup(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    match &args[..] {
+        &[ref table, ref key, ref default] => {
+            let table: Rc<Hashtable> = DatumCast::unwrap(table.clone())?;
+            Ok(table.get(key).unwrap_or_else(|| default.clone()))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 3 arguments, received {}", args.len())
+        })
+    }
+}
+
+/// `(hashtable-ref hashtable key default)` with the canonical key
+pub static PRIM_HASHTABLE_LOOKUP: FoldErr<RDatum> = FoldErr { fold: hashtable_lookup };
+
+fn hashtable_has_key(table: Rc<Hashtable>, key: RDatum) -> bool {
+    table.get(&key).is_some()
+}
+
+/// `(hashtable-contains? hashtable key)` with the canonical key
+pub static PRIM_HASHTABLE_HAS_KEY: F2<Rc<Hashtable>, RDatum, bool> = F2 { f2: hashtable_has_key };
+
+fn hashtable_store(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    match &args[..] {
+        &[ref table, ref hash, ref key, ref value] => {
+            let table: Rc<Hashtable> = DatumCast::unwrap(table.clone())?;
+            table.set(hash, key.clone(), value.clone())?;
+            Ok(Datum::Ext(RuntimeData::Hashtable(table)))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 4 arguments, received {}", args.len())
+        })
+    }
+}
+
+/// `(hashtable-set! hashtable key value)` with the hash value and the canonical key. It returns
+/// the hashtable, which `Inst::TrackStore` lets the collector track
+pub static PRIM_HASHTABLE_STORE: FoldErr<RDatum> = FoldErr { fold: hashtable_store };
+
+fn hashtable_remove(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    match &args[..] {
+        &[ref table, ref hash, ref key] => {
+            let table: Rc<Hashtable> = DatumCast::unwrap(table.clone())?;
+            table.delete(hash, key)?;
+            Ok(Datum::Ext(RuntimeData::Undefined))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 3 arguments, received {}", args.len())
+        })
+    }
+}
+
+/// `(hashtable-delete! hashtable key)` with the hash value and the canonical key
+pub static PRIM_HASHTABLE_REMOVE: FoldErr<RDatum> = FoldErr { fold: hashtable_remove };
+
+fn equal_hash(datum: RDatum) -> usize {
+    let mut state = DefaultHasher::new();
+    let mut budget = EQUAL_HASH_BUDGET;
+    hash_equal(&datum, &mut budget, &mut state);
+    finish(state)
+}
+
+/// `(equal-hash obj)`
+pub static PRIM_EQUAL_HASH: F1<RDatum, usize> = F1 { f1: equal_hash };
+
+fn string_hash(s: &Rc<RefCell<Vec<char>>>) -> usize {
+    let mut state = DefaultHasher::new();
+    s.borrow().hash(&mut state);
+    finish(state)
+}
+
+/// `(string-hash string)`
+pub static PRIM_STRING_HASH: R1<Rc<RefCell<Vec<char>>>, usize> = R1 { r1: string_hash };
+
+fn string_ci_hash(s: &Rc<RefCell<Vec<char>>>) -> usize {
+    let mut state = DefaultHasher::new();
+    fold_case(&s.borrow()).hash(&mut state);
+    finish(state)
+}
+
+/// `(string-ci-hash string)`
+pub static PRIM_STRING_CI_HASH: R1<Rc<RefCell<Vec<char>>>, usize> = R1 { r1: string_ci_hash };
+
+fn symbol_hash(datum: RDatum) -> Result<usize, RuntimeError> {
+    match datum {
+        Datum::Sym(ref s) => {
+            let mut state = DefaultHasher::new();
+            s.hash(&mut state);
+            Ok(finish(state))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: format!("expected Sym, but received {:?}", DatumType::get_type(&datum))
+        })
+    }
+}
+
+/// `(symbol-hash symbol)`
+pub static PRIM_SYMBOL_HASH: F1<RDatum, Result<usize, RuntimeError>> = F1 { f1: symbol_hash };
+
+/// Lists all hashtable procedures implemented natively with its name. The procedures calling
+/// the hash and equivalence procedures are compiled in `base`
+pub fn libhashtable() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("make-eq-hashtable", &PRIM_MAKE_EQ_HASHTABLE),
+        ("make-eqv-hashtable", &PRIM_MAKE_EQV_HASHTABLE),
+        ("make-hashtable", &PRIM_MAKE_HASHTABLE),
+        ("hashtable?", &PRIM_IS_HASHTABLE),
+        ("hashtable-size", &PRIM_HASHTABLE_SIZE),
+        ("hashtable-keys", &PRIM_HASHTABLE_KEYS),
+        ("hashtable-copy", &PRIM_HASHTABLE_COPY),
+        ("hashtable-clear!", &PRIM_HASHTABLE_CLEAR),
+        ("hashtable-mutable?", &PRIM_HASHTABLE_IS_MUTABLE),
+        ("hashtable-equivalence-function", &PRIM_HASHTABLE_EQUIVALENCE_FUNCTION),
+        ("hashtable-hash-function", &PRIM_HASHTABLE_HASH_FUNCTION),
+        ("equal-hash", &PRIM_EQUAL_HASH),
+        ("string-hash", &PRIM_STRING_HASH),
+        ("string-ci-hash", &PRIM_STRING_CI_HASH),
+        ("symbol-hash", &PRIM_SYMBOL_HASH)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{equal_hash, Hashtable, HashtableKind};
+    use std::borrow::Cow;
+    use datum::{cons, string, Datum};
+    use number::Number;
+    use runtime::RDatum;
+
+    #[test]
+    fn test_eqv_keys() {
+        let table = Hashtable::new(HashtableKind::Eqv);
+        let no_hash = Datum::Bool(false);
+        table.set(&no_hash, Datum::Num(Number::new_int(1, 0)), sym!("one")).unwrap();
+        table.set(&no_hash, Datum::Num(Number::new_flonum(-0.0)), sym!("zero")).unwrap();
+        assert_eq!(table.get(&Datum::Num(Number::new_ratio(2, 2))), Some(sym!("one")));
+        assert_eq!(table.get(&Datum::Num(Number::new_flonum(1.0))), None);
+        assert_eq!(table.get(&Datum::Num(Number::new_flonum(0.0))), Some(sym!("zero")));
+
+        let s: RDatum = string("a");
+        table.set(&no_hash, s.clone(), sym!("a")).unwrap();
+        assert_eq!(table.get(&s), Some(sym!("a")));
+        assert_eq!(table.get(&string("a")), None);
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn test_immutable_copy() {
+        let table = Hashtable::new(HashtableKind::Eq);
+        let no_hash = Datum::Bool(false);
+        table.set(&no_hash, sym!("a"), num!(1)).unwrap();
+        let copy = table.copy(false);
+        assert!(copy.set(&no_hash, sym!("b"), num!(2)).is_err());
+        assert!(copy.delete(&no_hash, &sym!("a")).is_err());
+        table.delete(&no_hash, &sym!("a")).unwrap();
+        assert_eq!(copy.get(&sym!("a")), Some(num!(1)));
+        assert_eq!(table.get(&sym!("a")), None);
+    }
+
+    #[test]
+    fn test_equal_hash() {
+        let x: RDatum = cons(string("a"), list![num!(1), Datum::Num(Number::new_flonum(1.0))]);
+        let y: RDatum = cons(string("a"), list![num!(1), Datum::Num(Number::new_flonum(1.0))]);
+        assert_eq!(equal_hash(x.clone()), equal_hash(y));
+
+        // cyclic lists are hashed in bounded time
+        if let Datum::Cons(ref pair) = x {
+            pair.set_cdr(x.clone());
+        }
+        equal_hash(x.clone());
+        if let Datum::Cons(ref pair) = x {
+            pair.set_cdr(Datum::Nil);
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use condition::Condition;
+use datum::{Datum, Pair};
+use hashtable::Hashtable;
+use library::Environment;
+use parameter::Parameter;
+use port::Port;
+use promise::Promise;
+use record::{Record, RecordConstructor, RecordType};
+use runtime::{CaseLambda, Closure, Continuation, Inst, MemRef, RDatum, RuntimeData, ScopePtr, StaticLink, Winder};
+
+/// Number of the tracked cells triggering the first collection
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Values holding references to heap objects
+pub trait Trace {
+    /// Pushes the heap objects directly referred by this value
+    fn trace(&self, out: &mut Vec<HeapObject>);
+}
+
+/// Reference counted object which may take part in a reference cycle
+#[derive(Clone)]
+pub enum HeapObject {
+    Pair(Rc<Pair<RuntimeData>>),
+    Vector(Rc<Vec<RDatum>>),
+    Code(Rc<Vec<Inst>>),
+    Scope(StaticLink),
+    Global(Rc<RefCell<RDatum>>),
+    Continuation(Rc<Continuation>),
+    Condition(Rc<Condition>),
+    Hashtable(Rc<Hashtable>),
+    Record(Rc<Record>),
+    RecordType(Rc<RecordType>),
+    RecordConstructor(Rc<RecordConstructor>),
+    Port(Rc<Port>),
+    Environment(Rc<Environment>),
+    Promise(Rc<Promise>),
+    Parameter(Rc<Parameter>),
+    Winder(Rc<Winder>)
+}
+
+impl HeapObject {
+    /// Address of the object, which identifies the object while it is alive
+    fn id(&self) -> usize {
+        match self {
+            &HeapObject::Pair(ref ptr) => &**ptr as *const Pair<RuntimeData> as usize,
+            &HeapObject::Vector(ref ptr) => &**ptr as *const Vec<RDatum> as usize,
+            &HeapObject::Code(ref ptr) => &**ptr as *const Vec<Inst> as usize,
+            &HeapObject::Scope(ref ptr) => &**ptr as *const RefCell<ScopePtr> as usize,
+            &HeapObject::Global(ref ptr) => &**ptr as *const RefCell<RDatum> as usize,
+            &HeapObject::Continuation(ref ptr) => &**ptr as *const Continuation as usize,
+            &HeapObject::Condition(ref ptr) => &**ptr as *const Condition as usize,
+            &HeapObject::Hashtable(ref ptr) => &**ptr as *const Hashtable as usize,
+            &HeapObject::Record(ref ptr) => &**ptr as *const Record as usize,
+            &HeapObject::RecordType(ref ptr) => &**ptr as *const RecordType as usize,
+            &HeapObject::RecordConstructor(ref ptr) => &**ptr as *const RecordConstructor as usize,
+            &HeapObject::Port(ref ptr) => &**ptr as *const Port as usize,
+            &HeapObject::Environment(ref ptr) => &**ptr as *const Environment as usize,
+            &HeapObject::Promise(ref ptr) => &**ptr as *const Promise as usize,
+            &HeapObject::Parameter(ref ptr) => &**ptr as *const Parameter as usize,
+            &HeapObject::Winder(ref ptr) => &**ptr as *const Winder as usize
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            &HeapObject::Pair(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Vector(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Code(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Scope(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Global(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Continuation(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Condition(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Hashtable(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Record(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::RecordType(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::RecordConstructor(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Port(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Environment(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Promise(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Parameter(ref ptr) => Rc::strong_count(ptr),
+            &HeapObject::Winder(ref ptr) => Rc::strong_count(ptr)
+        }
+    }
+
+    /// Drops the references held by a mutable cell. Every reference cycle passes through one of
+    /// the cells, so clearing the cells of the garbage lets the reference counts free it
+    fn clear(&self) {
+        match self {
+            &HeapObject::Pair(ref ptr) => {
+                ptr.set_car(Datum::Nil);
+                ptr.set_cdr(Datum::Nil);
+            },
+            &HeapObject::Scope(ref ptr) => {
+                let _old = mem::replace(&mut *ptr.borrow_mut(), ScopePtr::empty());
+            },
+            &HeapObject::Global(ref ptr) => {
+                let _old = mem::replace(&mut *ptr.borrow_mut(), Datum::Ext(RuntimeData::Undefined));
+            },
+            &HeapObject::Hashtable(ref ptr) => ptr.release(),
+            &HeapObject::Record(ref ptr) => for field in ptr.fields.borrow_mut().iter_mut() {
+                *field = Datum::Ext(RuntimeData::Undefined);
+            },
+            &HeapObject::RecordType(ref ptr) => ptr.release(),
+            &HeapObject::Promise(ref ptr) => ptr.release(),
+            _ => ()
+        }
+    }
+}
+
+impl Trace for HeapObject {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        match self {
+            &HeapObject::Pair(ref pair) => {
+                pair.car().trace(out);
+                pair.cdr().trace(out);
+            },
+            &HeapObject::Vector(ref vec) => for x in vec.iter() {
+                x.trace(out);
+            },
+            &HeapObject::Code(ref code) => for inst in code.iter() {
+                inst.trace(out);
+            },
+            &HeapObject::Scope(ref link) => link.borrow().trace(out),
+            &HeapObject::Global(ref cell) => cell.borrow().trace(out),
+            &HeapObject::Continuation(ref k) => k.trace(out),
+            &HeapObject::Condition(ref c) => for component in c.components.iter() {
+                for field in component.fields.iter() {
+                    field.trace(out);
+                }
+            },
+            &HeapObject::Hashtable(ref table) => table.for_each(|x| x.trace(out)),
+            &HeapObject::Record(ref record) => {
+                out.push(HeapObject::RecordType(record.rtd.clone()));
+                for field in record.fields.borrow().iter() {
+                    field.trace(out);
+                }
+            },
+            &HeapObject::RecordType(ref rtd) => rtd.trace(out),
+            &HeapObject::RecordConstructor(ref rcd) => rcd.trace(out),
+            &HeapObject::Port(ref port) => port.trace(out),
+            &HeapObject::Environment(ref env) => for cell in env.vars.values() {
+                out.push(HeapObject::Global(cell.clone()));
+            },
+            &HeapObject::Promise(ref promise) => promise.trace(out),
+            &HeapObject::Parameter(ref param) => param.trace(out),
+            &HeapObject::Winder(ref winder) => winder.trace(out)
+        }
+    }
+}
+
+impl Trace for RDatum {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        match self {
+            &Datum::Cons(ref pair) => out.push(HeapObject::Pair(pair.clone())),
+            &Datum::Vector(ref vec) => out.push(HeapObject::Vector(vec.clone())),
+            &Datum::Ext(ref data) => data.trace(out),
+            _ => ()
+        }
+    }
+}
+
+impl Trace for RuntimeData {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        match self {
+            &RuntimeData::Closure(ref closure) => closure.trace(out),
+            &RuntimeData::CaseLambda(ref case_lambda) => case_lambda.trace(out),
+            &RuntimeData::Continuation(ref k) => out.push(HeapObject::Continuation(k.clone())),
+            &RuntimeData::Condition(ref c) => out.push(HeapObject::Condition(c.clone())),
+            &RuntimeData::Hashtable(ref t) => out.push(HeapObject::Hashtable(t.clone())),
+            &RuntimeData::Record(ref r) => out.push(HeapObject::Record(r.clone())),
+            &RuntimeData::RecordType(ref t) => out.push(HeapObject::RecordType(t.clone())),
+            &RuntimeData::RecordConstructor(ref c) => out.push(HeapObject::RecordConstructor(c.clone())),
+            &RuntimeData::Port(ref p) => out.push(HeapObject::Port(p.clone())),
+            &RuntimeData::Environment(ref e) => out.push(HeapObject::Environment(e.clone())),
+            &RuntimeData::Promise(ref p) => out.push(HeapObject::Promise(p.clone())),
+            &RuntimeData::Parameter(ref p) => out.push(HeapObject::Parameter(p.clone())),
+            &RuntimeData::MultipleValues(ref vs) => for v in vs.iter() {
+                v.trace(out)
+            },
+            _ => ()
+        }
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        out.push(HeapObject::Code(self.code.clone()));
+        if let Some(ref link) = self.static_link {
+            out.push(HeapObject::Scope(link.clone()));
+        }
+    }
+}
+
+impl Trace for CaseLambda {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        for clause in self.clauses.iter() {
+            out.push(HeapObject::Code(clause.code.clone()));
+        }
+        if let Some(ref link) = self.static_link {
+            out.push(HeapObject::Scope(link.clone()));
+        }
+    }
+}
+
+impl Trace for Inst {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        match self {
+            &Inst::PushArg(ref ptr) | &Inst::PopArg(ref ptr) => ptr.trace(out),
+            _ => ()
+        }
+    }
+}
+
+impl Trace for MemRef {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        match self {
+            &MemRef::Global(ref cell) => out.push(HeapObject::Global(cell.clone())),
+            &MemRef::Closure(ref code, _, _) => out.push(HeapObject::Code(code.clone())),
+            &MemRef::CaseLambda(ref clauses, _) => for clause in clauses.iter() {
+                out.push(HeapObject::Code(clause.code.clone()));
+            },
+            _ => ()
+        }
+    }
+}
+
+/// Weak reference to a mutable cell tracked by the collector
+enum WeakCell {
+    Pair(Weak<Pair<RuntimeData>>),
+    Scope(Weak<RefCell<ScopePtr>>),
+    Global(Weak<RefCell<RDatum>>),
+    Hashtable(Weak<Hashtable>),
+    Record(Weak<Record>),
+    RecordType(Weak<RecordType>),
+    Promise(Weak<Promise>)
+}
+
+impl WeakCell {
+    fn upgrade(&self) -> Option<HeapObject> {
+        match self {
+            &WeakCell::Pair(ref ptr) => ptr.upgrade().map(HeapObject::Pair),
+            &WeakCell::Scope(ref ptr) => ptr.upgrade().map(HeapObject::Scope),
+            &WeakCell::Global(ref ptr) => ptr.upgrade().map(HeapObject::Global),
+            &WeakCell::Hashtable(ref ptr) => ptr.upgrade().map(HeapObject::Hashtable),
+            &WeakCell::Record(ref ptr) => ptr.upgrade().map(HeapObject::Record),
+            &WeakCell::RecordType(ref ptr) => ptr.upgrade().map(HeapObject::RecordType),
+            &WeakCell::Promise(ref ptr) => ptr.upgrade().map(HeapObject::Promise)
+        }
+    }
+}
+
+/// Statistics of the garbage collector
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GcStats {
+    /// Number of collections run so far
+    pub collections: usize,
+    /// Number of objects traced by the last collection
+    pub traced: usize,
+    /// Number of objects freed by the last collection
+    pub freed: usize,
+    /// Number of objects freed by all collections
+    pub total_freed: usize,
+    /// Number of cells tracked by the collector
+    pub tracked: usize
+}
+
+/// Tracing collector freeing the reference cycles which reference counting cannot.
+///
+/// The heap tracks the mutable cells through which cycles can be made. A collection traces every
+/// object reachable from the tracked cells, and counts the references among them. Objects having
+/// more references than counted are referred from the outside, such as the stack of the VM or the
+/// host program, and everything reachable from them is live. The rest is garbage only referred by
+/// each other.
+///
+/// A new object only refers to the objects existing before it, so every cycle is closed by a
+/// store into an existing object. The collector finds a cycle only if it passes through a tracked
+/// cell, hence the invariant: every store of a value into an object which the trace follows must
+/// track the object. The stores are
+///
+/// * `set-car!` and `set-cdr!`, tracking the pair in `Inst::SetCar` and `Inst::SetCdr`
+/// * `hashtable-set!`, `hashtable-update!`, the record mutators and `define-record-type` keeping
+///   the constructor descriptor in the type, whose primitives return the modified object for
+///   `Inst::TrackStore` to track
+/// * `set!` of a variable captured by closures, into the environment tracked when its frame leaves
+///   the stack
+/// * `set!` and `define` of a global variable. The cells bound in the global environment are live,
+///   and a cell is tracked when it is no longer bound
+/// * `force`, tracking the promise in `Inst::UpdatePromise`
+/// * `parameterize`, into the value of the parameter object tracked when it is made
+///
+/// The other mutable objects, such as strings, bytevectors and ports, never store a value into the
+/// objects traced after they are made.
+pub struct Heap {
+    cells: Vec<WeakCell>,
+    threshold: usize,
+    stats: GcStats
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            cells: Vec::new(),
+            threshold: INITIAL_THRESHOLD,
+            stats: GcStats::default()
+        }
+    }
+
+    /// Tracks the pair modified by `set-car!` or `set-cdr!`
+    pub fn track_pair(&mut self, pair: &Rc<Pair<RuntimeData>>) {
+        self.cells.push(WeakCell::Pair(Rc::downgrade(pair)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Tracks the environment which closures refer to
+    pub fn track_scope(&mut self, link: &StaticLink) {
+        self.cells.push(WeakCell::Scope(Rc::downgrade(link)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Tracks the global variable cell which is no longer bound to the name, or the cell of a
+    /// parameter object
+    pub fn track_global(&mut self, cell: &Rc<RefCell<RDatum>>) {
+        self.cells.push(WeakCell::Global(Rc::downgrade(cell)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Tracks the hashtable updated by `hashtable-set!` or `hashtable-update!`
+    pub fn track_hashtable(&mut self, table: &Rc<Hashtable>) {
+        self.cells.push(WeakCell::Hashtable(Rc::downgrade(table)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Tracks the record whose field is replaced by a mutator
+    pub fn track_record(&mut self, record: &Rc<Record>) {
+        self.cells.push(WeakCell::Record(Rc::downgrade(record)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Tracks the record type given its constructor descriptor by `define-record-type`
+    pub fn track_record_type(&mut self, rtd: &Rc<RecordType>) {
+        self.cells.push(WeakCell::RecordType(Rc::downgrade(rtd)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Tracks the promise updated by `force`
+    pub fn track_promise(&mut self, promise: &Rc<Promise>) {
+        self.cells.push(WeakCell::Promise(Rc::downgrade(promise)));
+        self.stats.tracked = self.cells.len();
+    }
+
+    /// Whether the heap has grown enough to run a collection
+    pub fn should_collect(&self) -> bool {
+        self.cells.len() >= self.threshold
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// Frees the garbage cycles, and returns the updated statistics
+    pub fn collect(&mut self) -> GcStats {
+        let mut objects: HashMap<usize, HeapObject> = HashMap::new();
+        let mut refs: HashMap<usize, usize> = HashMap::new();
+
+        // Trace phase: holds one handle for each object, and counts the references between them
+        let mut stack: Vec<HeapObject> = self.cells.iter().filter_map(WeakCell::upgrade).collect();
+        let mut children = Vec::new();
+        while let Some(obj) = stack.pop() {
+            let id = obj.id();
+            if objects.contains_key(&id) {
+                continue;
+            }
+            obj.trace(&mut children);
+            for child in children.iter() {
+                *refs.entry(child.id()).or_insert(0) += 1;
+            }
+            stack.extend(children.drain(..));
+            objects.insert(id, obj);
+        }
+
+        // Mark phase: the roots are the objects referred from the outside of the traced graph
+        let mut live = HashSet::new();
+        for (id, obj) in objects.iter() {
+            // one of the references is the handle in `objects`
+            if obj.strong_count() > 1 + refs.get(id).cloned().unwrap_or(0) {
+                stack.push(obj.clone());
+            }
+        }
+        while let Some(obj) = stack.pop() {
+            if live.insert(obj.id()) {
+                obj.trace(&mut stack);
+            }
+        }
+
+        // Sweep phase
+        let mut freed = 0;
+        for (id, obj) in objects.iter() {
+            if !live.contains(id) {
+                obj.clear();
+                freed += 1;
+            }
+        }
+        let traced = objects.len();
+        drop(objects);
+
+        let mut tracked = HashSet::new();
+        self.cells.retain(|cell| match cell.upgrade() {
+            Some(obj) => tracked.insert(obj.id()),
+            None => false
+        });
+        self.threshold = cmp::max(INITIAL_THRESHOLD, self.cells.len() * 2);
+
+        self.stats.collections += 1;
+        self.stats.traced = traced;
+        self.stats.freed = freed;
+        self.stats.total_freed += freed;
+        self.stats.tracked = self.cells.len();
+        self.stats
+    }
+}
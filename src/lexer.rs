@@ -174,8 +174,6 @@ impl <R: Read + Sized> Lexer<R> {
 
         let token = self.lex()?;
 
-        self.consume_whitespace()?;
-
         Ok(wrap(line, col, token))
     }
 
@@ -384,7 +382,8 @@ impl <R: Read + Sized> Lexer<R> {
         }
     }
 
-    fn lookahead(&mut self) -> Result<Option<char>, CharsError> {
+    /// Next character of the stream, which is read but not consumed yet
+    pub fn lookahead(&mut self) -> Result<Option<char>, CharsError> {
         match self.stream.peek() {
             Some(&Ok(c)) => Ok(Some(c)),
             Some(&Err(_)) => Err(self.stream.next().unwrap().unwrap_err()),
@@ -14,6 +14,7 @@ extern crate phf;
 extern crate regex;
 extern crate num;
 extern crate unicode_categories;
+extern crate unicode_normalization;
 extern crate immutable_map;
 
 #[cfg(test)]
@@ -61,3 +62,27 @@ pub mod number;
 pub mod cast;
 /// Macro implementations
 pub mod syntax;
+/// R6RS condition types
+pub mod condition;
+/// Garbage collector
+pub mod heap;
+/// Libraries and import sets
+pub mod library;
+/// R6RS string procedures
+pub mod string;
+/// Characters and Unicode procedures of `(rnrs unicode)`
+pub mod unicode;
+/// R6RS hashtables
+pub mod hashtable;
+/// R6RS records
+pub mod record;
+/// R6RS enumerations
+pub mod enums;
+/// R6RS bytevectors
+pub mod bytevector;
+/// R6RS ports
+pub mod port;
+/// Promises of `delay` and `force`
+pub mod promise;
+/// Parameter objects of `make-parameter` and `parameterize`
+pub mod parameter;
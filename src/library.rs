@@ -0,0 +1,435 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use compiler::Syntax;
+use datum::Datum;
+use error::{CompileError, CompileErrorKind};
+use number::Number;
+use real::Real;
+use runtime::RDatum;
+
+/// Name of a library without the version, such as `(rnrs base)`
+pub type LibraryName = Vec<Cow<'static, str>>;
+
+/// Binding of an identifier exported by a library
+#[derive(Debug, Clone)]
+pub enum Export {
+    /// Variable
+    Var(Rc<RefCell<RDatum>>),
+    /// Keyword
+    Syntax(Syntax)
+}
+
+impl PartialEq for Export {
+    fn eq(&self, other: &Export) -> bool {
+        match (self, other) {
+            (&Export::Var(ref x), &Export::Var(ref y)) =>
+                (&**x as *const RefCell<RDatum>) == (&**y as *const RefCell<RDatum>),
+            (&Export::Syntax(ref x), &Export::Syntax(ref y)) => x == y,
+            _ => false
+        }
+    }
+}
+
+/// Top-level bindings of a library body or a program
+#[derive(Clone, Default)]
+pub struct Environment {
+    pub vars: HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
+    pub syntax: HashMap<Cow<'static, str>, Syntax>,
+    /// Variables imported from libraries, which cannot be assigned
+    pub imported: HashSet<Cow<'static, str>>
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<environment>")
+    }
+}
+
+/// Environments are compared by identity, as `eqv?` compares the environments of `eval`
+impl PartialEq for Environment {
+    fn eq(&self, other: &Environment) -> bool {
+        self as *const Environment == other as *const Environment
+    }
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    /// Finds the binding of the identifier
+    pub fn get(&self, sym: &str) -> Option<Export> {
+        if let Some(cell) = self.vars.get(sym) {
+            Some(Export::Var(cell.clone()))
+        } else if let Some(syntax) = self.syntax.get(sym) {
+            Some(Export::Syntax(syntax.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Adds the imported bindings. Importing the same identifier with different bindings is an
+    /// error
+    pub fn import(&mut self, bindings: HashMap<Cow<'static, str>, Export>) -> Result<(), CompileError> {
+        for (sym, binding) in bindings.into_iter() {
+            match self.get(&sym) {
+                Some(ref old) if *old != binding =>
+                    return Err(CompileError { kind: CompileErrorKind::ImportConflict(sym) }),
+                _ => ()
+            }
+            match binding {
+                Export::Var(cell) => {
+                    self.vars.insert(sym.clone(), cell);
+                    self.imported.insert(sym);
+                },
+                Export::Syntax(syntax) => { self.syntax.insert(sym, syntax); }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Library registered on the runtime
+#[derive(Debug)]
+pub struct Library {
+    pub name: LibraryName,
+    pub version: Vec<usize>,
+    pub exports: HashMap<Cow<'static, str>, Export>
+}
+
+impl Library {
+    pub fn new(name: LibraryName, version: Vec<usize>, exports: HashMap<Cow<'static, str>, Export>)
+            -> Library
+    {
+        Library {
+            name: name,
+            version: version,
+            exports: exports
+        }
+    }
+}
+
+/// Formats the library name as `(rnrs base)`
+pub struct DisplayName<'a>(pub &'a [Cow<'static, str>]);
+
+impl<'a> fmt::Display for DisplayName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, part) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", part)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Libraries which import sets refer to
+#[derive(Clone)]
+pub struct LibraryRegistry {
+    libraries: HashMap<LibraryName, Rc<Library>>
+}
+
+fn to_list<T: Clone>(datum: &Datum<T>) -> Result<Vec<Datum<T>>, CompileError> {
+    datum.iter().collect::<Result<Vec<Datum<T>>, ()>>().map_err(|_| CompileError { kind: CompileErrorKind::BadSyntax })
+}
+
+fn to_sym<T>(datum: &Datum<T>) -> Result<Cow<'static, str>, CompileError> {
+    match datum {
+        &Datum::Sym(ref sym) => Ok(sym.clone()),
+        _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+    }
+}
+
+fn to_usize<T>(datum: &Datum<T>) -> Option<usize> {
+    if let &Datum::Num(ref n) = datum {
+        if let Number::Real(r) = n.clone().reduce() {
+            if let Real::Fixnum(f) = r.reduce() {
+                if f >= 0 {
+                    return Some(f as usize);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Parses `(<identifier> ... [<version>])` into the name and the version of the library
+pub fn parse_library_name<T: Clone>(datum: &Datum<T>) -> Result<(LibraryName, Vec<usize>), CompileError> {
+    let (name, version) = split_version(to_list(datum)?)?;
+    let version = match version {
+        Some(v) => to_list(&v)?.iter().map(|n| to_usize(n).ok_or(
+            CompileError { kind: CompileErrorKind::BadSyntax }
+        )).collect::<Result<Vec<usize>, CompileError>>()?,
+        None => Vec::new()
+    };
+    Ok((name, version))
+}
+
+/// Splits the trailing version or version reference from the library name
+fn split_version<T: Clone>(mut list: Vec<Datum<T>>) -> Result<(LibraryName, Option<Datum<T>>), CompileError> {
+    let version = match list.last() {
+        Some(&Datum::Sym(_)) | None => None,
+        Some(v) => Some(v.clone())
+    };
+    if version.is_some() {
+        list.pop();
+    }
+    if list.is_empty() {
+        return Err(CompileError { kind: CompileErrorKind::BadSyntax });
+    }
+
+    let name = list.iter().map(to_sym).collect::<Result<LibraryName, CompileError>>()?;
+    Ok((name, version))
+}
+
+/// Whether the version matches the version reference of an import set
+fn version_matches<T: Clone>(version: &[usize], vref: &Datum<T>) -> Result<bool, CompileError> {
+    let list = to_list(vref)?;
+    if let Some(&Datum::Sym(ref head)) = list.first() {
+        let refs = &list[1..];
+        match (head.as_ref(), refs) {
+            ("and", _) => {
+                for r in refs.iter() {
+                    if !version_matches(version, r)? {
+                        return Ok(false);
+                    }
+                }
+                return Ok(true);
+            },
+            ("or", _) => {
+                for r in refs.iter() {
+                    if version_matches(version, r)? {
+                        return Ok(true);
+                    }
+                }
+                return Ok(false);
+            },
+            ("not", &[ref r]) => return Ok(!version_matches(version, r)?),
+            _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+
+    if list.len() > version.len() {
+        return Ok(false);
+    }
+    for (&n, subref) in version.iter().zip(list.iter()) {
+        if !subversion_matches(n, subref)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn subversion_matches<T: Clone>(n: usize, subref: &Datum<T>) -> Result<bool, CompileError> {
+    if let Some(m) = to_usize(subref) {
+        return Ok(n == m);
+    }
+
+    let list = to_list(subref)?;
+    let head = match list.first() {
+        Some(head) => to_sym(head)?,
+        None => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+    };
+    let refs = &list[1..];
+    let res = match (head.as_ref(), refs) {
+        (">=", &[ref m]) => to_usize(m).map(|m| n >= m),
+        ("<=", &[ref m]) => to_usize(m).map(|m| n <= m),
+        ("and", _) => {
+            for r in refs.iter() {
+                if !subversion_matches(n, r)? {
+                    return Ok(false);
+                }
+            }
+            Some(true)
+        },
+        ("or", _) => {
+            for r in refs.iter() {
+                if subversion_matches(n, r)? {
+                    return Ok(true);
+                }
+            }
+            Some(false)
+        },
+        ("not", &[ref r]) => Some(!subversion_matches(n, r)?),
+        _ => None
+    };
+    res.ok_or(CompileError { kind: CompileErrorKind::BadSyntax })
+}
+
+/// Whether the import set is a library reference, rather than `(only <import set> ...)` and such
+fn is_library_reference<T: Clone>(list: &[Datum<T>]) -> bool {
+    match list {
+        &[Datum::Sym(ref head), ref set, ..] => match head.as_ref() {
+            "only" | "except" | "prefix" | "rename" | "for" | "library" =>
+                if let &Datum::Cons(_) = set { false } else { true },
+            _ => true
+        },
+        _ => true
+    }
+}
+
+/// Finds the name of the library which the import set refers to
+pub fn import_set_library<T: Clone>(set: &Datum<T>) -> Result<LibraryName, CompileError> {
+    let list = to_list(set)?;
+    if is_library_reference(&list) {
+        return Ok(split_version(list)?.0);
+    }
+
+    match (to_sym(&list[0])?.as_ref(), &list[1..]) {
+        ("library", &[ref libref]) => Ok(split_version(to_list(libref)?)?.0),
+        ("library", _) => Err(CompileError { kind: CompileErrorKind::BadSyntax }),
+        (_, args) => import_set_library(&args[0])
+    }
+}
+
+impl LibraryRegistry {
+    pub fn new() -> LibraryRegistry {
+        LibraryRegistry { libraries: HashMap::new() }
+    }
+
+    /// Registers the library, replacing the library of the same name
+    pub fn register(&mut self, library: Library) {
+        self.libraries.insert(library.name.clone(), Rc::new(library));
+    }
+
+    /// Finds the library by name
+    pub fn get(&self, name: &[Cow<'static, str>]) -> Option<Rc<Library>> {
+        self.libraries.get(name).cloned()
+    }
+
+    /// Finds the library by the library reference `(<identifier> ... [<version reference>])`
+    pub fn find<T: Clone>(&self, libref: &Datum<T>) -> Result<Rc<Library>, CompileError> {
+        let (name, vref) = split_version(to_list(libref)?)?;
+        if let Some(library) = self.get(&name) {
+            let matches = match vref {
+                Some(ref v) => version_matches(&library.version, v)?,
+                None => true
+            };
+            if matches {
+                return Ok(library);
+            }
+        }
+        Err(CompileError { kind: CompileErrorKind::UnknownLibrary(format!("{}", DisplayName(&name))) })
+    }
+
+    /// Resolves the import set into the bindings it imports
+    pub fn import<T: Clone>(&self, set: &Datum<T>) -> Result<HashMap<Cow<'static, str>, Export>, CompileError> {
+        let list = to_list(set)?;
+        if is_library_reference(&list) {
+            return Ok(self.find(set)?.exports.clone());
+        }
+
+        let head = to_sym(&list[0])?;
+        if head == "library" {
+            return match &list[1..] {
+                &[ref libref] => Ok(self.find(libref)?.exports.clone()),
+                _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            };
+        }
+
+        let mut bindings = self.import(&list[1])?;
+        let args = &list[2..];
+        match head.as_ref() {
+            "only" => {
+                let mut only = HashMap::new();
+                for arg in args.iter() {
+                    let sym = to_sym(arg)?;
+                    match bindings.remove(&sym) {
+                        Some(binding) => { only.insert(sym, binding); },
+                        None => return Err(CompileError { kind: CompileErrorKind::UnboundVariable(sym) })
+                    }
+                }
+                Ok(only)
+            },
+            "except" => {
+                for arg in args.iter() {
+                    let sym = to_sym(arg)?;
+                    if bindings.remove(&sym).is_none() {
+                        return Err(CompileError { kind: CompileErrorKind::UnboundVariable(sym) });
+                    }
+                }
+                Ok(bindings)
+            },
+            "prefix" => match args {
+                &[Datum::Sym(ref prefix)] => Ok(bindings.into_iter().map(|(sym, binding)|
+                    (Cow::Owned(format!("{}{}", prefix, sym)), binding)
+                ).collect()),
+                _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+            },
+            "rename" => {
+                let mut renamed = Vec::new();
+                for arg in args.iter() {
+                    match to_list(arg)?.as_slice() {
+                        &[Datum::Sym(ref from), Datum::Sym(ref to)] => match bindings.remove(from) {
+                            Some(binding) => renamed.push((to.clone(), binding)),
+                            None => return Err(CompileError {
+                                kind: CompileErrorKind::UnboundVariable(from.clone())
+                            })
+                        },
+                        _ => return Err(CompileError { kind: CompileErrorKind::BadSyntax })
+                    }
+                }
+                bindings.extend(renamed);
+                Ok(bindings)
+            },
+            // phases are not distinguished
+            "for" => Ok(bindings),
+            _ => Err(CompileError { kind: CompileErrorKind::BadSyntax })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use datum::Datum;
+    use error::CompileErrorKind;
+    use parser::Parser;
+    use super::{parse_library_name, version_matches, Library, LibraryRegistry};
+
+    fn parse(src: &str) -> Datum<()> {
+        Parser::new(src.as_bytes()).parse_datum().unwrap()
+    }
+
+    #[test]
+    fn test_library_name() {
+        assert_eq!(parse_library_name(&parse("(rnrs base (6))")),
+                   Ok((vec![Cow::Borrowed("rnrs"), Cow::Borrowed("base")], vec![6])));
+        assert_eq!(parse_library_name(&parse("(foo)")),
+                   Ok((vec![Cow::Borrowed("foo")], vec![])));
+        assert!(parse_library_name(&parse("((1))")).is_err());
+    }
+
+    #[test]
+    fn test_version_reference() {
+        let version = [1, 2];
+        assert_eq!(version_matches(&version, &parse("()")), Ok(true));
+        assert_eq!(version_matches(&version, &parse("(1)")), Ok(true));
+        assert_eq!(version_matches(&version, &parse("(1 2)")), Ok(true));
+        assert_eq!(version_matches(&version, &parse("(1 2 0)")), Ok(false));
+        assert_eq!(version_matches(&version, &parse("(2)")), Ok(false));
+        assert_eq!(version_matches(&version, &parse("((>= 1) (<= 1))")), Ok(false));
+        assert_eq!(version_matches(&version, &parse("(1 (or 0 2))")), Ok(true));
+        assert_eq!(version_matches(&version, &parse("(1 (not (and (>= 1) (<= 3))))")), Ok(false));
+        assert_eq!(version_matches(&version, &parse("(or (2) (not (3)))")), Ok(true));
+    }
+
+    #[test]
+    fn test_unknown_library() {
+        let mut registry = LibraryRegistry::new();
+        registry.register(Library::new(vec![Cow::Borrowed("foo")], vec![1], HashMap::new()));
+        assert!(registry.import(&parse("(foo (1))")).is_ok());
+        assert!(registry.import(&parse("(library (foo))")).is_ok());
+        assert_eq!(registry.import(&parse("(foo (2))")).unwrap_err().kind,
+                   CompileErrorKind::UnknownLibrary("(foo)".to_string()));
+        assert_eq!(registry.import(&parse("(only (bar) x)")).unwrap_err().kind,
+                   CompileErrorKind::UnknownLibrary("(bar)".to_string()));
+    }
+}
@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use error::RuntimeErrorKind;
+use heap::{HeapObject, Trace};
+use runtime::{DatumType, Inst, MemRef, RDatum};
+
+/// Parameter object made by `make-parameter`. Calling it returns the value in the cell, which
+/// `parameterize` replaces while its body runs
+#[derive(Debug)]
+pub struct Parameter {
+    /// Cell holding the current value
+    pub value: Rc<RefCell<RDatum>>,
+    /// Procedure converting the values given to `make-parameter` and `parameterize`
+    pub converter: Option<RDatum>
+}
+
+impl Parameter {
+    pub fn new(value: Rc<RefCell<RDatum>>, converter: Option<RDatum>) -> Parameter {
+        Parameter { value: value, converter: converter }
+    }
+}
+
+impl PartialEq for Parameter {
+    fn eq(&self, other: &Parameter) -> bool {
+        (self as *const Parameter) == (other as *const Parameter)
+    }
+}
+
+impl Trace for Parameter {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        out.push(HeapObject::Global(self.value.clone()));
+        if let Some(ref converter) = self.converter {
+            converter.trace(out);
+        }
+    }
+}
+
+/// Bytecode of `(make-parameter value)` or `(make-parameter value converter)`. The initial value
+/// is passed through the converter
+pub fn make_parameter_code() -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(0),
+        Inst::Type(DatumType::Pair),
+        Inst::JumpIfFalse(23),
+        Inst::DropArg(1),
+        Inst::Uncons,
+        Inst::SetArgSize(2),
+        Inst::Type(DatumType::Null),
+        Inst::JumpIfFalse(12),
+        Inst::DropArg(1),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::MakeParameter(false),
+        Inst::Return,
+        // 12
+        Inst::DropArg(1),
+        Inst::Uncons,
+        Inst::Type(DatumType::Null),
+        Inst::JumpIfFalse(23),
+        Inst::DropArg(2),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::MakeParameter(true),
+        Inst::Return,
+        // 23
+        Inst::Throw(RuntimeErrorKind::NumArgs, "make-parameter takes 1 or 2 arguments")
+    ]
+}
+
+/// Bytecode of the thunk entering or leaving the dynamic extent of `parameterize`. It is enclosed
+/// by the frame holding each parameter followed by its value, and swaps the values with the ones
+/// in the parameters
+pub fn swap_parameters_code(nparams: usize) -> Vec<Inst> {
+    let mut code = Vec::new();
+    for i in 0 .. nparams {
+        code.push(Inst::PushArg(MemRef::UpValue(0, 2*i)));
+        code.push(Inst::PushArg(MemRef::UpValue(0, 2*i + 1)));
+        code.push(Inst::SwapParameter);
+        code.push(Inst::PopArg(MemRef::UpValue(0, 2*i + 1)));
+    }
+    code.push(Inst::PushArg(MemRef::Undefined));
+    code.push(Inst::Return);
+    code
+}
@@ -10,7 +10,7 @@ use regex::{Regex, Captures};
 
 use real::{Real, rat2flo};
 use number::Number;
-use datum::{Datum, cons};
+use datum::{Datum, bytes, cons, string};
 use lexer::{Token, TokenWrapper, Lexer};
 use error::{ParserError, ParserErrorKind};
 use num::{Zero, One, FromPrimitive, Float, Num};
@@ -354,6 +354,22 @@ impl <R: Read + Sized> Parser<R> {
         }
     }
 
+    /// Parse next datum, or returns `None` at the end of the stream
+    pub fn parse_next<T>(&mut self) -> Result<Option<Datum<T>>, ParserError> {
+        let tok = self.consume_token()?;
+        if let Token::EOF = tok.token {
+            return Ok(None);
+        }
+        self.token_buf = Some(tok);
+        self.parse_datum().map(Some)
+    }
+
+    /// The character after the datums parsed so far, which the lexer has read from the stream
+    /// ahead of them
+    pub fn lookahead(&mut self) -> Result<Option<char>, ParserError> {
+        Ok(self.lexer.lookahead()?)
+    }
+
     /// Parse next datum
     pub fn parse_datum<T>(&mut self) -> Result<Datum<T>, ParserError> {
         let tok = self.consume_token()?;
@@ -366,7 +382,7 @@ impl <R: Read + Sized> Parser<R> {
             ),
             Token::OpenBytesParen => {
                 let v:Vec<Datum<T>> = self.parse_vector()?;
-                let bytes:Result<Vec<u8>, ParserError> = v.iter().map(|d|
+                let res:Result<Vec<u8>, ParserError> = v.iter().map(|d|
                     match d {
                         &Datum::Num(Number::Real(Real::Fixnum(n))) if 0 <= n && n <= 0xff =>
                             Ok(n as u8),
@@ -377,7 +393,7 @@ impl <R: Read + Sized> Parser<R> {
                                 kind: ParserErrorKind::ByteVectorElement
                             })
                     }).collect();
-                bytes.map(|v| Datum::Bytes(Rc::new(v)))
+                res.map(bytes)
             },
             Token::True => Ok(Datum::Bool(true)),
             Token::False => Ok(Datum::Bool(false)),
@@ -385,7 +401,7 @@ impl <R: Read + Sized> Parser<R> {
                 Some(c) => Ok(Datum::Char(c)),
                 None => Err(invalid_token(&tok))
             },
-            Token::String(s) => Ok(Datum::String(Rc::new(s))),
+            Token::String(s) => Ok(string(&s)),
             Token::Numeric(ref rep) => match self.number_parser.parse_numeric(rep.as_ref()) {
                 Ok(n) => Ok(Datum::Num(n)),
                 Err(e) => Err(ParserError {
@@ -501,7 +517,7 @@ mod test {
 
     use error::{ParserError, ParserErrorKind};
     use super::Parser;
-    use datum::{Datum, cons};
+    use datum::{Datum, bytes, cons, string};
     use number::Number;
     use real::Real;
 
@@ -554,10 +570,10 @@ mod test {
 
     #[test]
     fn test_string() {
-        test_parse_ok!(r#""abc""#, Datum::String(Rc::new("abc".to_string())));
-        test_parse_ok!(r#""\x41;bc""#, Datum::String(Rc::new("Abc".to_string())));
-        test_parse_ok!(r#""\x41; bc""#, Datum::String(Rc::new("A bc".to_string())));
-        test_parse_ok!(r#""\x41bc;""#, Datum::String(Rc::new("\u{41bc}".to_string())));
+        test_parse_ok!(r#""abc""#, string("abc"));
+        test_parse_ok!(r#""\x41;bc""#, string("Abc"));
+        test_parse_ok!(r#""\x41; bc""#, string("A bc"));
+        test_parse_ok!(r#""\x41bc;""#, string("\u{41bc}"));
     }
 
     #[test]
@@ -586,8 +602,8 @@ mod test {
 
     #[test]
     fn test_bytes() {
-        test_parse_ok!("#vu8()", Datum::Bytes(Rc::new(Vec::new())));
-        test_parse_ok!("#vu8(1 2 3)", Datum::Bytes(Rc::new(vec![1, 2, 3])));
+        test_parse_ok!("#vu8()", bytes(Vec::new()));
+        test_parse_ok!("#vu8(1 2 3)", bytes(vec![1, 2, 3]));
     }
 
     #[test]
@@ -664,4 +680,19 @@ mod test {
     fn test_parse_ellipsis_list() {
         test_parse_ok!("(a b ... c)", list!(sym!("a"), sym!("b"), sym!("..."), sym!("c")));
     }
+
+    #[test]
+    fn test_parse_next() {
+        let mut parser = Parser::new("a (b)c ; end\n".as_bytes());
+        let res: Result<Option<Datum<()>>, ParserError> = parser.parse_next();
+        assert_eq!(res, Ok(Some(sym!("a"))));
+        let res: Result<Option<Datum<()>>, ParserError> = parser.parse_next();
+        assert_eq!(res, Ok(Some(list!(sym!("b")))));
+        // `c` is read after the list, but not parsed yet
+        assert_eq!(parser.lookahead(), Ok(Some('c')));
+        let res: Result<Option<Datum<()>>, ParserError> = parser.parse_next();
+        assert_eq!(res, Ok(Some(sym!("c"))));
+        let res: Result<Option<Datum<()>>, ParserError> = parser.parse_next();
+        assert_eq!(res, Ok(None));
+    }
 }
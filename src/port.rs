@@ -0,0 +1,1995 @@
+use std::cell::{Cell, RefCell, RefMut};
+use std::borrow::Cow;
+use std::collections::{vec_deque, VecDeque};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Cursor, Read, Write};
+use std::mem;
+use std::rc::Rc;
+use std::str;
+
+use base::apply_code;
+use cast::DatumCast;
+use condition::{ConditionType, SimpleCondition, PRIM_IO_ENCODING_ERROR_CHAR, PRIM_IO_ERROR_FILENAME,
+                PRIM_IO_ERROR_PORT, PRIM_IO_ERROR_POSITION, PRIM_IS_IO_DECODING_ERROR,
+                PRIM_IS_IO_ENCODING_ERROR, PRIM_IS_IO_ERROR, PRIM_IS_IO_FILENAME_ERROR,
+                PRIM_IS_IO_FILE_ALREADY_EXISTS_ERROR, PRIM_IS_IO_FILE_DOES_NOT_EXIST_ERROR,
+                PRIM_IS_IO_FILE_IS_READ_ONLY_ERROR, PRIM_IS_IO_FILE_PROTECTION_ERROR,
+                PRIM_IS_IO_INVALID_POSITION_ERROR, PRIM_IS_IO_PORT_ERROR, PRIM_IS_IO_READ_ERROR,
+                PRIM_IS_IO_WRITE_ERROR, PRIM_MAKE_IO_DECODING_ERROR, PRIM_MAKE_IO_ENCODING_ERROR,
+                PRIM_MAKE_IO_ERROR, PRIM_MAKE_IO_FILENAME_ERROR, PRIM_MAKE_IO_FILE_ALREADY_EXISTS_ERROR,
+                PRIM_MAKE_IO_FILE_DOES_NOT_EXIST_ERROR, PRIM_MAKE_IO_FILE_IS_READ_ONLY_ERROR,
+                PRIM_MAKE_IO_FILE_PROTECTION_ERROR, PRIM_MAKE_IO_INVALID_POSITION_ERROR,
+                PRIM_MAKE_IO_PORT_ERROR, PRIM_MAKE_IO_READ_ERROR, PRIM_MAKE_IO_WRITE_ERROR};
+use datum::{bytes, cons, string, Datum, SimpleDatum};
+use enums::{EnumSet, EnumType};
+use error::{ParserErrorKind, RuntimeError, RuntimeErrorKind};
+use heap::{HeapObject, Trace};
+use number::Number;
+use parameter::Parameter;
+use parser::Parser;
+use primitive::{F1, F2, FoldErr, PrimFunc, R1};
+use runtime::{Closure, DatumType, Inst, MemRef, PrimFuncPtr, RDatum, RuntimeData};
+
+/// Symbols of `(eol-style <symbol>)`
+pub const EOL_STYLES: &'static [&'static str] = &["lf", "cr", "crlf", "nel", "crnel", "ls", "none"];
+
+/// Symbols of `(error-handling-mode <symbol>)`
+pub const ERROR_HANDLING_MODES: &'static [&'static str] = &["ignore", "raise", "replace"];
+
+/// Symbols of `(buffer-mode <symbol>)`
+pub const BUFFER_MODES: &'static [&'static str] = &["none", "line", "block"];
+
+/// Universe of the enum sets made by `(file-options <symbol> ...)`
+const FILE_OPTIONS: &'static [&'static str] = &["no-create", "no-fail", "no-truncate"];
+
+/// Number of the bytes or the characters read from the source at once, unless a specific number
+/// is requested
+const CHUNK_SIZE: usize = 4096;
+
+thread_local!(static FILE_OPTIONS_SET: Rc<EnumSet> = Rc::new(EnumSet::full(Rc::new(EnumType {
+    symbols: FILE_OPTIONS.iter().map(|&s| Cow::Borrowed(s)).collect()
+}))));
+
+/// The set of all file options, whose enumeration type the sets of `file-options` share
+pub fn file_options_universe() -> Rc<EnumSet> {
+    FILE_OPTIONS_SET.with(|set| set.clone())
+}
+
+/// Character encoding of a transcoder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Latin1,
+    Utf8
+}
+
+impl Codec {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Codec::Latin1 => "latin-1",
+            Codec::Utf8 => "utf-8"
+        }
+    }
+}
+
+/// Line ending written for `#\newline`. Any line ending is read as `#\newline` unless the style is
+/// `none`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EolStyle {
+    Lf,
+    Cr,
+    Crlf,
+    Nel,
+    Crnel,
+    Ls,
+    None
+}
+
+impl EolStyle {
+    fn from_name(name: &str) -> Option<EolStyle> {
+        match name {
+            "lf" => Some(EolStyle::Lf),
+            "cr" => Some(EolStyle::Cr),
+            "crlf" => Some(EolStyle::Crlf),
+            "nel" => Some(EolStyle::Nel),
+            "crnel" => Some(EolStyle::Crnel),
+            "ls" => Some(EolStyle::Ls),
+            "none" => Some(EolStyle::None),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        EOL_STYLES[*self as usize]
+    }
+
+    fn ending(&self) -> &'static str {
+        match *self {
+            EolStyle::Lf | EolStyle::None => "\n",
+            EolStyle::Cr => "\r",
+            EolStyle::Crlf => "\r\n",
+            EolStyle::Nel => "\u{85}",
+            EolStyle::Crnel => "\r\u{85}",
+            EolStyle::Ls => "\u{2028}"
+        }
+    }
+}
+
+/// Line ending of the platform
+pub fn native_eol_style() -> EolStyle {
+    if cfg!(windows) { EolStyle::Crlf } else { EolStyle::Lf }
+}
+
+/// What the transcoder does with the bytes it cannot decode or the characters it cannot encode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorMode {
+    Ignore,
+    Raise,
+    Replace
+}
+
+impl ErrorMode {
+    fn from_name(name: &str) -> Option<ErrorMode> {
+        match name {
+            "ignore" => Some(ErrorMode::Ignore),
+            "raise" => Some(ErrorMode::Raise),
+            "replace" => Some(ErrorMode::Replace),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        ERROR_HANDLING_MODES[*self as usize]
+    }
+}
+
+/// Transcoder translating the bytes of a binary port to the characters of a textual port
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transcoder {
+    pub codec: Codec,
+    pub eol_style: EolStyle,
+    pub error_mode: ErrorMode
+}
+
+impl Transcoder {
+    pub fn native() -> Transcoder {
+        Transcoder {
+            codec: Codec::Utf8,
+            eol_style: native_eol_style(),
+            error_mode: ErrorMode::Replace
+        }
+    }
+
+    /// Decodes the bytes at the front of `bytes` into `chars`. An incomplete sequence at the end
+    /// is left in `bytes` for the next call, unless it is the end of the input. An invalid
+    /// sequence is consumed before the error is raised, so that decoding resumes after it. `cr`
+    /// tells whether the last character decoded was CR, so that LF or NEL following it is skipped
+    fn decode(&self, bytes: &mut VecDeque<u8>, chars: &mut VecDeque<char>, cr: &mut bool, eof: bool)
+            -> Result<(), RuntimeError>
+    {
+        while let Some(&b0) = bytes.front() {
+            let len = match self.codec {
+                Codec::Latin1 => 1,
+                Codec::Utf8 => match b0 {
+                    0x00 ... 0x7f => 1,
+                    0xc2 ... 0xdf => 2,
+                    0xe0 ... 0xef => 3,
+                    0xf0 ... 0xf4 => 4,
+                    _ => 0
+                }
+            };
+            if len > bytes.len() && !eof {
+                break;
+            }
+
+            // the decoded character and the number of the bytes it takes. The longest prefix of a
+            // valid sequence is taken as a single invalid sequence
+            let (decoded, n) = if self.codec == Codec::Latin1 {
+                (Some(b0 as char), 1)
+            } else {
+                let seq: Vec<u8> = bytes.iter().take(len.max(1)).cloned().collect();
+                match str::from_utf8(&seq) {
+                    Ok(s) => (s.chars().next(), len),
+                    Err(e) => (None, e.error_len().unwrap_or(seq.len()))
+                }
+            };
+            match decoded {
+                Some(c) => {
+                    bytes.drain(.. n);
+                    self.push_char(c, chars, cr);
+                },
+                None => {
+                    let invalid: Vec<u8> = bytes.drain(.. n).collect();
+                    match self.error_mode {
+                        ErrorMode::Ignore => (),
+                        ErrorMode::Replace => self.push_char('\u{fffd}', chars, cr),
+                        ErrorMode::Raise => return Err(io_condition(
+                            ConditionType::IoDecoding, vec![Datum::Bool(false)],
+                            format!("cannot decode bytes {:?} as {}", invalid, self.codec.name())))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push_char(&self, c: char, chars: &mut VecDeque<char>, cr: &mut bool) {
+        if self.eol_style == EolStyle::None {
+            chars.push_back(c);
+            return;
+        }
+        let after_cr = mem::replace(cr, c == '\r');
+        match c {
+            '\n' | '\u{85}' if after_cr => (),
+            '\r' | '\u{85}' | '\u{2028}' => chars.push_back('\n'),
+            _ => chars.push_back(c)
+        }
+    }
+
+    /// Encodes the characters, writing the line ending of the eol style for `#\newline`
+    fn encode(&self, chars: &[char], bytes: &mut Vec<u8>) -> Result<(), RuntimeError> {
+        for &c in chars.iter() {
+            if c == '\n' {
+                for e in self.eol_style.ending().chars() {
+                    self.encode_char(e, bytes)?;
+                }
+            } else {
+                self.encode_char(c, bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_char(&self, c: char, bytes: &mut Vec<u8>) -> Result<(), RuntimeError> {
+        match self.codec {
+            Codec::Utf8 => {
+                let mut buf = [0; 4];
+                bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+            },
+            Codec::Latin1 => if (c as u32) < 0x100 {
+                bytes.push(c as u8);
+            } else {
+                match self.error_mode {
+                    ErrorMode::Ignore => (),
+                    ErrorMode::Replace => bytes.push(b'?'),
+                    ErrorMode::Raise => return Err(io_condition(
+                        ConditionType::IoEncoding, vec![Datum::Bool(false), Datum::Char(c)],
+                        format!("cannot encode {:?} as {}", c, self.codec.name())))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Transcoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<transcoder {} {} {}>", self.codec.name(), self.eol_style.name(), self.error_mode.name())
+    }
+}
+
+fn io_error(desc: String) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::Io,
+        desc: desc
+    }
+}
+
+/// Error raised as the condition of the subtype of `&i/o` with the fields
+fn io_condition(ctype: ConditionType, fields: Vec<RDatum>, desc: String) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::IoCondition(SimpleCondition::new(ctype, fields)),
+        desc: desc
+    }
+}
+
+fn from_io_error(ctype: ConditionType, err: io::Error) -> RuntimeError {
+    io_condition(ctype, Vec::new(), format!("{}", err))
+}
+
+/// Sets the port field of the `&i/o-port` condition of the error, which the transcoder cannot
+/// know, to the port raising it
+fn port_error(port: &Rc<Port>, mut err: RuntimeError) -> RuntimeError {
+    if let RuntimeErrorKind::IoCondition(ref mut c) = err.kind {
+        if c.ctype.is_subtype_of(ConditionType::IoPort) {
+            c.fields[0] = Datum::Ext(RuntimeData::Port(port.clone()));
+        }
+    }
+    err
+}
+
+fn invalid(desc: String) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::InvalidType,
+        desc: desc
+    }
+}
+
+/// Where an input port reads from
+enum Source {
+    Reader(Box<Read>),
+    /// `read!` procedure of a custom port, called by the bytecode of the input procedures
+    Custom(RDatum),
+    /// The source has nothing more than what is already buffered
+    Exhausted
+}
+
+struct Input {
+    source: Source,
+    /// Bytes read from the source, not yet consumed or decoded into characters
+    bytes: VecDeque<u8>,
+    /// Characters decoded or read from the source, not yet consumed
+    chars: VecDeque<char>,
+    /// Whether the last character decoded was CR
+    cr: bool,
+    /// Whether the end of the source was reached. Consuming the end of file clears it, so that a
+    /// source like the console can be read again
+    eof: bool,
+    /// Buffer passed to the pending call of the `read!` procedure of a custom port
+    pending: Option<RDatum>
+}
+
+impl Input {
+    fn new(source: Source) -> Input {
+        Input {
+            source: source,
+            bytes: VecDeque::new(),
+            chars: VecDeque::new(),
+            cr: false,
+            eof: false,
+            pending: None
+        }
+    }
+}
+
+/// Where an output port writes to
+enum Sink {
+    Writer(Box<Write>),
+    /// The bytes or the characters are kept by the port until they are extracted
+    Memory,
+    /// `write!` procedure of a custom port, called by the bytecode of the output procedures
+    Custom(RDatum)
+}
+
+struct Output {
+    sink: Sink,
+    /// Bytes kept in the memory, or not yet accepted by the `write!` procedure
+    bytes: Vec<u8>,
+    /// Characters kept in the memory, or not yet accepted by the `write!` procedure
+    chars: Vec<char>
+}
+
+impl Output {
+    fn new(sink: Sink) -> Output {
+        Output {
+            sink: sink,
+            bytes: Vec::new(),
+            chars: Vec::new()
+        }
+    }
+}
+
+/// What an input procedure needs in the buffer before it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Demand {
+    /// The number of bytes or characters
+    Units(usize),
+    /// A whole line
+    Line,
+    /// Everything up to the end of file
+    All,
+    /// As much as `read` parses. Files and the console are read by the parser itself, and custom
+    /// ports are read a character at a time until the buffer begins with a datum
+    Datum
+}
+
+/// Port, reading bytes or characters from its source or writing them to its sink
+pub struct Port {
+    /// Name of the file, or the identifier of a custom port
+    id: String,
+    textual: bool,
+    /// Transcoder of a textual port over bytes. String ports and custom textual ports read and
+    /// write the characters as is
+    transcoder: Option<Transcoder>,
+    input: Option<RefCell<Input>>,
+    output: Option<RefCell<Output>>,
+    /// Procedure called when a custom port is closed
+    close: Option<RDatum>,
+    closed: Cell<bool>
+}
+
+/// The procedures of a custom port
+impl Trace for Port {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        if let Some(ref input) = self.input {
+            if let Source::Custom(ref read) = input.borrow().source {
+                read.trace(out);
+            }
+        }
+        if let Some(ref output) = self.output {
+            if let Sink::Custom(ref write) = output.borrow().sink {
+                write.trace(out);
+            }
+        }
+        if let Some(ref close) = self.close {
+            close.trace(out);
+        }
+    }
+}
+
+impl PartialEq for Port {
+    fn eq(&self, other: &Port) -> bool {
+        self as *const Port == other as *const Port
+    }
+}
+
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.textual { "textual" } else { "binary" };
+        let direction = match (self.input.is_some(), self.output.is_some()) {
+            (true, true) => "input/output",
+            (true, false) => "input",
+            _ => "output"
+        };
+        write!(f, "#<{}-{}-port {}>", kind, direction, self.id)
+    }
+}
+
+impl Port {
+    fn new(id: String, transcoder: Option<Transcoder>, input: Option<Input>, output: Option<Output>) -> Port {
+        Port {
+            id: id,
+            textual: transcoder.is_some(),
+            transcoder: transcoder,
+            input: input.map(RefCell::new),
+            output: output.map(RefCell::new),
+            close: None,
+            closed: Cell::new(false)
+        }
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    pub fn is_textual(&self) -> bool {
+        self.textual
+    }
+
+    pub fn transcoder(&self) -> Option<Transcoder> {
+        self.transcoder
+    }
+
+    fn kind_name(textual: bool) -> &'static str {
+        if textual { "textual" } else { "binary" }
+    }
+
+    fn check(&self, textual: Option<bool>, direction: &str) -> Result<(), RuntimeError> {
+        if let Some(textual) = textual {
+            if textual != self.textual {
+                return Err(invalid(format!("expected {} {} port, but received {}",
+                                           Port::kind_name(textual), direction, self)));
+            }
+        }
+        if self.closed.get() {
+            return Err(io_error(format!("{} is closed", self)));
+        }
+        Ok(())
+    }
+
+    /// The input buffer, if the port is an open input port of the kind
+    fn input(&self, textual: Option<bool>) -> Result<RefMut<Input>, RuntimeError> {
+        match self.input {
+            Some(ref input) => {
+                self.check(textual, "input")?;
+                Ok(input.borrow_mut())
+            },
+            None => Err(invalid(format!("expected input port, but received {}", self)))
+        }
+    }
+
+    /// The output buffer, if the port is an open output port of the kind
+    fn output(&self, textual: Option<bool>) -> Result<RefMut<Output>, RuntimeError> {
+        match self.output {
+            Some(ref output) => {
+                self.check(textual, "output")?;
+                Ok(output.borrow_mut())
+            },
+            None => Err(invalid(format!("expected output port, but received {}", self)))
+        }
+    }
+
+    fn buffered(&self, input: &Input, demand: Demand) -> bool {
+        match demand {
+            Demand::Units(n) if self.textual => input.chars.len() >= n,
+            Demand::Units(n) => input.bytes.len() >= n,
+            Demand::Line => input.chars.contains(&'\n'),
+            Demand::All => false,
+            Demand::Datum => match input.source {
+                Source::Reader(_) => true,
+                _ => Port::datum_buffered(input)
+            }
+        }
+    }
+
+    /// Whether the buffered characters begin with a whole datum or a malformed one, so that
+    /// parsing them doesn't need more from the source
+    fn datum_buffered(input: &Input) -> bool {
+        let mut reader = BufferReader {
+            chars: input.chars.iter(),
+            eof: input.eof,
+            starved: false,
+            bytes: Vec::new()
+        };
+        let _ = Parser::new(&mut reader).parse_next::<()>();
+        !reader.starved
+    }
+
+    /// Reads from the source until the demand is buffered or the source ends. A custom port
+    /// cannot be read here, so the call of its `read!` procedure filling the buffer is returned
+    /// as the list of the procedure and the arguments
+    fn fill(&self, demand: Demand) -> Result<Option<RDatum>, RuntimeError> {
+        let mut input = self.input(None)?;
+        let input = &mut *input;
+        loop {
+            if let Some(ref transcoder) = self.transcoder {
+                transcoder.decode(&mut input.bytes, &mut input.chars, &mut input.cr, input.eof)?;
+            }
+            if input.eof || self.buffered(input, demand) {
+                return Ok(None);
+            }
+
+            let count = match demand {
+                Demand::Units(n) if self.textual => n - input.chars.len(),
+                Demand::Units(n) => n - input.bytes.len(),
+                Demand::Datum => 1,
+                _ => CHUNK_SIZE
+            };
+            match input.source {
+                Source::Reader(ref mut reader) => {
+                    let mut buf = vec![0; count.max(CHUNK_SIZE)];
+                    let n = reader.read(&mut buf)
+                        .map_err(|e| from_io_error(ConditionType::IoRead, e))?;
+                    if n == 0 {
+                        input.eof = true;
+                    }
+                    input.bytes.extend(buf[.. n].iter());
+                },
+                Source::Custom(ref read) => {
+                    // custom textual ports give the characters, custom binary ports the bytes
+                    // which may be transcoded
+                    let buf = if self.textual && self.transcoder.is_none() {
+                        Datum::String(Rc::new(RefCell::new(vec!['\0'; count])))
+                    } else {
+                        bytes(vec![0; count])
+                    };
+                    input.pending = Some(buf.clone());
+                    let args = vec![read.clone(), buf, Datum::Num(Number::new_int(0, 0)), count.wrap()];
+                    return Ok(Some(args.into_iter().collect()));
+                },
+                Source::Exhausted => input.eof = true
+            }
+        }
+    }
+
+    /// Takes the result of the `read!` call returned by `fill`, the number of the units read
+    fn supply(&self, n: usize) -> Result<(), RuntimeError> {
+        let mut input = self.input(None)?;
+        let pending = input.pending.take();
+        match pending {
+            Some(Datum::Bytes(ref b)) if n <= b.borrow().len() =>
+                input.bytes.extend(b.borrow()[.. n].iter()),
+            Some(Datum::String(ref s)) if n <= s.borrow().len() =>
+                input.chars.extend(s.borrow()[.. n].iter()),
+            Some(_) => return Err(io_error(format!("{}: read! returned {}, more than requested", self, n))),
+            None => return Err(io_error(format!("{}: no read! call is pending", self)))
+        }
+        if n == 0 {
+            input.eof = true;
+        }
+        Ok(())
+    }
+
+    /// Takes the end of file at the position, if nothing is buffered
+    fn take_eof(input: &mut Input, units: usize, peek: bool) -> bool {
+        if units == 0 && input.eof {
+            if !peek {
+                input.eof = false;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn get_u8(&self, peek: bool) -> Result<RDatum, RuntimeError> {
+        let mut input = self.input(Some(false))?;
+        let len = input.bytes.len();
+        if Port::take_eof(&mut *input, len, peek) {
+            return Ok(Datum::Ext(RuntimeData::Eof));
+        }
+        let b = if peek { input.bytes[0] } else { input.bytes.pop_front().unwrap() };
+        Ok(Datum::Num(Number::new_int(b as isize, 0)))
+    }
+
+    fn get_bytes(&self, n: Option<usize>) -> Result<RDatum, RuntimeError> {
+        let mut input = self.input(Some(false))?;
+        let len = input.bytes.len();
+        if n != Some(0) && Port::take_eof(&mut *input, len, false) {
+            return Ok(Datum::Ext(RuntimeData::Eof));
+        }
+        let n = n.unwrap_or(len).min(len);
+        Ok(bytes(input.bytes.drain(.. n).collect()))
+    }
+
+    fn get_char(&self, peek: bool) -> Result<RDatum, RuntimeError> {
+        let mut input = self.input(Some(true))?;
+        let len = input.chars.len();
+        if Port::take_eof(&mut *input, len, peek) {
+            return Ok(Datum::Ext(RuntimeData::Eof));
+        }
+        let c = if peek { input.chars[0] } else { input.chars.pop_front().unwrap() };
+        Ok(Datum::Char(c))
+    }
+
+    fn get_string(&self, n: Option<usize>) -> Result<RDatum, RuntimeError> {
+        let mut input = self.input(Some(true))?;
+        let len = input.chars.len();
+        if n != Some(0) && Port::take_eof(&mut *input, len, false) {
+            return Ok(Datum::Ext(RuntimeData::Eof));
+        }
+        let n = n.unwrap_or(len).min(len);
+        Ok(Datum::String(Rc::new(RefCell::new(input.chars.drain(.. n).collect()))))
+    }
+
+    fn get_line(&self) -> Result<RDatum, RuntimeError> {
+        let mut input = self.input(Some(true))?;
+        let len = input.chars.len();
+        if Port::take_eof(&mut *input, len, false) {
+            return Ok(Datum::Ext(RuntimeData::Eof));
+        }
+        let line: Vec<char> = match input.chars.iter().position(|&c| c == '\n') {
+            Some(n) => {
+                let line = input.chars.drain(.. n).collect();
+                input.chars.pop_front();
+                line
+            },
+            None => input.chars.drain(..).collect()
+        };
+        Ok(Datum::String(Rc::new(RefCell::new(line))))
+    }
+
+    /// Parses the next datum from the characters. The character which the parser has read ahead
+    /// of the datum is put back into the buffer
+    fn read_datum(&self) -> Result<RDatum, RuntimeError> {
+        self.input(Some(true))?;
+        let (res, next) = {
+            let mut parser = Parser::new(PortReader { port: self, bytes: Vec::new() });
+            let res = parser.parse_next();
+            let next = if res.is_ok() { parser.lookahead() } else { Ok(None) };
+            (res, next)
+        };
+        let mut input = self.input(None)?;
+        if let Ok(Some(c)) = next {
+            input.chars.push_front(c);
+        }
+        match res {
+            Ok(Some(datum)) => Ok(datum),
+            Ok(None) => {
+                let len = input.chars.len();
+                Port::take_eof(&mut *input, len, false);
+                Ok(Datum::Ext(RuntimeData::Eof))
+            },
+            // the failures of the source are reported by the port, and the malformed data by the parser
+            Err(e) => match e.kind {
+                ParserErrorKind::UnderlyingError(_) => Err(io_error(format!("{}: {}", self, e))),
+                _ => Err(RuntimeError {
+                    kind: RuntimeErrorKind::Lexical,
+                    desc: format!("{}: {}", self, e)
+                })
+            }
+        }
+    }
+
+    /// Takes the next character, reading the source if the buffer is empty. A custom port is
+    /// already read up to the end of the datum by the `datum` demand
+    fn next_char(&self) -> Result<Option<char>, RuntimeError> {
+        self.fill(Demand::Units(1))?;
+        let mut input = self.input(None)?;
+        Ok(input.chars.pop_front())
+    }
+
+    fn is_eof(&self) -> Result<bool, RuntimeError> {
+        let input = self.input(None)?;
+        let len = if self.textual { input.chars.len() } else { input.bytes.len() };
+        Ok(len == 0 && input.eof)
+    }
+
+    fn write_bytes(output: &mut Output, data: &[u8]) -> Result<(), RuntimeError> {
+        match output.sink {
+            Sink::Writer(ref mut writer) =>
+                writer.write_all(data).map_err(|e| from_io_error(ConditionType::IoWrite, e)),
+            _ => {
+                output.bytes.extend(data.iter());
+                Ok(())
+            }
+        }
+    }
+
+    fn put_bytes(&self, data: &[u8]) -> Result<(), RuntimeError> {
+        let mut output = self.output(Some(false))?;
+        Port::write_bytes(&mut output, data)
+    }
+
+    fn put_chars(&self, data: &[char]) -> Result<(), RuntimeError> {
+        let mut output = self.output(Some(true))?;
+        match self.transcoder {
+            Some(ref transcoder) => {
+                let mut encoded = Vec::new();
+                transcoder.encode(data, &mut encoded)?;
+                Port::write_bytes(&mut output, &encoded)
+            },
+            None => {
+                output.chars.extend(data.iter());
+                Ok(())
+            }
+        }
+    }
+
+    /// The call of the `write!` procedure of a custom port, passing the units not yet written
+    fn drain(&self) -> Result<Option<RDatum>, RuntimeError> {
+        let output = match self.output {
+            Some(ref output) if !self.closed.get() => output.borrow(),
+            _ => return Ok(None)
+        };
+        let write = match output.sink {
+            Sink::Custom(ref write) => write.clone(),
+            _ => return Ok(None)
+        };
+        let (buf, count) = if self.textual && self.transcoder.is_none() {
+            if output.chars.is_empty() {
+                return Ok(None);
+            }
+            (Datum::String(Rc::new(RefCell::new(output.chars.clone()))), output.chars.len())
+        } else {
+            if output.bytes.is_empty() {
+                return Ok(None);
+            }
+            (bytes(output.bytes.clone()), output.bytes.len())
+        };
+        let args = vec![write, buf, Datum::Num(Number::new_int(0, 0)), count.wrap()];
+        Ok(Some(args.into_iter().collect()))
+    }
+
+    /// Takes the result of the `write!` call returned by `drain`, the number of the units written
+    fn written(&self, n: usize) -> Result<(), RuntimeError> {
+        let mut output = self.output(None)?;
+        let len = if self.textual && self.transcoder.is_none() { output.chars.len() } else { output.bytes.len() };
+        if n == 0 || n > len {
+            return Err(io_error(format!("{}: write! returned {} for {} units", self, n, len)));
+        }
+        if self.textual && self.transcoder.is_none() {
+            output.chars.drain(.. n);
+        } else {
+            output.bytes.drain(.. n);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), RuntimeError> {
+        let mut output = self.output(None)?;
+        match output.sink {
+            Sink::Writer(ref mut writer) =>
+                writer.flush().map_err(|e| from_io_error(ConditionType::IoWrite, e)),
+            _ => Ok(())
+        }
+    }
+
+    /// Closes the port, returning the procedure of the custom port to call
+    fn close(&self) -> Result<Option<RDatum>, RuntimeError> {
+        if self.closed.get() {
+            return Ok(None);
+        }
+        if self.output.is_some() {
+            self.flush()?;
+        }
+        self.closed.set(true);
+        // the file is closed as the reader or the writer is dropped
+        if let Some(ref input) = self.input {
+            input.borrow_mut().source = Source::Exhausted;
+        }
+        if let Some(ref output) = self.output {
+            output.borrow_mut().sink = Sink::Memory;
+        }
+        Ok(self.close.clone())
+    }
+
+    /// Takes the contents of a bytevector or string output port
+    fn extract(&self) -> Result<RDatum, RuntimeError> {
+        let mut output = match self.output {
+            Some(ref output) => output.borrow_mut(),
+            None => return Err(invalid(format!("expected output port, but received {}", self)))
+        };
+        if self.textual && self.transcoder.is_none() {
+            let chars = mem::replace(&mut output.chars, Vec::new());
+            Ok(Datum::String(Rc::new(RefCell::new(chars))))
+        } else {
+            Ok(bytes(mem::replace(&mut output.bytes, Vec::new())))
+        }
+    }
+}
+
+/// Reader passing the characters of the textual input port to the parser, encoded in UTF-8
+struct PortReader<'a> {
+    port: &'a Port,
+    /// Bytes of the character not yet passed
+    bytes: Vec<u8>
+}
+
+impl<'a> Read for PortReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.bytes.is_empty() {
+            match self.port.next_char() {
+                Ok(Some(c)) => encode_char(&mut self.bytes, c),
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e.desc))
+            }
+        }
+        Ok(pass_bytes(&mut self.bytes, buf))
+    }
+}
+
+/// Reader passing the buffered characters of the input port to the parser without consuming
+/// them. Before the end of file, running out of the characters fails as a source that would block
+struct BufferReader<'a> {
+    chars: vec_deque::Iter<'a, char>,
+    eof: bool,
+    /// Whether the parser ran out of the characters
+    starved: bool,
+    /// Bytes of the character not yet passed
+    bytes: Vec<u8>
+}
+
+impl<'a> Read for BufferReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.bytes.is_empty() {
+            match self.chars.next() {
+                Some(&c) => encode_char(&mut self.bytes, c),
+                None if self.eof => return Ok(0),
+                None => {
+                    self.starved = true;
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "more characters are needed"));
+                }
+            }
+        }
+        Ok(pass_bytes(&mut self.bytes, buf))
+    }
+}
+
+fn encode_char(bytes: &mut Vec<u8>, c: char) {
+    let mut utf8 = [0; 4];
+    bytes.extend(c.encode_utf8(&mut utf8).as_bytes().iter());
+}
+
+/// Moves as many bytes as the buffer takes
+fn pass_bytes(bytes: &mut Vec<u8>, buf: &mut [u8]) -> usize {
+    let n = buf.len().min(bytes.len());
+    buf[.. n].copy_from_slice(&bytes[.. n]);
+    bytes.drain(.. n);
+    n
+}
+
+fn wrap_port(port: Port) -> RDatum {
+    Datum::Ext(RuntimeData::Port(Rc::new(port)))
+}
+
+/// The transcoder argument, which is `#f` for binary ports
+fn maybe_transcoder(datum: Option<RDatum>) -> Result<Option<Transcoder>, RuntimeError> {
+    match datum {
+        None | Some(Datum::Bool(false)) => Ok(None),
+        Some(datum) => DatumCast::unwrap(datum).map(Some)
+    }
+}
+
+fn symbol_arg<'a>(datum: &'a RDatum, names: &[&str]) -> Result<&'a str, RuntimeError> {
+    match datum {
+        &Datum::Sym(ref s) if names.contains(&s.as_ref()) => Ok(s),
+        _ => Err(invalid(format!("expected one of {:?}, but received {}", names, datum)))
+    }
+}
+
+fn num_args_error(min: usize, max: usize, n: usize) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::NumArgs,
+        desc: format!("Expected {} to {} arguments, received {}", min, max, n)
+    }
+}
+
+fn eof_object(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() {
+        Ok(Datum::Ext(RuntimeData::Eof))
+    } else {
+        Err(num_args_error(0, 0, args.len()))
+    }
+}
+
+/// `(eof-object)`
+pub static PRIM_EOF_OBJECT: FoldErr<RDatum> = FoldErr { fold: eof_object };
+
+fn is_eof_object(datum: RDatum) -> bool {
+    datum == Datum::Ext(RuntimeData::Eof)
+}
+
+/// `(eof-object? obj)`
+pub static PRIM_IS_EOF_OBJECT: F1<RDatum, bool> = F1 { f1: is_eof_object };
+
+fn latin_1_codec(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() { Ok(Codec::Latin1.wrap()) } else { Err(num_args_error(0, 0, args.len())) }
+}
+
+/// `(latin-1-codec)`
+pub static PRIM_LATIN_1_CODEC: FoldErr<RDatum> = FoldErr { fold: latin_1_codec };
+
+fn utf_8_codec(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() { Ok(Codec::Utf8.wrap()) } else { Err(num_args_error(0, 0, args.len())) }
+}
+
+/// `(utf-8-codec)`
+pub static PRIM_UTF_8_CODEC: FoldErr<RDatum> = FoldErr { fold: utf_8_codec };
+
+fn native_eol_style_prim(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() {
+        Ok(Datum::Sym(Cow::Borrowed(native_eol_style().name())))
+    } else {
+        Err(num_args_error(0, 0, args.len()))
+    }
+}
+
+/// `(native-eol-style)`
+pub static PRIM_NATIVE_EOL_STYLE: FoldErr<RDatum> = FoldErr { fold: native_eol_style_prim };
+
+fn native_transcoder(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() { Ok(Transcoder::native().wrap()) } else { Err(num_args_error(0, 0, args.len())) }
+}
+
+/// `(native-transcoder)`
+pub static PRIM_NATIVE_TRANSCODER: FoldErr<RDatum> = FoldErr { fold: native_transcoder };
+
+fn make_transcoder(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() || args.len() > 3 {
+        return Err(num_args_error(1, 3, args.len()));
+    }
+    let mut transcoder = Transcoder::native();
+    transcoder.codec = DatumCast::unwrap(args[0].clone())?;
+    if let Some(eol_style) = args.get(1) {
+        transcoder.eol_style = EolStyle::from_name(symbol_arg(eol_style, EOL_STYLES)?).unwrap();
+    }
+    if let Some(error_mode) = args.get(2) {
+        transcoder.error_mode = ErrorMode::from_name(symbol_arg(error_mode, ERROR_HANDLING_MODES)?).unwrap();
+    }
+    Ok(transcoder.wrap())
+}
+
+/// `(make-transcoder codec)`, `(make-transcoder codec eol-style)` or
+/// `(make-transcoder codec eol-style handling-mode)`
+pub static PRIM_MAKE_TRANSCODER: FoldErr<RDatum> = FoldErr { fold: make_transcoder };
+
+fn transcoder_codec(t: Transcoder) -> Codec {
+    t.codec
+}
+
+/// `(transcoder-codec transcoder)`
+pub static PRIM_TRANSCODER_CODEC: F1<Transcoder, Codec> = F1 { f1: transcoder_codec };
+
+fn transcoder_eol_style(t: Transcoder) -> RDatum {
+    Datum::Sym(Cow::Borrowed(t.eol_style.name()))
+}
+
+/// `(transcoder-eol-style transcoder)`
+pub static PRIM_TRANSCODER_EOL_STYLE: F1<Transcoder, RDatum> = F1 { f1: transcoder_eol_style };
+
+fn transcoder_error_handling_mode(t: Transcoder) -> RDatum {
+    Datum::Sym(Cow::Borrowed(t.error_mode.name()))
+}
+
+/// `(transcoder-error-handling-mode transcoder)`
+pub static PRIM_TRANSCODER_ERROR_HANDLING_MODE: F1<Transcoder, RDatum> = F1 { f1: transcoder_error_handling_mode };
+
+fn is_buffer_mode(datum: RDatum) -> bool {
+    symbol_arg(&datum, BUFFER_MODES).is_ok()
+}
+
+/// `(buffer-mode? obj)`
+pub static PRIM_IS_BUFFER_MODE: F1<RDatum, bool> = F1 { f1: is_buffer_mode };
+
+fn bytevector_to_string(bv: Rc<RefCell<Vec<u8>>>, transcoder: Transcoder) -> Result<RDatum, RuntimeError> {
+    let mut bytes: VecDeque<u8> = bv.borrow().iter().cloned().collect();
+    let mut chars = VecDeque::new();
+    transcoder.decode(&mut bytes, &mut chars, &mut false, true)?;
+    Ok(Datum::String(Rc::new(RefCell::new(chars.into_iter().collect()))))
+}
+
+/// `(bytevector->string bytevector transcoder)`
+pub static PRIM_BYTEVECTOR_TO_STRING: F2<Rc<RefCell<Vec<u8>>>, Transcoder, Result<RDatum, RuntimeError>> = F2 { f2: bytevector_to_string };
+
+fn string_to_bytevector(s: Rc<RefCell<Vec<char>>>, transcoder: Transcoder) -> Result<RDatum, RuntimeError> {
+    let mut encoded = Vec::new();
+    transcoder.encode(&s.borrow(), &mut encoded)?;
+    Ok(bytes(encoded))
+}
+
+/// `(string->bytevector string transcoder)`
+pub static PRIM_STRING_TO_BYTEVECTOR: F2<Rc<RefCell<Vec<char>>>, Transcoder, Result<RDatum, RuntimeError>> = F2 { f2: string_to_bytevector };
+
+fn is_port(datum: RDatum) -> bool {
+    DatumType::get_type(&datum) == DatumType::Port
+}
+
+/// `(port? obj)`
+pub static PRIM_IS_PORT: F1<RDatum, bool> = F1 { f1: is_port };
+
+fn is_input_port(datum: RDatum) -> bool {
+    match datum {
+        Datum::Ext(RuntimeData::Port(ref p)) => p.is_input(),
+        _ => false
+    }
+}
+
+/// `(input-port? obj)`
+pub static PRIM_IS_INPUT_PORT: F1<RDatum, bool> = F1 { f1: is_input_port };
+
+fn is_output_port(datum: RDatum) -> bool {
+    match datum {
+        Datum::Ext(RuntimeData::Port(ref p)) => p.is_output(),
+        _ => false
+    }
+}
+
+/// `(output-port? obj)`
+pub static PRIM_IS_OUTPUT_PORT: F1<RDatum, bool> = F1 { f1: is_output_port };
+
+fn is_textual_port(port: &Rc<Port>) -> bool {
+    port.is_textual()
+}
+
+/// `(textual-port? port)`
+pub static PRIM_IS_TEXTUAL_PORT: R1<Rc<Port>, bool> = R1 { r1: is_textual_port };
+
+fn is_binary_port(port: &Rc<Port>) -> bool {
+    !port.is_textual()
+}
+
+/// `(binary-port? port)`
+pub static PRIM_IS_BINARY_PORT: R1<Rc<Port>, bool> = R1 { r1: is_binary_port };
+
+fn port_transcoder(port: &Rc<Port>) -> RDatum {
+    match port.transcoder() {
+        Some(t) => t.wrap(),
+        None => Datum::Bool(false)
+    }
+}
+
+/// `(port-transcoder port)`
+pub static PRIM_PORT_TRANSCODER: R1<Rc<Port>, RDatum> = R1 { r1: port_transcoder };
+
+fn transcoded_port(port: Rc<Port>, transcoder: Transcoder) -> Result<RDatum, RuntimeError> {
+    if port.is_textual() || port.closed.get() {
+        return Err(invalid(format!("expected open binary port, but received {}", port)));
+    }
+    // the buffers move to the new port, and the binary port is closed
+    let input = port.input.as_ref().map(|input| mem::replace(&mut *input.borrow_mut(), Input::new(Source::Exhausted)));
+    let output = port.output.as_ref().map(|output| mem::replace(&mut *output.borrow_mut(), Output::new(Sink::Memory)));
+    port.closed.set(true);
+    let mut new_port = Port::new(port.id.clone(), Some(transcoder), input, output);
+    new_port.close = port.close.clone();
+    Ok(wrap_port(new_port))
+}
+
+/// `(transcoded-port binary-port transcoder)`
+pub static PRIM_TRANSCODED_PORT: F2<Rc<Port>, Transcoder, Result<RDatum, RuntimeError>> = F2 { f2: transcoded_port };
+
+/// Opens the file for `open-file-input-port` or `open-file-output-port`. The arguments after the
+/// file name are the file options, the buffer mode and the transcoder
+fn open_file_port(args: Vec<RDatum>, output: bool) -> Result<RDatum, RuntimeError> {
+    if args.is_empty() || args.len() > 4 {
+        return Err(num_args_error(1, 4, args.len()));
+    }
+    let mut args = args.into_iter();
+    let filename: String = DatumCast::unwrap(args.next().unwrap())?;
+    let options = match args.next() {
+        Some(options) => {
+            let options: Rc<EnumSet> = DatumCast::unwrap(options)?;
+            options.same_type(&file_options_universe())?;
+            options.members()
+        },
+        None => Vec::new()
+    };
+    if let Some(buffer_mode) = args.next() {
+        symbol_arg(&buffer_mode, BUFFER_MODES)?;
+    }
+    let transcoder = maybe_transcoder(args.next())?;
+    open_file(filename, &options, transcoder, output)
+}
+
+fn open_file(filename: String, options: &[Cow<'static, str>], transcoder: Option<Transcoder>, output: bool)
+        -> Result<RDatum, RuntimeError>
+{
+    let has = |name: &str| options.iter().any(|o| o == name);
+    let mut open = OpenOptions::new();
+    if !output {
+        open.read(true);
+    } else if has("no-create") {
+        open.write(true).truncate(!has("no-truncate"));
+    } else if has("no-fail") {
+        open.write(true).create(true).truncate(!has("no-truncate"));
+    } else {
+        open.write(true).create_new(true);
+    }
+    let file = open.open(&filename).map_err(|e| {
+        let ctype = match e.kind() {
+            io::ErrorKind::NotFound => ConditionType::IoFileDoesNotExist,
+            io::ErrorKind::AlreadyExists => ConditionType::IoFileAlreadyExists,
+            io::ErrorKind::PermissionDenied => ConditionType::IoFileProtection,
+            _ => ConditionType::IoFilename
+        };
+        io_condition(ctype, vec![string(&filename)], format!("{}: {}", filename, e))
+    })?;
+
+    let port = if output {
+        Port::new(filename, transcoder, None, Some(Output::new(Sink::Writer(Box::new(file)))))
+    } else {
+        Port::new(filename, transcoder, Some(Input::new(Source::Reader(Box::new(file)))), None)
+    };
+    Ok(wrap_port(port))
+}
+
+fn open_file_input_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    open_file_port(args, false)
+}
+
+/// `(open-file-input-port filename)`, optionally with the file options, the buffer mode and the
+/// transcoder
+pub static PRIM_OPEN_FILE_INPUT_PORT: FoldErr<RDatum> = FoldErr { fold: open_file_input_port };
+
+fn open_file_output_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    open_file_port(args, true)
+}
+
+/// `(open-file-output-port filename)`, optionally with the file options, the buffer mode and the
+/// transcoder
+pub static PRIM_OPEN_FILE_OUTPUT_PORT: FoldErr<RDatum> = FoldErr { fold: open_file_output_port };
+
+fn open_input_file(filename: String) -> Result<RDatum, RuntimeError> {
+    open_file(filename, &[], Some(Transcoder::native()), false)
+}
+
+/// `(open-input-file filename)`, the textual port with the native transcoder
+pub static PRIM_OPEN_INPUT_FILE: F1<String, Result<RDatum, RuntimeError>> = F1 { f1: open_input_file };
+
+fn open_output_file(filename: String) -> Result<RDatum, RuntimeError> {
+    open_file(filename, &[], Some(Transcoder::native()), true)
+}
+
+/// `(open-output-file filename)`, the textual port with the native transcoder. The file must not
+/// exist, as the file options are empty
+pub static PRIM_OPEN_OUTPUT_FILE: F1<String, Result<RDatum, RuntimeError>> = F1 { f1: open_output_file };
+
+fn open_bytevector_input_port(bv: Rc<RefCell<Vec<u8>>>, transcoder: Option<RDatum>) -> Result<RDatum, RuntimeError> {
+    let transcoder = maybe_transcoder(transcoder)?;
+    let reader = Cursor::new(bv.borrow().clone());
+    Ok(wrap_port(Port::new("bytevector".to_string(), transcoder,
+                           Some(Input::new(Source::Reader(Box::new(reader)))), None)))
+}
+
+/// `(open-bytevector-input-port bytevector)` or
+/// `(open-bytevector-input-port bytevector transcoder)`
+pub static PRIM_OPEN_BYTEVECTOR_INPUT_PORT: F2<Rc<RefCell<Vec<u8>>>, Option<RDatum>, Result<RDatum, RuntimeError>> = F2 { f2: open_bytevector_input_port };
+
+fn open_string_input_port(s: Rc<RefCell<Vec<char>>>) -> RDatum {
+    let mut input = Input::new(Source::Exhausted);
+    input.chars.extend(s.borrow().iter());
+    let mut port = Port::new("string".to_string(), None, Some(input), None);
+    port.textual = true;
+    wrap_port(port)
+}
+
+/// `(open-string-input-port string)`
+pub static PRIM_OPEN_STRING_INPUT_PORT: F1<Rc<RefCell<Vec<char>>>, RDatum> = F1 { f1: open_string_input_port };
+
+/// The pair of the port and the extraction procedure, which `open-bytevector-output-port` and
+/// `open-string-output-port` return as two values
+fn port_with_extractor(name: &'static str, port: Port) -> RDatum {
+    let cell = Rc::new(RefCell::new(wrap_port(port)));
+    let extractor = Closure::new(Rc::new(vec![
+        Inst::PushArg(prim(name, &PRIM_PORT_EXTRACT)),
+        Inst::PushArg(MemRef::Global(cell.clone())),
+        Inst::Call(1),
+        Inst::Return
+    ]), None, None);
+    let port = cell.borrow().clone();
+    cons(port, Datum::Ext(RuntimeData::Closure(extractor)))
+}
+
+fn open_bytevector_output_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.len() > 1 {
+        return Err(num_args_error(0, 1, args.len()));
+    }
+    let transcoder = maybe_transcoder(args.into_iter().next())?;
+    let port = Port::new("bytevector".to_string(), transcoder, None, Some(Output::new(Sink::Memory)));
+    Ok(port_with_extractor("open-bytevector-output-port", port))
+}
+
+/// Port and extractor of `open-bytevector-output-port`
+pub static PRIM_OPEN_BYTEVECTOR_OUTPUT_PORT: FoldErr<RDatum> = FoldErr { fold: open_bytevector_output_port };
+
+fn open_string_output_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if !args.is_empty() {
+        return Err(num_args_error(0, 0, args.len()));
+    }
+    let mut port = Port::new("string".to_string(), None, None, Some(Output::new(Sink::Memory)));
+    port.textual = true;
+    Ok(port_with_extractor("open-string-output-port", port))
+}
+
+/// Port and extractor of `open-string-output-port`
+pub static PRIM_OPEN_STRING_OUTPUT_PORT: FoldErr<RDatum> = FoldErr { fold: open_string_output_port };
+
+fn port_extract(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.extract()
+}
+
+/// Extraction procedure of a bytevector or string output port
+pub static PRIM_PORT_EXTRACT: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: port_extract };
+
+fn standard_port(args: Vec<RDatum>, name: &'static str) -> Result<RDatum, RuntimeError> {
+    if !args.is_empty() {
+        return Err(num_args_error(0, 0, args.len()));
+    }
+    let port = match name {
+        "stdin" => Port::new(name.to_string(), None, Some(Input::new(Source::Reader(Box::new(io::stdin())))), None),
+        "stdout" => Port::new(name.to_string(), None, None, Some(Output::new(Sink::Writer(Box::new(io::stdout()))))),
+        _ => Port::new(name.to_string(), None, None, Some(Output::new(Sink::Writer(Box::new(io::stderr())))))
+    };
+    Ok(wrap_port(port))
+}
+
+fn standard_input_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    standard_port(args, "stdin")
+}
+
+/// `(standard-input-port)`
+pub static PRIM_STANDARD_INPUT_PORT: FoldErr<RDatum> = FoldErr { fold: standard_input_port };
+
+fn standard_output_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    standard_port(args, "stdout")
+}
+
+/// `(standard-output-port)`
+pub static PRIM_STANDARD_OUTPUT_PORT: FoldErr<RDatum> = FoldErr { fold: standard_output_port };
+
+fn standard_error_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    standard_port(args, "stderr")
+}
+
+/// `(standard-error-port)`
+pub static PRIM_STANDARD_ERROR_PORT: FoldErr<RDatum> = FoldErr { fold: standard_error_port };
+
+/// Makes the custom port from `(id read!-or-write! get-position set-position! close)`. The
+/// positions are not supported, so `get-position` and `set-position!` are ignored
+fn make_custom_port(args: Vec<RDatum>, textual: bool, output: bool) -> Result<RDatum, RuntimeError> {
+    if args.len() != 5 {
+        return Err(num_args_error(5, 5, args.len()));
+    }
+    let mut args = args.into_iter();
+    let id: String = DatumCast::unwrap(args.next().unwrap())?;
+    let procedure = args.next().unwrap();
+    if DatumType::get_type(&procedure) != DatumType::Callable {
+        return Err(invalid(format!("expected Callable, but received {}", procedure)));
+    }
+    let close = args.nth(2).unwrap();
+
+    let mut port = if output {
+        Port::new(id, None, None, Some(Output::new(Sink::Custom(procedure))))
+    } else {
+        Port::new(id, None, Some(Input::new(Source::Custom(procedure))), None)
+    };
+    port.textual = textual;
+    if close != Datum::Bool(false) {
+        port.close = Some(close);
+    }
+    Ok(wrap_port(port))
+}
+
+fn make_custom_binary_input_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    make_custom_port(args, false, false)
+}
+
+/// `(make-custom-binary-input-port id read! get-position set-position! close)`
+pub static PRIM_MAKE_CUSTOM_BINARY_INPUT_PORT: FoldErr<RDatum> = FoldErr { fold: make_custom_binary_input_port };
+
+fn make_custom_textual_input_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    make_custom_port(args, true, false)
+}
+
+/// `(make-custom-textual-input-port id read! get-position set-position! close)`
+pub static PRIM_MAKE_CUSTOM_TEXTUAL_INPUT_PORT: FoldErr<RDatum> = FoldErr { fold: make_custom_textual_input_port };
+
+fn make_custom_binary_output_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    make_custom_port(args, false, true)
+}
+
+/// `(make-custom-binary-output-port id write! get-position set-position! close)`
+pub static PRIM_MAKE_CUSTOM_BINARY_OUTPUT_PORT: FoldErr<RDatum> = FoldErr { fold: make_custom_binary_output_port };
+
+fn make_custom_textual_output_port(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    make_custom_port(args, true, true)
+}
+
+/// `(make-custom-textual-output-port id write! get-position set-position! close)`
+pub static PRIM_MAKE_CUSTOM_TEXTUAL_OUTPUT_PORT: FoldErr<RDatum> = FoldErr { fold: make_custom_textual_output_port };
+
+fn port_fill(port: Rc<Port>, demand: RDatum) -> Result<RDatum, RuntimeError> {
+    let demand = match demand {
+        Datum::Sym(ref s) if s == "line" => Demand::Line,
+        Datum::Sym(ref s) if s == "all" => Demand::All,
+        Datum::Sym(ref s) if s == "datum" => Demand::Datum,
+        n => Demand::Units(DatumCast::unwrap(n)?)
+    };
+    Ok(port.fill(demand).map_err(|e| port_error(&port, e))?.unwrap_or(Datum::Bool(false)))
+}
+
+/// Fills the input buffer of the port for the demand, which is the number of the units, `line`,
+/// `all` or `datum`. Returns the call of `read!` for custom ports, or `#f` if the buffer is ready
+pub static PRIM_PORT_FILL: F2<Rc<Port>, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: port_fill };
+
+fn port_supply(port: Rc<Port>, n: usize) -> Result<RDatum, RuntimeError> {
+    port.supply(n)?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// Takes the result of the `read!` call of `PRIM_PORT_FILL`
+pub static PRIM_PORT_SUPPLY: F2<Rc<Port>, usize, Result<RDatum, RuntimeError>> = F2 { f2: port_supply };
+
+fn port_drain(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    Ok(port.drain()?.unwrap_or(Datum::Bool(false)))
+}
+
+/// The call of `write!` writing the output buffered by a custom port, or `#f` if there is none
+pub static PRIM_PORT_DRAIN: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: port_drain };
+
+fn port_written(port: Rc<Port>, n: usize) -> Result<RDatum, RuntimeError> {
+    port.written(n)?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// Takes the result of the `write!` call of `PRIM_PORT_DRAIN`
+pub static PRIM_PORT_WRITTEN: F2<Rc<Port>, usize, Result<RDatum, RuntimeError>> = F2 { f2: port_written };
+
+fn port_flush(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.flush()?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// Flushes the writer of the port
+pub static PRIM_PORT_FLUSH: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: port_flush };
+
+fn port_close(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    Ok(port.close()?.unwrap_or(Datum::Bool(false)))
+}
+
+/// Closes the port, returning the `close` procedure of a custom port or `#f`
+pub static PRIM_PORT_CLOSE: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: port_close };
+
+fn get_u8(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_u8(false)
+}
+
+pub static PRIM_GET_U8: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: get_u8 };
+
+fn lookahead_u8(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_u8(true)
+}
+
+pub static PRIM_LOOKAHEAD_U8: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: lookahead_u8 };
+
+fn get_bytevector_n(port: Rc<Port>, n: usize) -> Result<RDatum, RuntimeError> {
+    port.get_bytes(Some(n))
+}
+
+pub static PRIM_GET_BYTEVECTOR_N: F2<Rc<Port>, usize, Result<RDatum, RuntimeError>> = F2 { f2: get_bytevector_n };
+
+fn get_bytevector_all(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_bytes(None)
+}
+
+pub static PRIM_GET_BYTEVECTOR_ALL: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: get_bytevector_all };
+
+fn get_char(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_char(false)
+}
+
+pub static PRIM_GET_CHAR: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: get_char };
+
+fn lookahead_char(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_char(true)
+}
+
+pub static PRIM_LOOKAHEAD_CHAR: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: lookahead_char };
+
+fn get_string_n(port: Rc<Port>, n: usize) -> Result<RDatum, RuntimeError> {
+    port.get_string(Some(n))
+}
+
+pub static PRIM_GET_STRING_N: F2<Rc<Port>, usize, Result<RDatum, RuntimeError>> = F2 { f2: get_string_n };
+
+fn get_string_all(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_string(None)
+}
+
+pub static PRIM_GET_STRING_ALL: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: get_string_all };
+
+fn get_line(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.get_line()
+}
+
+pub static PRIM_GET_LINE: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: get_line };
+
+fn port_eof(port: Rc<Port>) -> Result<bool, RuntimeError> {
+    port.is_eof()
+}
+
+pub static PRIM_PORT_EOF: F1<Rc<Port>, Result<bool, RuntimeError>> = F1 { f1: port_eof };
+
+/// The range `[start, start+count)` of the optional arguments of `put-bytevector` and
+/// `put-string`
+fn put_range(args: &[RDatum], len: usize) -> Result<(usize, usize), RuntimeError> {
+    let start = match args.get(0) {
+        Some(start) => DatumCast::unwrap(start.clone())?,
+        None => 0
+    };
+    let count = match args.get(1) {
+        Some(count) => DatumCast::unwrap(count.clone())?,
+        None => len.saturating_sub(start)
+    };
+    if start + count > len {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::IndexOutOfRange,
+            desc: format!("length is {}, but {} units are written from {}", len, count, start)
+        });
+    }
+    Ok((start, start + count))
+}
+
+fn put_u8(port: Rc<Port>, octet: RDatum) -> Result<RDatum, RuntimeError> {
+    let b: usize = DatumCast::unwrap(octet)?;
+    if b > 255 {
+        return Err(invalid(format!("{} is not an octet", b)));
+    }
+    port.put_bytes(&[b as u8])?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_PUT_U8: F2<Rc<Port>, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: put_u8 };
+
+fn put_bytevector(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.len() < 2 || args.len() > 4 {
+        return Err(num_args_error(2, 4, args.len()));
+    }
+    let port: Rc<Port> = DatumCast::unwrap(args[0].clone())?;
+    let bv: Rc<RefCell<Vec<u8>>> = DatumCast::unwrap(args[1].clone())?;
+    let data = bv.borrow();
+    let (start, end) = put_range(&args[2..], data.len())?;
+    port.put_bytes(&data[start .. end])?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_PUT_BYTEVECTOR: FoldErr<RDatum> = FoldErr { fold: put_bytevector };
+
+fn put_char(port: Rc<Port>, c: char) -> Result<RDatum, RuntimeError> {
+    port.put_chars(&[c]).map_err(|e| port_error(&port, e))?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_PUT_CHAR: F2<Rc<Port>, char, Result<RDatum, RuntimeError>> = F2 { f2: put_char };
+
+fn put_string(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    if args.len() < 2 || args.len() > 4 {
+        return Err(num_args_error(2, 4, args.len()));
+    }
+    let port: Rc<Port> = DatumCast::unwrap(args[0].clone())?;
+    let s: Rc<RefCell<Vec<char>>> = DatumCast::unwrap(args[1].clone())?;
+    let data = s.borrow();
+    let (start, end) = put_range(&args[2..], data.len())?;
+    port.put_chars(&data[start .. end]).map_err(|e| port_error(&port, e))?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_PUT_STRING: FoldErr<RDatum> = FoldErr { fold: put_string };
+
+fn read(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.read_datum()
+}
+
+pub static PRIM_READ: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: read };
+
+fn display(port: Rc<Port>, datum: RDatum) -> Result<RDatum, RuntimeError> {
+    let chars: Vec<char> = datum.display().to_string().chars().collect();
+    port.put_chars(&chars).map_err(|e| port_error(&port, e))?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_DISPLAY: F2<Rc<Port>, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: display };
+
+fn write(port: Rc<Port>, datum: RDatum) -> Result<RDatum, RuntimeError> {
+    let chars: Vec<char> = datum.to_string().chars().collect();
+    port.put_chars(&chars).map_err(|e| port_error(&port, e))?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_WRITE: F2<Rc<Port>, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: write };
+
+fn newline(port: Rc<Port>) -> Result<RDatum, RuntimeError> {
+    port.put_chars(&['\n']).map_err(|e| port_error(&port, e))?;
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+pub static PRIM_NEWLINE: F1<Rc<Port>, Result<RDatum, RuntimeError>> = F1 { f1: newline };
+
+fn prim(name: &'static str, func: &'static (PrimFunc + 'static)) -> MemRef {
+    MemRef::PrimFunc(PrimFuncPtr::new(name, func))
+}
+
+fn closure(code: Vec<Inst>) -> MemRef {
+    MemRef::Closure(Rc::new(code), 0, None)
+}
+
+/// Appends the loop calling a procedure of the custom port at the argument 0. `request` pushes
+/// the call as the list of the procedure and its three arguments, or `#f` to leave the loop. The
+/// result of each call is passed to `reply` with the port
+fn custom_call_loop(name: &'static str, code: &mut Vec<Inst>, request: Vec<Inst>,
+                    reply: &'static (PrimFunc + 'static))
+{
+    let start = code.len();
+    code.extend(request);
+    let exit = code.len() + 14;
+    code.extend(vec![
+        Inst::JumpIfFalse(exit),
+        Inst::Uncons,
+        Inst::Uncons,
+        Inst::Uncons,
+        Inst::Uncons,
+        Inst::DropArg(1),
+        Inst::Call(3),
+        Inst::PushArg(prim(name, reply)),
+        Inst::SwapArg,
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::SwapArg,
+        Inst::Call(2),
+        Inst::DropArg(1),
+        Inst::Jump(start),
+        // exit
+        Inst::DropArg(1)
+    ]);
+}
+
+/// Bytecode of the input procedure taking `nargs` arguments, the port first. The input buffer is
+/// filled for the demand before `op` runs
+fn input_code(name: &'static str, demand: MemRef, nargs: usize, op: &'static (PrimFunc + 'static)) -> Vec<Inst> {
+    let mut code = Vec::new();
+    input_op(name, &mut code, demand, nargs, op);
+    code
+}
+
+/// Appends the bytecode of `input_code` to `code`
+fn input_op(name: &'static str, code: &mut Vec<Inst>, demand: MemRef, nargs: usize,
+            op: &'static (PrimFunc + 'static))
+{
+    custom_call_loop(name, code, vec![
+        Inst::PushArg(prim(name, &PRIM_PORT_FILL)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(demand),
+        Inst::Call(2)
+    ], &PRIM_PORT_SUPPLY);
+    code.push(Inst::PushArg(prim(name, op)));
+    code.extend((0 .. nargs).map(|i| Inst::PushArg(MemRef::Arg(i))));
+    code.push(Inst::Call(nargs));
+    code.push(Inst::Return);
+}
+
+fn units(n: isize) -> MemRef {
+    MemRef::Const(SimpleDatum::Num(Number::new_int(n, 0)))
+}
+
+fn demand(name: &'static str) -> MemRef {
+    MemRef::Const(SimpleDatum::Sym(Cow::Borrowed(name)))
+}
+
+/// Appends the loop writing the output buffered by the custom port at the argument 0
+fn drain_loop(name: &'static str, code: &mut Vec<Inst>) {
+    custom_call_loop(name, code, vec![
+        Inst::PushArg(prim(name, &PRIM_PORT_DRAIN)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1)
+    ], &PRIM_PORT_WRITTEN);
+}
+
+/// Bytecode writing the output buffered by the custom port at the argument 0
+fn drain_code(name: &'static str) -> Vec<Inst> {
+    let mut code = Vec::new();
+    drain_loop(name, &mut code);
+    code.push(Inst::PushArg(MemRef::Undefined));
+    code.push(Inst::Return);
+    code
+}
+
+/// Bytecode of the output procedure, the port first. `op` is applied to the arguments, then
+/// custom ports pass the output to their `write!` procedure at once
+fn output_code(name: &'static str, op: &'static (PrimFunc + 'static)) -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(0),
+        Inst::PushArg(closure(apply_code())),
+        Inst::PushArg(prim(name, op)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(2),
+        Inst::DropArg(1),
+        Inst::PushArg(closure(drain_code(name))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Uncons,
+        Inst::DropArg(1),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `(flush-output-port port)`
+fn flush_code() -> Vec<Inst> {
+    let mut code = Vec::new();
+    drain_loop("flush-output-port", &mut code);
+    code.extend(vec![
+        Inst::PushArg(prim("flush-output-port", &PRIM_PORT_FLUSH)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::Return
+    ]);
+    code
+}
+
+/// Bytecode of `(close-port port)`, which writes the pending output and calls the `close`
+/// procedure of a custom port
+pub fn close_code(name: &'static str) -> Vec<Inst> {
+    let mut code = Vec::new();
+    drain_loop(name, &mut code);
+    let end = code.len() + 5;
+    code.extend(vec![
+        Inst::PushArg(prim(name, &PRIM_PORT_CLOSE)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::JumpIfFalse(end),
+        Inst::Call(0),
+        // end
+        Inst::DropArg(1),
+        Inst::PushArg(MemRef::Undefined),
+        Inst::Return
+    ]);
+    code
+}
+
+/// Bytecode of `(call-with-port port proc)`, which closes the port after `proc` returns
+fn call_with_port_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::PushArg(closure(close_code("call-with-port"))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::DropArg(1),
+        Inst::Return
+    ]
+}
+
+/// Bytecode returning the port and the extraction procedure made by `op` as two values
+fn port_values_code(name: &'static str, op: &'static (PrimFunc + 'static)) -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(0),
+        Inst::PushArg(closure(vec![Inst::ReturnValues])),
+        Inst::PushArg(closure(apply_code())),
+        Inst::PushArg(prim(name, op)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(2),
+        Inst::Uncons,
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Lists the port procedures written in bytecode, which call the procedures of custom ports
+pub fn port_procedures() -> Vec<(&'static str, Vec<Inst>)> {
+    vec![
+        ("get-u8", input_code("get-u8", units(1), 1, &PRIM_GET_U8)),
+        ("lookahead-u8", input_code("lookahead-u8", units(1), 1, &PRIM_LOOKAHEAD_U8)),
+        ("get-bytevector-n", input_code("get-bytevector-n", MemRef::Arg(1), 2, &PRIM_GET_BYTEVECTOR_N)),
+        ("get-bytevector-all", input_code("get-bytevector-all", demand("all"), 1, &PRIM_GET_BYTEVECTOR_ALL)),
+        ("get-char", input_code("get-char", units(1), 1, &PRIM_GET_CHAR)),
+        ("lookahead-char", input_code("lookahead-char", units(1), 1, &PRIM_LOOKAHEAD_CHAR)),
+        ("get-string-n", input_code("get-string-n", MemRef::Arg(1), 2, &PRIM_GET_STRING_N)),
+        ("get-string-all", input_code("get-string-all", demand("all"), 1, &PRIM_GET_STRING_ALL)),
+        ("get-line", input_code("get-line", demand("line"), 1, &PRIM_GET_LINE)),
+        ("port-eof?", input_code("port-eof?", units(1), 1, &PRIM_PORT_EOF)),
+        ("put-u8", output_code("put-u8", &PRIM_PUT_U8)),
+        ("put-bytevector", output_code("put-bytevector", &PRIM_PUT_BYTEVECTOR)),
+        ("put-char", output_code("put-char", &PRIM_PUT_CHAR)),
+        ("put-string", output_code("put-string", &PRIM_PUT_STRING)),
+        ("flush-output-port", flush_code()),
+        ("close-port", close_code("close-port")),
+        ("call-with-port", call_with_port_code()),
+        ("open-bytevector-output-port",
+         port_values_code("open-bytevector-output-port", &PRIM_OPEN_BYTEVECTOR_OUTPUT_PORT)),
+        ("open-string-output-port",
+         port_values_code("open-string-output-port", &PRIM_OPEN_STRING_OUTPUT_PORT))
+    ]
+}
+
+/// Bytecode taking the optional port after `nargs` arguments, which is the port in `current` if
+/// omitted. The port becomes the argument `nargs`
+fn optional_port(nargs: usize, current: &Rc<RefCell<RDatum>>) -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(nargs),
+        Inst::Type(DatumType::Null),
+        Inst::JumpIfFalse(6),
+        Inst::DropArg(2),
+        Inst::PushArg(MemRef::Global(current.clone())),
+        Inst::Jump(13),
+        // 6
+        Inst::DropArg(1),
+        Inst::Uncons,
+        Inst::Type(DatumType::Null),
+        Inst::JumpIfFalse(12),
+        Inst::DropArg(2),
+        Inst::Jump(13),
+        // 12
+        Inst::Throw(RuntimeErrorKind::NumArgs, "too many arguments")
+    ]
+}
+
+/// Bytecode of the simple input procedure, which reads from the current input port by default
+fn simple_input_code(name: &'static str, demand: MemRef, current: &Rc<RefCell<RDatum>>,
+                     op: &'static (PrimFunc + 'static)) -> Vec<Inst>
+{
+    let mut code = optional_port(0, current);
+    input_op(name, &mut code, demand, 1, op);
+    code
+}
+
+/// Bytecode of the simple output procedure taking `nargs` arguments, which writes to the current
+/// output port by default. `op` is called with the port first
+fn simple_output_code(name: &'static str, nargs: usize, current: &Rc<RefCell<RDatum>>,
+                      op: &'static (PrimFunc + 'static)) -> Vec<Inst>
+{
+    let mut code = optional_port(nargs, current);
+    code.push(Inst::PushArg(prim(name, op)));
+    code.push(Inst::PushArg(MemRef::Arg(nargs)));
+    code.extend((0 .. nargs).map(|i| Inst::PushArg(MemRef::Arg(i))));
+    code.extend(vec![
+        Inst::Call(nargs + 1),
+        Inst::DropArg(1),
+        Inst::PushArg(closure(drain_code(name))),
+        Inst::PushArg(MemRef::Arg(nargs)),
+        Inst::TailCall,
+        Inst::Return
+    ]);
+    code
+}
+
+/// Bytecode of `call-with-input-file` or `call-with-output-file`, which opens the file with `open`
+/// then calls `call-with-port`
+fn call_with_file_code(name: &'static str, open: &'static (PrimFunc + 'static)) -> Vec<Inst> {
+    vec![
+        Inst::PushArg(closure(call_with_port_code())),
+        Inst::PushArg(prim(name, open)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `with-input-from-file` or `with-output-to-file`. The port of the file opened with
+/// `open` replaces the port in `current` while the thunk runs, then the port is closed
+fn with_file_code(name: &'static str, open: &'static (PrimFunc + 'static),
+                  current: &Rc<RefCell<RDatum>>) -> Vec<Inst>
+{
+    // swaps the port in `current` and the argument 2, entering or leaving the extent
+    let swap = vec![
+        Inst::PushArg(MemRef::Global(current.clone())),
+        Inst::PushArg(MemRef::UpValue(0, 2)),
+        Inst::PopArg(MemRef::Global(current.clone())),
+        Inst::PopArg(MemRef::UpValue(0, 2)),
+        Inst::PushArg(MemRef::Undefined),
+        Inst::Return
+    ];
+    vec![
+        Inst::PushArg(prim(name, open)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::SetArgSize(3),
+        Inst::PushArg(closure(swap.clone())),
+        Inst::Call(0),
+        Inst::DropArg(1),
+        Inst::PushArg(closure(swap.clone())),
+        Inst::PushArg(closure(swap.clone())),
+        Inst::PushWinder,
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(0),
+        Inst::PopWinder,
+        Inst::PushArg(closure(swap)),
+        Inst::Call(0),
+        Inst::DropArg(1),
+        Inst::PushArg(closure(close_code(name))),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::Call(1),
+        Inst::DropArg(1),
+        Inst::Return
+    ]
+}
+
+/// The textual port of the console for `current-input-port`, `current-output-port` or
+/// `current-error-port`
+fn console_port(name: &'static str) -> RDatum {
+    let transcoder = Some(Transcoder::native());
+    let port = match name {
+        "stdin" => Port::new(name.to_string(), transcoder, Some(Input::new(Source::Reader(Box::new(io::stdin())))), None),
+        "stdout" => Port::new(name.to_string(), transcoder, None, Some(Output::new(Sink::Writer(Box::new(io::stdout()))))),
+        _ => Port::new(name.to_string(), transcoder, None, Some(Output::new(Sink::Writer(Box::new(io::stderr())))))
+    };
+    wrap_port(port)
+}
+
+/// Lists the procedures of `(rnrs io simple)`. The current ports are the parameters holding the
+/// cells made here, so each environment has its own, and the other procedures are written in
+/// bytecode referring to the cells
+pub fn simple_procedures() -> Vec<(&'static str, RDatum)> {
+    let input = Rc::new(RefCell::new(console_port("stdin")));
+    let output = Rc::new(RefCell::new(console_port("stdout")));
+    let error = Rc::new(RefCell::new(console_port("stderr")));
+    let current = |cell: &Rc<RefCell<RDatum>>|
+        Datum::Ext(RuntimeData::Parameter(Rc::new(Parameter::new(cell.clone(), None))));
+    let procedures = vec![
+        ("read-char", simple_input_code("read-char", units(1), &input, &PRIM_GET_CHAR)),
+        ("peek-char", simple_input_code("peek-char", units(1), &input, &PRIM_LOOKAHEAD_CHAR)),
+        ("read", simple_input_code("read", demand("datum"), &input, &PRIM_READ)),
+        ("write-char", simple_output_code("write-char", 1, &output, &PRIM_PUT_CHAR)),
+        ("newline", simple_output_code("newline", 0, &output, &PRIM_NEWLINE)),
+        ("display", simple_output_code("display", 1, &output, &PRIM_DISPLAY)),
+        ("write", simple_output_code("write", 1, &output, &PRIM_WRITE)),
+        ("call-with-input-file", call_with_file_code("call-with-input-file", &PRIM_OPEN_INPUT_FILE)),
+        ("call-with-output-file", call_with_file_code("call-with-output-file", &PRIM_OPEN_OUTPUT_FILE)),
+        ("with-input-from-file", with_file_code("with-input-from-file", &PRIM_OPEN_INPUT_FILE, &input)),
+        ("with-output-to-file", with_file_code("with-output-to-file", &PRIM_OPEN_OUTPUT_FILE, &output)),
+        ("close-input-port", close_code("close-input-port")),
+        ("close-output-port", close_code("close-output-port"))
+    ];
+    let procedures = procedures.into_iter().map(|(name, code)|
+        (name, Datum::Ext(RuntimeData::Closure(Closure::new(Rc::new(code), None, None)))));
+
+    vec![
+        ("current-input-port", current(&input)),
+        ("current-output-port", current(&output)),
+        ("current-error-port", current(&error))
+    ].into_iter().chain(procedures).collect()
+}
+
+/// Lists all port procedures with its name
+pub fn libport() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("eof-object", &PRIM_EOF_OBJECT),
+        ("eof-object?", &PRIM_IS_EOF_OBJECT),
+        ("latin-1-codec", &PRIM_LATIN_1_CODEC),
+        ("utf-8-codec", &PRIM_UTF_8_CODEC),
+        ("native-eol-style", &PRIM_NATIVE_EOL_STYLE),
+        ("native-transcoder", &PRIM_NATIVE_TRANSCODER),
+        ("make-transcoder", &PRIM_MAKE_TRANSCODER),
+        ("transcoder-codec", &PRIM_TRANSCODER_CODEC),
+        ("transcoder-eol-style", &PRIM_TRANSCODER_EOL_STYLE),
+        ("transcoder-error-handling-mode", &PRIM_TRANSCODER_ERROR_HANDLING_MODE),
+        ("buffer-mode?", &PRIM_IS_BUFFER_MODE),
+        ("bytevector->string", &PRIM_BYTEVECTOR_TO_STRING),
+        ("string->bytevector", &PRIM_STRING_TO_BYTEVECTOR),
+        ("make-i/o-error", &PRIM_MAKE_IO_ERROR),
+        ("i/o-error?", &PRIM_IS_IO_ERROR),
+        ("make-i/o-read-error", &PRIM_MAKE_IO_READ_ERROR),
+        ("i/o-read-error?", &PRIM_IS_IO_READ_ERROR),
+        ("make-i/o-write-error", &PRIM_MAKE_IO_WRITE_ERROR),
+        ("i/o-write-error?", &PRIM_IS_IO_WRITE_ERROR),
+        ("make-i/o-invalid-position-error", &PRIM_MAKE_IO_INVALID_POSITION_ERROR),
+        ("i/o-invalid-position-error?", &PRIM_IS_IO_INVALID_POSITION_ERROR),
+        ("i/o-error-position", &PRIM_IO_ERROR_POSITION),
+        ("make-i/o-filename-error", &PRIM_MAKE_IO_FILENAME_ERROR),
+        ("i/o-filename-error?", &PRIM_IS_IO_FILENAME_ERROR),
+        ("i/o-error-filename", &PRIM_IO_ERROR_FILENAME),
+        ("make-i/o-file-protection-error", &PRIM_MAKE_IO_FILE_PROTECTION_ERROR),
+        ("i/o-file-protection-error?", &PRIM_IS_IO_FILE_PROTECTION_ERROR),
+        ("make-i/o-file-is-read-only-error", &PRIM_MAKE_IO_FILE_IS_READ_ONLY_ERROR),
+        ("i/o-file-is-read-only-error?", &PRIM_IS_IO_FILE_IS_READ_ONLY_ERROR),
+        ("make-i/o-file-already-exists-error", &PRIM_MAKE_IO_FILE_ALREADY_EXISTS_ERROR),
+        ("i/o-file-already-exists-error?", &PRIM_IS_IO_FILE_ALREADY_EXISTS_ERROR),
+        ("make-i/o-file-does-not-exist-error", &PRIM_MAKE_IO_FILE_DOES_NOT_EXIST_ERROR),
+        ("i/o-file-does-not-exist-error?", &PRIM_IS_IO_FILE_DOES_NOT_EXIST_ERROR),
+        ("make-i/o-port-error", &PRIM_MAKE_IO_PORT_ERROR),
+        ("i/o-port-error?", &PRIM_IS_IO_PORT_ERROR),
+        ("i/o-error-port", &PRIM_IO_ERROR_PORT),
+        ("make-i/o-decoding-error", &PRIM_MAKE_IO_DECODING_ERROR),
+        ("i/o-decoding-error?", &PRIM_IS_IO_DECODING_ERROR),
+        ("make-i/o-encoding-error", &PRIM_MAKE_IO_ENCODING_ERROR),
+        ("i/o-encoding-error?", &PRIM_IS_IO_ENCODING_ERROR),
+        ("i/o-encoding-error-char", &PRIM_IO_ENCODING_ERROR_CHAR),
+        ("port?", &PRIM_IS_PORT),
+        ("input-port?", &PRIM_IS_INPUT_PORT),
+        ("output-port?", &PRIM_IS_OUTPUT_PORT),
+        ("textual-port?", &PRIM_IS_TEXTUAL_PORT),
+        ("binary-port?", &PRIM_IS_BINARY_PORT),
+        ("port-transcoder", &PRIM_PORT_TRANSCODER),
+        ("transcoded-port", &PRIM_TRANSCODED_PORT),
+        ("open-file-input-port", &PRIM_OPEN_FILE_INPUT_PORT),
+        ("open-file-output-port", &PRIM_OPEN_FILE_OUTPUT_PORT),
+        ("open-input-file", &PRIM_OPEN_INPUT_FILE),
+        ("open-output-file", &PRIM_OPEN_OUTPUT_FILE),
+        ("open-bytevector-input-port", &PRIM_OPEN_BYTEVECTOR_INPUT_PORT),
+        ("open-string-input-port", &PRIM_OPEN_STRING_INPUT_PORT),
+        ("standard-input-port", &PRIM_STANDARD_INPUT_PORT),
+        ("standard-output-port", &PRIM_STANDARD_OUTPUT_PORT),
+        ("standard-error-port", &PRIM_STANDARD_ERROR_PORT),
+        ("make-custom-binary-input-port", &PRIM_MAKE_CUSTOM_BINARY_INPUT_PORT),
+        ("make-custom-textual-input-port", &PRIM_MAKE_CUSTOM_TEXTUAL_INPUT_PORT),
+        ("make-custom-binary-output-port", &PRIM_MAKE_CUSTOM_BINARY_OUTPUT_PORT),
+        ("make-custom-textual-output-port", &PRIM_MAKE_CUSTOM_TEXTUAL_OUTPUT_PORT)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::{Codec, EolStyle, ErrorMode, Transcoder};
+
+    fn decode(transcoder: Transcoder, input: &[u8], eof: bool) -> (String, usize) {
+        let mut bytes: VecDeque<u8> = input.iter().cloned().collect();
+        let mut chars = VecDeque::new();
+        transcoder.decode(&mut bytes, &mut chars, &mut false, eof).unwrap();
+        (chars.into_iter().collect(), bytes.len())
+    }
+
+    #[test]
+    fn test_decode() {
+        let utf8 = Transcoder { codec: Codec::Utf8, eol_style: EolStyle::Lf, error_mode: ErrorMode::Replace };
+        // the incomplete sequence waits for more bytes
+        assert_eq!(decode(utf8, &[0x61, 0xce], false), ("a".to_string(), 1));
+        assert_eq!(decode(utf8, &[0x61, 0xce], true), ("a\u{fffd}".to_string(), 0));
+        assert_eq!(decode(utf8, &[0xce, 0xbb, 0x0d, 0x0a, 0x0d, 0x62], true), ("λ\n\nb".to_string(), 0));
+
+        let latin1 = Transcoder { codec: Codec::Latin1, eol_style: EolStyle::None, error_mode: ErrorMode::Raise };
+        assert_eq!(decode(latin1, &[0xe9, 0x0d, 0x0a], false), ("é\r\n".to_string(), 0));
+    }
+
+    #[test]
+    fn test_encode() {
+        let crlf = Transcoder { codec: Codec::Latin1, eol_style: EolStyle::Crlf, error_mode: ErrorMode::Replace };
+        let mut bytes = Vec::new();
+        crlf.encode(&['é', '\n', 'λ'], &mut bytes).unwrap();
+        assert_eq!(bytes, vec![0xe9, 0x0d, 0x0a, b'?']);
+
+        let raise = Transcoder { error_mode: ErrorMode::Raise, .. crlf };
+        assert!(raise.encode(&['λ'], &mut Vec::new()).is_err());
+    }
+}
@@ -9,6 +9,7 @@ use cast::DatumCast;
 use number::Number;
 use real::Real;
 use datum::{concat, Datum};
+use eqv::DatumEqv;
 use error::{RuntimeError, RuntimeErrorKind};
 use runtime::{RDatum, RuntimeData, DatumType};
 
@@ -33,35 +34,39 @@ impl <T: DatumCast> PossibleError for Result<T, RuntimeError> {
 }
 
 pub struct Fold<P> {
-    fold: fn(Vec<P>) -> P
+    pub fold: fn(Vec<P>) -> P
 }
 
 pub struct FoldErr<P> {
-    fold: fn(Vec<P>) -> Result<P, RuntimeError>
+    pub fold: fn(Vec<P>) -> Result<P, RuntimeError>
 }
 
 pub struct Fold1<P> {
-    fold1: fn(P, Vec<P>) -> P
+    pub fold1: fn(P, Vec<P>) -> P
 }
 
 pub struct FoldR2<P, R> {
-    fold_r2: fn(&P, &P, &[P]) -> R
+    pub fold_r2: fn(&P, &P, &[P]) -> R
 }
 
 pub struct Fold1Err<P> {
-    fold1: fn(P, Vec<P>) -> Result<P, RuntimeError>
+    pub fold1: fn(P, Vec<P>) -> Result<P, RuntimeError>
 }
 
 pub struct F1<T0, R> {
-    f1: fn(T0) -> R
+    pub f1: fn(T0) -> R
 }
 
 pub struct F2<T0, T1, R> {
-    f2: fn(T0, T1) -> R
+    pub f2: fn(T0, T1) -> R
+}
+
+pub struct F3<T0, T1, T2, R> {
+    pub f3: fn(T0, T1, T2) -> R
 }
 
 pub struct R1<T0, R> {
-    r1: fn(&T0) -> R
+    pub r1: fn(&T0) -> R
 }
 
 impl<T> PrimFunc for Fold<T> where T: DatumCast {
@@ -182,6 +187,40 @@ impl<T0: DatumCast, T1: DatumCast, R: PossibleError> PrimFunc for F2<T0, Option<
     }
 }
 
+impl<T0: DatumCast, T1: DatumCast, T2: DatumCast, R: PossibleError> PrimFunc for F3<T0, T1, T2, R> {
+    fn call(&self, mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        if args.len() != 3 {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("Expected 3 arguments, received {:?}", args.len())
+            });
+        }
+
+        let a2 = DatumCast::unwrap(args.pop().unwrap())?;
+        let a1 = DatumCast::unwrap(args.pop().unwrap())?;
+        let a0 = DatumCast::unwrap(args.pop().unwrap())?;
+
+        ((self.f3)(a0, a1, a2)).make_result()
+    }
+}
+
+impl<T0: DatumCast, T1: DatumCast, T2: DatumCast, R: PossibleError> PrimFunc for F3<T0, T1, Option<T2>, R> {
+    fn call(&self, mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        let a2 = match args.len() {
+            2 => None,
+            3 => Some(DatumCast::unwrap(args.pop().unwrap())?),
+            _ => return Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("Expected 2 or 3 arguments, received {:?}", args.len())
+            })
+        };
+        let a1 = DatumCast::unwrap(args.pop().unwrap())?;
+        let a0 = DatumCast::unwrap(args.pop().unwrap())?;
+
+        ((self.f3)(a0, a1, a2)).make_result()
+    }
+}
+
 impl<T0: DatumCast, R: PossibleError> PrimFunc for F1<T0, R> {
     fn call(&self, mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
         if args.len() != 1 {
@@ -454,6 +493,91 @@ fn append(mut lists: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
     }
 }
 
+/// Finds the first tail of the list whose car matches the object
+fn member_by(obj: &RDatum, list: RDatum, matches: fn(&RDatum, &RDatum) -> bool)
+        -> Result<RDatum, RuntimeError>
+{
+    let mut tail = list;
+    loop {
+        let next = match tail {
+            Datum::Cons(ref pair) => if matches(obj, &pair.car()) {
+                    None
+                } else {
+                    Some(pair.cdr())
+                },
+            Datum::Nil => return Ok(Datum::Bool(false)),
+            _ => return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: "Non-list given to member".to_string()
+            })
+        };
+        match next {
+            Some(next) => tail = next,
+            None => return Ok(tail)
+        }
+    }
+}
+
+/// Finds the first pair of the association list whose car matches the object
+fn assoc_by(obj: &RDatum, alist: RDatum, matches: fn(&RDatum, &RDatum) -> bool)
+        -> Result<RDatum, RuntimeError>
+{
+    for item in alist.iter() {
+        let item = item.map_err(|_| RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: "Non-list given to assoc".to_string()
+        })?;
+        if let Datum::Cons(ref pair) = item {
+            if matches(obj, &pair.car()) {
+                return Ok(item.clone());
+            }
+            continue;
+        }
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: "Non-pair element given to assoc".to_string()
+        });
+    }
+    Ok(Datum::Bool(false))
+}
+
+fn equal(x: &RDatum, y: &RDatum) -> bool {
+    x == y
+}
+
+macro_rules! impl_member {
+    ($static_name:ident, $func_name:ident, $search:ident, $matches:path) => (
+        fn $func_name(obj: RDatum, list: RDatum) -> Result<RDatum, RuntimeError> {
+            $search(&obj, list, $matches)
+        }
+
+        pub static $static_name: F2<RDatum, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: $func_name };
+    )
+}
+
+impl_member!(PRIM_MEMQ, memq, member_by, DatumEqv::eqv);
+impl_member!(PRIM_MEMV, memv, member_by, DatumEqv::eqv);
+impl_member!(PRIM_MEMBER, member, member_by, equal);
+impl_member!(PRIM_ASSQ, assq, assoc_by, DatumEqv::eqv);
+impl_member!(PRIM_ASSV, assv, assoc_by, DatumEqv::eqv);
+impl_member!(PRIM_ASSOC, assoc, assoc_by, equal);
+
+pub static PRIM_CONS_STAR: FoldErr<RDatum> = FoldErr { fold: cons_star };
+
+fn cons_star(mut args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    let mut res = match args.pop() {
+        Some(last) => last,
+        None => return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: "Expected at least 1 arguments, received 0".to_string()
+        })
+    };
+    while let Some(item) = args.pop() {
+        res = cons(item, res);
+    }
+    Ok(res)
+}
+
 /// Lists all primitive functions with its name
 pub fn libprimitive() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
     vec![
@@ -492,6 +616,13 @@ pub fn libprimitive() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
         ("<=", &PRIM_LE),
         (">=", &PRIM_GE),
         ("symbol->string", &PRIM_SYMBOL_TO_STRING),
-        ("append", &PRIM_APPEND)
+        ("append", &PRIM_APPEND),
+        ("memq", &PRIM_MEMQ),
+        ("memv", &PRIM_MEMV),
+        ("member", &PRIM_MEMBER),
+        ("assq", &PRIM_ASSQ),
+        ("assv", &PRIM_ASSV),
+        ("assoc", &PRIM_ASSOC),
+        ("cons*", &PRIM_CONS_STAR)
     ]
 }
@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+use datum::Datum;
+use heap::{HeapObject, Trace};
+use primitive::{F1, PrimFunc};
+use runtime::{Inst, MemRef, RDatum, RuntimeData};
+
+/// State of a promise
+#[derive(Debug)]
+enum PromiseState {
+    /// Memoized value of the forced promise
+    Done(RDatum),
+    /// Thunk returning the promise whose value is the value of this promise
+    Delayed(RDatum),
+    /// The promise shares the state of another promise, which took over its thunk while forcing
+    /// a `delay-force` chain
+    Forward(Rc<Promise>)
+}
+
+/// Promise made by `delay`, `delay-force` or `make-promise`
+#[derive(Debug)]
+pub struct Promise {
+    state: RefCell<PromiseState>
+}
+
+impl Promise {
+    /// Promise already forced to the value
+    pub fn done(value: RDatum) -> Promise {
+        Promise { state: RefCell::new(PromiseState::Done(value)) }
+    }
+
+    /// Promise computed by the thunk, which returns another promise
+    pub fn delayed(thunk: RDatum) -> Promise {
+        Promise { state: RefCell::new(PromiseState::Delayed(thunk)) }
+    }
+
+    /// The promise holding the state of the given promise
+    fn resolve(promise: &Rc<Promise>) -> Rc<Promise> {
+        let mut promise = promise.clone();
+        loop {
+            let next = match *promise.state.borrow() {
+                PromiseState::Forward(ref next) => Some(next.clone()),
+                _ => None
+            };
+            match next {
+                Some(next) => promise = next,
+                None => return promise
+            }
+        }
+    }
+
+    /// The memoized value if the promise is forced, or the thunk computing it
+    pub fn value(promise: &Rc<Promise>) -> Result<RDatum, RDatum> {
+        match *Promise::resolve(promise).state.borrow() {
+            PromiseState::Done(ref value) => Ok(value.clone()),
+            PromiseState::Delayed(ref thunk) => Err(thunk.clone()),
+            PromiseState::Forward(_) => unreachable!()
+        }
+    }
+
+    /// Updates the promise with the promise returned by its thunk, and returns the promise holding
+    /// the new state. A promise forced while its thunk was running keeps its value. A delayed
+    /// promise hands its thunk over, and shares the state of the updated promise afterwards
+    pub fn update(promise: &Rc<Promise>, next: &Rc<Promise>) -> Rc<Promise> {
+        let promise = Promise::resolve(promise);
+        let next = Promise::resolve(next);
+        let done = if let PromiseState::Done(_) = *promise.state.borrow() { true } else { false };
+        if done || Rc::ptr_eq(&promise, &next) {
+            return promise;
+        }
+
+        let value = match *next.state.borrow() {
+            PromiseState::Done(ref value) => Some(value.clone()),
+            _ => None
+        };
+        let state = match value {
+            Some(value) => PromiseState::Done(value),
+            None => mem::replace(&mut *next.state.borrow_mut(), PromiseState::Forward(promise.clone()))
+        };
+        *promise.state.borrow_mut() = state;
+        promise
+    }
+
+    /// Drops the value or the thunk held by the promise
+    pub fn release(&self) {
+        *self.state.borrow_mut() = PromiseState::Done(Datum::Ext(RuntimeData::Undefined));
+    }
+}
+
+impl PartialEq for Promise {
+    fn eq(&self, other: &Promise) -> bool {
+        (self as *const Promise) == (other as *const Promise)
+    }
+}
+
+impl Trace for Promise {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        match *self.state.borrow() {
+            PromiseState::Done(ref datum) | PromiseState::Delayed(ref datum) => datum.trace(out),
+            PromiseState::Forward(ref promise) => out.push(HeapObject::Promise(promise.clone()))
+        }
+    }
+}
+
+fn make_promise(datum: RDatum) -> RDatum {
+    match datum {
+        Datum::Ext(RuntimeData::Promise(_)) => datum,
+        _ => Datum::Ext(RuntimeData::Promise(Rc::new(Promise::done(datum))))
+    }
+}
+
+/// `(make-promise obj)`, the promise forced to `obj` unless `obj` is already a promise
+pub static PRIM_MAKE_PROMISE: F1<RDatum, RDatum> = F1 { f1: make_promise };
+
+fn is_promise(datum: RDatum) -> bool {
+    if let Datum::Ext(RuntimeData::Promise(_)) = datum { true } else { false }
+}
+
+/// `(promise? obj)`
+pub static PRIM_IS_PROMISE: F1<RDatum, bool> = F1 { f1: is_promise };
+
+/// Bytecode of `(force promise)`. The thunk of `delay-force` returns another promise, which the
+/// promise is updated with before forcing it again, so a chain of `delay-force` is forced in
+/// constant space
+pub fn force_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PromiseValue(6),
+        Inst::Call(0),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::UpdatePromise,
+        Inst::Jump(0),
+        Inst::Return
+    ]
+}
+
+pub fn libpromise() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("make-promise", &PRIM_MAKE_PROMISE),
+        ("promise?", &PRIM_IS_PROMISE)
+    ]
+}
@@ -0,0 +1,862 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use base::apply_code;
+use cast::DatumCast;
+use datum::{Datum, SimpleDatum};
+use error::{RuntimeError, RuntimeErrorKind};
+use heap::{HeapObject, Trace};
+use number::Number;
+use primitive::{F1, F2, F3, FoldErr, PrimFunc, R1, PRIM_APPEND, PRIM_LIST, PRIM_VECTOR};
+use runtime::{DatumType, Inst, MemRef, PrimFuncPtr, RDatum, RuntimeData};
+
+/// Record type descriptor
+pub struct RecordType {
+    pub name: Cow<'static, str>,
+    pub parent: Option<Rc<RecordType>>,
+    /// Uid of a nongenerative record type
+    pub uid: Option<Cow<'static, str>>,
+    pub sealed: bool,
+    pub opaque: bool,
+    /// Names and mutability of the fields, not including the fields of the parent types
+    pub fields: Vec<(Cow<'static, str>, bool)>,
+    /// Parent and protocol of the constructor descriptor given by `define-record-type`, which
+    /// `record-constructor-descriptor` returns
+    descriptor: RefCell<Option<(Option<Rc<RecordConstructor>>, RDatum)>>
+}
+
+impl RecordType {
+    /// Number of the fields of the parent types, which come first in the record
+    fn offset(&self) -> usize {
+        self.parent.as_ref().map_or(0, |p| p.size())
+    }
+
+    /// Number of all fields of the record
+    fn size(&self) -> usize {
+        self.offset() + self.fields.len()
+    }
+
+    fn is_subtype_of(&self, other: &RecordType) -> bool {
+        let mut rtd = Some(self);
+        while let Some(t) = rtd {
+            if (t as *const RecordType) == (other as *const RecordType) {
+                return true;
+            }
+            rtd = t.parent.as_ref().map(|p| &**p);
+        }
+        false
+    }
+
+    fn field_names(&self) -> Vec<Cow<'static, str>> {
+        let mut names = self.parent.as_ref().map_or(Vec::new(), |p| p.field_names());
+        names.extend(self.fields.iter().map(|&(ref name, _)| name.clone()));
+        names
+    }
+
+    /// Drops the constructor descriptor kept by the type, used by the collector to break cycles
+    pub fn release(&self) {
+        let _descriptor = self.descriptor.borrow_mut().take();
+    }
+}
+
+impl Trace for RecordType {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        if let Some(ref parent) = self.parent {
+            out.push(HeapObject::RecordType(parent.clone()));
+        }
+        if let Some((ref parent, ref protocol)) = *self.descriptor.borrow() {
+            if let Some(ref parent) = *parent {
+                out.push(HeapObject::RecordConstructor(parent.clone()));
+            }
+            protocol.trace(out);
+        }
+    }
+}
+
+impl PartialEq for RecordType {
+    fn eq(&self, other: &RecordType) -> bool {
+        (self as *const RecordType) == (other as *const RecordType)
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<record-type {}>", self.name)
+    }
+}
+
+/// Record constructor descriptor
+pub struct RecordConstructor {
+    pub rtd: Rc<RecordType>,
+    pub parent: Option<Rc<RecordConstructor>>,
+    /// `#f` for the default protocol
+    pub protocol: RDatum
+}
+
+impl RecordConstructor {
+    /// Descriptor with the default protocol, taking the values of all fields
+    fn default(rtd: Rc<RecordType>) -> RecordConstructor {
+        RecordConstructor {
+            parent: rtd.parent.clone().map(|p| Rc::new(RecordConstructor::default(p))),
+            rtd: rtd,
+            protocol: Datum::Bool(false)
+        }
+    }
+}
+
+impl Trace for RecordConstructor {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        out.push(HeapObject::RecordType(self.rtd.clone()));
+        if let Some(ref parent) = self.parent {
+            out.push(HeapObject::RecordConstructor(parent.clone()));
+        }
+        self.protocol.trace(out);
+    }
+}
+
+impl PartialEq for RecordConstructor {
+    fn eq(&self, other: &RecordConstructor) -> bool {
+        (self as *const RecordConstructor) == (other as *const RecordConstructor)
+    }
+}
+
+impl fmt::Display for RecordConstructor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<record-constructor-descriptor {}>", self.rtd.name)
+    }
+}
+
+/// Record instance, holding the fields of the parent types first
+pub struct Record {
+    pub rtd: Rc<RecordType>,
+    pub fields: RefCell<Vec<RDatum>>
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        (self as *const Record) == (other as *const Record)
+    }
+}
+
+thread_local!(static WRITING: RefCell<HashSet<usize>> = RefCell::new(HashSet::new()));
+
+/// Writes the record with its fields. A record reached again from its own fields is written as
+/// `#<name ...>`, so that records in cycles are written in finite length
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = self as *const Record as usize;
+        if !WRITING.with(|w| w.borrow_mut().insert(id)) {
+            return write!(f, "#<{} ...>", self.rtd.name);
+        }
+        let res = (|| {
+            write!(f, "#<{}", self.rtd.name)?;
+            for (name, value) in self.rtd.field_names().iter().zip(self.fields.borrow().iter()) {
+                write!(f, " {}: {}", name, value)?;
+            }
+            write!(f, ">")
+        })();
+        WRITING.with(|w| w.borrow_mut().remove(&id));
+        res
+    }
+}
+
+/// Record type given by `define-record-type`
+pub struct RecordSpec {
+    pub name: Cow<'static, str>,
+    pub uid: Option<Cow<'static, str>>,
+    pub sealed: bool,
+    pub opaque: bool,
+    pub fields: Vec<(Cow<'static, str>, bool)>
+}
+
+fn invalid(desc: String) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::InvalidType,
+        desc: desc
+    }
+}
+
+fn is_true(datum: &RDatum) -> bool {
+    match datum {
+        &Datum::Bool(false) => false,
+        _ => true
+    }
+}
+
+fn parse_field(spec: &RDatum) -> Result<(Cow<'static, str>, bool), RuntimeError> {
+    let list: Vec<RDatum> = spec.iter().collect::<Result<_, _>>()
+        .map_err(|_| invalid(format!("invalid field specifier {}", spec)))?;
+    match list.as_slice() {
+        &[Datum::Sym(ref kind), Datum::Sym(ref name)] if kind == "mutable" || kind == "immutable" =>
+            Ok((name.clone(), kind == "mutable")),
+        _ => Err(invalid(format!("invalid field specifier {}", spec)))
+    }
+}
+
+fn make_record_type_descriptor(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    let (name, parent, uid, sealed, opaque, fields) = match args.as_slice() {
+        &[ref name, ref parent, ref uid, ref sealed, ref opaque, ref fields] =>
+            (name, parent, uid, sealed, opaque, fields),
+        _ => return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected 6 arguments, received {}", args.len())
+        })
+    };
+
+    let name: Cow<'static, str> = DatumCast::unwrap(name.clone())?;
+    let parent: Option<Rc<RecordType>> = match parent {
+        &Datum::Bool(false) => None,
+        p => Some(DatumCast::unwrap(p.clone())?)
+    };
+    if let Some(ref p) = parent {
+        if p.sealed {
+            return Err(invalid(format!("record type {} is sealed", p.name)));
+        }
+    }
+    let uid = match uid {
+        &Datum::Bool(false) => None,
+        u => Some(DatumCast::unwrap(u.clone())?)
+    };
+    let fields: Vec<RDatum> = DatumCast::unwrap(fields.clone())?;
+
+    let opaque = is_true(opaque) || parent.as_ref().map_or(false, |p| p.opaque);
+    let rtd = RecordType {
+        name: name,
+        parent: parent,
+        uid: uid,
+        sealed: is_true(sealed),
+        opaque: opaque,
+        fields: fields.iter().map(parse_field).collect::<Result<_, _>>()?,
+        descriptor: RefCell::new(None)
+    };
+    let rtd = match rtd.uid.clone() {
+        Some(uid) => nongenerative(uid, rtd)?,
+        None => Rc::new(rtd)
+    };
+    Ok(Datum::Ext(RuntimeData::RecordType(rtd)))
+}
+
+thread_local!(static NONGENERATIVE: RefCell<HashMap<Cow<'static, str>, Weak<RecordType>>> =
+              RefCell::new(HashMap::new()));
+
+/// The nongenerative record type with the uid. The type made first with the uid is returned again
+/// while it's alive, and the other arguments of `make-record-type-descriptor` must be the same
+fn nongenerative(uid: Cow<'static, str>, rtd: RecordType) -> Result<Rc<RecordType>, RuntimeError> {
+    NONGENERATIVE.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        if let Some(prev) = registry.get(&uid).and_then(|prev| prev.upgrade()) {
+            if prev.parent != rtd.parent || prev.sealed != rtd.sealed || prev.opaque != rtd.opaque ||
+                    prev.fields != rtd.fields {
+                return Err(invalid(format!("record type {} with uid {} is already defined differently",
+                                           rtd.name, uid)));
+            }
+            return Ok(prev);
+        }
+        let rtd = Rc::new(rtd);
+        registry.insert(uid, Rc::downgrade(&rtd));
+        Ok(rtd)
+    })
+}
+
+/// `(make-record-type-descriptor name parent uid sealed? opaque? fields)`
+pub static PRIM_MAKE_RECORD_TYPE_DESCRIPTOR: FoldErr<RDatum> = FoldErr { fold: make_record_type_descriptor };
+
+fn is_record_type_descriptor(datum: RDatum) -> bool {
+    DatumType::get_type(&datum) == DatumType::RecordType
+}
+
+/// `(record-type-descriptor? obj)`
+pub static PRIM_IS_RECORD_TYPE_DESCRIPTOR: F1<RDatum, bool> = F1 { f1: is_record_type_descriptor };
+
+fn make_record_constructor_descriptor(rtd: Rc<RecordType>, parent: RDatum, protocol: RDatum)
+        -> Result<Rc<RecordConstructor>, RuntimeError>
+{
+    let parent = match (parent, rtd.parent.clone()) {
+        (Datum::Bool(false), p) => p.map(|p| Rc::new(RecordConstructor::default(p))),
+        (datum, Some(p)) => {
+            let rcd: Rc<RecordConstructor> = DatumCast::unwrap(datum)?;
+            if !rcd.rtd.eq(&p) {
+                return Err(invalid(format!("{} is not the descriptor of the parent type {}", rcd, p.name)));
+            }
+            Some(rcd)
+        },
+        (datum, None) =>
+            return Err(invalid(format!("record type {} has no parent, but received {}", rtd.name, datum)))
+    };
+    match DatumType::get_type(&protocol) {
+        DatumType::Callable => (),
+        _ if !is_true(&protocol) => (),
+        t => return Err(invalid(format!("expected Callable, but received {:?}", t)))
+    }
+    Ok(Rc::new(RecordConstructor {
+        rtd: rtd,
+        parent: parent,
+        protocol: protocol
+    }))
+}
+
+/// `(make-record-constructor-descriptor rtd parent-constructor-descriptor protocol)`
+pub static PRIM_MAKE_RECORD_CONSTRUCTOR_DESCRIPTOR: F3<Rc<RecordType>, RDatum, RDatum, Result<Rc<RecordConstructor>, RuntimeError>>
+    = F3 { f3: make_record_constructor_descriptor };
+
+fn record_type_descriptor(rtd: Rc<RecordType>) -> Rc<RecordType> {
+    rtd
+}
+
+/// `(record-type-descriptor record-name)`. The record name is bound to its descriptor
+pub static PRIM_RECORD_TYPE_DESCRIPTOR: F1<Rc<RecordType>, Rc<RecordType>> = F1 { f1: record_type_descriptor };
+
+fn record_constructor_descriptor(rtd: Rc<RecordType>) -> Rc<RecordConstructor> {
+    let descriptor = rtd.descriptor.borrow().clone();
+    match descriptor {
+        Some((parent, protocol)) => Rc::new(RecordConstructor {
+            rtd: rtd,
+            parent: parent,
+            protocol: protocol
+        }),
+        None => Rc::new(RecordConstructor::default(rtd))
+    }
+}
+
+/// `(record-constructor-descriptor record-name)`, the descriptor given by `define-record-type`
+pub static PRIM_RECORD_CONSTRUCTOR_DESCRIPTOR: F1<Rc<RecordType>, Rc<RecordConstructor>> = F1 { f1: record_constructor_descriptor };
+
+fn set_record_descriptor(rtd: Rc<RecordType>, rcd: Rc<RecordConstructor>) -> Rc<RecordType> {
+    *rtd.descriptor.borrow_mut() = Some((rcd.parent.clone(), rcd.protocol.clone()));
+    rtd
+}
+
+/// Keeps the constructor descriptor of `define-record-type` in the record type, and returns the
+/// type which `Inst::TrackStore` lets the collector track
+pub static PRIM_SET_RECORD_DESCRIPTOR: F2<Rc<RecordType>, Rc<RecordConstructor>, Rc<RecordType>> = F2 { f2: set_record_descriptor };
+
+fn constructor_parent(rcd: &Rc<RecordConstructor>) -> RDatum {
+    match rcd.parent {
+        Some(ref p) => Datum::Ext(RuntimeData::RecordConstructor(p.clone())),
+        None => Datum::Bool(false)
+    }
+}
+
+/// Parent of the constructor descriptor, or `#f`
+pub static PRIM_CONSTRUCTOR_PARENT: R1<Rc<RecordConstructor>, RDatum> = R1 { r1: constructor_parent };
+
+fn constructor_protocol(rcd: &Rc<RecordConstructor>) -> RDatum {
+    rcd.protocol.clone()
+}
+
+/// Protocol of the constructor descriptor, or `#f`
+pub static PRIM_CONSTRUCTOR_PROTOCOL: R1<Rc<RecordConstructor>, RDatum> = R1 { r1: constructor_protocol };
+
+fn field_values(list: &RDatum) -> Result<Vec<RDatum>, RuntimeError> {
+    list.iter().collect::<Result<_, _>>()
+        .map_err(|_| invalid(format!("expected list, but received {}", list)))
+}
+
+fn split_fields(parent: Rc<RecordConstructor>, values: RDatum) -> Result<(RDatum, RDatum), RuntimeError> {
+    let mut values = field_values(&values)?;
+    let n = parent.rtd.size();
+    if values.len() < n {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected at least {} arguments, received {}", n, values.len())
+        });
+    }
+    let own = values.split_off(n);
+    Ok((values.into_iter().collect(), own.into_iter().collect()))
+}
+
+/// Splits the field values given to the default protocol into the values of the parent fields and
+/// the rest
+pub static PRIM_SPLIT_FIELDS: F2<Rc<RecordConstructor>, RDatum, Result<(RDatum, RDatum), RuntimeError>> = F2 { f2: split_fields };
+
+fn make_record(rcd: Rc<RecordConstructor>, values: RDatum) -> Result<RDatum, RuntimeError> {
+    let values = field_values(&values)?;
+    let n = rcd.rtd.size();
+    if values.len() != n {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: format!("Expected {} field values for {}, received {}", n, rcd.rtd.name, values.len())
+        });
+    }
+    Ok(Datum::Ext(RuntimeData::Record(Rc::new(Record {
+        rtd: rcd.rtd.clone(),
+        fields: RefCell::new(values)
+    }))))
+}
+
+/// Makes the record of the constructor descriptor with the list of all field values
+pub static PRIM_MAKE_RECORD: F2<Rc<RecordConstructor>, RDatum, Result<RDatum, RuntimeError>> = F2 { f2: make_record };
+
+fn is_instance(rtd: Rc<RecordType>, datum: RDatum) -> bool {
+    match datum {
+        Datum::Ext(RuntimeData::Record(ref r)) => r.rtd.is_subtype_of(&rtd),
+        _ => false
+    }
+}
+
+/// Whether the object is a record of the type or its subtypes
+pub static PRIM_IS_INSTANCE: F2<Rc<RecordType>, RDatum, bool> = F2 { f2: is_instance };
+
+fn field_index(rtd: Rc<RecordType>, k: usize) -> Result<usize, RuntimeError> {
+    if k < rtd.fields.len() {
+        Ok(rtd.offset() + k)
+    } else {
+        Err(RuntimeError {
+            kind: RuntimeErrorKind::IndexOutOfRange,
+            desc: format!("record type {} has {} fields, but index is {}", rtd.name, rtd.fields.len(), k)
+        })
+    }
+}
+
+/// Index of the field `k` of the type among all fields of the record
+pub static PRIM_FIELD_INDEX: F2<Rc<RecordType>, usize, Result<usize, RuntimeError>> = F2 { f2: field_index };
+
+fn mutable_field_index(rtd: Rc<RecordType>, k: usize) -> Result<usize, RuntimeError> {
+    let idx = field_index(rtd.clone(), k)?;
+    let (ref name, mutable) = rtd.fields[k];
+    if mutable {
+        Ok(idx)
+    } else {
+        Err(invalid(format!("field {} of {} is immutable", name, rtd.name)))
+    }
+}
+
+/// Index of the field `k` of the type, which must be mutable
+pub static PRIM_MUTABLE_FIELD_INDEX: F2<Rc<RecordType>, usize, Result<usize, RuntimeError>> = F2 { f2: mutable_field_index };
+
+fn instance(rtd: &Rc<RecordType>, datum: RDatum) -> Result<Rc<Record>, RuntimeError> {
+    match datum {
+        Datum::Ext(RuntimeData::Record(ref r)) if r.rtd.is_subtype_of(rtd) => Ok(r.clone()),
+        _ => Err(invalid(format!("expected record of type {}, but received {}", rtd.name, datum)))
+    }
+}
+
+fn record_ref(rtd: Rc<RecordType>, idx: usize, datum: RDatum) -> Result<RDatum, RuntimeError> {
+    let record = instance(&rtd, datum)?;
+    let value = record.fields.borrow()[idx].clone();
+    Ok(value)
+}
+
+/// Value of the field, given the index among all fields
+pub static PRIM_RECORD_REF: F3<Rc<RecordType>, usize, RDatum, Result<RDatum, RuntimeError>> = F3 { f3: record_ref };
+
+fn record_set(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    let mut args = args.into_iter();
+    match (args.next(), args.next(), args.next(), args.next(), args.next()) {
+        (Some(rtd), Some(idx), Some(datum), Some(value), None) => {
+            let rtd: Rc<RecordType> = DatumCast::unwrap(rtd)?;
+            let idx: usize = DatumCast::unwrap(idx)?;
+            let record = instance(&rtd, datum)?;
+            record.fields.borrow_mut()[idx] = value;
+            Ok(Datum::Ext(RuntimeData::Record(record)))
+        },
+        _ => Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: "Expected 4 arguments".to_string()
+        })
+    }
+}
+
+/// Replaces the value of the field, given the index among all fields. It returns the record, which
+/// `Inst::TrackStore` lets the collector track
+pub static PRIM_RECORD_SET: FoldErr<RDatum> = FoldErr { fold: record_set };
+
+fn is_record(datum: RDatum) -> bool {
+    match datum {
+        Datum::Ext(RuntimeData::Record(ref r)) => !r.rtd.opaque,
+        _ => false
+    }
+}
+
+/// `(record? obj)`, which is `#f` for opaque records
+pub static PRIM_IS_RECORD: F1<RDatum, bool> = F1 { f1: is_record };
+
+fn record_rtd(record: Rc<Record>) -> Result<Rc<RecordType>, RuntimeError> {
+    if record.rtd.opaque {
+        Err(invalid(format!("record of type {} is opaque", record.rtd.name)))
+    } else {
+        Ok(record.rtd.clone())
+    }
+}
+
+/// `(record-rtd record)`
+pub static PRIM_RECORD_RTD: F1<Rc<Record>, Result<Rc<RecordType>, RuntimeError>> = F1 { f1: record_rtd };
+
+fn record_type_name(rtd: &Rc<RecordType>) -> Cow<'static, str> {
+    rtd.name.clone()
+}
+
+/// `(record-type-name rtd)`
+pub static PRIM_RECORD_TYPE_NAME: R1<Rc<RecordType>, Cow<'static, str>> = R1 { r1: record_type_name };
+
+fn record_type_parent(rtd: &Rc<RecordType>) -> RDatum {
+    match rtd.parent {
+        Some(ref p) => Datum::Ext(RuntimeData::RecordType(p.clone())),
+        None => Datum::Bool(false)
+    }
+}
+
+/// `(record-type-parent rtd)`
+pub static PRIM_RECORD_TYPE_PARENT: R1<Rc<RecordType>, RDatum> = R1 { r1: record_type_parent };
+
+fn record_type_uid(rtd: &Rc<RecordType>) -> RDatum {
+    match rtd.uid {
+        Some(ref uid) => Datum::Sym(uid.clone()),
+        None => Datum::Bool(false)
+    }
+}
+
+/// `(record-type-uid rtd)`
+pub static PRIM_RECORD_TYPE_UID: R1<Rc<RecordType>, RDatum> = R1 { r1: record_type_uid };
+
+fn record_type_is_generative(rtd: &Rc<RecordType>) -> bool {
+    rtd.uid.is_none()
+}
+
+/// `(record-type-generative? rtd)`
+pub static PRIM_RECORD_TYPE_IS_GENERATIVE: R1<Rc<RecordType>, bool> = R1 { r1: record_type_is_generative };
+
+fn record_type_is_sealed(rtd: &Rc<RecordType>) -> bool {
+    rtd.sealed
+}
+
+/// `(record-type-sealed? rtd)`
+pub static PRIM_RECORD_TYPE_IS_SEALED: R1<Rc<RecordType>, bool> = R1 { r1: record_type_is_sealed };
+
+fn record_type_is_opaque(rtd: &Rc<RecordType>) -> bool {
+    rtd.opaque
+}
+
+/// `(record-type-opaque? rtd)`
+pub static PRIM_RECORD_TYPE_IS_OPAQUE: R1<Rc<RecordType>, bool> = R1 { r1: record_type_is_opaque };
+
+fn record_type_field_names(rtd: &Rc<RecordType>) -> RDatum {
+    Datum::Vector(Rc::new(rtd.fields.iter().map(|&(ref name, _)| Datum::Sym(name.clone())).collect()))
+}
+
+/// `(record-type-field-names rtd)`, not including the fields of the parent types
+pub static PRIM_RECORD_TYPE_FIELD_NAMES: R1<Rc<RecordType>, RDatum> = R1 { r1: record_type_field_names };
+
+fn record_field_is_mutable(rtd: Rc<RecordType>, k: usize) -> Result<bool, RuntimeError> {
+    field_index(rtd.clone(), k)?;
+    Ok(rtd.fields[k].1)
+}
+
+/// `(record-field-mutable? rtd k)`
+pub static PRIM_RECORD_FIELD_IS_MUTABLE: F2<Rc<RecordType>, usize, Result<bool, RuntimeError>> = F2 { f2: record_field_is_mutable };
+
+fn prim(name: &'static str, func: &'static (PrimFunc + 'static)) -> MemRef {
+    MemRef::PrimFunc(PrimFuncPtr::new(name, func))
+}
+
+fn closure(code: Vec<Inst>) -> MemRef {
+    MemRef::Closure(Rc::new(code), 0, None)
+}
+
+/// Bytecode of `record-constructor`.
+///
+/// The constructor is made by the protocols of the descriptor and its parents. The procedure
+/// `tail` given to each level receives the values of the fields up to the level, and the
+/// procedure given to the protocol of the level calls `tail` with its field values appended.
+/// The `tail` of the descriptor itself makes the record
+pub fn record_constructor_code() -> Vec<Inst> {
+    let chain = Rc::new(protocol_chain_code());
+    vec![
+        Inst::PushArg(MemRef::Closure(chain.clone(), 0, None)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(closure(vec![
+            Inst::PushArg(prim("record-constructor", &PRIM_MAKE_RECORD)),
+            Inst::PushArg(MemRef::UpValue(0, 0)),
+            Inst::PushArg(MemRef::Arg(0)),
+            Inst::Call(2),
+            Inst::Return
+        ])),
+        Inst::PushArg(MemRef::Closure(chain, 0, None)),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of the procedure taking the descriptor, `tail`, and the procedure itself for the
+/// recursion. It returns the procedure taking the arguments of the constructor of the descriptor
+fn protocol_chain_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(prim("record-constructor", &PRIM_CONSTRUCTOR_PARENT)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::JumpIfFalse(17),
+        // the parent descriptor is the argument 3
+        Inst::SetArgSize(4),
+        Inst::PushArg(prim("record-constructor", &PRIM_CONSTRUCTOR_PROTOCOL)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::JumpIfFalse(12),
+        Inst::PushArg(closure(parent_maker_code())),
+        Inst::TailCall,
+        Inst::Return,
+        // 12: the default protocol with the parent made by the argument 4
+        Inst::DropArg(1),
+        Inst::PushArg(closure(parent_maker_code())),
+        Inst::SetArgSize(5),
+        Inst::PushArg(closure(default_protocol_code())),
+        Inst::Return,
+        // 17: no parent
+        Inst::DropArg(1),
+        Inst::PushArg(prim("record-constructor", &PRIM_CONSTRUCTOR_PROTOCOL)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::JumpIfFalse(25),
+        Inst::PushArg(closure(field_maker_code())),
+        Inst::TailCall,
+        Inst::Return,
+        // 25
+        Inst::DropArg(1),
+        Inst::PushArg(closure(field_maker_code())),
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `(lambda fields (tail fields))` given to the protocol of a type without parent
+fn field_maker_code() -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(0),
+        Inst::PushArg(MemRef::UpValue(0, 1)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of the procedure given to the protocol of a type with parent. It applies the
+/// constructor of the parent descriptor to the arguments, whose `tail` returns the procedure
+/// taking the fields of this level
+fn parent_maker_code() -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(0),
+        Inst::PushArg(closure(apply_code())),
+        Inst::PushArg(MemRef::UpValue(0, 2)),
+        Inst::PushArg(MemRef::UpValue(0, 3)),
+        Inst::PushArg(closure(vec![
+            // the parent fields are the argument 0
+            Inst::PushArg(closure(vec![
+                Inst::RollArgs(0),
+                Inst::PushArg(MemRef::UpValue(2, 1)),
+                Inst::PushArg(prim("record-constructor", &PRIM_APPEND)),
+                Inst::PushArg(MemRef::UpValue(0, 0)),
+                Inst::PushArg(MemRef::Arg(0)),
+                Inst::Call(2),
+                Inst::TailCall,
+                Inst::Return
+            ])),
+            Inst::Return
+        ])),
+        Inst::PushArg(MemRef::UpValue(0, 2)),
+        Inst::Call(3),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of the constructor of the default protocol of a type with parent, which passes the
+/// values of the parent fields to the parent constructor
+fn default_protocol_code() -> Vec<Inst> {
+    vec![
+        Inst::RollArgs(0),
+        Inst::PushArg(prim("record-constructor", &PRIM_SPLIT_FIELDS)),
+        Inst::PushArg(MemRef::UpValue(0, 3)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(2),
+        Inst::Uncons,
+        Inst::SetArgSize(3),
+        Inst::PushArg(closure(apply_code())),
+        Inst::PushArg(closure(apply_code())),
+        Inst::PushArg(MemRef::UpValue(0, 4)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(2),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::TailCall,
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `record-predicate`
+pub fn record_predicate_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(prim("record-predicate", &PRIM_RECORD_TYPE_DESCRIPTOR)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::Call(1),
+        Inst::DropArg(1),
+        Inst::PushArg(closure(vec![
+            Inst::PushArg(prim("record-predicate", &PRIM_IS_INSTANCE)),
+            Inst::PushArg(MemRef::UpValue(0, 0)),
+            Inst::PushArg(MemRef::Arg(0)),
+            Inst::Call(2),
+            Inst::Return
+        ])),
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `record-accessor`
+pub fn record_accessor_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(prim("record-accessor", &PRIM_FIELD_INDEX)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(2),
+        Inst::SetArgSize(3),
+        Inst::PushArg(closure(vec![
+            Inst::PushArg(prim("record-accessor", &PRIM_RECORD_REF)),
+            Inst::PushArg(MemRef::UpValue(0, 0)),
+            Inst::PushArg(MemRef::UpValue(0, 2)),
+            Inst::PushArg(MemRef::Arg(0)),
+            Inst::Call(3),
+            Inst::Return
+        ])),
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `record-mutator`
+pub fn record_mutator_code() -> Vec<Inst> {
+    vec![
+        Inst::PushArg(prim("record-mutator", &PRIM_MUTABLE_FIELD_INDEX)),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::Call(2),
+        Inst::SetArgSize(3),
+        Inst::PushArg(closure(vec![
+            Inst::PushArg(prim("record-mutator", &PRIM_RECORD_SET)),
+            Inst::PushArg(MemRef::UpValue(0, 0)),
+            Inst::PushArg(MemRef::UpValue(0, 2)),
+            Inst::PushArg(MemRef::Arg(0)),
+            Inst::PushArg(MemRef::Arg(1)),
+            Inst::Call(4),
+            Inst::TrackStore,
+            Inst::Return
+        ])),
+        Inst::Return
+    ]
+}
+
+/// Bytecode of `define-record-type`, which takes the parent type, the parent constructor
+/// descriptor and the protocol, each possibly `#f`. It returns the type, the constructor, the
+/// predicate, the accessors of every field, then the mutators of the mutable fields
+pub fn record_definition_code(spec: &RecordSpec) -> Vec<Inst> {
+    let name = "define-record-type";
+    let uid = match spec.uid {
+        Some(ref uid) => SimpleDatum::Sym(uid.clone()),
+        None => SimpleDatum::Bool(false)
+    };
+    let mut code = vec![
+        Inst::PushArg(prim(name, &PRIM_MAKE_RECORD_TYPE_DESCRIPTOR)),
+        Inst::PushArg(MemRef::Const(SimpleDatum::Sym(spec.name.clone()))),
+        Inst::PushArg(MemRef::Arg(0)),
+        Inst::PushArg(MemRef::Const(uid)),
+        Inst::PushArg(MemRef::Const(SimpleDatum::Bool(spec.sealed))),
+        Inst::PushArg(MemRef::Const(SimpleDatum::Bool(spec.opaque))),
+        Inst::PushArg(prim(name, &PRIM_VECTOR))
+    ];
+    for &(ref field, mutable) in spec.fields.iter() {
+        let kind = if mutable { "mutable" } else { "immutable" };
+        code.push(Inst::PushArg(prim(name, &PRIM_LIST)));
+        code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Sym(Cow::Borrowed(kind)))));
+        code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Sym(field.clone()))));
+        code.push(Inst::Call(2));
+    }
+    code.extend(vec![
+        Inst::Call(spec.fields.len()),
+        Inst::Call(6),
+        // the type is the argument 3, and the constructor descriptor is the argument 4
+        Inst::PushArg(prim(name, &PRIM_MAKE_RECORD_CONSTRUCTOR_DESCRIPTOR)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::PushArg(MemRef::Arg(1)),
+        Inst::PushArg(MemRef::Arg(2)),
+        Inst::Call(3),
+        Inst::SetArgSize(5),
+        Inst::PushArg(prim(name, &PRIM_SET_RECORD_DESCRIPTOR)),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::PushArg(MemRef::Arg(4)),
+        Inst::Call(2),
+        Inst::TrackStore,
+        Inst::DropArg(1),
+        Inst::PushArg(closure(vec![Inst::ReturnValues])),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::PushArg(closure(record_constructor_code())),
+        Inst::PushArg(MemRef::Arg(4)),
+        Inst::Call(1),
+        Inst::PushArg(closure(record_predicate_code())),
+        Inst::PushArg(MemRef::Arg(3)),
+        Inst::Call(1)
+    ]);
+    let mutable: Vec<usize> = (0..spec.fields.len()).filter(|&k| spec.fields[k].1).collect();
+    let procs = (0..spec.fields.len()).map(|k| (record_accessor_code(), k))
+        .chain(mutable.into_iter().map(|k| (record_mutator_code(), k)));
+    for (proc_code, k) in procs {
+        code.push(Inst::PushArg(closure(proc_code)));
+        code.push(Inst::PushArg(MemRef::Arg(3)));
+        code.push(Inst::PushArg(MemRef::Const(SimpleDatum::Num(Number::new_int(k as isize, 0)))));
+        code.push(Inst::Call(2));
+    }
+    code.push(Inst::TailCall);
+    code.push(Inst::Return);
+    code
+}
+
+/// Lists all record procedures implemented natively with its name
+pub fn librecord() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("make-record-type-descriptor", &PRIM_MAKE_RECORD_TYPE_DESCRIPTOR),
+        ("record-type-descriptor?", &PRIM_IS_RECORD_TYPE_DESCRIPTOR),
+        ("make-record-constructor-descriptor", &PRIM_MAKE_RECORD_CONSTRUCTOR_DESCRIPTOR),
+        ("record-type-descriptor", &PRIM_RECORD_TYPE_DESCRIPTOR),
+        ("record-constructor-descriptor", &PRIM_RECORD_CONSTRUCTOR_DESCRIPTOR),
+        ("record?", &PRIM_IS_RECORD),
+        ("record-rtd", &PRIM_RECORD_RTD),
+        ("record-type-name", &PRIM_RECORD_TYPE_NAME),
+        ("record-type-parent", &PRIM_RECORD_TYPE_PARENT),
+        ("record-type-uid", &PRIM_RECORD_TYPE_UID),
+        ("record-type-generative?", &PRIM_RECORD_TYPE_IS_GENERATIVE),
+        ("record-type-sealed?", &PRIM_RECORD_TYPE_IS_SEALED),
+        ("record-type-opaque?", &PRIM_RECORD_TYPE_IS_OPAQUE),
+        ("record-type-field-names", &PRIM_RECORD_TYPE_FIELD_NAMES),
+        ("record-field-mutable?", &PRIM_RECORD_FIELD_IS_MUTABLE)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::rc::Rc;
+
+    use super::{field_index, make_record_type_descriptor, RecordType};
+    use cast::DatumCast;
+    use datum::Datum;
+    use runtime::RDatum;
+
+    fn rtd(name: &'static str, parent: RDatum, sealed: bool, fields: RDatum) -> Result<Rc<RecordType>, ()> {
+        let args = vec![sym!(name), parent, Datum::Bool(false), Datum::Bool(sealed), Datum::Bool(false), fields];
+        make_record_type_descriptor(args).map_err(|_| ()).and_then(|d| DatumCast::unwrap(d).map_err(|_| ()))
+    }
+
+    #[test]
+    fn test_field_index() {
+        let fields: RDatum = Datum::Vector(Rc::new(vec![list![sym!("mutable"), sym!("x")],
+                                                        list![sym!("immutable"), sym!("y")]]));
+        let point = rtd("point", Datum::Bool(false), false, fields.clone()).unwrap();
+        let point3 = rtd("point3", DatumCast::wrap(point.clone()), true, fields.clone()).unwrap();
+        assert!(point3.is_subtype_of(&point));
+        assert!(!point.is_subtype_of(&point3));
+        assert_eq!(field_index(point3.clone(), 1), Ok(3));
+        assert!(field_index(point3.clone(), 2).is_err());
+        assert_eq!(point3.field_names(), vec![Cow::Borrowed("x"), Cow::Borrowed("y"),
+                                              Cow::Borrowed("x"), Cow::Borrowed("y")]);
+
+        // sealed types can not be extended
+        assert!(rtd("point4", DatumCast::wrap(point3), false, fields).is_err());
+    }
+}
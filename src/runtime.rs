@@ -3,17 +3,32 @@ use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::mem;
+use std::cmp;
 use std::fmt;
 use std::fmt::Debug;
+use std::fs::File;
+use std::path::{self, Path, PathBuf};
 use std::ops::{Deref, DerefMut};
 
+use base::{raise_code, rnrs_libraries};
 use cast::DatumCast;
-use compiler::{Compiler, PrimitiveSyntax};
-use datum::{SimpleDatum, TryConv};
+use condition::Condition;
+use compiler::{free_identifier_eq, Compiler, PrimitiveSyntax, Syntax};
+use datum::{cons, SimpleDatum, TryConv};
 use eqv::DatumEqv;
+use enums::EnumSet;
 use error::{CompileError, CompileErrorKind, RuntimeError, RuntimeErrorKind};
+use hashtable::Hashtable;
+use heap::{GcStats, Heap, HeapObject, Trace};
+use library::{DisplayName, Environment, Library, LibraryName, LibraryRegistry};
+use parser::Parser;
+use port::{Codec, Port, Transcoder};
 use datum::Datum;
+use parameter::Parameter;
 use primitive::PrimFunc;
+use promise::Promise;
+use record::{Record, RecordConstructor, RecordType};
+use syntax::{SyntaxPattern, SyntaxTemplate};
 
 use log::LogLevel;
 
@@ -56,6 +71,61 @@ pub enum RuntimeData {
     /// Compiled Closure
     Closure(Closure),
 
+    /// Closure of `case-lambda`
+    CaseLambda(CaseLambda),
+
+    /// First-class continuation captured by `call/cc`
+    Continuation(Rc<Continuation>),
+
+    /// Escape-only continuation of `guard`
+    Escape(Rc<Escape>),
+
+    /// Condition object
+    Condition(Rc<Condition>),
+
+    /// Hashtable object
+    Hashtable(Rc<Hashtable>),
+
+    /// Record type descriptor
+    RecordType(Rc<RecordType>),
+
+    /// Record constructor descriptor
+    RecordConstructor(Rc<RecordConstructor>),
+
+    /// Record instance
+    Record(Rc<Record>),
+
+    /// Enumeration set
+    EnumSet(Rc<EnumSet>),
+
+    /// Input or output port
+    Port(Rc<Port>),
+
+    /// Transcoder of textual ports
+    Transcoder(Transcoder),
+
+    /// Codec of a transcoder
+    Codec(Codec),
+
+    /// End of file object
+    Eof,
+
+    /// Environment of `eval`, made by `environment`
+    Environment(Rc<Environment>),
+
+    /// Promise of `delay`
+    Promise(Rc<Promise>),
+
+    /// Parameter object made by `make-parameter`
+    Parameter(Rc<Parameter>),
+
+    /// Marker pushed on top of the values returned by `values`, unless exactly one value is
+    /// returned. Never visible to the Scheme code
+    Values(usize),
+
+    /// Multiple values returned by the program to the host, in order
+    MultipleValues(Vec<RDatum>),
+
     /// Undefined value
     Undefined
 }
@@ -66,6 +136,12 @@ impl TryConv<(), CompileError> for RuntimeData {
     }
 }
 
+impl TryConv<RuntimeData, CompileError> for () {
+    fn try_conv(&self) -> Result<RuntimeData, CompileError> {
+        Ok(RuntimeData::Undefined)
+    }
+}
+
 /// Compiled closure object
 #[derive(Debug, Clone, PartialEq)]
 pub struct Closure {
@@ -93,6 +169,110 @@ impl Closure {
     }
 }
 
+/// Clause of `case-lambda`, taking `nargs` arguments, or more if `rest` is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseClause {
+    pub nargs: usize,
+    pub rest: bool,
+    pub code: Rc<Vec<Inst>>
+}
+
+/// Closure of `case-lambda`. The clauses share the lexical environment, and the call runs the
+/// first clause accepting the number of the arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseLambda {
+    pub clauses: Rc<Vec<CaseClause>>,
+    pub static_link: Option<StaticLink>,
+    source: Option<Rc<Datum<()>>>
+}
+
+impl DatumEqv for CaseLambda {
+    fn eqv(&self, other: &CaseLambda) -> bool {
+        self.clauses.eqv(&other.clauses) && self.static_link.eqv(&other.static_link)
+    }
+}
+
+impl CaseLambda {
+    /// Closure of the clause called with `n` arguments
+    fn select(&self, n: usize) -> Result<Closure, RuntimeError> {
+        match self.clauses.iter().find(|c| c.nargs == n || (c.rest && c.nargs < n)) {
+            Some(clause) => Ok(Closure {
+                code: clause.code.clone(),
+                static_link: self.static_link.clone(),
+                source: self.source.clone()
+            }),
+            None => Err(RuntimeError {
+                kind: RuntimeErrorKind::NumArgs,
+                desc: format!("no clause of case-lambda takes {} arguments", n)
+            })
+        }
+    }
+}
+
+/// Snapshot of the VM captured by `call/cc`. Calling the continuation throws away the current
+/// stack and resumes the snapshot, passing the argument as the return value of `call/cc`
+#[derive(Debug)]
+pub struct Continuation {
+    arg_stack: Vec<RDatum>,
+    call_stack: Vec<StackFrame>,
+    frame: StackFrame,
+    handlers: Vec<RDatum>,
+    winders: Vec<Rc<Winder>>
+}
+
+/// Continuation which `guard` resumes when its body raises an exception. It only records the
+/// heights of the stacks, so it can be called only while the body is running, when the stacks
+/// below the heights are still the same
+#[derive(Debug)]
+pub struct Escape {
+    arg_stack: usize,
+    call_stack: usize,
+    pc: usize,
+    handlers: usize,
+    winders: usize
+}
+
+impl PartialEq for Escape {
+    fn eq(&self, other: &Escape) -> bool {
+        (self as *const Escape) == (other as *const Escape)
+    }
+}
+
+/// Before and after thunks of an active `dynamic-wind`
+#[derive(Debug, PartialEq)]
+pub struct Winder {
+    before: RDatum,
+    after: RDatum
+}
+
+impl Trace for Continuation {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        for arg in self.arg_stack.iter().chain(self.handlers.iter()) {
+            arg.trace(out);
+        }
+        for frame in self.call_stack.iter() {
+            frame.trace(out);
+        }
+        self.frame.trace(out);
+        for winder in self.winders.iter() {
+            out.push(HeapObject::Winder(winder.clone()));
+        }
+    }
+}
+
+impl Trace for Winder {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        self.before.trace(out);
+        self.after.trace(out);
+    }
+}
+
+impl PartialEq for Continuation {
+    fn eq(&self, other: &Continuation) -> bool {
+        (self as *const Continuation) == (other as *const Continuation)
+    }
+}
+
 /// Type representation of RDatum
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DatumType {
@@ -106,6 +286,18 @@ pub enum DatumType {
     Pair,
     Null,
     Callable,
+    Condition,
+    Hashtable,
+    RecordType,
+    RecordConstructor,
+    Record,
+    EnumSet,
+    Port,
+    Transcoder,
+    Codec,
+    Eof,
+    Environment,
+    Promise,
     Undefined
 }
 
@@ -124,6 +316,24 @@ impl DatumType {
             &Datum::Cons(_) => DatumType::Pair,
             &Datum::Ext(RuntimeData::PrimFunc(_)) => DatumType::Callable,
             &Datum::Ext(RuntimeData::Closure(_)) => DatumType::Callable,
+            &Datum::Ext(RuntimeData::CaseLambda(_)) => DatumType::Callable,
+            &Datum::Ext(RuntimeData::Continuation(_)) => DatumType::Callable,
+            &Datum::Ext(RuntimeData::Escape(_)) => DatumType::Callable,
+            &Datum::Ext(RuntimeData::Condition(_)) => DatumType::Condition,
+            &Datum::Ext(RuntimeData::Hashtable(_)) => DatumType::Hashtable,
+            &Datum::Ext(RuntimeData::RecordType(_)) => DatumType::RecordType,
+            &Datum::Ext(RuntimeData::RecordConstructor(_)) => DatumType::RecordConstructor,
+            &Datum::Ext(RuntimeData::Record(_)) => DatumType::Record,
+            &Datum::Ext(RuntimeData::EnumSet(_)) => DatumType::EnumSet,
+            &Datum::Ext(RuntimeData::Port(_)) => DatumType::Port,
+            &Datum::Ext(RuntimeData::Transcoder(_)) => DatumType::Transcoder,
+            &Datum::Ext(RuntimeData::Codec(_)) => DatumType::Codec,
+            &Datum::Ext(RuntimeData::Eof) => DatumType::Eof,
+            &Datum::Ext(RuntimeData::Environment(_)) => DatumType::Environment,
+            &Datum::Ext(RuntimeData::Promise(_)) => DatumType::Promise,
+            &Datum::Ext(RuntimeData::Parameter(_)) => DatumType::Callable,
+            &Datum::Ext(RuntimeData::Values(_)) => DatumType::Undefined,
+            &Datum::Ext(RuntimeData::MultipleValues(_)) => DatumType::Undefined,
             &Datum::Ext(RuntimeData::Undefined) => DatumType::Undefined
         }
     }
@@ -137,11 +347,103 @@ impl DatumEqv for RuntimeData {
                 } else {
                     false
                 },
+            &RuntimeData::CaseLambda(ref self_v) => if let &RuntimeData::CaseLambda(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
             &RuntimeData::PrimFunc(ref self_v) => if let &RuntimeData::PrimFunc(ref other_v) = other {
                     self_v.eqv(other_v)
                 } else {
                     false
                 },
+            &RuntimeData::Continuation(ref self_v) => if let &RuntimeData::Continuation(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Escape(ref self_v) => if let &RuntimeData::Escape(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Condition(ref self_v) => if let &RuntimeData::Condition(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Hashtable(ref self_v) => if let &RuntimeData::Hashtable(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::RecordType(ref self_v) => if let &RuntimeData::RecordType(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::RecordConstructor(ref self_v) => if let &RuntimeData::RecordConstructor(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Record(ref self_v) => if let &RuntimeData::Record(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::EnumSet(ref self_v) => if let &RuntimeData::EnumSet(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Port(ref self_v) => if let &RuntimeData::Port(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Transcoder(self_v) => if let &RuntimeData::Transcoder(other_v) = other {
+                    self_v == other_v
+                } else {
+                    false
+                },
+            &RuntimeData::Codec(self_v) => if let &RuntimeData::Codec(other_v) = other {
+                    self_v == other_v
+                } else {
+                    false
+                },
+            &RuntimeData::Eof => if let &RuntimeData::Eof = other {
+                    true
+                } else {
+                    false
+                },
+            &RuntimeData::Environment(ref self_v) => if let &RuntimeData::Environment(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Promise(ref self_v) => if let &RuntimeData::Promise(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Parameter(ref self_v) => if let &RuntimeData::Parameter(ref other_v) = other {
+                    self_v.eqv(other_v)
+                } else {
+                    false
+                },
+            &RuntimeData::Values(self_v) => if let &RuntimeData::Values(other_v) = other {
+                    self_v == other_v
+                } else {
+                    false
+                },
+            &RuntimeData::MultipleValues(ref self_v) =>
+                if let &RuntimeData::MultipleValues(ref other_v) = other {
+                    self_v.len() == other_v.len() &&
+                        self_v.iter().zip(other_v.iter()).all(|(a, b)| a.eqv(b))
+                } else {
+                    false
+                },
             &RuntimeData::Undefined => if let &RuntimeData::Undefined = other {
                     true
                 } else {
@@ -158,6 +460,47 @@ impl fmt::Debug for RuntimeData {
                 write!(f, "<primitive: {:?}>", func_ptr.name),
             &RuntimeData::Closure(ref closure) =>
                 write!(f, "<procedure {:?}: {:?}>", closure.static_link, closure.code),
+            &RuntimeData::CaseLambda(ref case_lambda) =>
+                write!(f, "<procedure {:?}: {:?}>", case_lambda.static_link, case_lambda.clauses),
+            &RuntimeData::Continuation(_) | &RuntimeData::Escape(_) =>
+                write!(f, "<continuation>"),
+            &RuntimeData::Condition(ref c) =>
+                write!(f, "{}", c),
+            &RuntimeData::Hashtable(_) =>
+                write!(f, "<hashtable>"),
+            &RuntimeData::RecordType(ref t) =>
+                write!(f, "{}", t),
+            &RuntimeData::RecordConstructor(ref c) =>
+                write!(f, "{}", c),
+            &RuntimeData::Record(ref r) =>
+                write!(f, "{}", r),
+            &RuntimeData::EnumSet(ref e) =>
+                write!(f, "{}", e),
+            &RuntimeData::Port(ref p) =>
+                write!(f, "{}", p),
+            &RuntimeData::Transcoder(ref t) =>
+                write!(f, "{}", t),
+            &RuntimeData::Codec(c) =>
+                write!(f, "#<codec {}>", c.name()),
+            &RuntimeData::Eof =>
+                write!(f, "#<eof>"),
+            &RuntimeData::Environment(_) =>
+                write!(f, "#<environment>"),
+            &RuntimeData::Promise(_) =>
+                write!(f, "#<promise>"),
+            &RuntimeData::Parameter(_) =>
+                write!(f, "#<parameter>"),
+            &RuntimeData::Values(n) =>
+                write!(f, "<values {}>", n),
+            &RuntimeData::MultipleValues(ref vs) => {
+                for (i, v) in vs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                Ok(())
+            },
             &RuntimeData::Undefined =>
                 write!(f, "<undefined>")
         }
@@ -176,6 +519,52 @@ impl fmt::Display for RuntimeData {
                     Some(ref ptr) => write!(f, ": {:?}>", ptr.deref())
                 }
             },
+            &RuntimeData::CaseLambda(ref case_lambda) => {
+                write!(f, "<procedure")?;
+                match case_lambda.source {
+                    None => write!(f, ">"),
+                    Some(ref ptr) => write!(f, ": {:?}>", ptr.deref())
+                }
+            },
+            &RuntimeData::Continuation(_) | &RuntimeData::Escape(_) =>
+                write!(f, "<continuation>"),
+            &RuntimeData::Condition(ref c) =>
+                write!(f, "{}", c),
+            &RuntimeData::Hashtable(_) =>
+                write!(f, "<hashtable>"),
+            &RuntimeData::RecordType(ref t) =>
+                write!(f, "{}", t),
+            &RuntimeData::RecordConstructor(ref c) =>
+                write!(f, "{}", c),
+            &RuntimeData::Record(ref r) =>
+                write!(f, "{}", r),
+            &RuntimeData::EnumSet(ref e) =>
+                write!(f, "{}", e),
+            &RuntimeData::Port(ref p) =>
+                write!(f, "{}", p),
+            &RuntimeData::Transcoder(ref t) =>
+                write!(f, "{}", t),
+            &RuntimeData::Codec(c) =>
+                write!(f, "#<codec {}>", c.name()),
+            &RuntimeData::Eof =>
+                write!(f, "#<eof>"),
+            &RuntimeData::Environment(_) =>
+                write!(f, "#<environment>"),
+            &RuntimeData::Promise(_) =>
+                write!(f, "#<promise>"),
+            &RuntimeData::Parameter(_) =>
+                write!(f, "#<parameter>"),
+            &RuntimeData::Values(n) =>
+                write!(f, "<values {}>", n),
+            &RuntimeData::MultipleValues(ref vs) => {
+                for (i, v) in vs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                Ok(())
+            },
             &RuntimeData::Undefined =>
                 write!(f, "<undefined>")
         }
@@ -195,7 +584,8 @@ pub enum MemRef {
     Const(SimpleDatum),
     Undefined,
     PrimFunc(PrimFuncPtr),
-    Closure(Rc<Vec<Inst>>, usize, Option<Datum<()>>)
+    Closure(Rc<Vec<Inst>>, usize, Option<Datum<()>>),
+    CaseLambda(Rc<Vec<CaseClause>>, Option<Datum<()>>)
 }
 
 /// The instruction of the bytecode
@@ -217,6 +607,13 @@ pub enum Inst {
     RollArgs(usize),
     /// pop pair(h, t) from the top of the list, then push h, then push t
     Uncons,
+    /// pop the value, then replace the car of the pair on the top of the stack with it
+    SetCar,
+    /// pop the value, then replace the cdr of the pair on the top of the stack with it
+    SetCdr,
+    /// pop the hashtable, the record or the record type modified by a primitive, track it in the
+    /// collector, then push the unspecified value
+    TrackStore,
     /// compare two top values of the stack with `eqv?` operator
     Eqv,
     /// compare two top values of the stack with `equal?` operator
@@ -231,6 +628,13 @@ pub enum Inst {
     CallSplicing,
     /// pop the call stack frame, and return to the call site
     Return,
+    /// pop the call stack frame, and return all arguments of the frame as multiple values
+    ReturnValues,
+    /// remove the multiple values marker, leaving the values on the stack
+    SpreadValues,
+    /// check the number of values on the stack, and roll the values after the first n into a list
+    /// if the flag is set
+    ReceiveValues(usize, bool),
     /// push the call stack without jumping, and move stack_bottom to (stack_top - n)
     PushFrame(usize),
     /// update the argument size of the current frame, letting the PopFrame correctly deallocate
@@ -238,6 +642,12 @@ pub enum Inst {
     SetArgSize(usize),
     /// pop the call stack without jumping
     PopFrame,
+    /// capture the current continuation and push it to the stack. When the continuation is
+    /// called, the current frame resumes at the given pc with the passed value pushed to the stack
+    PushContinuation(usize),
+    /// push the escape-only continuation resuming the current frame at the given pc, as
+    /// `PushContinuation` does, without copying the stacks
+    PushEscape(usize),
     /// jump to the given pc
     Jump(usize),
     /// jump to the given pc if current stack top is `#f`
@@ -245,7 +655,61 @@ pub enum Inst {
     /// jump to the given pc if current stack top is not `#f`
     JumpIfNotFalse(usize),
     /// throw error if current stack top is `#f`
-    ThrowIfFalse(&'static str)
+    ThrowIfFalse(&'static str),
+    /// throw error if current stack top is multiple values
+    CheckSingleValue,
+    /// throw error of the given kind
+    Throw(RuntimeErrorKind, &'static str),
+    /// pop the after thunk and the before thunk from the stack, and enter the dynamic extent of
+    /// them
+    PushWinder,
+    /// leave the innermost dynamic extent
+    PopWinder,
+    /// leave the innermost dynamic extent which the continuation at the first argument is not in,
+    /// and push its after thunk. Jumps to the given pc if there is no such extent
+    Unwind(usize),
+    /// push the before thunk of the outermost dynamic extent which the continuation at the first
+    /// argument is in, but the current code is not. Jumps to the given pc if there is no such
+    /// extent
+    Rewind(usize),
+    /// enter the dynamic extent whose before thunk was pushed by `Rewind`
+    Enter,
+    /// resume the continuation at the first argument, passing the rest of the arguments
+    Resume,
+    /// pop the value from the stack and install it as the current exception handler
+    InstallHandler,
+    /// uninstall the current exception handler
+    UninstallHandler,
+    /// uninstall the current exception handler and push it to the stack. If there is no handler,
+    /// the top of the stack is thrown as an uncaught exception
+    TakeHandler,
+    /// pop the syntax and match it against the pattern. On a match, push the values of the pattern
+    /// variables then `#t`, otherwise push `#f`
+    MatchSyntax(Rc<SyntaxPattern>),
+    /// pop the values of the pattern variables of the template, and push the syntax built from
+    /// them
+    BuildSyntax(Rc<SyntaxTemplate>),
+    /// pop the list of the import specs, and push the environment importing them. The libraries
+    /// not registered yet are loaded from the library paths
+    Environment,
+    /// pop the environment and the expression, and call the code compiled from the expression in
+    /// the environment
+    Eval,
+    /// pop the value, and push the promise forced to it. If the flag is `false`, pop the thunk
+    /// instead and push the promise computed by it
+    MakePromise(bool),
+    /// pop the promise, and push its value then jump to the given pc if it is forced. Otherwise
+    /// push the thunk computing it. A value which is not a promise is its own value
+    PromiseValue(usize),
+    /// pop the promise and the promise returned by its thunk, and update the promise with it
+    UpdatePromise,
+    /// pop the value, and push the parameter holding it. If the flag is `true`, pop the converter
+    /// of the parameter first
+    MakeParameter(bool),
+    /// pop the parameter, and push its converter. Jumps to the given pc if it has no converter
+    ParameterConverter(usize),
+    /// pop the value and the parameter, and push the value of the parameter replaced by it
+    SwapParameter
 }
 
 /// When the enclosing lexical env goes out of scope of the closure, the env is copied into heap
@@ -323,11 +787,31 @@ pub enum ScopePtr {
     Heap(HeapClosure)
 }
 
+impl ScopePtr {
+    /// Empty environment in the heap
+    pub fn empty() -> ScopePtr {
+        ScopePtr::Heap(HeapClosure { args: Vec::new(), static_link: None })
+    }
+}
+
+impl Trace for ScopePtr {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        if let &ScopePtr::Heap(ref heap) = self {
+            for arg in heap.args.iter() {
+                arg.trace(out);
+            }
+            if let Some(ref link) = heap.static_link {
+                out.push(HeapObject::Scope(link.clone()));
+            }
+        }
+    }
+}
+
 /// Shared link to the ScopePtr
 pub type StaticLink = Rc<RefCell<ScopePtr>>;
 
 /// StackFrame represents frame in the main stack
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StackFrame {
     // Current running code
     closure: Closure,
@@ -350,14 +834,123 @@ pub struct StackFrame {
     self_link: StaticLink
 }
 
+impl StackFrame {
+    /// Frame of no code, which the runtime has before running any program
+    fn empty() -> StackFrame {
+        StackFrame {
+            closure: Closure { code: Rc::new(Vec::new()), static_link: None, source: None },
+            pc: 0,
+            stack_bottom: 0,
+            arg_size: 0,
+            self_link: Rc::new(RefCell::new(ScopePtr::Stack(0)))
+        }
+    }
+}
+
+impl Trace for StackFrame {
+    fn trace(&self, out: &mut Vec<HeapObject>) {
+        self.closure.trace(out);
+        out.push(HeapObject::Scope(self.self_link.clone()));
+    }
+}
+
 /// The virtual machine running the bytecode
 pub struct Runtime {
     ret_val: RDatum,
     arg_stack: Vec<RDatum>,
     call_stack: Vec<StackFrame>,
     frame: StackFrame,
-    global: HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>,
-    compiler: Compiler
+    env: ProgramEnv,
+    compiler: Compiler,
+    // Installed exception handlers. The last one is the current handler
+    handlers: Vec<RDatum>,
+    // Bytecode of `raise`, which is called when the runtime throws an error
+    raise: Rc<Vec<Inst>>,
+    // Active `dynamic-wind`s. The last one is the innermost
+    winders: Vec<Rc<Winder>>,
+    // Bytecode running the before and after thunks before resuming a continuation
+    wind: Rc<Vec<Inst>>,
+    // Garbage collector freeing the reference cycles
+    heap: RefCell<Heap>,
+    // Libraries being loaded from the files, outermost first
+    loading: Vec<LibraryName>,
+    // The call which failed with the error being raised
+    failed_call: Option<FailedCall>
+}
+
+/// Global environment and libraries of a program, shared with the runtime calling the
+/// transformers of its macros
+#[derive(Clone)]
+pub struct ProgramEnv {
+    global: Rc<RefCell<HashMap<Cow<'static, str>, Rc<RefCell<RDatum>>>>>,
+    /// Libraries which `import` refers to
+    libraries: Rc<RefCell<LibraryRegistry>>,
+    /// Directories searched for the library files
+    library_paths: Rc<RefCell<Vec<PathBuf>>>
+}
+
+/// Procedure and arguments of a failed call, reported as `&who` and `&irritants` of the condition
+/// raised for its error
+struct FailedCall {
+    error: RuntimeError,
+    who: Option<RDatum>,
+    irritants: RDatum
+}
+
+/// Stacks of the running code, set aside while a nested program runs
+struct SavedState {
+    ret_val: RDatum,
+    arg_stack: Vec<RDatum>,
+    call_stack: Vec<StackFrame>,
+    frame: StackFrame,
+    handlers: Vec<RDatum>,
+    winders: Vec<Rc<Winder>>
+}
+
+/// Bytecode called with a continuation and its values. It leaves the dynamic extents not shared
+/// with the continuation, enters the extents of the continuation, then resumes the continuation.
+fn wind_code() -> Vec<Inst> {
+    vec![
+        // 0
+        Inst::Unwind(4),
+        Inst::Call(0),
+        Inst::DropArg(1),
+        Inst::Jump(0),
+        // 4
+        Inst::Rewind(9),
+        Inst::Call(0),
+        Inst::DropArg(1),
+        Inst::Enter,
+        Inst::Jump(4),
+        // 9
+        Inst::Resume
+    ]
+}
+
+/// Error of passing `n` values to the continuation taking a single value
+fn single_value_error(n: usize) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::NumArgs,
+        desc: format!("Expected a single value, received {} values", n)
+    }
+}
+
+/// Checks that none of the arguments is multiple values
+fn check_single_values(args: &[RDatum]) -> Result<(), RuntimeError> {
+    for arg in args.iter() {
+        if let &Datum::Ext(RuntimeData::Values(n)) = arg {
+            return Err(single_value_error(n));
+        }
+    }
+    Ok(())
+}
+
+/// Error of calling a parameter object with arguments
+fn parameter_args_error(n: usize) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::NumArgs,
+        desc: format!("parameter takes no arguments, but received {}", n)
+    }
 }
 
 fn runtime_panic(msg: String) -> RuntimeError {
@@ -373,22 +966,68 @@ impl Runtime {
                base_syntax: HashMap<Cow<'static, str>, PrimitiveSyntax>)
             -> Runtime
     {
+        let mut libraries = LibraryRegistry::new();
+        for library in rnrs_libraries(&base, &base_syntax) {
+            libraries.register(library);
+        }
+
+        let env = ProgramEnv {
+            global: Rc::new(RefCell::new(base)),
+            libraries: Rc::new(RefCell::new(libraries)),
+            library_paths: Rc::new(RefCell::new(Vec::new()))
+        };
+        Runtime::with_env(env, base_syntax)
+    }
+
+    /// Create the virtual machine of the program environment, such as the one calling the
+    /// transformers of the macros while the program is compiled
+    pub fn with_env(env: ProgramEnv, base_syntax: HashMap<Cow<'static, str>, PrimitiveSyntax>) -> Runtime {
+        let mut compiler = Compiler::new(base_syntax);
+        compiler.share_env(env.clone());
+
         Runtime {
             ret_val: Datum::Nil,
             arg_stack: Vec::new(),
             call_stack: Vec::new(),
-            frame: StackFrame {
-                closure: Closure { code: Rc::new(Vec::new()), static_link: None, source: None },
-                pc: 0,
-                stack_bottom: 0,
-                arg_size: 0,
-                self_link: Rc::new(RefCell::new(ScopePtr::Stack(0)))
-            },
-            global: base,
-            compiler: Compiler::new(base_syntax)
+            frame: StackFrame::empty(),
+            env: env,
+            compiler: compiler,
+            handlers: Vec::new(),
+            raise: Rc::new(raise_code(false)),
+            winders: Vec::new(),
+            wind: Rc::new(wind_code()),
+            heap: RefCell::new(Heap::new()),
+            loading: Vec::new(),
+            failed_call: None
         }
     }
 
+    /// Runs the code as the main program. The stacks of the code already running, as when
+    /// `environment` loads a library, are set aside while the program runs and restored after it
+    fn run_main(&mut self, code: Vec<Inst>, source: Option<Datum<()>>) -> Result<RDatum, RuntimeError> {
+        let outer = SavedState {
+            ret_val: mem::replace(&mut self.ret_val, Datum::Nil),
+            arg_stack: mem::replace(&mut self.arg_stack, Vec::new()),
+            call_stack: mem::replace(&mut self.call_stack, Vec::new()),
+            frame: mem::replace(&mut self.frame, StackFrame::empty()),
+            handlers: mem::replace(&mut self.handlers, Vec::new()),
+            winders: mem::replace(&mut self.winders, Vec::new())
+        };
+
+        self.load_main(code, source);
+        let res = self.run();
+
+        // the closures made by the program keep the variables of its frames
+        self.close_all_frames();
+        self.ret_val = outer.ret_val;
+        self.arg_stack = outer.arg_stack;
+        self.call_stack = outer.call_stack;
+        self.frame = outer.frame;
+        self.handlers = outer.handlers;
+        self.winders = outer.winders;
+        res
+    }
+
     pub fn load_main(&mut self, code: Vec<Inst>, source: Option<Datum<()>>) {
         let closure = Closure {
             code: Rc::new(code),
@@ -396,33 +1035,237 @@ impl Runtime {
             source: source.map(Rc::new)
         };
 
+        self.load_closure(closure, Vec::new());
+        self.winders = Vec::new();
+    }
+
+    /// Throws away the current stack, and sets up the stack to call the closure with the args
+    fn load_closure(&mut self, closure: Closure, mut args: Vec<RDatum>) {
+        self.close_all_frames();
+
+        let arg_size = args.len();
         self.arg_stack = vec![Datum::Ext(RuntimeData::Closure(closure.clone()))];
+        self.arg_stack.append(&mut args);
         self.call_stack = Vec::new();
+        self.handlers = Vec::new();
         self.frame = StackFrame {
             closure: closure,
             pc: 0,
             stack_bottom: 1,
-            arg_size: 0,
+            arg_size: arg_size,
             self_link: Rc::new(RefCell::new(ScopePtr::Stack(0)))
         }
     }
 
+    /// Runs the code as the main program
+    pub fn run_code(&mut self, code: Vec<Inst>) -> Result<RDatum, RuntimeError> {
+        self.load_main(code, None);
+        self.run()
+    }
+
+    /// Calls the procedure with the arguments, throwing away the current stack
+    pub fn apply(&mut self, f: RDatum, args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        let nargs = args.len();
+        let mut code: Vec<Inst> = (0 .. nargs + 1).map(|i| Inst::PushArg(MemRef::Arg(i))).collect();
+        code.push(Inst::Call(nargs));
+        code.push(Inst::Return);
+
+        let mut frame_args = vec![f];
+        frame_args.extend(args);
+        self.load_closure(Closure::new(Rc::new(code), None, None), frame_args);
+        self.winders = Vec::new();
+        self.run()
+    }
+
     pub fn eval<T>(&mut self, datum: &Datum<T>) -> Result<RDatum, RuntimeError>
         where T: Clone + Debug + TryConv<(), CompileError>
     {
         debug!("eval {:?}", datum);
 
-        let code = match self.compiler.compile(&self.global, datum) {
+        // the transformers run while the form is compiled may bind the globals of the program
+        let global = self.env.global.borrow().clone();
+        match self.compiler.toplevel_syntax(&global, datum) {
+            Some(PrimitiveSyntax::Library) => return self.eval_library(datum),
+            Some(PrimitiveSyntax::Import) => return self.eval_import(datum),
+            Some(PrimitiveSyntax::DefineSyntax) | Some(PrimitiveSyntax::DefineEnumeration) =>
+                return self.eval_define_syntax(datum),
+            _ => ()
+        }
+
+        let code = match self.compiler.compile(&global, datum) {
             Ok(c) => c,
-            Err(e) => return Err(RuntimeError {
-                kind: RuntimeErrorKind::CompileError,
-                desc: format!("{:?}", e.kind)
-            })
+            Err(e) => return Err(RuntimeError::from(e))
         };
 
         let src: Datum<()> = datum.try_conv()?;
-        self.load_main(code, Some(src));
-        self.run()
+        self.run_main(code, Some(src))
+    }
+
+    /// Compiles and runs the library definition, then registers the library
+    fn eval_library<T>(&mut self, datum: &Datum<T>) -> Result<RDatum, RuntimeError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        self.load_imports(datum)?;
+        let libraries = self.env.libraries.borrow().clone();
+        let (library, code) = self.compiler.compile_library(&libraries, datum)?;
+        let src: Datum<()> = datum.try_conv()?;
+        self.run_main(code, Some(src))?;
+        self.env.libraries.borrow_mut().register(library);
+        Ok(Datum::Ext(RuntimeData::Undefined))
+    }
+
+    /// Adds the bindings imported by `(import <import set> ...)` to the global environment
+    fn eval_import<T>(&mut self, datum: &Datum<T>) -> Result<RDatum, RuntimeError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        self.load_imports(datum)?;
+        let env = self.compiler.compile_import(&self.env.libraries.borrow(), datum)?;
+        for (sym, cell) in env.vars.into_iter() {
+            self.bind_global(sym, cell, true);
+        }
+        for (sym, syntax) in env.syntax.into_iter() {
+            self.bind_syntax(sym, syntax);
+        }
+        Ok(Datum::Ext(RuntimeData::Undefined))
+    }
+
+    /// Binds the keyword of `(define-syntax <keyword> <expr>)`, or the keywords of
+    /// `define-enumeration`, in the global environment, where they stay for the later evaluations
+    fn eval_define_syntax<T>(&mut self, datum: &Datum<T>) -> Result<RDatum, RuntimeError>
+        where T: Clone + Debug + TryConv<(), CompileError>
+    {
+        let global = self.env.global.borrow().clone();
+        for (sym, syntax) in self.compiler.compile_define_syntax(&global, datum)? {
+            self.bind_syntax(sym, syntax);
+        }
+        Ok(Datum::Ext(RuntimeData::Undefined))
+    }
+
+    /// Binds the keyword in the global environment, replacing the global variable of the name
+    fn bind_syntax(&mut self, sym: Cow<'static, str>, syntax: Syntax) {
+        let old = self.env.global.borrow_mut().remove(&sym);
+        if let Some(old) = old {
+            self.release_global(old);
+        }
+        self.compiler.define_syntax(sym, syntax);
+    }
+
+    /// Binds the global variable to the cell. An imported variable cannot be assigned
+    fn bind_global(&mut self, sym: Cow<'static, str>, cell: Rc<RefCell<RDatum>>, imported: bool) {
+        self.compiler.define_var(sym.clone(), imported);
+        let old = self.env.global.borrow_mut().insert(sym, cell);
+        if let Some(old) = old {
+            self.release_global(old);
+        }
+    }
+
+    /// Lets the collector track the cell no longer bound in the global environment, which the
+    /// code compiled before may still refer to
+    fn release_global(&self, old: Rc<RefCell<RDatum>>) {
+        if Rc::strong_count(&old) > 1 {
+            self.heap.borrow_mut().track_global(&old);
+        }
+    }
+
+    /// The environment of `eval` importing the bindings of the import specs
+    fn environment(&mut self, specs: RDatum) -> Result<Environment, RuntimeError> {
+        let form = cons(Datum::Sym(Cow::Borrowed("import")), specs);
+        self.load_imports(&form)?;
+        Ok(self.compiler.compile_import(&self.env.libraries.borrow(), &form)?)
+    }
+
+    /// Registers the library, which `import` and `library` forms can refer to
+    pub fn register_library(&mut self, library: Library) {
+        self.env.libraries.borrow_mut().register(library);
+    }
+
+    /// Finds the registered library by name
+    pub fn get_library(&self, name: &[Cow<'static, str>]) -> Option<Rc<Library>> {
+        self.env.libraries.borrow().get(name)
+    }
+
+    /// Adds the directory searched for the library files. `(import (foo bar))` loads
+    /// `foo/bar.sls` or `foo/bar.scm` under the first directory which has one
+    pub fn add_library_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.env.library_paths.borrow_mut().push(path.into());
+    }
+
+    /// Loads the libraries imported by the `library` or `import` form from the library paths,
+    /// unless they are already registered
+    fn load_imports<T: Clone>(&mut self, datum: &Datum<T>) -> Result<(), RuntimeError> {
+        for name in self.compiler.imported_libraries(datum)? {
+            if self.get_library(&name).is_none() {
+                self.load_library(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_library(&mut self, name: LibraryName) -> Result<(), RuntimeError> {
+        if let Some(pos) = self.loading.iter().position(|loading| *loading == name) {
+            let cycle: Vec<String> = self.loading[pos..].iter().chain(Some(&name))
+                .map(|n| DisplayName(n).to_string())
+                .collect();
+            return Err(RuntimeError::from(CompileError {
+                kind: CompileErrorKind::CircularImport(cycle.join(" -> "))
+            }));
+        }
+
+        // unknown libraries are reported by the import
+        let path = match self.find_library_file(&name) {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        self.loading.push(name.clone());
+        let res = self.load_library_file(&path);
+        self.loading.pop();
+        res?;
+
+        if self.get_library(&name).is_none() {
+            return Err(RuntimeError::from(CompileError {
+                kind: CompileErrorKind::LoadLibrary(
+                    format!("{} does not define {}", path.display(), DisplayName(&name)))
+            }));
+        }
+        Ok(())
+    }
+
+    /// Path of the file of the library in the library paths. A name whose part is not a plain
+    /// file name, such as `..`, is never found, so the file is always under one of the paths
+    fn find_library_file(&self, name: &[Cow<'static, str>]) -> Option<PathBuf> {
+        let plain = |part: &Cow<'static, str>| part != "" && part != "." && part != ".." &&
+            !part.contains(|c| c == '/' || c == '\\' || c == path::MAIN_SEPARATOR);
+        if !name.iter().all(plain) {
+            return None;
+        }
+
+        for root in self.env.library_paths.borrow().iter() {
+            let mut path = root.clone();
+            for part in name.iter() {
+                path.push(part.as_ref());
+            }
+            for ext in [".sls", ".scm"].iter() {
+                let mut file = path.clone().into_os_string();
+                file.push(ext);
+                let file = PathBuf::from(file);
+                if file.is_file() {
+                    return Some(file);
+                }
+            }
+        }
+        None
+    }
+
+    fn load_library_file(&mut self, path: &Path) -> Result<(), RuntimeError> {
+        let load_error = |e: String| RuntimeError::from(CompileError {
+            kind: CompileErrorKind::LoadLibrary(format!("{}: {}", path.display(), e))
+        });
+
+        let file = File::open(path).map_err(|e| load_error(e.to_string()))?;
+        let datum: Datum<()> = Parser::new(file).parse_full().map_err(|e| load_error(e.to_string()))?;
+        self.eval(&datum)?;
+        Ok(())
     }
 
     fn fetch(&self) -> Inst {
@@ -501,6 +1344,10 @@ impl Runtime {
             MemRef::RetVal => self.ret_val.clone(),
             MemRef::Arg(idx) => self.get_stack_val(idx),
             MemRef::UpValue(i, j) => self.get_upvalue(i, j)?,
+            // string and bytevector literals are copied, so that mutating them does not change the
+            // constants of the code
+            MemRef::Const(SimpleDatum::String(s)) => Datum::String(Rc::new(RefCell::new(s.borrow().clone()))),
+            MemRef::Const(SimpleDatum::Bytes(v)) => Datum::Bytes(Rc::new(RefCell::new(v.borrow().clone()))),
             MemRef::Const(val) => DatumCast::wrap(val),
             MemRef::Global(data) => data.borrow().clone(),
             MemRef::Undefined => Datum::Ext(RuntimeData::Undefined),
@@ -511,6 +1358,13 @@ impl Runtime {
                     static_link: Some(self.frame.self_link.clone()),
                     source: src.map(Rc::new)
                 }
+            )),
+            MemRef::CaseLambda(clauses, src) => Datum::Ext(RuntimeData::CaseLambda(
+                CaseLambda {
+                    clauses: clauses.clone(),
+                    static_link: Some(self.frame.self_link.clone()),
+                    source: src.map(Rc::new)
+                }
             ))
         };
 
@@ -530,23 +1384,88 @@ impl Runtime {
             MemRef::Const(_) => return Err(runtime_panic("Cannot write to read-only memory".to_string())),
             MemRef::Undefined => return Err(runtime_panic("Cannot write to undefined memory address".to_string())),
             MemRef::PrimFunc(_) => return Err(runtime_panic("Cannot write to code area".to_string())),
-            MemRef::Closure(_, _, _) => return Err(runtime_panic("Cannot write to instruction memory".to_string()))
+            MemRef::Closure(_, _, _) | MemRef::CaseLambda(_, _) =>
+                return Err(runtime_panic("Cannot write to instruction memory".to_string()))
         }
 
         Ok(())
     }
 
+    /// Copies the arguments of the frame into the heap, so that closures enclosed by the frame
+    /// can refer to the upvalues after the frame goes out of the stack
+    fn close_frame(&self, frame: &StackFrame) {
+        if let ScopePtr::Heap(_) = *frame.self_link.borrow() {
+            return;
+        }
+
+        // the stack may be already cleared when the program ended
+        let top = cmp::min(frame.stack_bottom + frame.arg_size, self.arg_stack.len());
+        let bottom = cmp::min(frame.stack_bottom, top);
+        let heap = HeapClosure {
+            args: self.arg_stack[bottom .. top].to_vec(),
+            static_link: frame.closure.static_link.clone()
+        };
+        *frame.self_link.borrow_mut() = ScopePtr::Heap(heap);
+
+        // the scope outlives the frame only if closures refer to it
+        if Rc::strong_count(&frame.self_link) > 1 {
+            self.heap.borrow_mut().track_scope(&frame.self_link);
+        }
+    }
+
+    /// Reverse of `close_frame`: copies the upvalues in the heap back into the stack, and lets the
+    /// closures enclosed by the frame refer to the n-th stack frame again
+    fn reopen_frame(&mut self, n: usize) {
+        let (link, bottom, arg_size) = {
+            let frame = if n == self.call_stack.len() {
+                &self.frame
+            } else {
+                &self.call_stack[n]
+            };
+            (frame.self_link.clone(), frame.stack_bottom, frame.arg_size)
+        };
+
+        let mut scope = link.borrow_mut();
+        if let ScopePtr::Heap(ref heap) = *scope {
+            for (i, arg) in heap.args.iter().take(arg_size).enumerate() {
+                if bottom + i < self.arg_stack.len() {
+                    self.arg_stack[bottom + i] = arg.clone();
+                }
+            }
+        }
+        *scope = ScopePtr::Stack(n);
+    }
+
+    fn close_all_frames(&self) {
+        for frame in self.call_stack.iter() {
+            self.close_frame(frame);
+        }
+        self.close_frame(&self.frame);
+    }
+
+    /// Throws away the current stack and resumes the continuation
+    fn reinstate(&mut self, k: &Continuation) {
+        // Frames leaving the stack keep their variables in the heap, and frames restored from the
+        // continuation take back the latest values of their variables. Variables are shared
+        // between the continuation and the code which ran after it was captured.
+        self.close_all_frames();
+
+        self.arg_stack = k.arg_stack.clone();
+        self.call_stack = k.call_stack.clone();
+        self.frame = k.frame.clone();
+        self.handlers = k.handlers.clone();
+        self.winders = k.winders.clone();
+
+        for n in 0 .. self.call_stack.len() + 1 {
+            self.reopen_frame(n);
+        }
+    }
+
     fn pop_call_stack(&mut self) -> bool {
         match self.call_stack.pop() {
             None => false,
             Some(f) => {
-                let bottom = self.frame.stack_bottom;
-                let top = bottom + self.frame.arg_size;
-                let heap = HeapClosure {
-                    args: self.arg_stack[bottom .. top].to_vec(),
-                    static_link: self.frame.closure.static_link.clone()
-                };
-                *self.frame.self_link.borrow_mut() = ScopePtr::Heap(heap);
+                self.close_frame(&self.frame);
                 self.frame = f;
                 true
             }
@@ -589,12 +1508,14 @@ impl Runtime {
     pub fn top_is_false(&self) -> Result<bool, RuntimeError> {
         match self.peek_stack()? {
             &Datum::Bool(false) => Ok(true),
+            &Datum::Ext(RuntimeData::Values(n)) => Err(single_value_error(n)),
             _ => Ok(false)
         }
     }
 
     fn call(&mut self, n: usize) -> Result<(), RuntimeError> {
         let top = self.arg_stack.len();
+        check_single_values(&self.arg_stack[top-n ..])?;
         let datum = self.arg_stack[top - n - 1].clone();
         match datum {
             Datum::Ext(RuntimeData::PrimFunc(fptr)) => {
@@ -603,7 +1524,7 @@ impl Runtime {
                 } else {
                     self.arg_stack.split_off(top-n)
                 };
-                let res = fptr.function.call(args)?;
+                let res = self.call_primitive(&fptr, args)?;
                 self.pop_stack()?;
                 self.push_stack(res);
                 self.frame.pc += 1;
@@ -611,65 +1532,319 @@ impl Runtime {
             Datum::Ext(RuntimeData::Closure(closure)) => {
                 self.push_call_stack(n, closure);
             },
+            Datum::Ext(RuntimeData::CaseLambda(case_lambda)) => {
+                let closure = case_lambda.select(n)?;
+                self.push_call_stack(n, closure);
+            },
+            Datum::Ext(RuntimeData::Parameter(param)) => {
+                if n > 0 {
+                    return Err(parameter_args_error(n));
+                }
+                let value = param.value.borrow().clone();
+                self.pop_stack()?;
+                self.push_stack(value);
+                self.frame.pc += 1;
+            },
+            Datum::Ext(RuntimeData::Continuation(k)) => {
+                if self.common_winders(&k) == self.winders.len() &&
+                        self.winders.len() == k.winders.len() {
+                    let vals = self.arg_stack.split_off(top-n);
+                    self.resume(&k, vals);
+                } else {
+                    let wind = Closure::new(self.wind.clone(), None, None);
+                    self.arg_stack.insert(top-n-1, Datum::Ext(RuntimeData::Closure(wind.clone())));
+                    self.push_call_stack(n+1, wind);
+                }
+            },
+            Datum::Ext(RuntimeData::Escape(k)) => {
+                if self.winders.len() == k.winders {
+                    let vals = self.arg_stack.split_off(top-n);
+                    self.escape(&k, vals)?;
+                } else {
+                    let wind = Closure::new(self.wind.clone(), None, None);
+                    self.arg_stack.insert(top-n-1, Datum::Ext(RuntimeData::Closure(wind.clone())));
+                    self.push_call_stack(n+1, wind);
+                }
+            },
             _ => {
-                return Err(runtime_panic(format!("{:?} is not callable", datum)))
+                let irritants = self.arg_stack[top-n-1 ..].iter().cloned().collect();
+                return Err(self.not_callable(datum, irritants));
             }
         }
 
         Ok(())
     }
 
+    /// Pops the parameter object from the stack
+    fn pop_parameter(&mut self) -> Result<Rc<Parameter>, RuntimeError> {
+        match self.pop_stack()? {
+            Datum::Ext(RuntimeData::Parameter(param)) => Ok(param),
+            datum => Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Parameter, but received {:?}", DatumType::get_type(&datum))
+            })
+        }
+    }
+
+    /// Replaces the car or the cdr of the pair under the value on the stack. The pair is tracked by
+    /// the collector, as it may now be a part of a cycle
+    fn set_pair(&mut self, car: bool) -> Result<(), RuntimeError> {
+        let value = self.pop_stack()?;
+        let pair = match self.pop_stack()? {
+            Datum::Cons(pair) => pair,
+            datum => return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Pair, but received {:?}", DatumType::get_type(&datum))
+            })
+        };
+        if car {
+            pair.set_car(value);
+        } else {
+            pair.set_cdr(value);
+        }
+        self.heap.borrow_mut().track_pair(&pair);
+        self.push_stack(Datum::Ext(RuntimeData::Undefined));
+        self.frame.pc += 1;
+        Ok(())
+    }
+
+    /// Tracks the hashtable, the record or the record type on the stack, which a primitive has
+    /// stored a value into, and replaces it with the unspecified value
+    fn track_store(&mut self) -> Result<(), RuntimeError> {
+        match self.pop_stack()? {
+            Datum::Ext(RuntimeData::Hashtable(table)) => self.heap.borrow_mut().track_hashtable(&table),
+            Datum::Ext(RuntimeData::Record(record)) => self.heap.borrow_mut().track_record(&record),
+            Datum::Ext(RuntimeData::RecordType(rtd)) => self.heap.borrow_mut().track_record_type(&rtd),
+            datum => return Err(RuntimeError {
+                kind: RuntimeErrorKind::InvalidType,
+                desc: format!("expected Hashtable, Record or RecordType, but received {:?}",
+                              DatumType::get_type(&datum))
+            })
+        }
+        self.push_stack(Datum::Ext(RuntimeData::Undefined));
+        self.frame.pc += 1;
+        Ok(())
+    }
+
     fn tail_call(&mut self) -> Result<(), RuntimeError> {
         let n = self.frame.arg_size;
         let cur_bottom = self.frame.stack_bottom;
         let new_bottom = cur_bottom + n;
-        let mut args = if new_bottom+1 == self.arg_stack.len() {
+        let args = if new_bottom+1 == self.arg_stack.len() {
             Vec::new()
         } else {
             self.arg_stack.split_off(new_bottom+1)
         };
         let datum = self.pop_stack()?;
+        check_single_values(&args)?;
 
         match datum {
             Datum::Ext(RuntimeData::PrimFunc(ref fptr)) => {
-                let res = fptr.function.call(args)?;
+                let res = self.call_primitive(fptr, args)?;
                 self.push_stack(res);
                 self.frame.pc += 1;
             },
             Datum::Ext(RuntimeData::Closure(ref closure)) => {
-                let heap = HeapClosure {
-                    args: self.arg_stack[cur_bottom .. new_bottom].to_vec(),
-                    static_link: self.frame.closure.static_link.clone()
-                };
-                *self.frame.self_link.borrow_mut() = ScopePtr::Heap(heap);
-
-                self.arg_stack.split_off(cur_bottom-1);
-                self.frame.closure = closure.clone();
-                self.frame.pc = 0;
-                self.frame.arg_size = args.len();
-                let idx = self.call_stack.len();
-                self.frame.self_link = Rc::new(RefCell::new(ScopePtr::Stack(idx)));
-                self.arg_stack.push(datum.clone());
-                self.arg_stack.append(&mut args);
+                self.replace_frame(closure.clone(), args);
+            },
+            Datum::Ext(RuntimeData::CaseLambda(ref case_lambda)) => {
+                let closure = case_lambda.select(args.len())?;
+                self.replace_frame(closure, args);
+            },
+            Datum::Ext(RuntimeData::Parameter(ref param)) => {
+                if !args.is_empty() {
+                    return Err(parameter_args_error(args.len()));
+                }
+                let value = param.value.borrow().clone();
+                self.push_stack(value);
+                self.frame.pc += 1;
+            },
+            Datum::Ext(RuntimeData::Continuation(ref k)) => {
+                if self.common_winders(k) == self.winders.len() &&
+                        self.winders.len() == k.winders.len() {
+                    self.resume(k, args);
+                } else {
+                    let wind = Closure::new(self.wind.clone(), None, None);
+                    let mut wind_args = vec![datum.clone()];
+                    wind_args.extend(args);
+                    self.replace_frame(wind, wind_args);
+                }
+            },
+            Datum::Ext(RuntimeData::Escape(ref k)) => {
+                if self.winders.len() == k.winders {
+                    self.escape(k, args)?;
+                } else {
+                    let wind = Closure::new(self.wind.clone(), None, None);
+                    let mut wind_args = vec![datum.clone()];
+                    wind_args.extend(args);
+                    self.replace_frame(wind, wind_args);
+                }
             },
             _ => {
-                return Err(runtime_panic(format!("{:?} is not callable", datum)))
+                let irritants = Some(datum.clone()).into_iter().chain(args.into_iter()).collect();
+                return Err(self.not_callable(datum, irritants));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Calls the primitive. When it fails, the primitive and the arguments are kept for the
+    /// condition raised for the error
+    fn call_primitive(&mut self, fptr: &PrimFuncPtr, args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+        match fptr.function.call(args.clone()) {
+            Ok(res) => Ok(res),
+            Err(e) => {
+                self.failed_call = Some(FailedCall {
+                    error: e.clone(),
+                    who: Some(Datum::Sym(Cow::Borrowed(fptr.name))),
+                    irritants: args.into_iter().collect()
+                });
+                Err(e)
             }
         }
+    }
+
+    /// Error of calling the datum which is not a procedure, with the datum and the arguments as
+    /// the irritants
+    fn not_callable(&mut self, datum: RDatum, irritants: RDatum) -> RuntimeError {
+        let e = RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: format!("{:?} is not callable", datum)
+        };
+        self.failed_call = Some(FailedCall {
+            error: e.clone(),
+            who: None,
+            irritants: irritants
+        });
+        e
+    }
+
+    /// Replaces the current frame with the call to the closure
+    fn replace_frame(&mut self, closure: Closure, mut args: Vec<RDatum>) {
+        let cur_bottom = self.frame.stack_bottom;
+        self.close_frame(&self.frame);
+
+        self.arg_stack.truncate(cur_bottom-1);
+        self.frame.closure = closure.clone();
+        self.frame.pc = 0;
+        self.frame.arg_size = args.len();
+        let idx = self.call_stack.len();
+        self.frame.self_link = Rc::new(RefCell::new(ScopePtr::Stack(idx)));
+        self.arg_stack.push(Datum::Ext(RuntimeData::Closure(closure)));
+        self.arg_stack.append(&mut args);
+    }
 
+    /// Number of the dynamic extents shared by the current code and the continuation
+    fn common_winders(&self, k: &Continuation) -> usize {
+        self.winders.iter().zip(k.winders.iter()).take_while(|&(x, y)| x.eqv(y)).count()
+    }
+
+    /// Resumes the continuation, passing the values
+    fn resume(&mut self, k: &Continuation, mut vals: Vec<RDatum>) {
+        let n = vals.len();
+        self.reinstate(k);
+        self.arg_stack.append(&mut vals);
+        if n != 1 {
+            self.push_stack(Datum::Ext(RuntimeData::Values(n)));
+        }
+    }
+
+    /// Resumes the escape-only continuation, passing the values. The frames above it are left as
+    /// when they return
+    fn escape(&mut self, k: &Escape, mut vals: Vec<RDatum>) -> Result<(), RuntimeError> {
+        if self.call_stack.len() < k.call_stack || self.arg_stack.len() < k.arg_stack ||
+                self.handlers.len() < k.handlers || self.winders.len() != k.winders {
+            return Err(runtime_panic("escape-only continuation called out of its extent".to_string()));
+        }
+        while self.call_stack.len() > k.call_stack {
+            self.pop_call_stack();
+        }
+        self.arg_stack.truncate(k.arg_stack);
+        self.handlers.truncate(k.handlers);
+        self.frame.pc = k.pc;
+
+        let n = vals.len();
+        self.arg_stack.append(&mut vals);
+        if n != 1 {
+            self.push_stack(Datum::Ext(RuntimeData::Values(n)));
+        }
+        Ok(())
+    }
+
+    /// Number of the dynamic extents shared by the current code and the continuation at the first
+    /// argument of the current frame
+    fn kept_winders(&self) -> Result<usize, RuntimeError> {
+        match self.arg_stack.get(self.frame.stack_bottom) {
+            Some(&Datum::Ext(RuntimeData::Escape(ref k))) => Ok(k.winders),
+            _ => Ok(self.common_winders(&*self.continuation_arg()?))
+        }
+    }
+
+    /// Continuation at the first argument of the current frame
+    fn continuation_arg(&self) -> Result<Rc<Continuation>, RuntimeError> {
+        match self.arg_stack.get(self.frame.stack_bottom) {
+            Some(&Datum::Ext(RuntimeData::Continuation(ref k))) => Ok(k.clone()),
+            _ => Err(runtime_panic("the first argument is not a continuation".to_string()))
+        }
+    }
+
+    /// Number of stack items taken by the value on top of the stack, which may be multiple values
+    fn result_size(&self) -> usize {
+        match self.values_marker() {
+            Some(n) => n + 1,
+            None => 1
+        }
+    }
+
+    /// Number of the multiple values on top of the stack, or `None` for a single value
+    fn values_marker(&self) -> Option<usize> {
+        match self.arg_stack.last() {
+            Some(&Datum::Ext(RuntimeData::Values(n))) => Some(n),
+            _ => None
+        }
+    }
+
+    /// Pops the value on top of the stack, which must be a single value
+    fn pop_value(&mut self) -> Result<RDatum, RuntimeError> {
+        if let Some(n) = self.values_marker() {
+            return Err(single_value_error(n));
+        }
+        self.pop_stack()
+    }
+
+    /// Removes `n` stack items under the value on top of the stack
+    fn drop_under_result(&mut self, n: usize) -> Result<(), RuntimeError> {
+        let top = self.arg_stack.len();
+        let size = self.result_size();
+        if top < n + size {
+            return Err(runtime_panic("stack too low".to_string()));
+        }
+        self.arg_stack.drain(top - size - n .. top - size);
         Ok(())
     }
 
     fn return_value(&mut self) -> Result<bool, RuntimeError> {
         let n = self.frame.arg_size;
-        let top = self.arg_stack.len();
         let res = self.pop_call_stack();
-        let retval = self.pop_stack()?;
-        if top < n+2 {
-            return Err(runtime_panic("stack too low".to_string()));
+        self.drop_under_result(n+1)?;
+        if res {
+            self.frame.pc += 1;
+        }
+        return Ok(res)
+    }
+
+    fn return_values(&mut self) -> Result<bool, RuntimeError> {
+        let n = self.frame.arg_size;
+        let bottom = self.frame.stack_bottom;
+        if bottom == 0 || self.arg_stack.len() != bottom + n {
+            return Err(runtime_panic("stack mismatch on return".to_string()));
+        }
+        let res = self.pop_call_stack();
+        self.arg_stack.remove(bottom - 1);
+        if n != 1 {
+            self.push_stack(Datum::Ext(RuntimeData::Values(n)));
         }
-        self.arg_stack.truncate(top - n - 2);
-        self.push_stack(retval);
         if res {
             self.frame.pc += 1;
         }
@@ -711,10 +1886,13 @@ impl Runtime {
                 if n_args == 0 {
                     return Err(runtime_panic("Call args empty".to_string()));
                 }
-                self.call(n_args - 1)?;
+                // the spliced arguments are passed to the callee, not kept by this frame
                 self.frame.arg_size = 0;
+                self.call(n_args - 1)?;
             },
             Inst::PushFrame(n) => {
+                let top = self.arg_stack.len();
+                check_single_values(&self.arg_stack[top-n ..])?;
                 let new_closure = Closure {
                     code: self.frame.closure.code.clone(),
                     static_link: Some(self.frame.self_link.clone()),
@@ -740,16 +1918,34 @@ impl Runtime {
             Inst::PopFrame => {
                 let pc = self.frame.pc;
                 let n = self.frame.arg_size;
-                let top = self.arg_stack.len();
                 self.pop_call_stack();
-                let retval = self.pop_stack()?;
-                if top < n+1 {
-                    return Err(runtime_panic("stack too low".to_string()));
-                }
-                self.arg_stack.truncate(top - n - 1);
-                self.push_stack(retval);
+                self.drop_under_result(n)?;
                 self.frame.pc = pc+1;
             },
+            Inst::PushContinuation(pc) => {
+                let mut frame = self.frame.clone();
+                frame.pc = pc;
+                let k = Continuation {
+                    arg_stack: self.arg_stack.clone(),
+                    call_stack: self.call_stack.clone(),
+                    frame: frame,
+                    handlers: self.handlers.clone(),
+                    winders: self.winders.clone()
+                };
+                self.push_stack(Datum::Ext(RuntimeData::Continuation(Rc::new(k))));
+                self.frame.pc += 1;
+            },
+            Inst::PushEscape(pc) => {
+                let k = Escape {
+                    arg_stack: self.arg_stack.len(),
+                    call_stack: self.call_stack.len(),
+                    pc: pc,
+                    handlers: self.handlers.len(),
+                    winders: self.winders.len()
+                };
+                self.push_stack(Datum::Ext(RuntimeData::Escape(Rc::new(k))));
+                self.frame.pc += 1;
+            },
             Inst::Jump(pc) => {
                 self.frame.pc = pc;
             },
@@ -771,27 +1967,113 @@ impl Runtime {
                 } else {
                     self.frame.pc += 1;
                 },
+            Inst::Throw(kind, msg) => {
+                return Err(RuntimeError { kind: kind, desc: msg.to_string() });
+            },
+            Inst::CheckSingleValue => {
+                if let Some(n) = self.values_marker() {
+                    return Err(single_value_error(n));
+                }
+                self.frame.pc += 1;
+            },
+            Inst::PushWinder => {
+                let after = self.pop_stack()?;
+                let before = self.pop_stack()?;
+                self.winders.push(Rc::new(Winder { before: before, after: after }));
+                self.frame.pc += 1;
+            },
+            Inst::PopWinder => {
+                if self.winders.pop().is_none() {
+                    return Err(runtime_panic("winder stack empty!".to_string()));
+                }
+                self.frame.pc += 1;
+            },
+            Inst::Unwind(pc) => {
+                if self.kept_winders()? < self.winders.len() {
+                    let winder = self.winders.pop().unwrap();
+                    self.push_stack(winder.after.clone());
+                    self.frame.pc += 1;
+                } else {
+                    self.frame.pc = pc;
+                }
+            },
+            Inst::Rewind(pc) => {
+                let before = match self.arg_stack.get(self.frame.stack_bottom) {
+                    Some(&Datum::Ext(RuntimeData::Escape(_))) => None,
+                    _ => self.continuation_arg()?.winders.get(self.winders.len()).map(|w| w.before.clone())
+                };
+                match before {
+                    Some(before) => {
+                        self.push_stack(before);
+                        self.frame.pc += 1;
+                    },
+                    None => self.frame.pc = pc
+                }
+            },
+            Inst::Enter => {
+                let k = self.continuation_arg()?;
+                let n = self.winders.len();
+                self.winders.push(k.winders[n].clone());
+                self.frame.pc += 1;
+            },
+            Inst::Resume => {
+                let vals = self.arg_stack.split_off(self.frame.stack_bottom + 1);
+                match self.arg_stack[self.frame.stack_bottom].clone() {
+                    Datum::Ext(RuntimeData::Escape(k)) => self.escape(&k, vals)?,
+                    _ => {
+                        let k = self.continuation_arg()?;
+                        self.resume(&k, vals);
+                    }
+                }
+            },
+            Inst::InstallHandler => {
+                let handler = self.pop_stack()?;
+                self.handlers.push(handler);
+                self.frame.pc += 1;
+            },
+            Inst::UninstallHandler => {
+                if self.handlers.pop().is_none() {
+                    return Err(runtime_panic("exception handler stack empty!".to_string()));
+                }
+                self.frame.pc += 1;
+            },
+            Inst::TakeHandler => {
+                match self.handlers.pop() {
+                    Some(handler) => self.push_stack(handler),
+                    None => return Err(match self.pop_stack()? {
+                        Datum::Ext(RuntimeData::Condition(c)) => c.to_runtime_error(),
+                        obj => RuntimeError {
+                            kind: RuntimeErrorKind::Uncaught,
+                            desc: format!("{}", obj)
+                        }
+                    })
+                }
+                self.frame.pc += 1;
+            },
             Inst::PushArg(ptr) => {
                 let val = self.fetch_mem(ptr)?;
                 self.arg_stack.push(val);
                 self.frame.pc += 1;
             },
             Inst::PopArg(ptr) => {
-                let val = self.pop_stack()?;
+                let val = self.pop_value()?;
                 self.write_mem(ptr, val)?;
                 self.frame.pc += 1;
             },
             Inst::PopGlobal(sym) => {
-                let val = self.pop_stack()?;
-                self.global.insert(sym, Rc::new(RefCell::new(val)));
+                let val = self.pop_value()?;
+                self.bind_global(sym, Rc::new(RefCell::new(val)), false);
                 self.frame.pc += 1;
             },
             Inst::DropArg(n) => {
-                if n > self.arg_stack.len() {
-                    return Err(runtime_panic("arg_stack too low".to_string()));
+                for _ in 0..n {
+                    let size = self.result_size();
+                    if size > self.arg_stack.len() {
+                        return Err(runtime_panic("arg_stack too low".to_string()));
+                    }
+                    let new_size = self.arg_stack.len() - size;
+                    self.arg_stack.truncate(new_size);
                 }
-                let new_size = self.arg_stack.len() - n;
-                self.arg_stack.truncate(new_size);
                 self.frame.pc += 1;
             },
             Inst::SwapArg => {
@@ -835,27 +2117,223 @@ impl Runtime {
                 self.arg_stack.push(Datum::Bool(b));
                 self.frame.pc += 1;
             },
+            Inst::MatchSyntax(pattern) => {
+                let syntax = self.pop_stack()?;
+                match pattern.bind(&syntax, &free_identifier_eq) {
+                    Some(values) => {
+                        self.arg_stack.extend(values);
+                        self.arg_stack.push(Datum::Bool(true));
+                    },
+                    None => self.arg_stack.push(Datum::Bool(false))
+                }
+                self.frame.pc += 1;
+            },
+            Inst::BuildSyntax(template) => {
+                let n = template.vars().len();
+                if self.arg_stack.len() < self.frame.stack_bottom + n {
+                    return Err(runtime_panic("arg_stack too low!".to_string()));
+                }
+                let start = self.arg_stack.len() - n;
+                let values = self.arg_stack.split_off(start);
+                let syntax = template.instantiate(values)
+                    .map_err(|e| RuntimeError::from(CompileError::from(e)))?;
+                self.arg_stack.push(syntax);
+                self.frame.pc += 1;
+            },
+            Inst::Environment => {
+                let specs = self.pop_stack()?;
+                let env = self.environment(specs)?;
+                self.push_stack(Datum::Ext(RuntimeData::Environment(Rc::new(env))));
+                self.frame.pc += 1;
+            },
+            Inst::Eval => {
+                let env = match self.pop_stack()? {
+                    Datum::Ext(RuntimeData::Environment(env)) => env,
+                    datum => return Err(RuntimeError {
+                        kind: RuntimeErrorKind::InvalidType,
+                        desc: format!("expected Environment, but received {:?}", DatumType::get_type(&datum))
+                    })
+                };
+                let expr = self.pop_stack()?;
+                let code = self.compiler.compile_eval(&env, &expr)?;
+                let closure = Closure::new(Rc::new(code), None, None);
+                self.push_stack(Datum::Ext(RuntimeData::Closure(closure.clone())));
+                self.push_call_stack(0, closure);
+            },
+            Inst::MakePromise(done) => {
+                let datum = self.pop_value()?;
+                let promise = if done { Promise::done(datum) } else { Promise::delayed(datum) };
+                self.push_stack(Datum::Ext(RuntimeData::Promise(Rc::new(promise))));
+                self.frame.pc += 1;
+            },
+            Inst::PromiseValue(pc) => {
+                let datum = self.pop_stack()?;
+                let value = match datum {
+                    Datum::Ext(RuntimeData::Promise(ref promise)) => Promise::value(promise),
+                    _ => Ok(datum.clone())
+                };
+                match value {
+                    Ok(value) => {
+                        self.push_stack(value);
+                        self.frame.pc = pc;
+                    },
+                    Err(thunk) => {
+                        self.push_stack(thunk);
+                        self.frame.pc += 1;
+                    }
+                }
+            },
+            Inst::UpdatePromise => {
+                let promise = self.pop_stack()?;
+                let next = self.pop_value()?;
+                match (promise, next) {
+                    (Datum::Ext(RuntimeData::Promise(promise)), Datum::Ext(RuntimeData::Promise(next))) => {
+                        let promise = Promise::update(&promise, &next);
+                        self.heap.borrow_mut().track_promise(&promise);
+                    },
+                    (_, datum) => return Err(RuntimeError {
+                        kind: RuntimeErrorKind::InvalidType,
+                        desc: format!("expected Promise, but received {:?}", DatumType::get_type(&datum))
+                    })
+                }
+                self.frame.pc += 1;
+            },
+            Inst::MakeParameter(converted) => {
+                let converter = if converted { Some(self.pop_stack()?) } else { None };
+                let value = Rc::new(RefCell::new(self.pop_stack()?));
+                self.heap.borrow_mut().track_global(&value);
+                let param = Parameter::new(value, converter);
+                self.push_stack(Datum::Ext(RuntimeData::Parameter(Rc::new(param))));
+                self.frame.pc += 1;
+            },
+            Inst::ParameterConverter(pc) => {
+                let param = self.pop_parameter()?;
+                match param.converter {
+                    Some(ref converter) => {
+                        self.push_stack(converter.clone());
+                        self.frame.pc += 1;
+                    },
+                    None => self.frame.pc = pc
+                }
+            },
+            Inst::SwapParameter => {
+                let value = self.pop_stack()?;
+                let param = self.pop_parameter()?;
+                let old = mem::replace(&mut *param.value.borrow_mut(), value);
+                self.push_stack(old);
+                self.frame.pc += 1;
+            },
             Inst::Uncons => {
                 let arg = self.pop_stack()?;
                 if let Datum::Cons(pair) = arg {
-                    self.arg_stack.push(pair.0.clone());
-                    self.arg_stack.push(pair.1.clone());
+                    self.arg_stack.push(pair.car());
+                    self.arg_stack.push(pair.cdr());
                     self.frame.pc += 1;
                 } else {
                     return Err(runtime_panic("top of the stack is not a pair".to_string()));
                 }
             },
+            Inst::SetCar => self.set_pair(true)?,
+            Inst::SetCdr => self.set_pair(false)?,
+            Inst::TrackStore => self.track_store()?,
+            Inst::SpreadValues => {
+                if self.values_marker().is_some() {
+                    self.arg_stack.pop();
+                }
+                self.frame.pc += 1;
+            },
+            Inst::ReceiveValues(n, rest) => {
+                let count = match self.values_marker() {
+                    Some(k) => {
+                        self.arg_stack.pop();
+                        k
+                    },
+                    None => 1
+                };
+                if count < n || (!rest && count != n) {
+                    return Err(RuntimeError {
+                        kind: RuntimeErrorKind::NumArgs,
+                        desc: format!("Expected {}{} values, received {}",
+                                      n, if rest { " or more" } else { "" }, count)
+                    });
+                }
+                if rest {
+                    let start = self.arg_stack.len() - (count - n);
+                    let list: RDatum = self.arg_stack.drain(start ..).collect();
+                    self.push_stack(list);
+                }
+                self.frame.pc += 1;
+            },
+            Inst::ReturnValues => return self.return_values(),
             Inst::Return => return self.return_value()
         }
 
         Ok(true)
     }
 
+    /// Raises the error as an exception to the current handler. If there is no handler, the error
+    /// is returned as is
+    fn raise_error(&mut self, err: RuntimeError) -> Result<(), RuntimeError> {
+        let failed_call = self.failed_call.take();
+        if self.handlers.is_empty() {
+            return Err(err);
+        }
+
+        let condition = match failed_call {
+            Some(call) if call.error == err => Condition::from(err).with_call(call.who, call.irritants),
+            _ => Condition::from(err)
+        };
+        let raise = Closure::new(self.raise.clone(), None, None);
+        self.push_stack(Datum::Ext(RuntimeData::Closure(raise)));
+        self.push_stack(Datum::Ext(RuntimeData::Condition(Rc::new(condition))));
+        self.call(1)
+    }
+
+    /// Pops the result of the program. Unless exactly one value is returned, the values are
+    /// wrapped in `RuntimeData::MultipleValues`
+    fn pop_result(&mut self) -> Result<RDatum, RuntimeError> {
+        match self.pop_stack()? {
+            Datum::Ext(RuntimeData::Values(n)) => {
+                let start = self.arg_stack.len() - n;
+                let values = self.arg_stack.drain(start ..).collect();
+                Ok(Datum::Ext(RuntimeData::MultipleValues(values)))
+            },
+            res => Ok(res)
+        }
+    }
+
+    /// Runs the after thunks of the active `dynamic-wind`s, when an error escapes the program
+    fn unwind_all(&mut self) -> Result<(), RuntimeError> {
+        while let Some(winder) = self.winders.pop() {
+            let code = vec![Inst::PushArg(MemRef::Arg(0)), Inst::Call(0), Inst::Return];
+            self.load_closure(Closure::new(Rc::new(code), None, None), vec![winder.after.clone()]);
+            self.run()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the garbage collector, freeing the objects kept alive only by reference cycles
+    pub fn gc(&mut self) -> GcStats {
+        self.heap.borrow_mut().collect()
+    }
+
+    /// Statistics of the garbage collector
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.borrow().stats()
+    }
+
     pub fn run(&mut self) -> Result<RDatum, RuntimeError> {
         loop {
-            let cont = self.step()?;
-            if !cont {
-                return self.pop_stack();
+            if self.heap.borrow().should_collect() {
+                self.gc();
+            }
+            match self.step() {
+                Ok(true) => (),
+                Ok(false) => return self.pop_result(),
+                Err(e) => if let Err(e) = self.raise_error(e) {
+                    self.unwind_all()?;
+                    return Err(e);
+                }
             }
         }
     }
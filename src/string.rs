@@ -0,0 +1,265 @@
+use std::cell::RefCell;
+use std::iter::{repeat, FromIterator};
+use std::rc::Rc;
+
+use cast::DatumCast;
+use datum::Datum;
+use error::{RuntimeError, RuntimeErrorKind};
+use primitive::{F1, F2, F3, Fold, FoldErr, FoldR2, PrimFunc, R1};
+use runtime::{DatumType, RDatum, RuntimeData};
+use unicode::fold_case;
+
+/// Characters of the string, shared by every reference to the string
+type Chars = Rc<RefCell<Vec<char>>>;
+
+fn new_string(chars: Vec<char>) -> Chars {
+    Rc::new(RefCell::new(chars))
+}
+
+fn index_error(len: usize, k: usize) -> RuntimeError {
+    RuntimeError {
+        kind: RuntimeErrorKind::IndexOutOfRange,
+        desc: format!("string length is {}, but index is {}", len, k)
+    }
+}
+
+/// Collects the characters in the list
+fn list_chars(list: &RDatum) -> Result<Vec<char>, RuntimeError> {
+    let mut chars = Vec::new();
+    for item in list.iter() {
+        let item = item.map_err(|_| RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: format!("Expected list, but received {:?}", DatumType::get_type(list))
+        })?;
+        chars.push(DatumCast::unwrap(item)?);
+    }
+    Ok(chars)
+}
+
+fn make_string(k: usize, fill: Option<char>) -> Chars {
+    new_string(repeat(fill.unwrap_or(' ')).take(k).collect())
+}
+
+/// `(make-string k)` or `(make-string k char)`
+pub static PRIM_MAKE_STRING: F2<usize, Option<char>, Chars> = F2 { f2: make_string };
+
+fn string(args: Vec<RDatum>) -> Result<RDatum, RuntimeError> {
+    let chars: Vec<char> = args.into_iter().map(DatumCast::unwrap).collect::<Result<_, _>>()?;
+    Ok(Datum::String(new_string(chars)))
+}
+
+/// `(string char ...)`
+pub static PRIM_STRING: FoldErr<RDatum> = FoldErr { fold: string };
+
+fn string_length(s: &Chars) -> usize {
+    s.borrow().len()
+}
+
+/// `(string-length string)`
+pub static PRIM_STRING_LENGTH: R1<Chars, usize> = R1 { r1: string_length };
+
+fn string_ref(s: Chars, k: usize) -> Result<char, RuntimeError> {
+    let chars = s.borrow();
+    chars.get(k).cloned().ok_or_else(|| index_error(chars.len(), k))
+}
+
+/// `(string-ref string k)`
+pub static PRIM_STRING_REF: F2<Chars, usize, Result<char, RuntimeError>> = F2 { f2: string_ref };
+
+fn string_set(s: Chars, k: usize, c: char) -> Result<RDatum, RuntimeError> {
+    let mut chars = s.borrow_mut();
+    let len = chars.len();
+    match chars.get_mut(k) {
+        Some(place) => *place = c,
+        None => return Err(index_error(len, k))
+    }
+    Ok(Datum::Ext(RuntimeData::Undefined))
+}
+
+/// `(string-set! string k char)`
+pub static PRIM_STRING_SET: F3<Chars, usize, char, Result<RDatum, RuntimeError>> = F3 { f3: string_set };
+
+fn string_fill(s: Chars, c: char) -> RDatum {
+    for place in s.borrow_mut().iter_mut() {
+        *place = c;
+    }
+    Datum::Ext(RuntimeData::Undefined)
+}
+
+/// `(string-fill! string char)`
+pub static PRIM_STRING_FILL: F2<Chars, char, RDatum> = F2 { f2: string_fill };
+
+fn substring(s: Chars, start: usize, end: usize) -> Result<Chars, RuntimeError> {
+    let chars = s.borrow();
+    if end > chars.len() {
+        return Err(index_error(chars.len(), end));
+    }
+    if start > end {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::IndexOutOfRange,
+            desc: format!("substring start {} is after the end {}", start, end)
+        });
+    }
+    Ok(new_string(chars[start..end].to_vec()))
+}
+
+/// `(substring string start end)`
+pub static PRIM_SUBSTRING: F3<Chars, usize, usize, Result<Chars, RuntimeError>> = F3 { f3: substring };
+
+fn string_append(args: Vec<Chars>) -> Chars {
+    let mut chars = Vec::new();
+    for s in args.iter() {
+        chars.extend(s.borrow().iter().cloned());
+    }
+    new_string(chars)
+}
+
+/// `(string-append string ...)`
+pub static PRIM_STRING_APPEND: Fold<Chars> = Fold { fold: string_append };
+
+fn string_to_list(s: Chars) -> RDatum {
+    let chars = s.borrow();
+    Datum::from_iter(chars.iter().map(|&c| Datum::Char(c)))
+}
+
+/// `(string->list string)`
+pub static PRIM_STRING_TO_LIST: F1<Chars, RDatum> = F1 { f1: string_to_list };
+
+fn list_to_string(list: RDatum) -> Result<Chars, RuntimeError> {
+    list_chars(&list).map(new_string)
+}
+
+/// `(list->string list)`
+pub static PRIM_LIST_TO_STRING: F1<RDatum, Result<Chars, RuntimeError>> = F1 { f1: list_to_string };
+
+fn string_copy(s: Chars) -> Chars {
+    new_string(s.borrow().clone())
+}
+
+/// `(string-copy string)`
+pub static PRIM_STRING_COPY: F1<Chars, Chars> = F1 { f1: string_copy };
+
+fn same_case(s: &[char]) -> Vec<char> {
+    s.to_vec()
+}
+
+macro_rules! impl_string_comp {
+    ($static_name:ident, $func_name:ident, $key:ident, $op:ident) => (
+        fn $func_name(arg0: &Chars, arg1: &Chars, args: &[Chars]) -> bool {
+            let keys: Vec<Vec<char>> = [arg0, arg1].iter().cloned().chain(args.iter())
+                .map(|s| $key(&s.borrow())).collect();
+            keys.windows(2).all(|pair| pair[0].$op(&pair[1]))
+        }
+
+        pub static $static_name: FoldR2<Chars, bool> = FoldR2 { fold_r2: $func_name };
+    )
+}
+
+impl_string_comp!(PRIM_STRING_EQ, string_eq, same_case, eq);
+impl_string_comp!(PRIM_STRING_LT, string_lt, same_case, lt);
+impl_string_comp!(PRIM_STRING_GT, string_gt, same_case, gt);
+impl_string_comp!(PRIM_STRING_LE, string_le, same_case, le);
+impl_string_comp!(PRIM_STRING_GE, string_ge, same_case, ge);
+impl_string_comp!(PRIM_STRING_CI_EQ, string_ci_eq, fold_case, eq);
+impl_string_comp!(PRIM_STRING_CI_LT, string_ci_lt, fold_case, lt);
+impl_string_comp!(PRIM_STRING_CI_GT, string_ci_gt, fold_case, gt);
+impl_string_comp!(PRIM_STRING_CI_LE, string_ci_le, fold_case, le);
+impl_string_comp!(PRIM_STRING_CI_GE, string_ci_ge, fold_case, ge);
+
+/// Transposes the list of strings into the list of the characters at each index, which
+/// `string-for-each` passes to the procedure
+fn string_columns(strings: RDatum) -> Result<RDatum, RuntimeError> {
+    let strings: Vec<Chars> = strings.iter()
+        .map(|s| s.map_err(|_| RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: "Non-list given to string-for-each".to_string()
+        }).and_then(DatumCast::unwrap))
+        .collect::<Result<_, _>>()?;
+
+    let len = match strings.first() {
+        Some(s) => s.borrow().len(),
+        None => return Err(RuntimeError {
+            kind: RuntimeErrorKind::NumArgs,
+            desc: "Expected at least 2 arguments, received 1".to_string()
+        })
+    };
+    if strings.iter().any(|s| s.borrow().len() != len) {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::InvalidType,
+            desc: "Strings of different lengths given to string-for-each".to_string()
+        });
+    }
+
+    Ok((0..len).map(|k| strings.iter().map(|s| Datum::Char(s.borrow()[k])).collect()).collect())
+}
+
+/// Rows of the characters passed by `string-for-each`
+pub static PRIM_STRING_COLUMNS: F1<RDatum, Result<RDatum, RuntimeError>> = F1 { f1: string_columns };
+
+/// Lists all string procedures with its name
+pub fn libstring() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("make-string", &PRIM_MAKE_STRING),
+        ("string", &PRIM_STRING),
+        ("string-length", &PRIM_STRING_LENGTH),
+        ("string-ref", &PRIM_STRING_REF),
+        ("string-set!", &PRIM_STRING_SET),
+        ("string-fill!", &PRIM_STRING_FILL),
+        ("substring", &PRIM_SUBSTRING),
+        ("string-append", &PRIM_STRING_APPEND),
+        ("string->list", &PRIM_STRING_TO_LIST),
+        ("list->string", &PRIM_LIST_TO_STRING),
+        ("string-copy", &PRIM_STRING_COPY),
+        ("string=?", &PRIM_STRING_EQ),
+        ("string<?", &PRIM_STRING_LT),
+        ("string>?", &PRIM_STRING_GT),
+        ("string<=?", &PRIM_STRING_LE),
+        ("string>=?", &PRIM_STRING_GE),
+        ("string-ci=?", &PRIM_STRING_CI_EQ),
+        ("string-ci<?", &PRIM_STRING_CI_LT),
+        ("string-ci>?", &PRIM_STRING_CI_GT),
+        ("string-ci<=?", &PRIM_STRING_CI_LE),
+        ("string-ci>=?", &PRIM_STRING_CI_GE)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fold_case, string_columns, string_lt, substring, new_string};
+    use datum::Datum;
+    use runtime::RDatum;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn test_substring() {
+        let s = new_string(chars("hello"));
+        assert_eq!(*substring(s.clone(), 1, 3).unwrap().borrow(), chars("el"));
+        assert_eq!(*substring(s.clone(), 5, 5).unwrap().borrow(), chars(""));
+        assert!(substring(s.clone(), 3, 2).is_err());
+        assert!(substring(s, 0, 6).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = new_string(chars("abc"));
+        let b = new_string(chars("abd"));
+        let c = new_string(chars("abde"));
+        assert!(string_lt(&a, &b, &[c.clone()]));
+        assert!(!string_lt(&b, &a, &[]));
+        assert_eq!(fold_case(&chars("StraSSe")), chars("strasse"));
+    }
+
+    #[test]
+    fn test_columns() {
+        let strings: RDatum = vec![Datum::String(new_string(chars("ab"))),
+                                   Datum::String(new_string(chars("xy")))].into_iter().collect();
+        let columns: RDatum = vec![
+            vec![Datum::Char('a'), Datum::Char('x')].into_iter().collect(),
+            vec![Datum::Char('b'), Datum::Char('y')].into_iter().collect()
+        ].into_iter().collect();
+        assert_eq!(string_columns(strings).unwrap(), columns);
+    }
+}
@@ -1,12 +1,109 @@
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::rc::Rc;
 
+use compiler;
 use datum::{cons, Datum, SimpleDatum};
 use error::{MacroError, MacroErrorKind};
+use primitive::{F1, F2, PrimFunc};
+use runtime::RDatum;
 
 pub type Vars = HashSet<Cow<'static, str>>;
 
+/// Separates the identifier renamed by a macro expansion from the mark of the expansion
+const ALIAS_MARK: char = '\u{0}';
+
+/// Renames the identifier introduced by the template of the expansion `mark`
+pub fn alias(sym: &str, mark: usize) -> Cow<'static, str> {
+    Cow::Owned(format!("{}{}{}", sym, ALIAS_MARK, mark))
+}
+
+/// Splits the alias into the identifier it renames and the mark of the expansion. Returns `None`
+/// if the identifier is not renamed
+pub fn unalias(sym: &str) -> Option<(Cow<'static, str>, usize)> {
+    let pos = match sym.rfind(ALIAS_MARK) {
+        Some(pos) => pos,
+        None => return None
+    };
+    sym[pos + ALIAS_MARK.len_utf8() ..].parse().ok().map(|mark| (Cow::Owned(sym[..pos].to_string()), mark))
+}
+
+/// The identifier written in the source, stripping the renames of all the expansions
+pub fn base_name(sym: &str) -> &str {
+    match sym.find(ALIAS_MARK) {
+        Some(pos) => &sym[..pos],
+        None => sym
+    }
+}
+
+/// Compares the identifier of the form with the literal of the pattern by the names written in the
+/// source, as the unhygienic `transform` does
+pub fn same_name(sym: &str, literal: &str) -> bool {
+    base_name(sym) == base_name(literal)
+}
+
+/// Strips the renames from the identifiers in the datum, such as the quoted data in a template
+pub fn strip_aliases<T: Clone>(datum: &Datum<T>) -> Datum<T> {
+    match datum {
+        &Datum::Sym(ref sym) => match sym.find(ALIAS_MARK) {
+            Some(pos) => Datum::Sym(Cow::Owned(sym[..pos].to_string())),
+            None => datum.clone()
+        },
+        &Datum::Cons(ref pair) => cons(strip_aliases(&pair.car()), strip_aliases(&pair.cdr())),
+        &Datum::Vector(ref vec) => Datum::Vector(Rc::new(vec.iter().map(strip_aliases).collect())),
+        _ => datum.clone()
+    }
+}
+
+/// Toggles the mark of a transformer call on the identifiers of the syntax: the mark is removed
+/// from the identifiers whose last mark it is, and added to the others. Toggling both the input
+/// and the output of the transformer leaves the mark only on the identifiers it introduced
+pub fn toggle_mark<T: Clone>(datum: &Datum<T>, mark: usize) -> Datum<T> {
+    map_identifiers(datum, &|sym| toggle_alias(sym, mark))
+}
+
+/// Toggles the mark of a transformer call on the identifier
+pub fn toggle_alias(sym: &str, mark: usize) -> Cow<'static, str> {
+    match unalias(sym) {
+        Some((renamed, m)) if m == mark => renamed,
+        _ => alias(sym, mark)
+    }
+}
+
+/// Identifiers appearing in the datum
+pub fn identifiers<T: Clone>(datum: &Datum<T>) -> Vars {
+    fn collect<T: Clone>(datum: &Datum<T>, ids: &mut Vars) {
+        match datum {
+            &Datum::Sym(ref sym) => { ids.insert(sym.clone()); },
+            &Datum::Cons(ref pair) => {
+                collect(&pair.car(), ids);
+                collect(&pair.cdr(), ids);
+            },
+            &Datum::Vector(ref vec) => for e in vec.iter() {
+                collect(e, ids);
+            },
+            _ => ()
+        }
+    }
+
+    let mut ids = HashSet::new();
+    collect(datum, &mut ids);
+    ids
+}
+
+fn map_identifiers<T, F>(datum: &Datum<T>, f: &F) -> Datum<T>
+    where T: Clone, F: Fn(&Cow<'static, str>) -> Cow<'static, str>
+{
+    match datum {
+        &Datum::Sym(ref sym) => Datum::Sym(f(sym)),
+        &Datum::Cons(ref pair) => cons(map_identifiers(&pair.car(), f), map_identifiers(&pair.cdr(), f)),
+        &Datum::Vector(ref vec) =>
+            Datum::Vector(Rc::new(vec.iter().map(|e| map_identifiers(e, f)).collect())),
+        _ => datum.clone()
+    }
+}
+
 macro_rules! hashset {
     ($($e:expr),*) => ({
         let mut s = HashSet::new();
@@ -40,12 +137,31 @@ impl CompiledMacro {
         res.map(|pat| CompiledMacro { patterns: pat })
     }
 
+    /// Transforms the form, leaving the identifiers introduced by the template as they are
     pub fn transform<T>(&self, datum: &Datum<T>) -> Result<Datum<T>, MacroError>
         where T: Clone
+    {
+        self.transform_marked(datum, None, &same_name)
+    }
+
+    /// Transforms the form hygienically: the identifiers introduced by the template are renamed
+    /// to the aliases of `mark`, so that they neither capture nor are captured by the identifiers
+    /// of the form. `literal_eq` tells whether an identifier of the form matches a literal, which
+    /// must refer to the same binding as `free-identifier=?` does
+    pub fn expand<T>(&self, datum: &Datum<T>, mark: usize, literal_eq: &Fn(&str, &str) -> bool)
+            -> Result<Datum<T>, MacroError>
+        where T: Clone
+    {
+        self.transform_marked(datum, Some(mark), literal_eq)
+    }
+
+    fn transform_marked<T>(&self, datum: &Datum<T>, mark: Option<usize>, literal_eq: &Fn(&str, &str) -> bool)
+            -> Result<Datum<T>, MacroError>
+        where T: Clone
     {
         for &MacroPattern { ref pattern, ref template } in self.patterns.iter() {
-            if let Some(m) = pattern.compute_match(datum) {
-                return Ok(template.transform(&m));
+            if let Some(m) = pattern.compute_match(datum, literal_eq) {
+                return Ok(template.transform(&m, mark));
             }
         }
 
@@ -125,7 +241,7 @@ impl CompiledPattern {
 
         let patts: Vec<&[Datum<T>]> = form.split(|s|
             if let &Datum::Sym(ref sym) = s {
-                sym == "..."
+                base_name(sym) == "..."
             } else {
                 false
             }
@@ -170,10 +286,10 @@ impl CompiledPattern {
         }
     }
 
-    fn compute_match<T>(&self, datum: &Datum<T>) -> Option<PatternMatch<T>>
+    fn compute_match<T>(&self, datum: &Datum<T>, literal_eq: &Fn(&str, &str) -> bool) -> Option<PatternMatch<T>>
         where T: Clone
     {
-        if let &Datum::Cons(_) = datum {
+        if let &Datum::Cons(_) | &Datum::Nil = datum {
             let list: Vec<Datum<T>> = match datum.iter().collect() {
                 Ok(l) => l,
                 Err(_) => return None
@@ -185,7 +301,7 @@ impl CompiledPattern {
                     }
                     let mut matches = Vec::new();
                     for (sp, elem) in pat.iter().zip(list) {
-                        let mut m = match sp.compute_match(&elem) {
+                        let mut m = match sp.compute_match(&elem, literal_eq) {
                             Some(m) => m,
                             None => return None
                         };
@@ -200,7 +316,7 @@ impl CompiledPattern {
                     let mut matches = Vec::new();
 
                     for (sp, elem) in prefix.iter().zip(list.iter()) {
-                        let mut m = match sp.compute_match(&elem) {
+                        let mut m = match sp.compute_match(&elem, literal_eq) {
                             Some(m) => m,
                             None => return None
                         };
@@ -212,7 +328,7 @@ impl CompiledPattern {
 
                     let mut repeats = Vec::new();
                     for elem in list[rep_start .. rep_end].iter() {
-                        let m = match repeat.compute_match(elem) {
+                        let m = match repeat.compute_match(elem, literal_eq) {
                             Some(m) => PatternMatch::new(m),
                             None => return None
                         };
@@ -222,7 +338,7 @@ impl CompiledPattern {
                     matches.push(rep);
 
                     for (sp, elem) in suffix.iter().zip(list[rep_end ..].iter()) {
-                        let mut m = match sp.compute_match(&elem) {
+                        let mut m = match sp.compute_match(&elem, literal_eq) {
                             Some(m) => m,
                             None => return None
                         };
@@ -236,6 +352,24 @@ impl CompiledPattern {
             None
         }
     }
+
+    /// Collects the pattern variables with the number of the ellipses following them
+    fn var_depths(&self, depth: usize, out: &mut Vec<(Cow<'static, str>, usize)>) {
+        match self.pattern {
+            Pattern::List(ref list) => for sp in list.iter() {
+                sp.var_depths(depth, out);
+            },
+            Pattern::DelimitedList(ref prefix, ref repeat, ref suffix) => {
+                for sp in prefix.iter() {
+                    sp.var_depths(depth, out);
+                }
+                repeat.var_depths(depth + 1, out);
+                for sp in suffix.iter() {
+                    sp.var_depths(depth, out);
+                }
+            }
+        }
+    }
 }
 
 fn union_vars(lhs: &mut Vars, rhs: Vars)
@@ -310,7 +444,7 @@ impl SubPattern {
                 CompiledPattern::compile(literals, datum)
                         .map(|pat| (pat.vars.clone(), SubPattern::Pattern(pat))),
             &Datum::Sym(ref sym) =>
-                if sym == "_" {
+                if base_name(sym) == "_" {
                     Ok((HashSet::new(), SubPattern::Underscore))
                 } else if literals.contains(sym) {
                     Ok((HashSet::new(), SubPattern::Const(SimpleDatum::Sym(sym.clone()))))
@@ -329,18 +463,22 @@ impl SubPattern {
         }
     }
 
-    fn compute_match<T>(&self, datum: &Datum<T>) -> Option<Vec<MatchData<T>>>
+    fn compute_match<T>(&self, datum: &Datum<T>, literal_eq: &Fn(&str, &str) -> bool) -> Option<Vec<MatchData<T>>>
         where T: Clone
     {
         match self {
             &SubPattern::Underscore => Some(Vec::new()),
             &SubPattern::Var(ref sym) => Some(vec![MatchData::Var(sym.clone(), datum.clone())]),
+            &SubPattern::Const(SimpleDatum::Sym(ref lit)) => match datum {
+                &Datum::Sym(ref sym) if literal_eq(sym, lit) => Some(Vec::new()),
+                _ => None
+            },
             &SubPattern::Const(ref c) => if c.equals(datum) {
                     Some(Vec::new())
                 } else {
                     None
                 },
-            &SubPattern::Pattern(ref pat) => pat.compute_match(datum).map(|m| m.matches)
+            &SubPattern::Pattern(ref pat) => pat.compute_match(datum, literal_eq).map(|m| m.matches)
         }
     }
 
@@ -352,6 +490,14 @@ impl SubPattern {
         }
     }
 
+    fn var_depths(&self, depth: usize, out: &mut Vec<(Cow<'static, str>, usize)>) {
+        match self {
+            &SubPattern::Var(ref sym) => out.push((sym.clone(), depth)),
+            &SubPattern::Pattern(ref pat) => pat.var_depths(depth, out),
+            _ => ()
+        }
+    }
+
     fn contains_var(&self, var: &str) -> bool {
         match self {
             &SubPattern::Var(ref sym) => sym == var,
@@ -442,10 +588,14 @@ impl Template {
         }
     }
 
-    fn transform<T>(&self, data: &PatternMatch<T>) -> Datum<T>
+    fn transform<T>(&self, data: &PatternMatch<T>, mark: Option<usize>) -> Datum<T>
         where T: Clone
     {
         match self {
+            &Template::Const(SimpleDatum::Sym(ref sym)) => match mark {
+                Some(mark) => Datum::Sym(alias(sym, mark)),
+                None => Datum::Sym(sym.clone())
+            },
             &Template::Const(ref c) =>
                 c.clone().to_datum(),
             &Template::Var(ref sym) => {
@@ -457,19 +607,19 @@ impl Template {
             },
             &Template::List(ref list, ref tail) => {
                 let mut res = match tail {
-                    &Some(ref t) => t.transform(data),
+                    &Some(ref t) => t.transform(data, mark),
                     &None => Datum::Nil
                 };
 
                 for elem in list.iter().rev() {
                     match elem {
                         &TemplateElement::Template(ref t) => {
-                            res = cons(t.transform(data), res);
+                            res = cons(t.transform(data, mark), res);
                         },
                         &TemplateElement::Repeat(ref vars, ref t) => {
                             let matches = data.find_var_repeat(vars).expect("Unknown pattern");
                             for m in matches.iter().rev() {
-                                res = cons(t.transform_repeat(m), res);
+                                res = cons(t.transform_repeat(m, mark), res);
                             }
                         }
                     }
@@ -483,12 +633,12 @@ impl Template {
                 for elem in vec.iter().rev() {
                     match elem {
                         &TemplateElement::Template(ref t) => {
-                            res = cons(t.transform(data), res);
+                            res = cons(t.transform(data, mark), res);
                         },
                         &TemplateElement::Repeat(ref vars, ref t) => {
                             let matches = data.find_var_repeat(vars).expect("Unknown pattern");
                             for m in matches.iter().rev() {
-                                res = cons(t.transform_repeat(m), res);
+                                res = cons(t.transform_repeat(m, mark), res);
                             }
                         }
                     }
@@ -571,15 +721,15 @@ impl TemplateElement {
         }
     }
 
-    fn transform_repeat<T>(&self, data: &PatternMatch<T>) -> Datum<T>
+    fn transform_repeat<T>(&self, data: &PatternMatch<T>, mark: Option<usize>) -> Datum<T>
         where T: Clone
     {
         match self {
             &TemplateElement::Template(ref tmpl) =>
-                tmpl.transform(data),
+                tmpl.transform(data, mark),
             &TemplateElement::Repeat(ref vars, ref tmpl) => {
                 let matches = data.find_var_repeat(vars).expect("Template pattern not found");
-                matches.iter().map(|m| tmpl.transform_repeat(m)).collect()
+                matches.iter().map(|m| tmpl.transform_repeat(m, mark)).collect()
             }
         }
     }
@@ -605,7 +755,11 @@ impl<'a> TemplateCompiler<'a> {
         let mut vars = last_vars.clone();
         let mut last_elem = TemplateElement::Template(elem);
         for elem in data[1 .. ].iter() {
-            if let &Datum::Sym(Cow::Borrowed("...")) = elem {
+            let is_ellipsis = match elem {
+                &Datum::Sym(ref sym) => base_name(sym) == "...",
+                _ => false
+            };
+            if is_ellipsis {
                 last_elem = TemplateElement::Repeat(last_vars.clone(), Box::new(last_elem));
             } else {
                 res.push(last_elem);
@@ -666,12 +820,261 @@ impl<'a> TemplateCompiler<'a> {
     }
 }
 
+/// Pattern of a `syntax-case` clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxPattern {
+    pattern: SubPattern,
+    vars: Vec<(Cow<'static, str>, usize)>
+}
+
+/// Template of a `syntax` form. The values of the pattern variables are given when the template
+/// is instantiated, rather than by a match
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTemplate {
+    template: Template,
+    vars: Vec<(Cow<'static, str>, usize)>
+}
+
+/// Values of the pattern variables with their depths, by the names of the variables
+type SyntaxBindings<T> = HashMap<Cow<'static, str>, (usize, Datum<T>)>;
+
+impl SyntaxPattern {
+    pub fn compile<T>(literals: &Vars, datum: &Datum<T>) -> Result<SyntaxPattern, MacroError>
+        where T: Clone
+    {
+        let (_, pattern) = SubPattern::compile(literals, datum)?;
+        let mut vars = Vec::new();
+        pattern.var_depths(0, &mut vars);
+
+        Ok(SyntaxPattern {
+            pattern: pattern,
+            vars: vars
+        })
+    }
+
+    /// Pattern variables in the order `bind` returns their values, with the number of the
+    /// ellipses following them
+    pub fn vars(&self) -> &[(Cow<'static, str>, usize)] {
+        &self.vars
+    }
+
+    /// Matches the syntax against the pattern, returning the values of the pattern variables.
+    /// The value of a variable followed by ellipses is the list of the syntax it matched
+    pub fn bind<T>(&self, datum: &Datum<T>, literal_eq: &Fn(&str, &str) -> bool) -> Option<Vec<Datum<T>>>
+        where T: Clone
+    {
+        self.pattern.compute_match(datum, literal_eq).map(|matches|
+            self.vars.iter().map(|&(ref var, depth)| bind_var(&matches, var, depth)).collect()
+        )
+    }
+}
+
+fn bind_var<T>(matches: &[MatchData<T>], var: &str, depth: usize) -> Datum<T>
+    where T: Clone
+{
+    for m in matches.iter() {
+        match m {
+            &MatchData::Var(ref v, ref val) if depth == 0 && v == var =>
+                return val.clone(),
+            &MatchData::Repeated(ref vs, ref repeats) if depth > 0 && vs.contains(var) =>
+                return repeats.iter().map(|r| bind_var(&r.matches, var, depth - 1)).collect(),
+            _ => ()
+        }
+    }
+
+    panic!("Unknown variable `{}`", var)
+}
+
+impl SyntaxTemplate {
+    /// Compiles the template, where `pattern_vars` are the pattern variables it refers to with
+    /// their depths
+    pub fn compile<T>(datum: &Datum<T>, pattern_vars: &[(Cow<'static, str>, usize)])
+            -> Result<SyntaxTemplate, MacroError>
+        where T: Clone
+    {
+        let names = pattern_vars.iter().map(|&(ref var, _)| var.clone()).collect();
+        let (_, template) = TemplateCompiler::new(&names).compile(datum)?;
+
+        Ok(SyntaxTemplate {
+            template: template,
+            vars: pattern_vars.to_vec()
+        })
+    }
+
+    /// Pattern variables in the order `instantiate` takes their values
+    pub fn vars(&self) -> &[(Cow<'static, str>, usize)] {
+        &self.vars
+    }
+
+    /// Builds the syntax from the values of the pattern variables
+    pub fn instantiate<T>(&self, values: Vec<Datum<T>>) -> Result<Datum<T>, MacroError>
+        where T: Clone
+    {
+        let bindings = self.vars.iter().zip(values.into_iter())
+            .map(|(&(ref var, depth), val)| (var.clone(), (depth, val)))
+            .collect();
+        self.template.instantiate(&bindings)
+    }
+}
+
+impl Template {
+    fn instantiate<T>(&self, bindings: &SyntaxBindings<T>) -> Result<Datum<T>, MacroError>
+        where T: Clone
+    {
+        match self {
+            &Template::Const(ref c) =>
+                Ok(c.clone().to_datum()),
+            &Template::Var(ref sym) => match bindings.get(sym) {
+                Some(&(0, ref val)) => Ok(val.clone()),
+                Some(_) => Err(MacroError {
+                    kind: MacroErrorKind::BadRepeatingTemplate,
+                    desc: format!("Pattern variable `{}` is used without an ellipsis", sym)
+                }),
+                None => Err(MacroError {
+                    kind: MacroErrorKind::UnknownVariable,
+                    desc: format!("Unknown variable `{}`", sym)
+                })
+            },
+            &Template::List(ref list, ref tail) => {
+                let mut res = match tail {
+                    &Some(ref t) => t.instantiate(bindings)?,
+                    &None => Datum::Nil
+                };
+
+                let mut elems = Vec::new();
+                for elem in list.iter() {
+                    elem.instantiate(bindings, &mut elems)?;
+                }
+                for elem in elems.into_iter().rev() {
+                    res = cons(elem, res);
+                }
+
+                Ok(res)
+            },
+            &Template::Vector(ref vec) => {
+                let mut elems = Vec::new();
+                for elem in vec.iter() {
+                    elem.instantiate(bindings, &mut elems)?;
+                }
+
+                Ok(Datum::Vector(Rc::new(elems)))
+            }
+        }
+    }
+}
+
+impl TemplateElement {
+    /// Instantiates the element into `out`. A repeated element is instantiated once for each
+    /// syntax matched by the repeated pattern variables in it
+    fn instantiate<T>(&self, bindings: &SyntaxBindings<T>, out: &mut Vec<Datum<T>>)
+            -> Result<(), MacroError>
+        where T: Clone
+    {
+        match self {
+            &TemplateElement::Template(ref t) => {
+                out.push(t.instantiate(bindings)?);
+                Ok(())
+            },
+            &TemplateElement::Repeat(ref vars, ref elem) => {
+                let mut repeated = Vec::new();
+                for var in vars.iter() {
+                    if let Some(&(depth, ref val)) = bindings.get(var) {
+                        if depth > 0 {
+                            let items: Vec<Datum<T>> = val.iter().collect::<Result<_, ()>>()
+                                .map_err(|_| MacroError {
+                                    kind: MacroErrorKind::InvalidDatum,
+                                    desc: format!("Value of `{}` is not a list", var)
+                                })?;
+                            repeated.push((var.clone(), depth - 1, items));
+                        }
+                    }
+                }
+
+                let len = match repeated.first() {
+                    Some(&(_, _, ref items)) => items.len(),
+                    None => return Err(MacroError {
+                        kind: MacroErrorKind::BadRepeatingTemplate,
+                        desc: "No pattern variable to repeat in the template".to_string()
+                    })
+                };
+                if repeated.iter().any(|&(_, _, ref items)| items.len() != len) {
+                    return Err(MacroError {
+                        kind: MacroErrorKind::BadRepeatingTemplate,
+                        desc: "Pattern variables repeated together have different lengths".to_string()
+                    });
+                }
+
+                for i in 0 .. len {
+                    let mut sub_bindings = bindings.clone();
+                    for &(ref var, depth, ref items) in repeated.iter() {
+                        sub_bindings.insert(var.clone(), (depth, items[i].clone()));
+                    }
+                    elem.instantiate(&sub_bindings, out)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The syntax objects are the data of the forms with the identifiers renamed by the expansions, so
+/// any symbol is an identifier, including a quoted one
+fn is_identifier(datum: RDatum) -> bool {
+    if let Datum::Sym(_) = datum {
+        true
+    } else {
+        false
+    }
+}
+
+fn bound_identifier_eq(id1: Cow<'static, str>, id2: Cow<'static, str>) -> bool {
+    id1 == id2
+}
+
+fn free_identifier_eq(id1: Cow<'static, str>, id2: Cow<'static, str>) -> bool {
+    compiler::free_identifier_eq(&id1, &id2)
+}
+
+/// Gives the identifiers of the datum the marks of the template identifier, so that they refer
+/// to the bindings visible where the template identifier came from
+fn datum_to_syntax(template_id: Cow<'static, str>, datum: RDatum) -> RDatum {
+    let marks = &template_id[base_name(&template_id).len() ..];
+    map_identifiers(&strip_aliases(&datum), &|sym| Cow::Owned(format!("{}{}", sym, marks)))
+}
+
+fn syntax_to_datum(syntax: RDatum) -> RDatum {
+    strip_aliases(&syntax)
+}
+
+/// `(identifier? obj)`
+pub static PRIM_IS_IDENTIFIER: F1<RDatum, bool> = F1 { f1: is_identifier };
+/// `(bound-identifier=? id1 id2)`
+pub static PRIM_BOUND_IDENTIFIER_EQ: F2<Cow<'static, str>, Cow<'static, str>, bool> = F2 { f2: bound_identifier_eq };
+/// `(free-identifier=? id1 id2)`
+pub static PRIM_FREE_IDENTIFIER_EQ: F2<Cow<'static, str>, Cow<'static, str>, bool> = F2 { f2: free_identifier_eq };
+/// `(datum->syntax template-id datum)`
+pub static PRIM_DATUM_TO_SYNTAX: F2<Cow<'static, str>, RDatum, RDatum> = F2 { f2: datum_to_syntax };
+/// `(syntax->datum syntax)`
+pub static PRIM_SYNTAX_TO_DATUM: F1<RDatum, RDatum> = F1 { f1: syntax_to_datum };
+
+/// Lists all syntax object procedures with its name
+pub fn libsyntax() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("identifier?", &PRIM_IS_IDENTIFIER),
+        ("bound-identifier=?", &PRIM_BOUND_IDENTIFIER_EQ),
+        ("free-identifier=?", &PRIM_FREE_IDENTIFIER_EQ),
+        ("datum->syntax", &PRIM_DATUM_TO_SYNTAX),
+        ("syntax->datum", &PRIM_SYNTAX_TO_DATUM)
+    ]
+}
+
 #[cfg(test)]
 mod test_matches {
     use std::borrow::Cow;
     use std::collections::HashSet;
 
-    use super::{CompiledPattern, MatchData, PatternMatch};
+    use super::{same_name, CompiledPattern, MatchData, PatternMatch};
     use datum::Datum;
     use number::Number;
     use parser::Parser;
@@ -689,7 +1092,7 @@ mod test_matches {
             let compiled_pattern = CompiledPattern::compile(&literals, &pattern)
                     .expect("Failed to compile pattern");
 
-            if let Some(PatternMatch { matches: result }) = compiled_pattern.compute_match(&datum) {
+            if let Some(PatternMatch { matches: result }) = compiled_pattern.compute_match(&datum, &same_name) {
                 assert_eq!($result, result);
             } else {
                 panic!("compute_match returned None");
@@ -937,3 +1340,48 @@ mod test_syntax_transform {
         );
     }
 }
+
+#[cfg(test)]
+mod test_syntax_case {
+    use std::borrow::Cow;
+    use std::collections::HashSet;
+
+    use datum::Datum;
+    use parser::Parser;
+
+    use super::{same_name, toggle_mark, SyntaxPattern, SyntaxTemplate};
+
+    fn parse(src: &str) -> Datum<()> {
+        Parser::new(src.as_bytes()).parse_full::<()>().expect("Failed to parse datum")
+    }
+
+    #[test]
+    fn test_bind_and_instantiate() {
+        let pattern = SyntaxPattern::compile(&HashSet::new(), &parse("(_ (name val ...) ...)"))
+            .expect("Failed to compile pattern");
+        assert_eq!(vec![(Cow::Borrowed("name"), 1), (Cow::Borrowed("val"), 2)], pattern.vars().to_vec());
+
+        let values = pattern.bind(&parse("(m (a 1 2) (b) (c 3))"), &same_name).expect("Pattern did not match");
+        assert_eq!(vec![parse("(a b c)"), parse("((1 2) () (3))")], values);
+
+        let template = SyntaxTemplate::compile(&parse("(begin (name val ...) ... #(val ... ...))"),
+                                               pattern.vars())
+            .expect("Failed to compile template");
+        assert_eq!(Ok(parse("(begin (a 1 2) (b) (c 3) #(1 2 3))")), template.instantiate(values));
+    }
+
+    #[test]
+    fn test_repeat_length_mismatch() {
+        let vars = vec![(Cow::Borrowed("a"), 1), (Cow::Borrowed("b"), 1)];
+        let template = SyntaxTemplate::compile(&parse("((a b) ...)"), &vars)
+            .expect("Failed to compile template");
+        assert!(template.instantiate(vec![parse("(1 2)"), parse("(3)")]).is_err());
+    }
+
+    #[test]
+    fn test_toggle_mark() {
+        let marked = toggle_mark(&parse("(a (b . c))"), 3);
+        assert!(marked != parse("(a (b . c))"));
+        assert_eq!(parse("(a (b . c))"), toggle_mark(&marked, 3));
+    }
+}
@@ -0,0 +1,309 @@
+use std::borrow::Cow;
+use std::char;
+
+use unicode_categories::UnicodeCategories;
+use unicode_normalization::UnicodeNormalization;
+
+use error::{RuntimeError, RuntimeErrorKind};
+use primitive::{F1, FoldR2, PrimFunc, R1};
+
+/// Simple case mapping: the character is kept if the mapping is not a single character
+fn simple_mapping<I: Iterator<Item=char>>(c: char, mut mapped: I) -> char {
+    match (mapped.next(), mapped.next()) {
+        (Some(m), None) => m,
+        _ => c
+    }
+}
+
+fn char_upcase(c: char) -> char {
+    simple_mapping(c, c.to_uppercase())
+}
+
+fn char_downcase(c: char) -> char {
+    simple_mapping(c, c.to_lowercase())
+}
+
+/// Only the digraphs have the titlecase letters different from the uppercase letters
+fn char_titlecase(c: char) -> char {
+    match c {
+        '\u{1c4}'...'\u{1c6}' => '\u{1c5}',
+        '\u{1c7}'...'\u{1c9}' => '\u{1c8}',
+        '\u{1ca}'...'\u{1cc}' => '\u{1cb}',
+        '\u{1f1}'...'\u{1f3}' => '\u{1f2}',
+        _ => char_upcase(c)
+    }
+}
+
+/// Simple case folding, which maps the dotted and dotless i of Turkish to themselves
+pub fn char_foldcase(c: char) -> char {
+    match c {
+        '\u{130}' | '\u{131}' => c,
+        _ => char_downcase(char_upcase(c))
+    }
+}
+
+/// Full case folding of the characters, as used by `string-foldcase`
+pub fn fold_case(s: &[char]) -> Vec<char> {
+    let mut folded = Vec::with_capacity(s.len());
+    for &c in s.iter() {
+        match c {
+            '\u{130}' | '\u{131}' => folded.push(c),
+            _ => folded.extend(c.to_uppercase().flat_map(|u| u.to_lowercase()))
+        }
+    }
+    folded
+}
+
+fn char_to_integer(c: char) -> usize {
+    c as usize
+}
+
+/// `(char->integer char)`
+pub static PRIM_CHAR_TO_INTEGER: F1<char, usize> = F1 { f1: char_to_integer };
+
+fn integer_to_char(n: usize) -> Result<char, RuntimeError> {
+    if n <= 0x10ffff {
+        if let Some(c) = char::from_u32(n as u32) {
+            return Ok(c);
+        }
+    }
+    Err(RuntimeError {
+        kind: RuntimeErrorKind::InvalidType,
+        desc: format!("{} is not a Unicode scalar value", n)
+    })
+}
+
+/// `(integer->char sv)`
+pub static PRIM_INTEGER_TO_CHAR: F1<usize, Result<char, RuntimeError>> = F1 { f1: integer_to_char };
+
+fn same_char(c: char) -> char {
+    c
+}
+
+macro_rules! impl_char_comp {
+    ($static_name:ident, $func_name:ident, $key:ident, $op:ident) => (
+        fn $func_name(arg0: &char, arg1: &char, args: &[char]) -> bool {
+            let keys: Vec<char> = [*arg0, *arg1].iter().chain(args.iter()).map(|&c| $key(c)).collect();
+            keys.windows(2).all(|pair| pair[0].$op(&pair[1]))
+        }
+
+        pub static $static_name: FoldR2<char, bool> = FoldR2 { fold_r2: $func_name };
+    )
+}
+
+impl_char_comp!(PRIM_CHAR_EQ, char_eq, same_char, eq);
+impl_char_comp!(PRIM_CHAR_LT, char_lt, same_char, lt);
+impl_char_comp!(PRIM_CHAR_GT, char_gt, same_char, gt);
+impl_char_comp!(PRIM_CHAR_LE, char_le, same_char, le);
+impl_char_comp!(PRIM_CHAR_GE, char_ge, same_char, ge);
+impl_char_comp!(PRIM_CHAR_CI_EQ, char_ci_eq, char_foldcase, eq);
+impl_char_comp!(PRIM_CHAR_CI_LT, char_ci_lt, char_foldcase, lt);
+impl_char_comp!(PRIM_CHAR_CI_GT, char_ci_gt, char_foldcase, gt);
+impl_char_comp!(PRIM_CHAR_CI_LE, char_ci_le, char_foldcase, le);
+impl_char_comp!(PRIM_CHAR_CI_GE, char_ci_ge, char_foldcase, ge);
+
+/// `(char-upcase char)`
+pub static PRIM_CHAR_UPCASE: F1<char, char> = F1 { f1: char_upcase };
+/// `(char-downcase char)`
+pub static PRIM_CHAR_DOWNCASE: F1<char, char> = F1 { f1: char_downcase };
+/// `(char-titlecase char)`
+pub static PRIM_CHAR_TITLECASE: F1<char, char> = F1 { f1: char_titlecase };
+/// `(char-foldcase char)`
+pub static PRIM_CHAR_FOLDCASE: F1<char, char> = F1 { f1: char_foldcase };
+
+macro_rules! impl_char_property {
+    ($static_name:ident, $func_name:ident, $property:ident) => (
+        fn $func_name(c: &char) -> bool {
+            c.$property()
+        }
+
+        pub static $static_name: R1<char, bool> = R1 { r1: $func_name };
+    )
+}
+
+impl_char_property!(PRIM_IS_CHAR_ALPHABETIC, is_char_alphabetic, is_alphabetic);
+impl_char_property!(PRIM_IS_CHAR_NUMERIC, is_char_numeric, is_numeric);
+impl_char_property!(PRIM_IS_CHAR_WHITESPACE, is_char_whitespace, is_whitespace);
+impl_char_property!(PRIM_IS_CHAR_UPPER_CASE, is_char_upper_case, is_uppercase);
+impl_char_property!(PRIM_IS_CHAR_LOWER_CASE, is_char_lower_case, is_lowercase);
+impl_char_property!(PRIM_IS_CHAR_TITLE_CASE, is_char_title_case, is_letter_titlecase);
+
+fn general_category(c: char) -> &'static str {
+    if c.is_letter_uppercase() { "Lu" }
+    else if c.is_letter_lowercase() { "Ll" }
+    else if c.is_letter_titlecase() { "Lt" }
+    else if c.is_letter_modifier() { "Lm" }
+    else if c.is_letter_other() { "Lo" }
+    else if c.is_mark_nonspacing() { "Mn" }
+    else if c.is_mark_spacing_combining() { "Mc" }
+    else if c.is_mark_enclosing() { "Me" }
+    else if c.is_number_decimal_digit() { "Nd" }
+    else if c.is_number_letter() { "Nl" }
+    else if c.is_number_other() { "No" }
+    else if c.is_punctuation_connector() { "Pc" }
+    else if c.is_punctuation_dash() { "Pd" }
+    else if c.is_punctuation_open() { "Ps" }
+    else if c.is_punctuation_close() { "Pe" }
+    else if c.is_punctuation_initial_quote() { "Pi" }
+    else if c.is_punctuation_final_quote() { "Pf" }
+    else if c.is_punctuation_other() { "Po" }
+    else if c.is_symbol_math() { "Sm" }
+    else if c.is_symbol_currency() { "Sc" }
+    else if c.is_symbol_modifier() { "Sk" }
+    else if c.is_symbol_other() { "So" }
+    else if c.is_separator_space() { "Zs" }
+    else if c.is_separator_line() { "Zl" }
+    else if c.is_separator_paragraph() { "Zp" }
+    else if c.is_other_control() { "Cc" }
+    else if c.is_other_format() { "Cf" }
+    else if c.is_other_private_use() { "Co" }
+    else { "Cn" }
+}
+
+fn char_general_category(c: char) -> Cow<'static, str> {
+    Cow::Borrowed(general_category(c))
+}
+
+/// `(char-general-category char)`
+pub static PRIM_CHAR_GENERAL_CATEGORY: F1<char, Cow<'static, str>> = F1 { f1: char_general_category };
+
+fn string_upcase(s: String) -> String {
+    s.to_uppercase()
+}
+
+fn string_downcase(s: String) -> String {
+    s.to_lowercase()
+}
+
+fn string_foldcase(s: String) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    fold_case(&chars).into_iter().collect()
+}
+
+/// Titlecases the first letter of each word, and downcases the others. Words are runs of letters
+/// and digits, possibly joined by apostrophes
+fn string_titlecase(s: String) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut res = String::with_capacity(s.len());
+    let mut in_word = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_alphabetic() {
+            if !in_word {
+                res.push(char_titlecase(c));
+            } else if c == '\u{3a3}' && !chars.get(i+1).map_or(false, |n| n.is_alphabetic()) {
+                // final sigma
+                res.push('\u{3c2}');
+            } else {
+                res.extend(c.to_lowercase());
+            }
+            in_word = true;
+        } else {
+            in_word = in_word && (c.is_numeric() || c == '\'' || c == '\u{2019}');
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// `(string-upcase string)`
+pub static PRIM_STRING_UPCASE: F1<String, String> = F1 { f1: string_upcase };
+/// `(string-downcase string)`
+pub static PRIM_STRING_DOWNCASE: F1<String, String> = F1 { f1: string_downcase };
+/// `(string-titlecase string)`
+pub static PRIM_STRING_TITLECASE: F1<String, String> = F1 { f1: string_titlecase };
+/// `(string-foldcase string)`
+pub static PRIM_STRING_FOLDCASE: F1<String, String> = F1 { f1: string_foldcase };
+
+fn string_normalize_nfd(s: String) -> String {
+    s.nfd().collect()
+}
+
+fn string_normalize_nfkd(s: String) -> String {
+    s.nfkd().collect()
+}
+
+fn string_normalize_nfc(s: String) -> String {
+    s.nfc().collect()
+}
+
+fn string_normalize_nfkc(s: String) -> String {
+    s.nfkc().collect()
+}
+
+/// `(string-normalize-nfd string)`
+pub static PRIM_STRING_NORMALIZE_NFD: F1<String, String> = F1 { f1: string_normalize_nfd };
+/// `(string-normalize-nfkd string)`
+pub static PRIM_STRING_NORMALIZE_NFKD: F1<String, String> = F1 { f1: string_normalize_nfkd };
+/// `(string-normalize-nfc string)`
+pub static PRIM_STRING_NORMALIZE_NFC: F1<String, String> = F1 { f1: string_normalize_nfc };
+/// `(string-normalize-nfkc string)`
+pub static PRIM_STRING_NORMALIZE_NFKC: F1<String, String> = F1 { f1: string_normalize_nfkc };
+
+/// Lists all character and Unicode procedures with its name
+pub fn libunicode() -> Vec<(&'static str, &'static (PrimFunc + 'static))> {
+    vec![
+        ("char->integer", &PRIM_CHAR_TO_INTEGER),
+        ("integer->char", &PRIM_INTEGER_TO_CHAR),
+        ("char=?", &PRIM_CHAR_EQ),
+        ("char<?", &PRIM_CHAR_LT),
+        ("char>?", &PRIM_CHAR_GT),
+        ("char<=?", &PRIM_CHAR_LE),
+        ("char>=?", &PRIM_CHAR_GE),
+        ("char-ci=?", &PRIM_CHAR_CI_EQ),
+        ("char-ci<?", &PRIM_CHAR_CI_LT),
+        ("char-ci>?", &PRIM_CHAR_CI_GT),
+        ("char-ci<=?", &PRIM_CHAR_CI_LE),
+        ("char-ci>=?", &PRIM_CHAR_CI_GE),
+        ("char-upcase", &PRIM_CHAR_UPCASE),
+        ("char-downcase", &PRIM_CHAR_DOWNCASE),
+        ("char-titlecase", &PRIM_CHAR_TITLECASE),
+        ("char-foldcase", &PRIM_CHAR_FOLDCASE),
+        ("char-alphabetic?", &PRIM_IS_CHAR_ALPHABETIC),
+        ("char-numeric?", &PRIM_IS_CHAR_NUMERIC),
+        ("char-whitespace?", &PRIM_IS_CHAR_WHITESPACE),
+        ("char-upper-case?", &PRIM_IS_CHAR_UPPER_CASE),
+        ("char-lower-case?", &PRIM_IS_CHAR_LOWER_CASE),
+        ("char-title-case?", &PRIM_IS_CHAR_TITLE_CASE),
+        ("char-general-category", &PRIM_CHAR_GENERAL_CATEGORY),
+        ("string-upcase", &PRIM_STRING_UPCASE),
+        ("string-downcase", &PRIM_STRING_DOWNCASE),
+        ("string-titlecase", &PRIM_STRING_TITLECASE),
+        ("string-foldcase", &PRIM_STRING_FOLDCASE),
+        ("string-normalize-nfd", &PRIM_STRING_NORMALIZE_NFD),
+        ("string-normalize-nfkd", &PRIM_STRING_NORMALIZE_NFKD),
+        ("string-normalize-nfc", &PRIM_STRING_NORMALIZE_NFC),
+        ("string-normalize-nfkc", &PRIM_STRING_NORMALIZE_NFKC)
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{char_foldcase, char_titlecase, fold_case, general_category, string_titlecase};
+
+    #[test]
+    fn test_case_mapping() {
+        assert_eq!(char_foldcase('\u{3a3}'), '\u{3c3}');
+        assert_eq!(char_foldcase('\u{3c2}'), '\u{3c3}');
+        assert_eq!(char_foldcase('\u{130}'), '\u{130}');
+        assert_eq!(char_titlecase('\u{1c6}'), '\u{1c5}');
+        assert_eq!(fold_case(&['\u{df}', 'X']), vec!['s', 's', 'x']);
+    }
+
+    #[test]
+    fn test_string_titlecase() {
+        assert_eq!(string_titlecase("kNock KNoCK".to_string()), "Knock Knock");
+        assert_eq!(string_titlecase("who's there?".to_string()), "Who's There?");
+        assert_eq!(string_titlecase("r6rs".to_string()), "R6rs");
+        assert_eq!(string_titlecase("R6RS".to_string()), "R6rs");
+        assert_eq!(string_titlecase("\u{3a7}\u{391}\u{39f}\u{3a3}".to_string()), "\u{3a7}\u{3b1}\u{3bf}\u{3c2}");
+    }
+
+    #[test]
+    fn test_general_category() {
+        assert_eq!(general_category('a'), "Ll");
+        assert_eq!(general_category('A'), "Lu");
+        assert_eq!(general_category(' '), "Zs");
+        assert_eq!(general_category('5'), "Nd");
+        assert_eq!(general_category('\u{e000}'), "Co");
+    }
+}
@@ -1,8 +1,14 @@
 extern crate r6;
 extern crate env_logger;
 
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
 use std::sync::{Once, ONCE_INIT};
 use r6::base::{base_syntax, libbase};
+use r6::datum::Datum;
 use r6::error::{RuntimeError, RuntimeErrorKind};
 use r6::parser::Parser;
 use r6::runtime::Runtime;
@@ -50,6 +56,35 @@ macro_rules! assert_evaluates_to {
     )
 }
 
+macro_rules! assert_evaluation_fails {
+    ( $($src:expr),+ => $kind:expr) => (
+        {
+            let syntax = base_syntax();
+            let base = libbase();
+            let mut runtime = Runtime::new(base, syntax);
+
+            let srcs = vec!($($src),+);
+            let mut result = Ok(Datum::Nil);
+
+            for src in srcs.into_iter() {
+                let mut src_parser = Parser::new(src.as_bytes());
+                let sourcecode = match src_parser.parse_datum::<()>() {
+                    Ok(code) => code,
+                    Err(e) => panic!("failed to parse {}: {:?}", src, e)
+                };
+                result = runtime.eval(&sourcecode)
+            }
+
+            match result {
+                Err(e) => if e.kind != $kind {
+                    panic!("test failed: expected {:?} but got {:?}", $kind, e)
+                },
+                Ok(datum) => panic!("test failed: expected {:?} but got `{:?}`", $kind, datum)
+            }
+        }
+    )
+}
+
 macro_rules! assert_evaluates_datum {
     ($src:expr, $expected:expr) => (
         {
@@ -183,6 +218,88 @@ fn letrec_value_test() {
         "27");
 }
 
+#[test]
+fn begin_test() {
+    assert_evaluates_to!("(begin 1 2 3)" => "3");
+    assert_evaluates_to!("(begin (define x 1) (define y 2))", "(+ x y)" => "3");
+    assert_evaluates_to!("((lambda () (begin (define a 1) (begin (define b 2))) (+ a b)))" => "3");
+    assert_evaluates_to!("(library (pair-of-defs) (export a b) (import (rnrs))
+                            (begin (define a 1) (define b 2)))",
+                         "(import (pair-of-defs))",
+                         "(list a b)"
+                         => "(1 2)");
+    assert_evaluation_fails!("(begin)" => RuntimeErrorKind::CompileError);
+}
+
+#[test]
+fn when_unless_test() {
+    assert_evaluates_to!("(list (when (< 1 2) 'a 'b) (unless (> 1 2) 'c 'd))" => "(b d)");
+    assert_evaluates_to!("(define x 0)",
+                         "(when (> 1 2) (set! x 1))",
+                         "(unless (< 1 2) (set! x 2))",
+                         "x"
+                         => "0");
+    assert_evaluates_to!("(define (count n) (when (> n 0) (count (- n 1))))",
+                         "(count 100000)",
+                         "'done"
+                         => "done");
+    assert_evaluation_fails!("(when #t)" => RuntimeErrorKind::CompileError);
+}
+
+#[test]
+fn named_let_test() {
+    assert_evaluates_to!("(let loop ((i 0) (acc '())) (if (= i 5) acc (loop (+ i 1) (cons i acc))))"
+                         => "(4 3 2 1 0)");
+    assert_evaluates_to!("(let loop ((i 0)) (define limit 3) (cond ((= i limit) i) (else (loop (+ i 1)))))"
+                         => "3");
+    assert_evaluates_to!("(let loop () 'done)" => "done");
+    // the name is bound only in the body
+    assert_evaluates_to!("(define (loop x) 'outer)",
+                         "(let loop ((f loop)) (if (procedure? f) (f 1) f))"
+                         => "outer");
+    // calls in tail position run in constant space
+    assert_evaluates_to!("(define (count n) (let loop ((i 0)) (if (< i n) (loop (+ i 1)) i)))",
+                         "(count 100000)"
+                         => "100000");
+}
+
+#[test]
+fn do_test() {
+    assert_evaluates_to!("(do ((i 0 (+ i 1)) (acc '() (cons i acc))) ((= i 4) acc))" => "(3 2 1 0)");
+    assert_evaluates_to!("(let ((s (make-string 3 #\\a)))
+                            (do ((i 0 (+ i 1)))
+                                ((= i 3) s)
+                              (string-set! s i (integer->char (+ 48 i)))))"
+                         => "\"012\"");
+    assert_evaluates_to!("(define x 0)",
+                         "(do ((i 0 (+ i 1)) (unchanged 'u)) ((= i 3) (set! x (+ x 1)) (list x unchanged)) (set! x (+ x i)))"
+                         => "(4 u)");
+    // each iteration binds the variables afresh
+    assert_evaluates_to!("(do ((i 0 (+ i 1)) (fs '() (cons (lambda () i) fs)))
+                             ((= i 3) (list ((car fs)) ((car (cdr fs))) ((car (cdr (cdr fs)))))))"
+                         => "(2 1 0)");
+    assert_evaluates_to!("(do ((i 0 (+ i 1))) ((= i 100000) i))" => "100000");
+    assert_evaluation_fails!("(do ((i 0 1 2)) (#t))" => RuntimeErrorKind::CompileError);
+}
+
+#[test]
+fn case_lambda_test() {
+    assert_evaluates_to!("(define area (case-lambda ((r) (* 3 r r)) ((w h) (* w h)) ((a b . rest) rest)))",
+                         "(list (area 2) (area 2 3) (area 1 2 3 4) (apply area '(4 5)))"
+                         => "(12 6 (3 4) 20)");
+    assert_evaluates_to!("(define f (case-lambda (args args)))", "(list (f) (f 1 2))" => "(() (1 2))");
+    assert_evaluates_to!("(define (make-counter n) (case-lambda (() n) ((k) (set! n (+ n k)) n)))",
+                         "(define c (make-counter 10))",
+                         "(c 5)",
+                         "(list (c) (procedure? c))"
+                         => "(15 #t)");
+    assert_evaluates_to!("(define down (case-lambda ((n) (if (= n 0) 'end (down (- n 1))))))",
+                         "(down 100000)"
+                         => "end");
+    assert_evaluation_fails!("((case-lambda ((a) a) ((a b c) c)) 1 2)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("((case-lambda))" => RuntimeErrorKind::NumArgs);
+}
+
 #[test]
 fn set_test() {
     assert_evaluates_to!("(let ((x 23)) (set! x 24) x)" => "24");
@@ -214,6 +331,26 @@ fn quasiquote_test() {
     assert_evaluates_to!("`(0 ,(+ 1 2) 4)" => "(0 3 4)");
     assert_evaluates_to!("`(0 ,@(list 1 2) 4)" => "(0 1 2 4)");
     assert_evaluates_to!("`(1 `,(+ 1 ,(+ 2 3)) 4)" => "(1 `,(+ 1 5) 4)");
+    assert_evaluates_to!("`(a `(b ,(+ 1 2) ,(foo ,(+ 1 3) d) e) f)" => "(a `(b ,(+ 1 2) ,(foo 4 d) e) f)");
+    assert_evaluates_to!("(let ((name1 'x) (name2 'y)) `(a `(b ,,name1 ,',name2 d) e))"
+                         => "(a `(b ,x ,'y d) e)");
+    assert_evaluates_to!("`(a `(b ,@,@(list 1 2)))" => "(a `(b (unquote-splicing 1 2)))");
+    assert_evaluates_to!("`(a . ,(+ 1 2))" => "(a . 3)");
+    assert_evaluates_to!("`(1 ,@'() . 2)" => "(1 . 2)");
+}
+
+#[test]
+fn quasiquote_vector_test() {
+    assert_evaluates_to!("`#(1 2)" => "#(1 2)");
+    assert_evaluates_to!("(let ((x 2) (ys '(3 4))) `#(1 ,x ,@ys 5))" => "#(1 2 3 4 5)");
+    assert_evaluates_to!("`#(,@'())" => "#()");
+    assert_evaluates_to!("`(1 #(a ,(+ 1 1)) #(b ,@(list 3 4)))" => "(1 #(a 2) #(b 3 4))");
+    assert_evaluates_to!("`#(#(,(* 2 3)) (,@(list 7 8)))" => "#(#(6) (7 8))");
+    // nested quasiquote levels inside vectors
+    assert_evaluates_to!("`#(1 `#(,(+ 1 2) ,,(+ 1 3)))" => "#(1 `#(,(+ 1 2) ,4))");
+    assert_evaluates_to!("`#(1 `#(,@,@(list 2 3)))" => "#(1 `#((unquote-splicing 2 3)))");
+    assert_evaluates_to!("`#(`#(,,@(list 1 2)))" => "#(`#((unquote 1 2)))");
+    assert_evaluates_to!("(let ((x 'y)) `(a `#(b ,,x ,',x)))" => "(a `#(b ,y ,'y))");
 }
 
 #[test]
@@ -342,12 +479,38 @@ fn cond_test() {
     assert_evaluates_to!("(cond ((> 3 2) 'greater) ((< 3 2) 'less))" => "greater");
     assert_evaluates_to!("(cond ((> 3 3) 'greater) ((< 3 3) 'less) (else 'equal))" => "equal");
     assert_evaluates_to!("(cond ('(1 2 3) => car) (else #f))" => "1");
+    assert_evaluates_to!("(cond ((assv 'b '((a 1) (b 2))) => (lambda (p) (car (cdr p)))) (else #f))" => "2");
+    assert_evaluates_to!("(list (cond (#f) ((memv 2 '(1 2 3)))) 4)" => "((2 3) 4)");
+    // the receiver is called in tail position
+    assert_evaluates_to!("(define (count n) (cond ((= n 0) 'done) ((- n 1) => count)))",
+                         "(count 100000)"
+                         => "done");
+    assert_evaluation_fails!("(cond (#t => car cdr))" => RuntimeErrorKind::CompileError);
+    // a bound `=>` or `else` is a variable, but the `=>` introduced by a macro is still the keyword
+    assert_evaluates_to!("(let ((=> #f)) (cond (#t => 'ok)))" => "ok");
+    assert_evaluates_to!("(let ((else #f)) (cond (else 1) (#t 2)))" => "2");
+    assert_evaluates_to!("(define-syntax first-of (syntax-rules () ((_ l) (cond (l => car)))))",
+                         "(let ((=> #f)) (first-of '(1 2)))"
+                         => "1");
 }
 
 #[test]
 fn case_test() {
     assert_evaluates_to!("(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) 'composite))" => "composite");
     assert_evaluates_to!("(case (car '(c d)) ((a e i o u) 'vowel) ((w y) 'semivowel) (else 'consonant))" => "consonant");
+    assert_evaluates_to!("(list (case 5 ((1) 'a) (else 'b)) 2)" => "(b 2)");
+    assert_evaluates_to!("(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) => (lambda (x) (list 'composite x))))"
+                         => "(composite 6)");
+    assert_evaluates_to!("(list (case 'z ((a) 1) (else => (lambda (k) (list k k)))) 0)" => "((z z) 0)");
+    // the clauses are in tail position
+    assert_evaluates_to!("(define (count n) (case n ((0) 'done) (else (count (- n 1)))))",
+                         "(count 100000)"
+                         => "done");
+    assert_evaluates_to!("(define (count n) (case n ((0) 'done) (else => (lambda (k) (count (- k 1))))))",
+                         "(count 100000)"
+                         => "done");
+    assert_evaluation_fails!("(case 1 ((1) =>))" => RuntimeErrorKind::CompileError);
+    assert_evaluates_to!("(let ((=> #f)) (case 1 ((1) => 'ok)))" => "ok");
 }
 
 #[test]
@@ -421,6 +584,23 @@ fn equal_test() {
     assert_evaluates_to!("(equal? 2 2)" => "#t");
     assert_evaluates_to!("(equal? (make-vector 5 'a) (make-vector 5 'a))" => "#t");
     assert_evaluates_to!("(let* ((x (list 'a)) (y (list 'a)) (z (list x y))) (list (equal? z (list y x)) (equal? z (list x x))))" => "(#t #t)");
+
+    assert_evaluates_to!("(let ((a (list 1 2)) (b (list 1 2 1 2)))
+                            (set-cdr! (cdr a) a)
+                            (set-cdr! (cdr (cdr (cdr b))) b)
+                            (equal? a b))" => "#t");
+    assert_evaluates_to!("(let ((a (list 1)) (b (list 2)))
+                            (set-cdr! a a)
+                            (set-cdr! b b)
+                            (equal? a b))" => "#f");
+    assert_evaluates_to!("(let ((p (list 1)) (q (list 1)))
+                            (set-car! p (vector 'a p))
+                            (set-car! q (vector 'a q))
+                            (list (equal? p q) (equal? (car p) (car q))))" => "(#t #t)");
+    assert_evaluates_to!("(let ((p (list 1)) (q (list 1)))
+                            (set-car! p (vector p))
+                            (set-car! q (vector q 'b))
+                            (equal? p q))" => "#f");
 }
 
 #[test]
@@ -541,3 +721,1802 @@ fn test_macro() {
         "now"
     );
 }
+
+#[test]
+fn hygiene_test() {
+    // the temporary of the template does not capture the variable of the form
+    assert_evaluates_to!(
+        "(let-syntax
+            ((my-or (syntax-rules ()
+                ((_ e1 e2) (let ((t e1)) (if t t e2))))))
+            (let ((t 5))
+                (my-or #f t)))"
+        =>
+        "5"
+    );
+
+    // the bindings around the macro use do not capture the identifiers of the template
+    assert_evaluates_to!(
+        "(let-syntax
+            ((my-if (syntax-rules ()
+                ((_ c a b) (cond (c a) (else b))))))
+            (let ((if list) (cond list) (else #t))
+                (my-if #f 'then 'else)))"
+        =>
+        "else"
+    );
+    assert_evaluates_to!(
+        "(let ((x 'outer))
+            (let-syntax ((m (syntax-rules () ((_) x))))
+                (let ((x 'inner))
+                    (m))))"
+        =>
+        "outer"
+    );
+
+    // literals introduced by another template still match
+    assert_evaluates_to!(
+        "(let-syntax
+            ((kw (syntax-rules (=>)
+                ((_ => e) e)
+                ((_ other e) 'no-match))))
+            (let-syntax ((call-kw (syntax-rules () ((_ e) (kw => e)))))
+                (call-kw 'matched)))"
+        =>
+        "matched"
+    );
+
+    // a literal bound around the macro use is a variable, not the literal
+    assert_evaluates_to!(
+        "(define-syntax kw (syntax-rules (=>) ((_ a => b) (list a b)) ((_ a b c) 'no-match)))",
+        "(list (kw 1 => 2) (let ((=> 0)) (kw 1 => 2)))"
+        =>
+        "((1 2) no-match)"
+    );
+}
+
+#[test]
+fn syntax_case_test() {
+    assert_evaluates_to!(
+        "(let ((tmp 1) (y 2))
+            (let-syntax
+                ((swap! (lambda (x)
+                    (syntax-case x ()
+                        ((_ a b) (syntax (let ((tmp a)) (set! a b) (set! b tmp))))))))
+                (swap! tmp y)
+                (list tmp y)))"
+        =>
+        "(2 1)"
+    );
+    assert_evaluates_to!(
+        "(let-syntax
+            ((my-let (lambda (x)
+                (syntax-case x ()
+                    ((_ ((n v) ...) e1 e2 ...) (syntax ((lambda (n ...) e1 e2 ...) v ...)))))))
+            (my-let ((a 1) (b 2)) (+ a b)))"
+        =>
+        "3"
+    );
+
+    // fenders
+    assert_evaluates_to!(
+        "(let-syntax
+            ((kind (lambda (x)
+                (syntax-case x ()
+                    ((_ e) (identifier? (syntax e)) (syntax 'identifier))
+                    ((_ e) (syntax 'other))))))
+            (list (kind a) (kind 1) (kind (f x))))"
+        =>
+        "(identifier other other)"
+    );
+
+    assert_evaluates_to!(
+        "(let-syntax
+            ((add (lambda (x)
+                (syntax-case x ()
+                    ((_ a b) (with-syntax ((sum (+ (syntax->datum (syntax a))
+                                                   (syntax->datum (syntax b)))))
+                                (syntax sum)))))))
+            (add 1 2))"
+        =>
+        "3"
+    );
+    assert_evaluates_to!(
+        "(let-syntax
+            ((m (lambda (x)
+                (syntax-case x ()
+                    ((_ e ...) #`(list #,(+ 1 2) #,@(syntax (e ...))))))))
+            (m 4 5))"
+        =>
+        "(3 4 5)"
+    );
+
+    // datum->syntax breaks the hygiene on purpose
+    assert_evaluates_to!(
+        "(let-syntax
+            ((with-it (lambda (x)
+                (syntax-case x ()
+                    ((k e body) (with-syntax ((it (datum->syntax (syntax k) 'it)))
+                                    (syntax (let ((it e)) body))))))))
+            (with-it 42 (+ it 1)))"
+        =>
+        "43"
+    );
+    assert_evaluates_to!(
+        "(let ((it 'outer))
+            (let-syntax
+                ((m (lambda (x)
+                    (syntax-case x ()
+                        ((_ body) (syntax (let ((it 'inner)) body)))))))
+                (m it)))"
+        =>
+        "outer"
+    );
+
+    assert_evaluates_to!("(identifier? (syntax a))" => "#t");
+    assert_evaluates_to!("(identifier? (syntax (a b)))" => "#f");
+    assert_evaluates_to!("(syntax->datum (syntax (a #(b) \"c\")))" => "(a #(b) \"c\")");
+    assert_evaluates_to!("(bound-identifier=? (syntax a) (syntax a))" => "#t");
+    assert_evaluates_to!("(free-identifier=? (syntax a) (syntax b))" => "#f");
+    // identifiers are symbols, so a quoted symbol is an identifier too
+    assert_evaluates_to!("(identifier? 'a)" => "#t");
+    // a local binding shadows the global one for free-identifier=? and the literals
+    assert_evaluates_to!(
+        "(define-syntax fid
+            (lambda (x)
+                (syntax-case x ()
+                    ((_ id) (if (free-identifier=? (syntax id) (syntax car)) (syntax 'same) (syntax 'different))))))",
+        "(list (fid car) (let ((car 1)) (fid car)) (fid cdr))"
+        =>
+        "(same different different)"
+    );
+    assert_evaluates_to!(
+        "(define-syntax kw
+            (lambda (x)
+                (syntax-case x (=>)
+                    ((_ a => b) (syntax (list a b)))
+                    ((_ a b c) (syntax 'no-match)))))",
+        "(list (kw 1 => 2) (let ((=> 0)) (kw 1 => 2)))"
+        =>
+        "((1 2) no-match)"
+    );
+    assert_evaluation_fails!(
+        "(let-syntax ((m (lambda (x) (syntax-case x () ((_ a) (syntax a))))))
+            (m 1 2))"
+        =>
+        RuntimeErrorKind::CompileError
+    );
+
+    // the transformers run with the libraries of the program
+    assert_evaluates_to!(
+        "(library (helpers) (export twice) (import (rnrs)) (define (twice x) (* x 2)))",
+        "(define-syntax m
+            (lambda (x)
+                (syntax-case x ()
+                    ((_ n) (eval (list 'twice (syntax->datum (syntax n))) (environment '(helpers)))))))",
+        "(m 21)"
+        =>
+        "42"
+    );
+
+    assert_evaluates_to!(
+        "(guard (e ((syntax-violation? e)
+                    (list (condition-who e) (condition-message e)
+                          (syntax-violation-form e) (syntax-violation-subform e))))
+            (syntax-violation 'foo \"bad form\" '(foo 1) 1))"
+        =>
+        "(foo \"bad form\" (foo 1) 1)"
+    );
+    // the syntax violation of a transformer is raised to the program expanding the macro
+    assert_evaluates_to!(
+        "(guard (e ((syntax-violation? e)
+                    (list (condition-who e) (condition-message e)
+                          (syntax->datum (syntax-violation-form e)) (syntax-violation-subform e))))
+            (eval '(let-syntax ((m (lambda (x) (syntax-violation 'm \"bad use\" x))))
+                     (m 1))
+                  (environment '(rnrs))))"
+        =>
+        "(m \"bad use\" (m 1) #f)"
+    );
+}
+
+#[test]
+fn define_syntax_test() {
+    assert_evaluates_to!(
+        "(define-syntax my-inc (syntax-rules () ((_ x) (+ x 1))))",
+        "(my-inc 41)"
+        =>
+        "42"
+    );
+    assert_evaluates_to!(
+        "(define-syntax my-or
+            (syntax-rules ()
+                ((_) #f)
+                ((_ e) e)
+                ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))",
+        "(let ((t 5)) (my-or #f #f t))"
+        =>
+        "5"
+    );
+    assert_evaluates_to!(
+        "(define-syntax swap!
+            (lambda (x)
+                (syntax-case x ()
+                    ((_ a b) (syntax (let ((tmp a)) (set! a b) (set! b tmp)))))))",
+        "(define p 1)",
+        "(define q 2)",
+        "(swap! p q)",
+        "(list p q)"
+        =>
+        "(2 1)"
+    );
+
+    // the keyword replaces the global variable
+    assert_evaluates_to!(
+        "(define foo 1)",
+        "(define-syntax foo (syntax-rules () ((_) 2)))",
+        "(foo)"
+        =>
+        "2"
+    );
+
+    // internal definitions of a body can refer to each other
+    assert_evaluates_to!(
+        "(let ()
+            (define-syntax ev? (syntax-rules () ((_) #t) ((_ x y ...) (od? y ...))))
+            (define odd (od? 1))
+            (define-syntax od? (syntax-rules () ((_) #f) ((_ x y ...) (ev? y ...))))
+            (list (ev? 1 2 3) odd))"
+        =>
+        "(#f #t)"
+    );
+
+    assert_evaluation_fails!(
+        "(list (define-syntax m (syntax-rules () ((_) 1))))"
+        =>
+        RuntimeErrorKind::CompileError
+    );
+}
+
+#[test]
+fn letrec_syntax_test() {
+    assert_evaluates_to!(
+        "(letrec-syntax
+            ((my-or (syntax-rules ()
+                ((_) #f)
+                ((_ e r ...) (let ((t e)) (if t t (my-or r ...)))))))
+            (let ((t 3))
+                (my-or #f t)))"
+        =>
+        "3"
+    );
+    assert_evaluates_to!(
+        "(letrec-syntax
+            ((ev? (syntax-rules () ((_) #t) ((_ x y ...) (od? y ...))))
+             (od? (syntax-rules () ((_) #f) ((_ x y ...) (ev? y ...)))))
+            (ev? 1 2 3 4))"
+        =>
+        "#t"
+    );
+}
+
+#[test]
+fn call_cc_test() {
+    assert_evaluates_to!("(call-with-current-continuation procedure?)" => "#t");
+    assert_evaluates_to!("(+ 1 (call/cc (lambda (k) (+ 10 (k 1)))))" => "2");
+    assert_evaluates_to!("(+ 1 (call/cc (lambda (k) 10)))" => "11");
+    assert_evaluates_to!(
+        "(define (find-first pred l)
+            (call/cc (lambda (return)
+                (letrec ((loop (lambda (l)
+                            (cond ((null? l) #f)
+                                  ((pred (car l)) (return (car l)))
+                                  (else (loop (cdr l)))))))
+                    (loop l)))))",
+        "(find-first (lambda (x) (> x 2)) '(1 2 3 4))"
+        =>
+        "3"
+    );
+}
+
+#[test]
+fn call_cc_reentry_test() {
+    assert_evaluates_to!(
+        "(let ((k #f) (n 0))
+            (let ((v (call/cc (lambda (c) (set! k c) 0))))
+                (set! n (+ n 1))
+                (if (< v 3) (k (+ v 1)) (list v n))))"
+        =>
+        "(3 4)"
+    );
+    assert_evaluates_to!(
+        "(define r #f)",
+        "(define (f) (+ 1 (call/cc (lambda (k) (set! r k) 1))))",
+        "(f)",
+        "(r 10)"
+        =>
+        "11"
+    );
+}
+
+#[test]
+fn guard_test() {
+    assert_evaluates_to!("(guard (e (#t (list 'caught e))) (raise 'oops))" => "(caught oops)");
+    assert_evaluates_to!(
+        "(guard (e ((symbol? e) (list 'sym e))
+                   ((string? e) (list 'str e)))
+            (raise \"boom\"))"
+        =>
+        "(str \"boom\")"
+    );
+    assert_evaluates_to!("(guard (e ((pair? e) => (lambda (x) (list x e)))) (raise '(1)))" => "(#t (1))");
+    assert_evaluates_to!("(guard (e ((pair? e) 'pair) ((null? e) 'null)) (raise '()))" => "null");
+    assert_evaluates_to!("(guard (e (else 'div)) (/ 1 0))" => "div");
+    assert_evaluates_to!("(+ 1 (guard (e (#t 2)) (define x 10) (+ x 20)))" => "31");
+    assert_evaluates_to!(
+        "(guard (e ((number? e) (* e 2)))
+            (guard (e2 ((symbol? e2) 'inner))
+                (raise 21)))"
+        =>
+        "42"
+    );
+    assert_evaluates_to!("(guard (e ((string? e) 'outer)) (guard (e ((symbol? e) 'inner)) (raise \"x\")))" => "outer");
+    assert_evaluates_to!("(define trace '())",
+                         "(guard (e (#t (list e trace)))
+                            (dynamic-wind (lambda () (set! trace (cons 'in trace)))
+                                          (lambda () (raise 'x))
+                                          (lambda () (set! trace (cons 'out trace)))))"
+                         => "(x (out in))");
+    // entering the guard doesn't copy the stacks
+    assert_evaluates_to!("(define (depth n)
+                            (if (= n 0)
+                                (raise 'bottom)
+                                (guard (e ((eq? e 'bottom) 1)) (+ 1 (depth (- n 1))))))",
+                         "(depth 100000)"
+                         => "100000");
+    // the clauses are in tail position
+    assert_evaluates_to!("(define (count n) (if (= n 0) 'done (guard (e (#t (count e))) (raise (- n 1)))))",
+                         "(count 100000)"
+                         => "done");
+    // the condition is raised again in the dynamic environment of the raise
+    assert_evaluates_to!(
+        "(with-exception-handler
+            (lambda (e) 42)
+            (lambda () (guard (e (#f 'no)) (+ 1 (raise-continuable 'c)))))"
+        =>
+        "43"
+    );
+}
+
+#[test]
+fn exception_handler_test() {
+    assert_evaluates_to!(
+        "(with-exception-handler
+            (lambda (c) 42)
+            (lambda () (+ (raise-continuable 'oops) 2)))"
+        =>
+        "44"
+    );
+    assert_evaluates_to!(
+        "(guard (e (#t 'secondary))
+            (with-exception-handler
+                (lambda (c) 0)
+                (lambda () (raise 'x))))"
+        =>
+        "secondary"
+    );
+    assert_evaluates_to!(
+        "(with-exception-handler
+            (lambda (c) (list 'outer c))
+            (lambda ()
+                (with-exception-handler
+                    (lambda (c) (raise-continuable (list 'inner c)))
+                    (lambda () (raise-continuable 'x)))))"
+        =>
+        "(outer (inner x))"
+    );
+}
+
+#[test]
+fn uncaught_exception_test() {
+    assert_evaluation_fails!("(raise 'oops)" => RuntimeErrorKind::Uncaught);
+    assert_evaluation_fails!("(guard (e ((string? e) e)) (raise 'oops))" => RuntimeErrorKind::Uncaught);
+    assert_evaluation_fails!("(guard (e ((string? e) e)) (/ 1 0))" => RuntimeErrorKind::DivideByZero);
+    assert_evaluation_fails!(
+        "(with-exception-handler (lambda (c) 0) (lambda () (raise 'x)))"
+        =>
+        RuntimeErrorKind::NonContinuable
+    );
+}
+
+#[test]
+fn condition_test() {
+    assert_evaluates_to!("(guard (e ((error? e) (condition-message e))) (error 'foo \"bad thing\" 1 2))" => "\"bad thing\"");
+    assert_evaluates_to!("(guard (e ((error? e) (condition-who e))) (error 'foo \"bad thing\" 1 2))" => "foo");
+    assert_evaluates_to!("(guard (e ((error? e) (condition-irritants e))) (error 'foo \"bad thing\" 1 2))" => "(1 2)");
+    assert_evaluates_to!("(guard (e ((error? e) 'error) ((assertion-violation? e) 'assertion)) (car 1))" => "assertion");
+    assert_evaluates_to!("(guard (e ((assertion-violation? e) 'assertion) ((violation? e) 'violation)) (vector-ref (vector 1) 3))" => "assertion");
+    assert_evaluates_to!("(guard (e ((assertion-violation? e) 'caught)) (string-ref \"ab\" 5))" => "caught");
+    assert_evaluates_to!("(guard (e ((assertion-violation? e) 'caught)) (bytevector-u8-ref (make-bytevector 2) 2))" => "caught");
+    assert_evaluates_to!("(guard (e ((assertion-violation? e) (who-condition? e))) (assertion-violation 'f \"msg\"))" => "#t");
+    assert_evaluates_to!("(guard (e ((assertion-violation? e) (message-condition? e))) (/ 1 0))" => "#t");
+    assert_evaluates_to!("(guard (e ((non-continuable-violation? e) 'nc)) (with-exception-handler (lambda (c) 0) (lambda () (raise 'x))))" => "nc");
+    assert_evaluates_to!("(condition? (condition (make-error) (make-message-condition \"m\")))" => "#t");
+    assert_evaluates_to!("(let ((l (simple-conditions (condition (make-error) (make-message-condition \"m\")))))
+            (list (error? (car l)) (message-condition? (car (cdr l))) (null? (cdr (cdr l)))))" => "(#t #t #t)");
+    assert_evaluates_to!("(serious-condition? (make-violation))" => "#t");
+    assert_evaluates_to!("(error? (make-violation))" => "#f");
+    assert_evaluates_to!("(condition? 'error)" => "#f");
+
+    // the errors of the primitives are told apart by the condition types, and report the
+    // primitive and its arguments
+    assert_evaluates_to!("(define (kind thunk)
+                            (guard (e ((wrong-type-violation? e) 'type)
+                                      ((out-of-range-violation? e) 'range)
+                                      ((arity-violation? e) 'arity)
+                                      ((divide-by-zero-violation? e) 'zero))
+                              (thunk)))",
+                         "(list (kind (lambda () (vector-ref '#(1) 'a)))
+                                (kind (lambda () (vector-ref '#(1) 3)))
+                                (kind (lambda () (vector-ref '#(1))))
+                                (kind (lambda () (/ 1 0)))
+                                (kind (lambda () (5 1))))"
+                         => "(type range arity zero type)");
+    assert_evaluates_to!("(guard (e ((who-condition? e) (list (condition-who e) (condition-irritants e)))) (car 5))"
+                         => "(car (5))");
+    assert_evaluates_to!("(guard (e ((irritants-condition? e) (condition-irritants e)))
+                            (let ((s \"ab\")) (string-ref s 5)))"
+                         => "(\"ab\" 5)");
+    assert_evaluates_to!("(guard (e ((assertion-violation? e) (list (who-condition? e) (condition-irritants e)))) (5 1))"
+                         => "(#f (5 1))");
+    assert_evaluates_to!("(assertion-violation? (make-out-of-range-violation))" => "#t");
+}
+
+#[test]
+fn uncaught_condition_test() {
+    assert_evaluation_fails!("(error 'foo \"bad thing\")" => RuntimeErrorKind::Uncaught);
+    assert_evaluation_fails!("(guard (e ((error? e) 'error)) (car 1))" => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn values_test() {
+    assert_evaluates_to!("(call-with-values (lambda () (values 1 2 3)) list)" => "(1 2 3)");
+    assert_evaluates_to!("(call-with-values (lambda () (values)) list)" => "()");
+    assert_evaluates_to!("(call-with-values (lambda () 4) (lambda (x) (* x x)))" => "16");
+    assert_evaluates_to!("(+ 1 (values 2))" => "3");
+    assert_evaluates_to!("(call-with-values (lambda () (call/cc (lambda (k) (k 1 2)))) cons)" => "(1 . 2)");
+    assert_evaluates_to!("(let ((f (lambda () (values 1 2) (values 3 4)))) (call-with-values f list))" => "(3 4)");
+
+    // the continuations taking a single value reject multiple values
+    assert_evaluation_fails!("(define x (values 1 2))" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(define y 0)", "(set! y (values 1 2))" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(define (h) (define z (values 1 2)) z)", "(h)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(let ((a (values 1 2))) a)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(if (values #f 1) 'y 'n)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(if (values) 'y 'n)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(let* ((x (values 1 2)) (y 5)) (list x y))" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(let* ((y 5) (x (values 1 2))) (list x y))" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(case (values 1 2) ((1) 'one) (else 'other))" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(case (values) (else 'e))" => RuntimeErrorKind::NumArgs);
+    assert_evaluates_to!("(guard (e (#t 'caught)) (list (let* ((x (values 1 2))) x) 'after))" => "caught");
+    assert_evaluates_to!("(define y 0)",
+                         "(guard (e (#t (list 'caught y)))
+                            (set! y (values 1 2)))"
+                         => "(caught 0)");
+}
+
+#[test]
+fn top_level_values_test() {
+    use r6::runtime::RuntimeData;
+
+    // multiple values returned to the host are not confused with a list
+    assert_evaluates_datum!(
+        "(values 'a 'b)",
+        Datum::Ext(RuntimeData::MultipleValues(vec![Datum::Sym(Cow::Borrowed("a")),
+                                                     Datum::Sym(Cow::Borrowed("b"))]))
+    );
+    assert_evaluates_datum!("(values)", Datum::Ext(RuntimeData::MultipleValues(vec![])));
+}
+
+#[test]
+fn let_values_test() {
+    assert_evaluates_to!("(let-values (((a b) (values 1 2)) ((c) (values 3))) (list a b c))" => "(1 2 3)");
+    assert_evaluates_to!("(let-values (((a . rest) (values 1 2 3)) (all (values 4 5))) (list a rest all))" => "(1 (2 3) (4 5))");
+    assert_evaluates_to!("(let ((a 'outer)) (let-values (((a) (values 1)) ((b) (values a))) (list a b)))" => "(1 outer)");
+    assert_evaluates_to!("(let*-values (((a b) (values 1 2)) ((c) (values (+ a b)))) (list a b c))" => "(1 2 3)");
+    assert_evaluates_to!("(let-values (((a b) (values 1 2))) (define c 3) (list a b c))" => "(1 2 3)");
+}
+
+#[test]
+fn define_values_test() {
+    assert_evaluates_to!("(define-values (q r) (values 3 1))", "(list q r)" => "(3 1)");
+    assert_evaluates_to!("(define-values (x . y) (values 1 2 3))", "(list x y)" => "(1 (2 3))");
+    assert_evaluates_to!(
+        "(define (f)
+            (define-values (a b) (values 1 2))
+            (define c (+ a b))
+            (list a b c))",
+        "(f)"
+        =>
+        "(1 2 3)"
+    );
+    assert_evaluation_fails!("(let-values (((a b) (values 1 2 3))) a)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(list (values 1 2))" => RuntimeErrorKind::NumArgs);
+}
+
+#[test]
+fn dynamic_wind_test() {
+    assert_evaluates_to!(
+        "(define trace '())",
+        "(define (note x) (set! trace (cons x trace)))",
+        "(dynamic-wind (lambda () (note 'before)) (lambda () (note 'during) 42) (lambda () (note 'after)))",
+        "(list trace)"
+        =>
+        "((after during before))"
+    );
+    assert_evaluates_to!(
+        "(define trace '())",
+        "(define (note x) (set! trace (cons x trace)))",
+        "(define res
+            (call/cc (lambda (k)
+                (dynamic-wind
+                    (lambda () (note 'before))
+                    (lambda () (k 'escaped) (note 'unreachable))
+                    (lambda () (note 'after))))))",
+        "(list res trace)"
+        =>
+        "(escaped (after before))"
+    );
+    assert_evaluates_to!(
+        "(define trace '())",
+        "(define (note x) (set! trace (cons x trace)))",
+        "(define k #f)",
+        "(define n 0)",
+        "(dynamic-wind
+            (lambda () (note 'in))
+            (lambda () (call/cc (lambda (c) (set! k c))) (set! n (+ n 1)))
+            (lambda () (note 'out)))",
+        "(if (< n 2) (k 'again) 'done)",
+        "trace"
+        =>
+        "(out in out in)"
+    );
+    assert_evaluates_to!(
+        "(define trace '())",
+        "(define (note x) (set! trace (cons x trace)))",
+        "(guard (e (#t (note 'handled)))
+            (dynamic-wind
+                (lambda () (note 'in))
+                (lambda () (raise 'oops))
+                (lambda () (note 'out))))",
+        "trace"
+        =>
+        "(handled out in)"
+    );
+    assert_evaluates_to!(
+        "(call-with-values (lambda () (dynamic-wind (lambda () 0) (lambda () (values 1 2)) (lambda () 3))) list)"
+        =>
+        "(1 2)"
+    );
+}
+
+#[test]
+fn dynamic_wind_error_test() {
+    let syntax = base_syntax();
+    let base = libbase();
+    let mut runtime = Runtime::new(base, syntax);
+
+    let srcs = [
+        "(define trace '())",
+        "(define (note x) (set! trace (cons x trace)))",
+        "(dynamic-wind
+            (lambda () (note 'outer-in))
+            (lambda () (dynamic-wind
+                (lambda () (note 'inner-in))
+                (lambda () (car '()))
+                (lambda () (note 'inner-out))))
+            (lambda () (note 'outer-out)))"
+    ];
+    let mut results = Vec::new();
+    for src in srcs.iter() {
+        let mut src_parser = Parser::new(src.as_bytes());
+        let sourcecode = src_parser.parse_datum::<()>().unwrap();
+        results.push(runtime.eval(&sourcecode));
+    }
+    assert_eq!(results[2].as_ref().unwrap_err().kind, RuntimeErrorKind::InvalidType);
+
+    let mut src_parser = Parser::new("trace".as_bytes());
+    let trace = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+
+    let mut expected_parser = Parser::new("(outer-out inner-out inner-in outer-in)".as_bytes());
+    let expected = expected_parser.parse_datum().unwrap();
+    assert_eq!(trace, expected);
+}
+
+fn eval_all(runtime: &mut Runtime, srcs: &[&str]) -> Result<(), RuntimeError> {
+    let mut result = Ok(());
+    for src in srcs.iter() {
+        let mut src_parser = Parser::new(src.as_bytes());
+        let sourcecode = src_parser.parse_datum::<()>().unwrap();
+        result = runtime.eval(&sourcecode).map(|_| ());
+    }
+    result
+}
+
+#[test]
+fn gc_test() {
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+
+    // a recursive procedure refers to itself through the environment of its definition
+    eval_all(&mut runtime, &[
+        "(define (f n) (if (= n 0) 0 (f (- n 1))))",
+        "(define (make-counter) (let ((n 0)) (lambda () (set! n (+ n 1)) n)))",
+        "(define counter (make-counter))",
+        "(counter)",
+        "(define (f) 'redefined)"
+    ]).unwrap();
+
+    let stats = runtime.gc();
+    assert_eq!(stats.collections, 1);
+    assert!(stats.freed > 0, "{:?}", stats);
+    assert_eq!(runtime.gc().freed, 0);
+
+    let mut src_parser = Parser::new("(list (f) (counter))".as_bytes());
+    let res = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+    let mut expected_parser = Parser::new("(redefined 2)".as_bytes());
+    assert_eq!(res, expected_parser.parse_datum().unwrap());
+}
+
+#[test]
+fn gc_auto_test() {
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+
+    eval_all(&mut runtime, &[
+        "(define (loop n)
+            (if (= n 0)
+                'done
+                (let ((g (letrec ((h (lambda () h))) h)))
+                  (loop (- n 1)))))",
+        "(loop 5000)"
+    ]).unwrap();
+
+    let stats = runtime.gc_stats();
+    assert!(stats.collections > 0, "{:?}", stats);
+    assert!(stats.total_freed >= 4000, "{:?}", stats);
+}
+
+#[test]
+fn gc_hashtable_test() {
+    use std::rc::Rc;
+    use r6::runtime::RuntimeData;
+
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+
+    // the hashtables refer to themselves through their values
+    let srcs = ["(let ((h (make-eq-hashtable))) (hashtable-set! h 'self h) h)",
+                "(let ((h (make-eqv-hashtable))) (hashtable-set! h 'f (lambda () h)) h)",
+                "(let ((h (make-eq-hashtable))) (hashtable-update! h 'self (lambda (x) h) #f) h)"];
+    for src in srcs.iter() {
+        let mut src_parser = Parser::new(src.as_bytes());
+        let table = match runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap() {
+            Datum::Ext(RuntimeData::Hashtable(table)) => Rc::downgrade(&table),
+            res => panic!("expected hashtable, but received {}", res)
+        };
+        assert!(table.upgrade().is_some());
+        let stats = runtime.gc();
+        assert!(table.upgrade().is_none(), "{}: {:?}", src, stats);
+    }
+}
+
+#[test]
+fn gc_record_test() {
+    use std::rc::Rc;
+    use r6::runtime::RuntimeData;
+
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+    eval_all(&mut runtime, &["(define-record-type node (fields (mutable next)))"]).unwrap();
+
+    // the records refer to themselves through their mutable fields
+    let srcs = ["(let ((n (make-node #f))) (node-next-set! n n) n)",
+                "(let ((n (make-node #f))) (node-next-set! n (lambda () n)) n)",
+                "(let ((n (make-node #f))) ((record-mutator (record-rtd n) 0) n (list n)) n)"];
+    for src in srcs.iter() {
+        let mut src_parser = Parser::new(src.as_bytes());
+        let record = match runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap() {
+            Datum::Ext(RuntimeData::Record(record)) => Rc::downgrade(&record),
+            res => panic!("expected record, but received {}", res)
+        };
+        assert!(record.upgrade().is_some());
+        let stats = runtime.gc();
+        assert!(record.upgrade().is_none(), "{}: {:?}", src, stats);
+    }
+}
+
+#[test]
+fn gc_record_type_test() {
+    use std::rc::Rc;
+    use r6::runtime::RuntimeData;
+
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+
+    // the types and the constructor descriptors refer to themselves through their protocols
+    let srcs = ["(let ()
+                   (define-record-type point (fields x) (protocol (lambda (p) (lambda (x) (p (point? x))))))
+                   (record-type-descriptor point))",
+                "(letrec ((rcd (make-record-constructor-descriptor
+                                 (make-record-type-descriptor 'node #f #f #f #f '#((immutable next)))
+                                 #f
+                                 (lambda (p) (lambda () (p rcd))))))
+                   rcd)"];
+    for src in srcs.iter() {
+        let mut src_parser = Parser::new(src.as_bytes());
+        let res = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+        let check: Box<Fn() -> bool> = match res {
+            Datum::Ext(RuntimeData::RecordType(rtd)) => {
+                let rtd = Rc::downgrade(&rtd);
+                Box::new(move || rtd.upgrade().is_some())
+            },
+            Datum::Ext(RuntimeData::RecordConstructor(rcd)) => {
+                let rcd = Rc::downgrade(&rcd);
+                Box::new(move || rcd.upgrade().is_some())
+            },
+            res => panic!("expected record type, but received {}", res)
+        };
+        assert!(check());
+        let stats = runtime.gc();
+        assert!(!check(), "{}: {:?}", src, stats);
+    }
+}
+
+#[test]
+fn gc_port_test() {
+    use std::rc::Rc;
+    use r6::runtime::RuntimeData;
+
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+
+    // the custom ports refer to themselves through their procedures
+    let srcs = ["(letrec ((p (make-custom-textual-input-port \"in\" (lambda (s start count) (get-char p)) #f #f #f)))
+                   p)",
+                "(letrec ((p (make-custom-binary-output-port \"out\" (lambda (bv start count) (p)) #f #f #f)))
+                   p)",
+                "(letrec ((p (make-custom-binary-input-port \"close\" (lambda (bv start count) 0) #f #f
+                                                             (lambda () (close-port p)))))
+                   p)"];
+    for src in srcs.iter() {
+        let mut src_parser = Parser::new(src.as_bytes());
+        let port = match runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap() {
+            Datum::Ext(RuntimeData::Port(port)) => Rc::downgrade(&port),
+            res => panic!("expected port, but received {}", res)
+        };
+        assert!(port.upgrade().is_some());
+        let stats = runtime.gc();
+        assert!(port.upgrade().is_none(), "{}: {:?}", src, stats);
+    }
+}
+
+#[test]
+fn library_test() {
+    assert_evaluates_to!(
+        "(library (arith (1 2))
+           (export double (rename (triple thrice)))
+           (import (rnrs base))
+           (define (double x) (add x x))
+           (define (triple x) (+ x (double x)))
+           (define (add x y) (+ x y)))",
+        "(import (arith))",
+        "(list (double 2) (thrice 2))"
+        => "(4 6)"
+    );
+
+    assert_evaluates_to!(
+        "(library (counter)
+           (export next!)
+           (import (rnrs))
+           (define count 0)
+           (define (next!) (set! count (+ count 1)) count))",
+        "(import (prefix (counter) counter:))",
+        "(counter:next!)",
+        "(counter:next!)"
+        => "2"
+    );
+
+    assert_evaluates_to!(
+        "(library (macros)
+           (export unless2)
+           (import (rnrs base))
+           (define-syntax unless2
+             (syntax-rules () ((_ c e) (if c #f e)))))",
+        "(import (macros))",
+        "(unless2 #f 'done)"
+        => "done"
+    );
+
+    // the expansions of a library macro refer to the bindings of the library body
+    assert_evaluates_to!(
+        "(library (foo)
+           (export m get)
+           (import (rnrs))
+           (define hidden 'h)
+           (define-syntax helper (syntax-rules () ((_ x) (list hidden x))))
+           (define-syntax m (syntax-rules () ((_) (helper 'm))))
+           (define-syntax get (lambda (x) (syntax-case x () ((_) #'hidden)))))",
+        "(import (foo))",
+        "(define (list . xs) 'shadowed)",
+        "(let ((hidden 'local)) (cons (m) (cons (get) hidden)))"
+        => "((h m) h . local)"
+    );
+
+    // imported variables are immutable, but the library and the expansions of its macros assign
+    // its own variables
+    assert_evaluation_fails!(
+        "(library (cell) (export x) (import (rnrs)) (define x 1))",
+        "(import (cell))",
+        "(set! x 3)"
+        => RuntimeErrorKind::CompileError
+    );
+    assert_evaluation_fails!(
+        "(library (cell) (export x) (import (rnrs)) (define x 1))",
+        "(library (user) (export) (import (rnrs) (cell)) (set! x 2))"
+        => RuntimeErrorKind::CompileError
+    );
+    assert_evaluates_to!(
+        "(library (cell) (export x get-x inc!) (import (rnrs))
+           (define x 1)
+           (define (get-x) x)
+           (define-syntax inc! (syntax-rules () ((_) (set! x (+ x 1))))))",
+        "(import (cell))",
+        "(inc!)",
+        "(define result (guard (e ((syntax-violation? e) (list 'caught x)))
+                          (eval '(set! x 3) (environment '(rnrs) '(cell)))))",
+        "(define x 5)",
+        "(set! x 6)",
+        "(list result x (get-x))"
+        => "((caught 2) 6 2)"
+    );
+
+    assert_evaluation_fails!(
+        "(library (hidden) (export) (import (rnrs base)) (define secret 1))",
+        "(import (hidden))",
+        "secret"
+        => RuntimeErrorKind::UnboundVariable
+    );
+
+    // `define` is not imported
+    assert_evaluation_fails!(
+        "(library (no-base) (export x) (import (rnrs lists)) (define x 1))"
+        => RuntimeErrorKind::UnboundVariable
+    );
+}
+
+#[test]
+fn import_set_test() {
+    assert_evaluates_to!(
+        "(import (prefix (only (rnrs base) car cdr) b:))",
+        "(list (b:car '(1 2)) (b:cdr '(1 2)))"
+        => "(1 (2))"
+    );
+
+    assert_evaluates_to!(
+        "(import (rename (rnrs base) (lambda fn) (car first)))",
+        "((fn (x) (first x)) '(a b))"
+        => "a"
+    );
+
+    assert_evaluates_to!(
+        "(import (except (rnrs lists) memq) (for (rnrs exceptions) run))",
+        "(list (assq 'b '((a 1) (b 2))) (member '(1) '(0 (1) 2)) (cons* 1 2 '(3)))"
+        => "((b 2) ((1) 2) (1 2 3))"
+    );
+
+    assert_evaluation_fails!(
+        "(import (only (rnrs base) no-such-procedure))"
+        => RuntimeErrorKind::UnboundVariable
+    );
+}
+
+#[test]
+fn library_version_test() {
+    assert_evaluates_to!(
+        "(library (versioned (2 1)) (export v) (import (rnrs base)) (define v 'ok))",
+        "(import (versioned ((>= 2) (or 0 1))))",
+        "v"
+        => "ok"
+    );
+
+    assert_evaluates_to!(
+        "(import (rnrs base (6)) (library (rnrs lists (6))))",
+        "(memv 2 '(1 2 3))"
+        => "(2 3)"
+    );
+
+    assert_evaluation_fails!(
+        "(library (versioned (2 1)) (export v) (import (rnrs base)) (define v 'ok))",
+        "(import (versioned (3)))"
+        => RuntimeErrorKind::CompileError
+    );
+}
+
+/// Creates the library files under a new directory in the temp directory
+fn library_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = env::temp_dir().join(format!("r6-{}", test));
+    let _ = fs::remove_dir_all(&root);
+    for &(path, src) in files.iter() {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(path).unwrap().write_all(src.as_bytes()).unwrap();
+    }
+    root
+}
+
+#[test]
+fn library_loader_test() {
+    let root = library_dir("loader", &[
+        ("foo/bar.sls", "(library (foo bar (1)) (export twice) (import (rnrs base) (foo util))
+                           (define (twice f x) (f (f x))))"),
+        ("foo/util.scm", "(library (foo util) (export inc) (import (rnrs base))
+                            (define (inc x) (+ x 1)))")
+    ]);
+
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+    runtime.add_library_path(env::temp_dir().join("r6-no-such-directory"));
+    runtime.add_library_path(&root);
+    eval_all(&mut runtime, &["(import (foo bar (1)) (foo util))"]).unwrap();
+
+    // the library is loaded only once
+    fs::File::create(root.join("foo/util.scm")).unwrap().write_all(b"(library (foo util) (export) (import))").unwrap();
+    eval_all(&mut runtime, &["(import (only (foo util) inc))"]).unwrap();
+
+    let mut src_parser = Parser::new("(twice inc 1)".as_bytes());
+    let res = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+    let mut expected_parser = Parser::new("3".as_bytes());
+    assert_eq!(res, expected_parser.parse_datum().unwrap());
+
+    let err = eval_all(&mut runtime, &["(import (foo baz))"]).unwrap_err();
+    assert_eq!(err.kind, RuntimeErrorKind::CompileError);
+
+    // the name cannot refer to the files out of the library paths
+    fs::File::create(root.join("secret.sls")).unwrap()
+        .write_all(b"(library (secret) (export) (import))").unwrap();
+    let mut inner = Runtime::new(libbase(), base_syntax());
+    inner.add_library_path(root.join("foo"));
+    for name in [&["..", "secret"][..], &["../secret"][..], &[".", "util"][..]].iter() {
+        let name: Datum<()> = name.iter().map(|&part| Datum::Sym(Cow::Borrowed(part))).collect();
+        let import = vec![Datum::Sym(Cow::Borrowed("import")), name].into_iter().collect();
+        let err = inner.eval(&import).unwrap_err();
+        assert!(err.desc.contains("UnknownLibrary"), "{}", err.desc);
+    }
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn circular_import_test() {
+    let root = library_dir("circular", &[
+        ("a.sls", "(library (a) (export) (import (b)))"),
+        ("b.sls", "(library (b) (export) (import (rnrs) (a)))")
+    ]);
+
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+    runtime.add_library_path(&root);
+    let err = eval_all(&mut runtime, &["(import (a))"]).unwrap_err();
+    assert_eq!(err.kind, RuntimeErrorKind::CompileError);
+    assert!(err.desc.contains("(a) -> (b) -> (a)"), "{}", err.desc);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn mutable_pairs_test() {
+    assert_evaluates_to!("(let ((p (cons 1 2))) (set-car! p 3) (set-cdr! p '(4)) p)" => "(3 4)");
+    assert_evaluates_to!("(let* ((p (list 1 2)) (q (cons 0 p))) (set-car! p 'x) q)" => "(0 x 2)");
+    assert_evaluates_to!("(let ((a (list 1)) (b (list 1)))
+                            (set-car! a 2)
+                            (list (eqv? a a) (eqv? a b) (equal? a b)))" => "(#t #f #f)");
+
+    // a queue keeping its last pair
+    assert_evaluates_to!("(define (make-queue) (cons '() '()))",
+                         "(define (enqueue! q x)
+                            (let ((cell (list x)))
+                              (if (null? (car q))
+                                  (set-car! q cell)
+                                  (set-cdr! (cdr q) cell))
+                              (set-cdr! q cell)))",
+                         "(define q (make-queue))",
+                         "(enqueue! q 1)",
+                         "(enqueue! q 2)",
+                         "(enqueue! q 3)",
+                         "(car q)" => "(1 2 3)");
+
+    assert_evaluates_to!("(import (rnrs mutable-pairs))",
+                         "(let ((p (list 1 2 3))) (set-cdr! (cdr p) '()) p)" => "(1 2)");
+    assert_evaluation_fails!("(set-car! '() 1)" => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn circular_list_test() {
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+
+    let mut src_parser = Parser::new("(let ((p (list 1 2 3)))
+                                        (set-cdr! (cdr (cdr p)) p)
+                                        (list p (car (cdr (cdr (cdr p))))))".as_bytes());
+    let res = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+    assert_eq!(format!("{}", res), "(#0=(1 2 3 . #0#) 1)");
+    drop(res);
+
+    eval_all(&mut runtime, &[
+        "(define (make-ring n)
+            (let ((p (list n)))
+              (set-cdr! p p)
+              p))",
+        "(make-ring 1)",
+        "(car (make-ring 2))"
+    ]).unwrap();
+    let stats = runtime.gc();
+    assert!(stats.freed >= 3, "{:?}", stats);
+}
+
+#[test]
+fn string_test() {
+    assert_evaluates_to!("(string-length \"hello\")" => "5");
+    assert_evaluates_to!("(string-length \"\\x3bb;x\")" => "2");
+    assert_evaluates_to!("(string-ref \"a\\x3bb;c\" 1)" => "#\\x3bb");
+    assert_evaluates_to!("(substring \"hello\" 1 3)" => "\"el\"");
+    assert_evaluates_to!("(string-append \"foo\" \"\" \"bar\")" => "\"foobar\"");
+    assert_evaluates_to!("(string-append)" => "\"\"");
+    // string literals are copied when evaluated
+    assert_evaluates_to!("(define (f) \"abc\")",
+                         "(string-set! (f) 0 #\\z)",
+                         "(f)"
+                         => "\"abc\"");
+    assert_evaluates_to!("(define (f) '(\"abc\"))",
+                         "(string-fill! (car (f)) #\\z)",
+                         "(f)"
+                         => "(\"abc\")");
+    assert_evaluates_to!("(string->list \"abc\")" => "(#\\a #\\b #\\c)");
+    assert_evaluates_to!("(list->string '(#\\a #\\b))" => "\"ab\"");
+    assert_evaluates_to!("(string #\\x #\\y)" => "\"xy\"");
+    assert_evaluates_to!("(list (string=? \"ab\" \"ab\" \"ab\") (string<? \"ab\" \"abc\" \"b\")
+                                (string>? \"b\" \"a\") (string<=? \"a\" \"a\") (string>=? \"a\" \"b\"))"
+                         => "(#t #t #t #t #f)");
+    assert_evaluates_to!("(list (string-ci=? \"Hello\" \"hELLO\") (string-ci<? \"apple\" \"Banana\")
+                                (string<? \"apple\" \"Banana\"))" => "(#t #t #f)");
+
+    assert_evaluates_to!("(let ((s (make-string 3 #\\a)))
+                            (string-set! s 1 #\\b)
+                            s)" => "\"aba\"");
+    assert_evaluates_to!("(let* ((s (string-copy \"abc\")) (t (string-copy s)))
+                            (string-fill! s #\\z)
+                            (list s t))" => "(\"zzz\" \"abc\")");
+    assert_evaluates_to!("(let ((s (make-string 2 #\\a)) (acc '()))
+                            (string-for-each (lambda (c d) (set! acc (cons (list c d) acc)))
+                                             \"xy\" s)
+                            acc)" => "((#\\y #\\a) (#\\x #\\a))");
+    assert_evaluates_to!("(let ((s (string #\\a))) (eqv? s s))" => "#t");
+    assert_evaluates_to!("(eqv? (string #\\a) (string #\\a))" => "#f");
+    assert_evaluates_to!("(equal? (string #\\a) (string #\\a))" => "#t");
+
+    assert_evaluation_fails!("(string-ref \"abc\" 3)" => RuntimeErrorKind::IndexOutOfRange);
+    assert_evaluation_fails!("(substring \"abc\" 2 1)" => RuntimeErrorKind::IndexOutOfRange);
+    assert_evaluation_fails!("(string-set! (make-string 1) 0 1)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(list->string '(1 2))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(string-for-each (lambda (c d) c) \"a\" \"bc\")"
+                             => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn char_test() {
+    assert_evaluates_to!("(char->integer #\\A)" => "65");
+    assert_evaluates_to!("(integer->char 955)" => "#\\x3bb");
+    assert_evaluates_to!("(list (char=? #\\a #\\a #\\a) (char<? #\\a #\\b #\\c) (char>? #\\a #\\b)
+                                (char<=? #\\a #\\a) (char>=? #\\b #\\a))" => "(#t #t #f #t #t)");
+    assert_evaluates_to!("(list (char-ci=? #\\a #\\A) (char-ci<? #\\a #\\B))" => "(#t #t)");
+    assert_evaluates_to!("(list (char-upcase #\\i) (char-downcase #\\x3a3) (char-titlecase #\\x1c6)
+                                (char-foldcase #\\x3c2) (char-upcase #\\xdf))"
+                         => "(#\\I #\\x3c3 #\\x1c5 #\\x3c3 #\\xdf)");
+    assert_evaluates_to!("(list (char-alphabetic? #\\a) (char-alphabetic? #\\1) (char-numeric? #\\1)
+                                (char-whitespace? #\\space) (char-whitespace? #\\x3000)
+                                (char-upper-case? #\\A) (char-lower-case? #\\A)
+                                (char-title-case? #\\x1c5))"
+                         => "(#t #f #t #t #t #t #f #t)");
+    assert_evaluates_to!("(list (char-general-category #\\a) (char-general-category #\\Z)
+                                (char-general-category #\\0) (char-general-category #\\space)
+                                (char-general-category #\\() (char-general-category #\\x5b57))"
+                         => "(Ll Lu Nd Zs Ps Lo)");
+    assert_evaluation_fails!("(integer->char 55296)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(char->integer \"a\")" => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn unicode_string_test() {
+    assert_evaluates_to!("(string-upcase \"Stra\\xdf;e\")" => "\"STRASSE\"");
+    assert_evaluates_to!("(string-downcase \"\\x3a7;\\x391;\\x39f;\\x3a3;\")" => "\"\\x3c7;\\x3b1;\\x3bf;\\x3c2;\"");
+    assert_evaluates_to!("(string-foldcase \"Stra\\xdf;e\")" => "\"strasse\"");
+    assert_evaluates_to!("(string-titlecase \"kNock KNoCK who's there?\")" => "\"Knock Knock Who's There?\"");
+    assert_evaluates_to!("(string-ci=? \"Stra\\xdf;e\" \"STRASSE\")" => "#t");
+
+    // U+00C5 and U+212B are both "A with ring above", U+0041 U+030A is the decomposed form
+    assert_evaluates_to!("(string-normalize-nfd \"\\xc5;\")" => "\"A\\x30a;\"");
+    assert_evaluates_to!("(string-normalize-nfc \"A\\x30a;\")" => "\"\\xc5;\"");
+    assert_evaluates_to!("(string-normalize-nfc \"\\x212b;\")" => "\"\\xc5;\"");
+    assert_evaluates_to!("(string-normalize-nfkd \"\\xfb01;\")" => "\"fi\"");
+    assert_evaluates_to!("(string-normalize-nfkc \"\\xfb01;\\x2075;\")" => "\"fi5\"");
+    assert_evaluates_to!("(string-normalize-nfd \"\\xfb01;\")" => "\"\\xfb01;\"");
+
+    assert_evaluates_to!("(import (rnrs unicode))",
+                         "(char-upcase #\\a)" => "#\\A");
+}
+
+#[test]
+fn hashtable_test() {
+    assert_evaluates_to!("(define h (make-eqv-hashtable))",
+                         "(hashtable-set! h 1 'one)",
+                         "(hashtable-set! h 2.0 'two)",
+                         "(list (hashtable-ref h 1 #f) (hashtable-ref h 1.0 #f) (hashtable-ref h 2.0 #f)
+                                (hashtable-contains? h 2) (hashtable-size h))"
+                         => "(one #f two #f 2)");
+    assert_evaluates_to!("(define h (make-eq-hashtable))",
+                         "(hashtable-set! h 'a 1)",
+                         "(hashtable-update! h 'a (lambda (x) (+ x 10)) 0)",
+                         "(hashtable-update! h 'b (lambda (x) (+ x 10)) 0)",
+                         "(hashtable-delete! h 'c)",
+                         "(list (hashtable-ref h 'a #f) (hashtable-ref h 'b #f) (hashtable-size h))"
+                         => "(11 10 2)");
+    assert_evaluates_to!("(define h (make-hashtable string-hash string=?))",
+                         "(hashtable-set! h (string #\\a) 1)",
+                         "(hashtable-set! h \"a\" 2)",
+                         "(hashtable-set! h \"b\" 3)",
+                         "(hashtable-delete! h \"b\")",
+                         "(list (hashtable-ref h \"a\" #f) (hashtable-contains? h \"b\") (hashtable-size h))"
+                         => "(2 #f 1)");
+
+    // every key has the same hash value
+    assert_evaluates_to!("(define h (make-hashtable (lambda (x) 0) equal?))",
+                         "(hashtable-set! h '(1 2) 'x)",
+                         "(hashtable-set! h '(3) 'y)",
+                         "(hashtable-update! h (list 1 2) (lambda (v) (list v v)) #f)",
+                         "(list (hashtable-ref h (list 1 2) #f) (hashtable-ref h (list 3) #f)
+                                (hashtable-ref h (list 4) 'none) (hashtable-size h))"
+                         => "((x x) y none 2)");
+    assert_evaluates_to!("(define h (make-hashtable equal-hash equal?))",
+                         "(hashtable-set! h (vector 1 \"a\") 'v)",
+                         "(call-with-values (lambda () (hashtable-entries h)) list)"
+                         => "(#(#(1 \"a\")) #(v))");
+    assert_evaluates_to!("(define h (make-eqv-hashtable))",
+                         "(hashtable-set! h 1 2)",
+                         "(hashtable-keys h)"
+                         => "#(1)");
+
+    assert_evaluates_to!("(define h (make-eq-hashtable))",
+                         "(hashtable-set! h 'a 1)",
+                         "(define c (hashtable-copy h))",
+                         "(define m (hashtable-copy h #t))",
+                         "(hashtable-set! m 'b 2)",
+                         "(hashtable-clear! h)",
+                         "(list (hashtable-ref c 'a #f) (hashtable-size m) (hashtable-size h)
+                                (hashtable-mutable? c) (hashtable-mutable? m) (hashtable? c)
+                                (hashtable? '()))"
+                         => "(1 2 0 #f #t #t #f)");
+    assert_evaluates_to!("(list (hashtable-hash-function (make-eqv-hashtable))
+                                (eqv? (hashtable-hash-function (make-hashtable string-hash string=?))
+                                      string-hash)
+                                ((hashtable-equivalence-function (make-eq-hashtable)) 'a 'a))"
+                         => "(#f #t #t)");
+    assert_evaluates_to!("(list (= (equal-hash (list 1 (string #\\a))) (equal-hash (list 1 \"a\")))
+                                (= (string-hash \"abc\") (string-hash (string #\\a #\\b #\\c)))
+                                (= (string-ci-hash \"ABC\") (string-ci-hash \"abc\"))
+                                (= (symbol-hash 'abc) (symbol-hash 'abc)))"
+                         => "(#t #t #t #t)");
+    assert_evaluates_to!("(import (rnrs hashtables))",
+                         "(hashtable-size (make-eq-hashtable 10))" => "0");
+
+    assert_evaluation_fails!("(define h (hashtable-copy (make-eq-hashtable)))",
+                             "(hashtable-set! h 'a 1)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(define h (hashtable-copy (make-eq-hashtable)))",
+                             "(hashtable-update! h 'a (lambda (x) x) 1)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(hashtable-ref '() 1 2)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(hashtable-set! (make-hashtable (lambda (x) 'a) eqv?) 1 2)"
+                             => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn record_test() {
+    assert_evaluates_to!("(define-record-type point (fields x (mutable y)))",
+                         "(define p (make-point 1 2))",
+                         "(point-y-set! p 5)",
+                         "(list (point? p) (point? 1) (point-x p) (point-y p))"
+                         => "(#t #f 1 5)");
+    assert_evaluates_to!("(define-record-type (point mk-point point?) (fields (immutable x px) (mutable y py set-py!)))",
+                         "(define p (mk-point 1 2))",
+                         "(set-py! p 3)",
+                         "(list (px p) (py p))"
+                         => "(1 3)");
+
+    // the protocol of the child receives the constructor of the parent
+    assert_evaluates_to!("(define-record-type point (fields x y))",
+                         "(define-record-type cpoint (parent point) (fields color)
+                            (protocol (lambda (p) (lambda (x c) ((p x 0) c)))))",
+                         "(define c (make-cpoint 3 'red))",
+                         "(list (point? c) (cpoint? c) (point-x c) (point-y c) (cpoint-color c))"
+                         => "(#t #t 3 0 red)");
+    assert_evaluates_to!("(define-record-type point (fields x y))",
+                         "(define-record-type point3 (parent point) (fields z))",
+                         "(define-record-type point4 (parent point3) (fields w)
+                            (protocol (lambda (p) (lambda (x) ((p x (* x 2) (* x 3)) (* x 4))))))",
+                         "(define p (make-point3 1 2 7))",
+                         "(define q (make-point4 1))",
+                         "(list (point-x p) (point-y p) (point3-z p) (point-y q) (point3-z q) (point4-w q))"
+                         => "(1 2 7 2 3 4)");
+    assert_evaluates_to!("(define (f)
+                            (define-record-type node (fields (mutable next)))
+                            (define n (make-node #f))
+                            (node-next-set! n n)
+                            (eq? (node-next n) n))",
+                         "(f)"
+                         => "#t");
+    assert_evaluates_to!("(define-record-type node (fields (mutable next)))",
+                         "(define n (make-node #f))",
+                         "(define m (make-node n))",
+                         "(node-next-set! n (list m))",
+                         "(let-values (((p extract) (open-string-output-port)))
+                            (write (list n m) p)
+                            (extract))"
+                         => "\"(#<node next: (#<node next: #<node ...>>)> #<node next: #<node next: (#<node ...>)>>)\"");
+
+    // procedural layer
+    assert_evaluates_to!("(define rtd (make-record-type-descriptor 'pare #f #f #f #f '#((mutable kar) (immutable kdr))))",
+                         "(define rcd (make-record-constructor-descriptor rtd #f #f))",
+                         "(define kons (record-constructor rcd))",
+                         "(define k (kons 1 2))",
+                         "((record-mutator rtd 0) k 10)",
+                         "(list ((record-predicate rtd) k) ((record-accessor rtd 0) k) ((record-accessor rtd 1) k))"
+                         => "(#t 10 2)");
+    assert_evaluates_to!("(define (make-rtd) (make-record-type-descriptor 'node #f 'uid1 #f #f '#((mutable next))))",
+                         "(define-record-type leaf (fields x) (nongenerative leaf-uid))",
+                         "(define leaf-rtd (record-type-descriptor leaf))",
+                         "(define-record-type leaf (fields x) (nongenerative leaf-uid))",
+                         "(list (eqv? (make-rtd) (make-rtd))
+                                (eqv? leaf-rtd (record-type-descriptor leaf))
+                                (eqv? (make-rtd) (make-record-type-descriptor 'node #f #f #f #f '#((mutable next)))))"
+                         => "(#t #t #f)");
+    assert_evaluation_fails!("(make-record-type-descriptor 'node #f 'uid2 #f #f '#((mutable next)))",
+                             "(make-record-type-descriptor 'node #f 'uid2 #f #f '#((immutable next)))"
+                             => RuntimeErrorKind::InvalidType);
+
+    // inspection layer
+    assert_evaluates_to!("(define-record-type point (fields x (mutable y)) (nongenerative) (sealed #t))",
+                         "(define rtd (record-rtd (make-point 1 2)))",
+                         "(list (eq? rtd (record-type-descriptor point)) (record-type-name rtd)
+                                (record-type-parent rtd) (record-type-uid rtd) (record-type-generative? rtd)
+                                (record-type-sealed? rtd) (record-type-opaque? rtd)
+                                (record-type-field-names rtd) (record-field-mutable? rtd 1)
+                                (record? (make-point 1 2)) (record? 1))"
+                         => "(#t point #f point #f #t #f #(x y) #t #t #f)");
+    assert_evaluates_to!("(define-record-type secret (fields x) (opaque #t))",
+                         "(list (record? (make-secret 1)) (secret? (make-secret 1)))"
+                         => "(#f #t)");
+    assert_evaluates_to!("(import (rnrs records syntactic) (rnrs records procedural) (rnrs records inspection))",
+                         "(define-record-type point (fields x))",
+                         "((record-accessor (record-type-descriptor point) 0) (make-point 4))"
+                         => "4");
+
+    assert_evaluation_fails!("(define-record-type point (fields x))",
+                             "(make-point 1 2)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(define-record-type point (fields x))",
+                             "(point-x 1)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(define-record-type point (fields x))",
+                             "(record-mutator (record-type-descriptor point) 0)" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(define-record-type point (fields x) (sealed #t))",
+                             "(define-record-type point3 (parent point) (fields z))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(define-record-type secret (fields x) (opaque #t))",
+                             "(record-rtd (make-secret 1))" => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn enum_test() {
+    assert_evaluates_to!("(define colors (make-enumeration '(red green blue red)))",
+                         "(list (enum-set->list colors)
+                                (enum-set-member? 'green colors)
+                                (enum-set-member? 'yellow colors)
+                                ((enum-set-indexer colors) 'blue)
+                                ((enum-set-indexer colors) 'yellow))"
+                         => "((red green blue) #t #f 2 #f)");
+    assert_evaluates_to!("(define (lists sets)
+                            (if (null? sets) '() (cons (enum-set->list (car sets)) (lists (cdr sets)))))",
+                         "(define c (enum-set-constructor (make-enumeration '(a b c d))))",
+                         "(define x (c '(c a)))",
+                         "(define y (c '(b c)))",
+                         "(lists (list (enum-set-union x y) (enum-set-intersection x y)
+                                       (enum-set-difference x y) (enum-set-complement x)
+                                       (enum-set-universe x)))"
+                         => "((a b c) (c) (a) (b d) (a b c d))");
+    assert_evaluates_to!("(define c (enum-set-constructor (make-enumeration '(a b c d))))",
+                         "(define e (make-enumeration '(a b c d e)))",
+                         "(list (enum-set-subset? (c '(a b)) (c '(a b c)))
+                                (enum-set-subset? (c '(a d)) (c '(a b c)))
+                                (enum-set-subset? (c '(a b)) e)
+                                (enum-set-subset? e (c '(a b c d)))
+                                (enum-set=? (c '(a b c d)) (enum-set-universe (c '())))
+                                (enum-set->list (enum-set-projection e (c '(a))))
+                                (enum-set->list (enum-set-projection (c '(b c)) (make-enumeration '(c d)))))"
+                         => "(#t #f #t #f #t (a b c d) (c))");
+
+    // flags of a configuration
+    assert_evaluates_to!("(define-enumeration mode (read write exec) mode-set)",
+                         "(define rw (mode-set write read))",
+                         "(list (mode exec)
+                                (enum-set->list rw)
+                                (enum-set-member? (mode write) rw)
+                                (enum-set->list (enum-set-union rw (mode-set exec)))
+                                (enum-set->list (enum-set-complement rw)))"
+                         => "(exec (read write) #t (read write exec) (exec))");
+    assert_evaluates_to!("(define (f)
+                            (define-enumeration day (mon tue wed) days)
+                            (enum-set->list (enum-set-complement (days tue))))",
+                         "(f)"
+                         => "(mon wed)");
+    assert_evaluates_to!("(import (rnrs enums))",
+                         "(enum-set->list (make-enumeration '(x)))" => "(x)");
+
+    assert_evaluation_fails!("(define-enumeration mode (read write) mode-set)",
+                             "(mode-set read exec)" => RuntimeErrorKind::CompileError);
+    assert_evaluation_fails!("(define-enumeration mode (read write) mode-set)",
+                             "(mode exec)" => RuntimeErrorKind::CompileError);
+    assert_evaluation_fails!("(enum-set-union (make-enumeration '(a)) (make-enumeration '(a)))"
+                             => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("((enum-set-constructor (make-enumeration '(a))) '(b))"
+                             => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(make-enumeration '(a 1))" => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn bytevector_test() {
+    assert_evaluates_to!("(define b (make-bytevector 4 255))",
+                         "(bytevector-u8-set! b 1 1)",
+                         "(list (bytevector? b) (bytevector? \"a\") (bytevector-length b)
+                                (bytevector-u8-ref b 1) (bytevector-s8-ref b 0)
+                                (bytevector->u8-list b))"
+                         => "(#t #f 4 1 -1 (255 1 255 255))");
+    assert_evaluates_to!("(define b (u8-list->bytevector '(1 2 3 4 5)))",
+                         "(define c b)",
+                         "(bytevector-copy! b 0 b 1 3)",
+                         "(list c (bytevector=? b (bytevector-copy b)) (eq? b (bytevector-copy b)))"
+                         => "(#vu8(1 1 2 3 5) #t #f)");
+
+    // header of a binary packet
+    assert_evaluates_to!("(define p (make-bytevector 16 0))",
+                         "(bytevector-u16-set! p 0 #xcafe (endianness big))",
+                         "(bytevector-s32-set! p 4 -2 (endianness little))",
+                         "(bytevector-u64-set! p 8 #x0102030405060708 'big)",
+                         "(list (bytevector-u16-ref p 0 'little)
+                                (bytevector-u32-ref p 4 'little)
+                                (bytevector-s32-ref p 4 (endianness little))
+                                (bytevector-u8-ref p 15)
+                                (bytevector-u64-ref p 8 'big)
+                                (bytevector-s16-native-ref p 6))"
+                         => "(65226 4294967294 -2 8 72623859790382856 -1)");
+    assert_evaluates_to!("(define f (make-bytevector 16 0))",
+                         "(bytevector-ieee-single-set! f 0 1.5 'big)",
+                         "(bytevector-ieee-double-native-set! f 8 0.25)",
+                         "(list (bytevector-u32-ref f 0 'big)
+                                (bytevector-ieee-single-ref f 0 'big)
+                                (bytevector-ieee-double-native-ref f 8))"
+                         => "(1069547520 1.5 0.25)");
+    assert_evaluates_to!("(define b (make-bytevector 12 0))",
+                         "(bytevector-uint-set! b 0 #x0102030405060708090a 'big 10)",
+                         "(bytevector-sint-set! b 9 -2 'little 3)",
+                         "(list (bytevector-uint-ref b 0 'big 3) (bytevector-sint-ref b 9 'little 3)
+                                (bytevector-uint-ref b 9 'little 3) (bytevector-uint-ref b 0 'big 9))"
+                         => "(66051 -2 16777214 18591708106338011145)");
+    assert_evaluates_to!("(list (bytevector->uint-list #vu8(1 2 255 255) 'little 2)
+                                (bytevector->sint-list #vu8(1 2 255 255) 'big 2)
+                                (uint-list->bytevector '(1 65535) 'big 2)
+                                (sint-list->bytevector '(-1 256) (endianness little) 3))"
+                         => "((513 65535) (258 -1) #vu8(0 1 255 255) #vu8(255 255 255 0 1 0))");
+    // bytevector literals are copied when evaluated
+    assert_evaluates_to!("(define (f) #vu8(1 2))",
+                         "(bytevector-u8-set! (f) 0 9)",
+                         "(f)"
+                         => "#vu8(1 2)");
+    assert_evaluates_to!("(list (string->utf8 \"λx\") (utf8->string #vu8(104 105))
+                                (string->utf16 \"a\") (string->utf16 \"a\" 'little)
+                                (utf16->string #vu8(255 254 97 0) 'big)
+                                (string->utf32 \"a\" (endianness little))
+                                (utf32->string #vu8(0 0 0 98) 'big))"
+                         => "(#vu8(206 187 120) \"hi\" #vu8(0 97) #vu8(97 0) \"a\" #vu8(97 0 0 0) \"b\")");
+
+    assert_evaluation_fails!("(bytevector-u8-ref (make-bytevector 2) 2)"
+                             => RuntimeErrorKind::IndexOutOfRange);
+    assert_evaluation_fails!("(bytevector-u32-ref (make-bytevector 4) 2 'big)"
+                             => RuntimeErrorKind::IndexOutOfRange);
+    assert_evaluation_fails!("(bytevector-u8-set! (make-bytevector 2) 0 256)"
+                             => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(bytevector-u16-ref (make-bytevector 2) 0 'middle)"
+                             => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(bytevector-u16-native-ref (make-bytevector 4) 1)"
+                             => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(bytevector-uint-ref (make-bytevector 4) 2 'big 3)"
+                             => RuntimeErrorKind::IndexOutOfRange);
+    assert_evaluation_fails!("(bytevector-sint-set! (make-bytevector 4) 0 128 'big 1)"
+                             => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(bytevector->uint-list #vu8(1 2 3) 'big 2)"
+                             => RuntimeErrorKind::InvalidType);
+}
+
+#[test]
+fn port_test() {
+    assert_evaluates_to!("(define p (open-string-input-port \"ab\\ncd\\n\\nefg\"))",
+                         "(list (lookahead-char p) (get-char p) (get-line p) (get-line p)
+                                (get-line p) (get-string-n p 2) (port-eof? p) (get-string-all p)
+                                (eof-object? (get-char p)) (eof-object? (get-line p)))"
+                         => "(#\\a #\\a \"b\" \"cd\" \"\" \"ef\" #f \"g\" #t #t)");
+    assert_evaluates_to!("(define p (open-bytevector-input-port #vu8(1 2 3 4 5)))",
+                         "(list (binary-port? p) (textual-port? p) (lookahead-u8 p) (get-u8 p)
+                                (get-bytevector-n p 2) (get-bytevector-all p)
+                                (eof-object? (get-u8 p)))"
+                         => "(#t #f 1 1 #vu8(2 3) #vu8(4 5) #t)");
+    assert_evaluates_to!("(define p (open-bytevector-input-port #vu8(206 187 13 10 97 13 98 233)
+                                                               (make-transcoder (utf-8-codec))))",
+                         "(list (get-line p) (get-line p) (get-string-all p))"
+                         => "(\"λ\" \"a\" \"b\u{fffd}\")");
+    assert_evaluates_to!("(let-values (((p extract) (open-string-output-port)))
+                            (put-string p \"hello\")
+                            (put-char p #\\space)
+                            (put-string p \"big world\" 4)
+                            (list (textual-port? p) (extract) (extract)))"
+                         => "(#t \"hello world\" \"\")");
+    assert_evaluates_to!("(define t (make-transcoder (latin-1-codec) (eol-style crlf) (error-handling-mode replace)))",
+                         "(let-values (((p extract) (open-bytevector-output-port t)))
+                            (put-string p \"é\\nλ\")
+                            (list (transcoder-eol-style (port-transcoder p)) (extract)
+                                  (bytevector->string #vu8(97 13 10) t)
+                                  (string->bytevector \"a\\n\" t)))"
+                         => "(crlf #vu8(233 13 10 63) \"a\\n\" #vu8(97 13 10))");
+
+    // custom input port reading the chunks, and custom output port collecting the output
+    assert_evaluates_to!("(define chunks '(\"li\" \"ne1\\nli\" \"ne2\"))",
+                         "(define (copy! from to start i)
+                            (cond ((< i (string-length from))
+                                   (string-set! to (+ start i) (string-ref from i))
+                                   (copy! from to start (+ i 1)))))",
+                         "(define (read! s start count)
+                            (if (null? chunks)
+                                0
+                                (let ((chunk (car chunks)))
+                                  (set! chunks (cdr chunks))
+                                  (copy! chunk s start 0)
+                                  (string-length chunk))))",
+                         "(define p (make-custom-textual-input-port \"chunks\" read! #f #f #f))",
+                         "(list (get-line p) (get-line p) (eof-object? (get-line p)))"
+                         => "(\"line1\" \"line2\" #t)");
+    assert_evaluates_to!("(define out '())",
+                         "(define closed #f)",
+                         "(define (write! bv start count)
+                            (set! out (cons (bytevector-u8-ref bv start) out))
+                            1)",
+                         "(define p (make-custom-binary-output-port \"bytes\" write! #f #f
+                                                                   (lambda () (set! closed #t))))",
+                         "(put-bytevector p #vu8(1 2 3 4) 1 2)",
+                         "(put-u8 p 9)",
+                         "(close-port p)",
+                         "(list out closed)"
+                         => "((9 3 2) #t)");
+
+    let root = library_dir("ports", &[("in.txt", "first\r\nsecond\n")]);
+    let input = root.join("in.txt");
+    let output = root.join("out.bin");
+    let open_input = format!("(define in (open-file-input-port {:?} (file-options) (buffer-mode block)
+                                                            (native-transcoder)))",
+                             input.to_str().unwrap());
+    let open_output = format!("(define out (open-file-output-port {:?} (file-options no-fail)))",
+                              output.to_str().unwrap());
+    let read_output = format!("(call-with-port (open-file-input-port {:?})
+                                 (lambda (p) (cons (get-bytevector-all p) lines)))",
+                              output.to_str().unwrap());
+    let reopen_error = format!("(#t {:?})", output.to_str().unwrap());
+    let reopen_output = format!("(guard (e ((i/o-file-already-exists-error? e)
+                                          (list (i/o-filename-error? e) (i/o-error-filename e))))
+                                   (open-file-output-port {:?}))",
+                                output.to_str().unwrap());
+    assert_evaluates_to!(open_input.as_str(),
+                         "(define lines (list (get-line in) (get-line in) (port-eof? in)))",
+                         "(close-port in)",
+                         open_output.as_str(),
+                         "(put-bytevector out #vu8(1 2 3))",
+                         "(close-port out)",
+                         read_output.as_str()
+                         => "(#vu8(1 2 3) \"first\" \"second\" #t)");
+    assert_evaluates_to!(reopen_output.as_str() => reopen_error.as_str());
+    fs::remove_dir_all(root).unwrap();
+
+    assert_evaluation_fails!("(get-u8 (open-string-input-port \"a\"))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(define p (open-string-input-port \"a\"))",
+                             "(close-port p)",
+                             "(get-char p)"
+                             => RuntimeErrorKind::Io);
+    assert_evaluates_to!("(guard (e ((i/o-file-does-not-exist-error? e)
+                                     (list (i/o-error? e) (i/o-error-filename e))))
+                            (open-file-input-port \"/nonexistent/r6\"))"
+                         => "(#t \"/nonexistent/r6\")");
+    assert_evaluates_to!("(guard (e ((i/o-decoding-error? e) (list (i/o-port-error? e) (i/o-error-port e))))
+                            (bytevector->string #vu8(255) (make-transcoder (utf-8-codec) 'lf 'raise)))"
+                         => "(#t #f)");
+    assert_evaluates_to!("(guard (e ((i/o-encoding-error? e) (i/o-encoding-error-char e)))
+                            (string->bytevector \"aλ\" (make-transcoder (latin-1-codec) 'lf 'raise)))"
+                         => "#\\λ");
+
+    // the invalid sequence is consumed by the error, and reading resumes after it
+    assert_evaluates_to!("(define p (open-bytevector-input-port #vu8(226 130 98)
+                                                               (make-transcoder (utf-8-codec) 'lf 'raise)))",
+                         "(define (next) (guard (e ((i/o-decoding-error? e) (eq? (i/o-error-port e) p))) (get-char p)))",
+                         "(let* ((a (next)) (b (next)) (c (next))) (list a b (eof-object? c)))"
+                         => "(#t #\\b #t)");
+    assert_evaluates_to!("(bytevector->string #vu8(226 130 98 255) (make-transcoder (utf-8-codec)))"
+                         => "\"\u{fffd}b\u{fffd}\"");
+    assert_evaluates_to!("(list (i/o-file-is-read-only-error? (make-i/o-file-is-read-only-error \"f\"))
+                                (i/o-file-protection-error? (make-i/o-file-is-read-only-error \"f\"))
+                                (i/o-error-filename (make-i/o-file-is-read-only-error \"f\"))
+                                (i/o-error-position (make-i/o-invalid-position-error 3))
+                                (i/o-read-error? (make-i/o-write-error)))"
+                         => "(#t #t \"f\" 3 #f)");
+}
+
+#[test]
+fn simple_io_test() {
+    assert_evaluates_to!("(define p (open-string-input-port \"(a \\\"b\\\" #\\\\c . #(1))x  12 ; end\\n\"))",
+                         "(list (read p) (peek-char p) (read p) (read p) (eof-object? (read p))
+                                (eof-object? (read-char p)))"
+                         => "((a \"b\" #\\c . #(1)) #\\x x 12 #t #t)");
+    // the delimiter after a datum is left in the port
+    assert_evaluates_to!("(define p (open-string-input-port \"abc\\nnext line\"))",
+                         "(list (read p) (get-line p) (get-line p))"
+                         => "(abc \"\" \"next line\")");
+    assert_evaluates_to!("(define p (open-string-input-port \"(1) (2)\"))",
+                         "(list (read p) (get-char p) (read p) (eof-object? (get-char p)))"
+                         => "((1) #\\space (2) #t)");
+    assert_evaluates_to!("(let-values (((p extract) (open-string-output-port)))
+                            (display \"a\\\"b\" p)
+                            (write \"a\\\"b\" p)
+                            (write-char #\\z p)
+                            (newline p)
+                            (display '(1 \"x\" #\\y) p)
+                            (write '(1 \"x\" #\\y) p)
+                            (extract))"
+                         => "\"a\\\"b\\\"a\\\\\\\"b\\\"z\\n(1 x y)(1 \\\"x\\\" #\\\\y)\"");
+    assert_evaluates_to!("(list (textual-port? (current-input-port)) (input-port? (current-input-port))
+                                (output-port? (current-output-port))
+                                (output-port? (current-error-port)))"
+                         => "(#t #t #t #t)");
+
+    // the custom port is read up to the end of the datum
+    assert_evaluates_to!("(define src \"(a b) c\")",
+                         "(define i 0)",
+                         "(define (read! s start count)
+                            (cond ((= i (string-length src)) 0)
+                                  (else (string-set! s start (string-ref src i))
+                                        (set! i (+ i 1))
+                                        1)))",
+                         "(define p (make-custom-textual-input-port \"chunks\" read! #f #f #f))",
+                         "(list (read p) (read p) (eof-object? (read p)))"
+                         => "((a b) c #t)");
+    // the port never reaches the end of file, and is read a character ahead of the datum
+    assert_evaluates_to!("(define src \"(a) b \")",
+                         "(define i 0)",
+                         "(define (read! s start count)
+                            (if (= i (string-length src)) (set! i 0))
+                            (string-set! s start (string-ref src i))
+                            (set! i (+ i 1))
+                            1)",
+                         "(define p (make-custom-textual-input-port \"endless\" read! #f #f #f))",
+                         "(list (read p) (read p) (read p) i)"
+                         => "((a) b (a) 4)");
+
+    let root = library_dir("simple-io", &[]);
+    let path = root.join("out.scm");
+    let write_file = format!("(with-output-to-file {:?}
+                                (lambda ()
+                                  (write '(define x \"x\"))
+                                  (display \" \")
+                                  (write #\\y)))",
+                             path.to_str().unwrap());
+    let read_file = format!("(call-with-input-file {:?}
+                               (lambda (p) (let* ((a (read p)) (b (read p))) (list a b (eof-object? (read p))))))",
+                            path.to_str().unwrap());
+    let read_chars = format!("(with-input-from-file {:?}
+                                (lambda () (list (read-char) (peek-char) (read))))",
+                             path.to_str().unwrap());
+    let overwrite = format!("(guard (e ((i/o-file-already-exists-error? e) 'exists))
+                               (call-with-output-file {:?} (lambda (p) (display 1 p))))",
+                            path.to_str().unwrap());
+    fs::create_dir_all(&root).unwrap();
+    assert_evaluates_to!(write_file.as_str(),
+                         "(output-port? (current-output-port))",
+                         read_file.as_str()
+                         => "((define x \"x\") #\\y #t)");
+    assert_evaluates_to!(read_chars.as_str() => "(#\\( #\\d define)");
+    assert_evaluates_to!(overwrite.as_str() => "exists");
+    fs::remove_dir_all(root).unwrap();
+
+    assert_evaluation_fails!("(read (open-string-input-port \"(1 2\"))" => RuntimeErrorKind::Lexical);
+    assert_evaluates_to!("(guard (e ((lexical-violation? e) 'lexical) ((i/o-error? e) 'io))
+                            (read (open-string-input-port \"#(1 . 2)\")))"
+                         => "lexical");
+    assert_evaluation_fails!("(read (open-bytevector-input-port #vu8(1)))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(display 1 (current-output-port) 2)" => RuntimeErrorKind::NumArgs);
+}
+
+#[test]
+fn eval_test() {
+    assert_evaluates_to!("(eval '(+ 1 2) (environment '(rnrs base)))" => "3");
+    assert_evaluates_to!("(eval '(let ((x 2)) ((lambda (y) (* x y)) 3)) (environment '(rnrs base)))" => "6");
+    assert_evaluates_to!("((eval '(lambda (x) (cons x x)) (environment '(rnrs base))) 1)" => "(1 . 1)");
+    assert_evaluates_to!("(eval '(memv 2 '(3 1 2 4)) (environment '(rnrs base) '(only (rnrs lists) memv)))"
+                         => "(2 4)");
+
+    // the code runs within the caller's expression
+    assert_evaluates_to!("(define env (environment '(rnrs)))",
+                         "(list 1 (eval '(* 2 3) env) (let ((x 4)) (eval `(+ ,x 1) env)) 6)"
+                         => "(1 6 5 6)");
+    assert_evaluates_to!("(define env (environment '(rnrs)))",
+                         "(define (loop n acc) (if (= n 0) acc (loop (- n 1) (eval `(+ ,acc 1) env))))",
+                         "(loop 100 0)"
+                         => "100");
+    assert_evaluates_to!("(eqv? (environment '(rnrs)) (environment '(rnrs)))" => "#f");
+    assert_evaluates_to!("(let ((env (environment '(rnrs)))) (eqv? env env))" => "#t");
+
+    // the bindings of the caller are not visible, and errors are raised in the caller
+    assert_evaluation_fails!("(define x 1)", "(eval 'x (environment '(rnrs base)))" => RuntimeErrorKind::UnboundVariable);
+    assert_evaluation_fails!("(eval '(define x 1) (environment '(rnrs base)))" => RuntimeErrorKind::CompileError);
+    assert_evaluation_fails!("(eval '(car '()) (environment '(rnrs base)))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(eval 1 '(rnrs base))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(environment '(no such library))" => RuntimeErrorKind::CompileError);
+    assert_evaluates_to!("(guard (e (#t 'caught)) (eval '(raise 'oops) (environment '(rnrs))))" => "caught");
+    assert_evaluates_to!("(guard (e (#t (list 'caught e))) (+ 1 (eval '(raise 'oops) (environment '(rnrs)))))"
+                         => "(caught oops)");
+
+    // the library is loaded in the middle of the caller's expression
+    let root = library_dir("eval", &[
+        ("gen/util.sls", "(library (gen util) (export twice) (import (rnrs base))
+                            (define (twice x) (* x 2)))")
+    ]);
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+    runtime.add_library_path(&root);
+    let mut src_parser = Parser::new("(list 1 (eval '(twice 21) (environment '(rnrs base) '(gen util))) 3)".as_bytes());
+    let res = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+    let mut expected_parser = Parser::new("(1 42 3)".as_bytes());
+    assert_eq!(res, expected_parser.parse_datum().unwrap());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn promise_test() {
+    assert_evaluates_to!("(force (delay (+ 1 2)))" => "3");
+    assert_evaluates_to!("(let ((p (delay (+ 1 2)))) (list (force p) (force p)))" => "(3 3)");
+    assert_evaluates_to!("(force (make-promise 1))" => "1");
+    assert_evaluates_to!("(let ((p (delay 1))) (eq? p (make-promise p)))" => "#t");
+    assert_evaluates_to!("(list (promise? (delay 1)) (promise? (delay-force (delay 1))) (promise? 1))"
+                         => "(#t #t #f)");
+    assert_evaluates_to!("(promise? (force (delay (delay 1))))" => "#t");
+    assert_evaluates_to!("(force 3)" => "3");
+    assert_evaluates_to!("(eval '(force (delay-force (delay 1))) (environment '(rnrs r5rs)))" => "1");
+    assert_evaluation_fails!("(force (delay-force 1))" => RuntimeErrorKind::InvalidType);
+    assert_evaluation_fails!("(force (delay (values 1 2)))" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(force (delay-force (values 1 2)))" => RuntimeErrorKind::NumArgs);
+    assert_evaluates_to!("(define p (delay (values 1 2)))",
+                         "(list (guard (e (#t 'caught)) (force p))
+                                (guard (e (#t 'caught)) (force p))
+                                (+ 1 2))"
+                         => "(caught caught 3)");
+
+    // the value is memoized
+    assert_evaluates_to!("(define count 0)",
+                         "(define p (delay (begin (set! count (+ count 1)) count)))",
+                         "(list (force p) (force p) count)"
+                         => "(1 1 1)");
+
+    // the first value computed is kept when the promise is forced again while computing it
+    assert_evaluates_to!("(define count 0)",
+                         "(define x 5)",
+                         "(define p
+                            (delay (begin (set! count (+ count 1))
+                                          (if (> count x)
+                                              count
+                                              (force p)))))",
+                         "(list (force p) (begin (set! x 10) (force p)))"
+                         => "(6 6)");
+    assert_evaluates_to!("(define count 0)",
+                         "(define r
+                            (delay-force (begin (set! count (+ count 1))
+                                                (if (> count 3) (delay count) r))))",
+                         "(list (force r) (force r) count)"
+                         => "(4 4 4)");
+
+    // an error leaves the promise unforced
+    assert_evaluates_to!("(define n 0)",
+                         "(define p (delay (begin (set! n (+ n 1)) (if (= n 1) (raise 'oops) n))))",
+                         "(list (guard (e (#t e)) (force p)) (force p) (force p))"
+                         => "(oops 2 2)");
+
+    // lazy streams, and a long chain of delay-force
+    assert_evaluates_to!("(define (ints n) (cons n (delay (ints (+ n 1)))))",
+                         "(define (nth s k) (if (= k 0) (car s) (nth (force (cdr s)) (- k 1))))",
+                         "(define s (ints 0))",
+                         "(list (nth s 1000) (nth s 10))"
+                         => "(1000 10)");
+    assert_evaluates_to!("(define (loop n) (delay-force (if (= n 0) (delay 'done) (loop (- n 1)))))",
+                         "(force (loop 100000))"
+                         => "done");
+    assert_evaluates_to!("(define (stream-tail s n)
+                            (delay-force (if (= n 0) s (stream-tail (cdr (force s)) (- n 1)))))",
+                         "(define (from n) (delay (cons n (from (+ n 1)))))",
+                         "(car (force (stream-tail (from 0) 10000)))"
+                         => "10000");
+
+    // a forced promise referring to itself is collected
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+    eval_all(&mut runtime, &[
+        "(define (make-knot) (letrec ((p (delay (cons 1 p)))) (car (force p))))",
+        "(make-knot)",
+        "(make-knot)"
+    ]).unwrap();
+    let stats = runtime.gc();
+    assert!(stats.freed >= 4, "{:?}", stats);
+}
+
+#[test]
+fn parameterize_test() {
+    assert_evaluates_to!("(define p (make-parameter 10))",
+                         "(list (p) (parameterize ((p 1)) (p)) (p))"
+                         => "(10 1 10)");
+    assert_evaluates_to!("(define p (make-parameter 10 (lambda (x) (* x 2))))",
+                         "(list (p) (parameterize ((p 1)) (list (p) (parameterize ((p 2)) (p)) (p))) (p))"
+                         => "(20 (2 4 2) 20)");
+    assert_evaluates_to!("(define p (make-parameter 1))",
+                         "(define q (make-parameter 2))",
+                         "(parameterize ((p 3) (q (p))) (list (p) (q)))"
+                         => "(3 1)");
+    assert_evaluates_to!("(define p (make-parameter 1))",
+                         "(define (get) (p))",
+                         "(parameterize ((p 2)) (define x (get)) (list x (get)))"
+                         => "(2 2)");
+    assert_evaluates_to!("(procedure? (make-parameter 1))" => "#t");
+    assert_evaluation_fails!("(make-parameter)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("((make-parameter 1) 2)" => RuntimeErrorKind::NumArgs);
+    assert_evaluation_fails!("(parameterize ((car 1)) 2)" => RuntimeErrorKind::InvalidType);
+
+    // the value is restored when the body is left by an exception or a continuation, and
+    // installed again when the body is reentered
+    assert_evaluates_to!("(define p (make-parameter 1))",
+                         "(list (guard (e (#t (p))) (parameterize ((p 2)) (raise 'oops))) (p))"
+                         => "(1 1)");
+    assert_evaluates_to!("(define p (make-parameter 1))",
+                         "(list (call/cc (lambda (k) (parameterize ((p 2)) (k (p))))) (p))"
+                         => "(2 1)");
+    assert_evaluates_to!("(define p (make-parameter 1 (lambda (x) (* x 2))))",
+                         "(define k #f)",
+                         "(define n 0)",
+                         "(let ((v (parameterize ((p 7)) (call/cc (lambda (c) (set! k c))) (p))))
+                            (set! n (+ n 1))
+                            (if (< n 3) (k #f) (list v (p) n)))"
+                         => "(14 2 3)");
+
+    // the current ports are parameters
+    assert_evaluates_to!("(let-values (((port extract) (open-string-output-port)))
+                            (parameterize ((current-output-port port))
+                              (display \"hello\")
+                              (write-char #\\!))
+                            (list (extract) (eq? port (current-output-port))))"
+                         => "(\"hello!\" #f)");
+
+    // an error escaping the runtime restores the value for the next evaluation
+    let mut runtime = Runtime::new(libbase(), base_syntax());
+    let res = eval_all(&mut runtime, &[
+        "(define p (make-parameter 1))",
+        "(parameterize ((p 2)) (car '()))"
+    ]);
+    assert_eq!(res.unwrap_err().kind, RuntimeErrorKind::InvalidType);
+    let mut src_parser = Parser::new("(p)".as_bytes());
+    let res = runtime.eval(&src_parser.parse_datum::<()>().unwrap()).unwrap();
+    let mut expected_parser = Parser::new("1".as_bytes());
+    assert_eq!(res, expected_parser.parse_datum().unwrap());
+}